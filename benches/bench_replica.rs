@@ -0,0 +1,60 @@
+#![feature(test)]
+
+extern crate test;
+
+use std::sync::Arc;
+
+use test::Bencher;
+
+use epaxos::cmdvec;
+use epaxos::qpaxos::*;
+use epaxos::replica::load_max_instance_ids;
+use epaxos::replica::Replica;
+use epaxos::testutil;
+use epaxos::StorageAPI;
+use storage::MemEngine;
+use storage::RawKV;
+use storage::Storage;
+
+/// new_foo_replica creates replica 1 in group [1, 2, 3] with `n` instances by every replica.
+fn new_foo_replica(n: i64) -> Replica {
+    let eng: Arc<dyn RawKV> = Arc::new(MemEngine::new().unwrap());
+    let sto = Storage::new(1, eng.clone());
+
+    for rid in 1..4 {
+        for idx in 0..n {
+            let mut inst = Instance::default();
+            inst.instance_id = Some((rid, idx).into());
+            sto.set_instance(&inst.instance_id.unwrap(), &inst).unwrap();
+        }
+    }
+
+    testutil::new_replica(1, vec![1, 2, 3], vec![], eng)
+}
+
+#[bench]
+fn bench_new_instance(b: &mut Bencher) {
+    let r = new_foo_replica(10_000);
+    let cmds = cmdvec![("Set", "x", "1")];
+
+    let mut rt = tokio::runtime::Runtime::new().unwrap();
+    b.iter(|| rt.block_on(r.new_instance(&cmds)).unwrap());
+}
+
+/// The cost of finding max instance ids in storage, which every proposal paid before max ids are
+/// kept in memory.
+#[bench]
+fn bench_load_max_instance_ids(b: &mut Bencher) {
+    let r = new_foo_replica(10_000);
+
+    let grids = r.get_group_replica_ids();
+    b.iter(|| load_max_instance_ids(&r.storage, &grids));
+}
+
+#[bench]
+fn bench_get_max_instance_ids(b: &mut Bencher) {
+    let r = new_foo_replica(10_000);
+
+    let grids = r.get_group_replica_ids();
+    b.iter(|| r.get_max_instance_ids(&grids));
+}
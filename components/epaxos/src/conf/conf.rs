@@ -1,4 +1,5 @@
 use std::collections::BTreeMap;
+use std::collections::HashMap;
 use std::fs;
 use std::net::AddrParseError;
 use std::net::SocketAddr;
@@ -37,6 +38,46 @@ pub struct GroupInfo {
     /// It is a left-close right-open range.
     pub range: (String, String),
     pub replicas: BTreeMap<ReplicaId, NodeId>,
+    /// learners receive and execute committed instances of the group but never vote. They
+    /// serve stale reads.
+    #[serde(default)]
+    pub learners: BTreeMap<ReplicaId, NodeId>,
+}
+
+/// Executor defines how a replica finds out the order to execute committed instances.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Executor {
+    /// execute the smallest instances of every leader that no other smallest instance is after.
+    LocalMin,
+    /// execute strongly connected components of the dependency graph of committed instances.
+    Scc,
+}
+
+impl Default for Executor {
+    fn default() -> Self {
+        Executor::LocalMin
+    }
+}
+
+/// Routing defines how a node serves a command on a key it has no replica for.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Routing {
+    /// forward the command to a node that has a replica for the key, and relay the reply.
+    Forward,
+    /// reply a range-based redirect: `-REDIRECT <start> <end> <api_addr>`, in which `[start, end)`
+    /// is the range of the group serving the key, and `api_addr` a node that has a voter of it.
+    ///
+    /// It is not a Redis Cluster `MOVED`: keys are routed by range, not by hash slot. A client may
+    /// cache the node for the range until it is redirected on a key in it.
+    Redirect,
+}
+
+impl Default for Routing {
+    fn default() -> Self {
+        Routing::Forward
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
@@ -54,6 +95,14 @@ pub struct ClusterInfo {
     /// No two groups have the same replica id.
     pub groups: Vec<GroupInfo>,
 
+    /// executor is the execution algorithm used by every replica in this cluster.
+    #[serde(default)]
+    pub executor: Executor,
+
+    /// routing is how a node serves a command on a key of a group it has no replica of.
+    #[serde(default)]
+    pub routing: Routing,
+
     #[serde(skip)]
     pub replicas: BTreeMap<ReplicaId, ReplicaInfo>,
 }
@@ -126,6 +175,156 @@ impl ClusterInfo {
         Some(&self.groups[rinfo.group_idx])
     }
 
+    /// get_node_by_replication returns the node with the replication address `addr`, e.g.
+    /// "http://127.0.0.1:4441" or "127.0.0.1:4441".
+    pub fn get_node_by_replication(&self, addr: &str) -> Option<&Node> {
+        let addr = addr.trim_start_matches("http://");
+        self.nodes
+            .values()
+            .find(|n| n.replication.to_string() == addr)
+    }
+
+    /// move_replica assigns replica `rid` to node `nid`, e.g. when the replica migrates to it.
+    pub fn move_replica(&mut self, rid: ReplicaId, nid: &str) -> Result<(), ConfError> {
+        let gidx = self
+            .get_replica(rid)
+            .ok_or(ConfError::UnknownReplica(rid))?
+            .group_idx;
+
+        if !self.nodes.contains_key(nid) {
+            return Err(ConfError::OrphanReplica(rid, nid.into()));
+        }
+
+        let g = &mut self.groups[gidx];
+        if g.learners.contains_key(&rid) {
+            g.learners.insert(rid, nid.into());
+        } else {
+            g.replicas.insert(rid, nid.into());
+        }
+        self.populate_replicas()
+    }
+
+    /// set_members sets the voters and learners of the group replica `rid` is in, e.g. when the
+    /// group changes its membership.
+    pub fn set_members(
+        &mut self,
+        rid: ReplicaId,
+        replicas: BTreeMap<ReplicaId, NodeId>,
+        learners: BTreeMap<ReplicaId, NodeId>,
+    ) -> Result<(), ConfError> {
+        let gidx = self
+            .get_replica(rid)
+            .ok_or(ConfError::UnknownReplica(rid))?
+            .group_idx;
+
+        let mut cluster = self.clone();
+        cluster.groups[gidx].replicas = replicas;
+        cluster.groups[gidx].learners = learners;
+
+        cluster.populate_replicas()?;
+        cluster.check_replicas()?;
+
+        *self = cluster;
+        Ok(())
+    }
+
+    /// split_group splits the group `rid` is in at key `at`: the group keeps `[start, at)` and a
+    /// new group serves `[at, end)`.
+    /// `pairs` maps every replica of the group, including learners, to the replica of the new
+    /// group on the same node.
+    pub fn split_group(
+        &mut self,
+        rid: ReplicaId,
+        at: &str,
+        pairs: &HashMap<ReplicaId, ReplicaId>,
+    ) -> Result<(), ConfError> {
+        let gidx = self
+            .get_replica(rid)
+            .ok_or(ConfError::UnknownReplica(rid))?
+            .group_idx;
+
+        let mut cluster = self.clone();
+        let g = &mut cluster.groups[gidx];
+
+        if at <= g.range.0.as_str() || at >= g.range.1.as_str() {
+            return Err(ConfError::BadSplitKey(at.into()));
+        }
+
+        let mut replicas = BTreeMap::new();
+        for (grid, nid) in g.replicas.iter() {
+            let prid = pairs.get(grid).ok_or(ConfError::NoPairedReplica(*grid))?;
+            replicas.insert(*prid, nid.clone());
+        }
+
+        let mut learners = BTreeMap::new();
+        for (grid, nid) in g.learners.iter() {
+            let prid = pairs.get(grid).ok_or(ConfError::NoPairedReplica(*grid))?;
+            learners.insert(*prid, nid.clone());
+        }
+
+        let new_group = GroupInfo {
+            range: (at.into(), g.range.1.clone()),
+            replicas,
+            learners,
+        };
+        g.range.1 = at.into();
+        cluster.groups.insert(gidx + 1, new_group);
+
+        cluster.check_group()?;
+        cluster.populate_replicas()?;
+        cluster.check_replicas()?;
+
+        *self = cluster;
+        Ok(())
+    }
+
+    /// merge_groups merges the group right after the one `rid` is in, into the latter.
+    /// `pairs` maps every replica of the group to the replica of the merged group on the same
+    /// node.
+    pub fn merge_groups(
+        &mut self,
+        rid: ReplicaId,
+        pairs: &HashMap<ReplicaId, ReplicaId>,
+    ) -> Result<(), ConfError> {
+        let gidx = self
+            .get_replica(rid)
+            .ok_or(ConfError::UnknownReplica(rid))?
+            .group_idx;
+
+        let mut cluster = self.clone();
+        let g = &cluster.groups[gidx];
+        let h = cluster
+            .groups
+            .get(gidx + 1)
+            .ok_or(ConfError::NotAdjacent(g.range.1.clone()))?;
+
+        if g.range.1 != h.range.0 {
+            return Err(ConfError::NotAdjacent(g.range.1.clone()));
+        }
+
+        if g.replicas.len() != h.replicas.len() {
+            return Err(ConfError::NotColocated(rid));
+        }
+
+        for (grid, nid) in g.replicas.iter() {
+            let prid = pairs.get(grid).ok_or(ConfError::NoPairedReplica(*grid))?;
+            if h.replicas.get(prid) != Some(nid) {
+                return Err(ConfError::NotColocated(*grid));
+            }
+        }
+
+        let end = h.range.1.clone();
+        cluster.groups[gidx].range.1 = end;
+        cluster.groups.remove(gidx + 1);
+
+        cluster.check_group()?;
+        cluster.populate_replicas()?;
+        cluster.check_replicas()?;
+
+        *self = cluster;
+        Ok(())
+    }
+
     // TODO test bad node id as replication addr
     // make a node id from key, i.e. mac address
     pub fn norm_node(nid: &str, node: &mut Node) -> Result<(), AddrParseError> {
@@ -164,13 +363,13 @@ impl ClusterInfo {
         self.replicas = BTreeMap::new();
 
         for (gidx, g) in self.groups.iter().enumerate() {
-            for (rid, _) in g.replicas.iter() {
+            for (rid, _) in g.replicas.iter().chain(g.learners.iter()) {
                 if self.replicas.contains_key(rid) {
                     return Err(ConfError::DupReplica(*rid));
                 }
             }
 
-            for (rid, nid) in g.replicas.iter() {
+            for (rid, nid) in g.replicas.iter().chain(g.learners.iter()) {
                 self.replicas.insert(
                     *rid,
                     ReplicaInfo {
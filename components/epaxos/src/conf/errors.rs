@@ -22,6 +22,16 @@ quick_error! {
         DupReplica(rid: ReplicaId) {}
 
         GroupOutOfOrder(a: String, b: String) {}
+
+        UnknownReplica(rid: ReplicaId) {}
+
+        BadSplitKey(key: String) {}
+
+        NoPairedReplica(rid: ReplicaId) {}
+
+        NotColocated(rid: ReplicaId) {}
+
+        NotAdjacent(key: String) {}
     }
 }
 
@@ -34,6 +44,11 @@ impl PartialEq<ConfError> for ConfError {
             (Self::OrphanReplica(a, b), Self::OrphanReplica(x, y)) => a == x && b == y,
             (Self::DupReplica(a), Self::DupReplica(b)) => a == b,
             (Self::GroupOutOfOrder(a, b), Self::GroupOutOfOrder(x, y)) => a == x && b == y,
+            (Self::UnknownReplica(a), Self::UnknownReplica(b)) => a == b,
+            (Self::BadSplitKey(a), Self::BadSplitKey(b)) => a == b,
+            (Self::NoPairedReplica(a), Self::NoPairedReplica(b)) => a == b,
+            (Self::NotColocated(a), Self::NotColocated(b)) => a == b,
+            (Self::NotAdjacent(a), Self::NotAdjacent(b)) => a == b,
             _ => false,
         }
     }
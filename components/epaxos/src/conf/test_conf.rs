@@ -1,4 +1,7 @@
 use super::*;
+use crate::qpaxos::ReplicaId;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
 use std::io::Write;
 use tempfile;
 
@@ -309,3 +312,260 @@ groups:
     let g = ci.get_group_for_key("h");
     assert!(g.is_none());
 }
+
+#[test]
+fn test_conf_executor() {
+    let cont = "
+nodes:
+    127.0.0.1:4441:
+        api_addr: 127.0.0.1:3331
+        replication: 127.0.0.1:5551
+groups: []
+";
+    let ci = ClusterInfo::from_str(cont).unwrap();
+    assert_eq!(Executor::LocalMin, ci.executor);
+
+    let ci = ClusterInfo::from_str(&format!("{}executor: scc\n", cont)).unwrap();
+    assert_eq!(Executor::Scc, ci.executor);
+
+    let ci = ClusterInfo::from_str(&format!("{}executor: local_min\n", cont)).unwrap();
+    assert_eq!(Executor::LocalMin, ci.executor);
+
+    let r = ClusterInfo::from_str(&format!("{}executor: foo\n", cont));
+    assert!(r.is_err());
+}
+
+#[test]
+fn test_conf_routing() {
+    let cont = "
+nodes:
+    127.0.0.1:4441:
+        api_addr: 127.0.0.1:3331
+        replication: 127.0.0.1:5551
+groups: []
+";
+    let ci = ClusterInfo::from_str(cont).unwrap();
+    assert_eq!(Routing::Forward, ci.routing);
+
+    let ci = ClusterInfo::from_str(&format!("{}routing: redirect\n", cont)).unwrap();
+    assert_eq!(Routing::Redirect, ci.routing);
+
+    let ci = ClusterInfo::from_str(&format!("{}routing: forward\n", cont)).unwrap();
+    assert_eq!(Routing::Forward, ci.routing);
+
+    let r = ClusterInfo::from_str(&format!("{}routing: foo\n", cont));
+    assert!(r.is_err());
+}
+
+#[test]
+fn test_conf_split_merge_groups() {
+    let cont = "
+nodes:
+    127.0.0.1:4441:
+        api_addr: 127.0.0.1:3331
+        replication: 127.0.0.1:5551
+    192.168.0.1:4442:
+        api_addr: 192.168.0.1:3332
+        replication: 192.168.0.1:4442
+groups:
+-   range:
+    -   a
+    -   d
+    replicas:
+        1: 192.168.0.1:4442
+        2: 127.0.0.1:4441
+-   range:
+    -   g
+    -   h
+    replicas:
+        3: 127.0.0.1:4441
+";
+    let ci = ClusterInfo::from_str(cont).unwrap();
+    let pairs: HashMap<ReplicaId, ReplicaId> = hashmap! {1=>11, 2=>12};
+
+    for (at, want) in vec![("a", "a"), ("d", "d"), ("e", "e")] {
+        let mut c = ci.clone();
+        let r = c.split_group(1, at, &pairs);
+        assert_eq!(ConfError::BadSplitKey(want.into()), r.err().unwrap());
+        assert_eq!(ci, c);
+    }
+
+    let mut c = ci.clone();
+    let r = c.split_group(1, "b", &hashmap! {1=>11});
+    assert_eq!(ConfError::NoPairedReplica(2), r.err().unwrap());
+
+    let r = c.split_group(1, "b", &hashmap! {1=>11, 2=>3});
+    assert_eq!(ConfError::DupReplica(3), r.err().unwrap());
+
+    let r = c.split_group(5, "b", &pairs);
+    assert_eq!(ConfError::UnknownReplica(5), r.err().unwrap());
+
+    c.split_group(1, "b", &pairs).unwrap();
+    assert_eq!(3, c.groups.len());
+    assert_eq!(("a".to_string(), "b".to_string()), c.groups[0].range);
+    assert_eq!(("b".to_string(), "d".to_string()), c.groups[1].range);
+    assert_eq!(Some(&c.groups[1]), c.get_group_for_key("c"));
+    assert_eq!(Some(&c.groups[1]), c.get_group(11));
+    assert_eq!(
+        Some(&ReplicaInfo {
+            group_idx: 1,
+            node_id: "127.0.0.1:4441".into(),
+        }),
+        c.get_replica(12)
+    );
+    assert_eq!(2, c.get_replica(3).unwrap().group_idx);
+
+    // not adjacent
+    let r = c.merge_groups(11, &hashmap! {11=>3});
+    assert_eq!(ConfError::NotAdjacent("d".into()), r.err().unwrap());
+
+    let r = c.merge_groups(1, &hashmap! {1=>12, 2=>11});
+    assert_eq!(ConfError::NotColocated(1), r.err().unwrap());
+
+    c.merge_groups(1, &pairs).unwrap();
+    assert_eq!(ci, c);
+}
+
+#[test]
+fn test_conf_move_replica() {
+    let cont = "
+nodes:
+    127.0.0.1:4441:
+        api_addr: 127.0.0.1:3331
+        replication: 127.0.0.1:4441
+    127.0.0.1:4442:
+        api_addr: 127.0.0.1:3332
+        replication: 127.0.0.1:4442
+groups:
+-   range:
+    -   a
+    -   z
+    replicas:
+        1: 127.0.0.1:4441
+        2: 127.0.0.1:4441
+";
+    let mut ci = ClusterInfo::from_str(cont).unwrap();
+
+    let n = ci.get_node_by_replication("http://127.0.0.1:4442").unwrap();
+    assert_eq!("127.0.0.1:4442", n.node_id);
+    let n = ci.get_node_by_replication("127.0.0.1:4441").unwrap();
+    assert_eq!("127.0.0.1:4441", n.node_id);
+    assert!(ci.get_node_by_replication("127.0.0.1:4443").is_none());
+
+    assert_eq!(
+        ConfError::UnknownReplica(3),
+        ci.move_replica(3, "127.0.0.1:4442").unwrap_err()
+    );
+    assert_eq!(
+        ConfError::OrphanReplica(1, "127.0.0.1:4443".into()),
+        ci.move_replica(1, "127.0.0.1:4443").unwrap_err()
+    );
+
+    ci.move_replica(1, "127.0.0.1:4442").unwrap();
+    assert_eq!("127.0.0.1:4442", ci.get_replica(1).unwrap().node_id);
+    assert_eq!("127.0.0.1:4442", ci.groups[0].replicas[&1]);
+    assert_eq!("127.0.0.1:4441", ci.get_replica(2).unwrap().node_id);
+}
+
+#[test]
+fn test_conf_set_members() {
+    let cont = "
+nodes:
+    127.0.0.1:4441:
+        api_addr: 127.0.0.1:3331
+        replication: 127.0.0.1:4441
+    127.0.0.1:4442:
+        api_addr: 127.0.0.1:3332
+        replication: 127.0.0.1:4442
+groups:
+-   range:
+    -   a
+    -   z
+    replicas:
+        1: 127.0.0.1:4441
+        2: 127.0.0.1:4441
+";
+    let mut ci = ClusterInfo::from_str(cont).unwrap();
+    let (na, nb) = ("127.0.0.1:4441".to_string(), "127.0.0.1:4442".to_string());
+
+    let replicas: BTreeMap<_, _> = vec![(1, na.clone()), (2, na.clone())].into_iter().collect();
+    let learners: BTreeMap<_, _> = vec![(3, nb.clone())].into_iter().collect();
+
+    assert_eq!(
+        ConfError::UnknownReplica(3),
+        ci.set_members(3, replicas.clone(), learners.clone())
+            .unwrap_err()
+    );
+
+    let bad: BTreeMap<_, _> = vec![(3, "127.0.0.1:4443".to_string())]
+        .into_iter()
+        .collect();
+    assert_eq!(
+        ConfError::OrphanReplica(3, "127.0.0.1:4443".into()),
+        ci.set_members(1, replicas.clone(), bad).unwrap_err()
+    );
+    assert!(ci.get_replica(3).is_none());
+
+    // add learner 3
+    ci.set_members(1, replicas.clone(), learners.clone())
+        .unwrap();
+    assert_eq!(&ci.groups[0], ci.get_group(3).unwrap());
+    assert_eq!(nb, ci.groups[0].learners[&3]);
+
+    // promote learner 3 in place of voter 1
+    let replicas: BTreeMap<_, _> = vec![(2, na.clone()), (3, nb.clone())].into_iter().collect();
+    ci.set_members(3, replicas.clone(), BTreeMap::new())
+        .unwrap();
+    assert!(ci.get_replica(1).is_none());
+    assert_eq!(replicas, ci.groups[0].replicas);
+    assert_eq!(0, ci.groups[0].learners.len());
+    assert_eq!(nb, ci.get_replica(3).unwrap().node_id);
+}
+
+#[test]
+fn test_conf_learners() {
+    let cont = "
+nodes:
+    127.0.0.1:4441:
+        api_addr: 127.0.0.1:3331
+        replication: 127.0.0.1:4441
+    127.0.0.1:4442:
+        api_addr: 127.0.0.1:3332
+        replication: 127.0.0.1:4442
+groups:
+-   range:
+    -   a
+    -   z
+    replicas:
+        1: 127.0.0.1:4441
+        2: 127.0.0.1:4441
+    learners:
+        3: 127.0.0.1:4442
+";
+    let mut ci = ClusterInfo::from_str(cont).unwrap();
+
+    assert_eq!(1, ci.groups[0].learners.len());
+    assert_eq!(&ci.groups[0], ci.get_group(3).unwrap());
+    assert_eq!("127.0.0.1:4442", ci.get_replica(3).unwrap().node_id);
+
+    // a learner is not a voter.
+    ci.move_replica(3, "127.0.0.1:4441").unwrap();
+    assert_eq!("127.0.0.1:4441", ci.groups[0].learners[&3]);
+    assert!(!ci.groups[0].replicas.contains_key(&3));
+
+    // learners are split along with voters.
+    let r = ci.split_group(1, "b", &hashmap! {1=>11, 2=>12});
+    assert_eq!(ConfError::NoPairedReplica(3), r.err().unwrap());
+
+    ci.split_group(1, "b", &hashmap! {1=>11, 2=>12, 3=>13})
+        .unwrap();
+    assert_eq!("127.0.0.1:4441", ci.groups[1].learners[&13]);
+    assert_eq!(1, ci.get_replica(13).unwrap().group_idx);
+
+    // a learner id must be unique too.
+    let dup = cont.replace("3: 127.0.0.1:4442", "2: 127.0.0.1:4442");
+    assert_eq!(
+        ConfError::DupReplica(2),
+        ClusterInfo::from_str(&dup).unwrap_err()
+    );
+}
@@ -58,6 +58,43 @@ pub trait StorageAPI: ObjectKV + RawKV {
         self.get(DBColumnFamily::Status, key)
     }
 
+    /// set a ballot status, e.g., the last seen ballot of a replica.
+    fn set_ballot_status(
+        &self,
+        key: &ReplicaStatus,
+        value: &BallotNum,
+    ) -> Result<(), StorageError> {
+        self.set(DBColumnFamily::Status, key, value)
+    }
+
+    /// get a ballot status by key
+    fn get_ballot_status(&self, key: &ReplicaStatus) -> Result<Option<BallotNum>, StorageError> {
+        self.get(DBColumnFamily::Status, key)
+    }
+
+    /// get the membership of the group this replica has applied.
+    fn get_membership(&self) -> Result<Option<Membership>, StorageError> {
+        self.get(DBColumnFamily::Status, &ReplicaStatus::Membership)
+    }
+
+    /// get the last range change this replica has applied.
+    fn get_range_change(&self) -> Result<Option<RangeChange>, StorageError> {
+        self.get(DBColumnFamily::Status, &ReplicaStatus::Range)
+    }
+
+    /// get the state of a transaction in the group of this replica.
+    fn get_txn_state(&self, txn_id: &[u8]) -> Result<Option<TxnState>, StorageError> {
+        self.get(DBColumnFamily::Status, &ReplicaStatus::Txn(txn_id.to_vec()))
+    }
+
+    /// get the lock of a key held by a pending transaction.
+    fn get_txn_lock(&self, key: &[u8]) -> Result<Option<TxnLock>, StorageError> {
+        self.get(
+            DBColumnFamily::Status,
+            &ReplicaStatus::TxnLock(key.to_vec()),
+        )
+    }
+
     /// set an instance
     fn set_instance(&self, key: &InstanceId, v: &Instance) -> Result<(), StorageError> {
         self.set(DBColumnFamily::Instance, key, v)
@@ -116,6 +153,29 @@ pub trait StorageAPI: ObjectKV + RawKV {
             v,
         );
     }
+    fn make_status_entry(&self, key: &ReplicaStatus, value: &InstanceIds) -> WriteEntry {
+        let mut v = vec![];
+        value.encode(&mut v).unwrap();
+        return WriteEntry::Set(DBColumnFamily::Status, self.prepend_ns(key), v);
+    }
+    fn make_membership_entry(&self, m: &Membership) -> WriteEntry {
+        let mut v = vec![];
+        m.encode(&mut v).unwrap();
+        return WriteEntry::Set(
+            DBColumnFamily::Status,
+            self.prepend_ns(&ReplicaStatus::Membership),
+            v,
+        );
+    }
+    fn make_range_change_entry(&self, rc: &RangeChange) -> WriteEntry {
+        let mut v = vec![];
+        rc.encode(&mut v).unwrap();
+        return WriteEntry::Set(
+            DBColumnFamily::Status,
+            self.prepend_ns(&ReplicaStatus::Range),
+            v,
+        );
+    }
 }
 
 impl StorageAPI for Storage {}
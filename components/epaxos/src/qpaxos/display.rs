@@ -20,6 +20,7 @@ use crate::qpaxos::QError;
 use crate::qpaxos::ReplicateReply;
 use crate::qpaxos::ReplicateRequest;
 use crate::qpaxos::StorageFailure;
+use std::collections::BTreeSet;
 use std::collections::HashMap;
 use std::fmt;
 
@@ -105,6 +106,38 @@ impl ToStringExt for Command {
             v if v == (OpCode::Delete as i32) => {
                 format!("Delete:{}", String::from_utf8_lossy(&self.key),)
             }
+            v if v == (OpCode::Membership as i32) => match self.get_membership() {
+                Some(m) => format!(
+                    "Membership:{:?}->{:?}",
+                    m.voters.keys().collect::<BTreeSet<_>>(),
+                    m.joint.keys().collect::<BTreeSet<_>>(),
+                ),
+                None => format!("Membership:?"),
+            },
+            v if v == (OpCode::Range as i32) => match self.get_range_change() {
+                Some(rc) => format!(
+                    "Range:{},split:{}",
+                    rc.range.tostr_ext(),
+                    rc.split.tostr_ext()
+                ),
+                None => format!("Range:?"),
+            },
+            v if v == (OpCode::TxnPrepare as i32) => match self.get_txn_prepare() {
+                Some(p) => format!(
+                    "TxnPrepare:{},writes:{}",
+                    String::from_utf8_lossy(&p.txn_id),
+                    p.writes.len()
+                ),
+                None => format!("TxnPrepare:?"),
+            },
+            v if v == (OpCode::TxnDecide as i32) => match self.get_txn_decide() {
+                Some(d) => format!(
+                    "TxnDecide:{},commit:{}",
+                    String::from_utf8_lossy(&d.txn_id),
+                    d.commit
+                ),
+                None => format!("TxnDecide:?"),
+            },
             _ => format!("UnknownCmd"),
         }
     }
@@ -154,6 +187,7 @@ impl_tostr_ext!(i32);
 impl_tostr_ext!(InstanceId, "({}, {})", replica_id, idx);
 impl_tostr_ext!(Dep, "({}, {}, {})", replica_id, idx, seq);
 impl_tostr_ext!(BallotNum, "({}, {})", num, replica_id);
+impl_tostr_ext!(KeyRange, "[{}, {})", start, end);
 impl_tostr_ext!(
     Instance,
     "{{id:{}, blt:{}, ablt:{}, cmds:{}, deps:{}, c:{}}}",
@@ -1,4 +1,4 @@
-use crate::qpaxos::{InvalidRequest, QError, ReplicaId};
+use crate::qpaxos::{InstanceId, InvalidRequest, QError, ReplicaId};
 
 quick_error! {
     #[derive(Debug, Eq, PartialEq)]
@@ -19,6 +19,10 @@ quick_error! {
         Incomplete(field: String, want: i32, actual: i32) {
             display("incomplete field:{}, need:{}, but:{}", field, want, actual)
         }
+
+        Truncated(iid: InstanceId) {
+            display("instance {} is deleted by gc", iid)
+        }
     }
 }
 
@@ -64,6 +68,15 @@ impl Into<QError> for ProtocolError {
                 }),
                 ..Default::default()
             },
+
+            Self::Truncated(_) => QError {
+                req: Some(InvalidRequest {
+                    field: "instance_id".into(),
+                    problem: "Truncated".into(),
+                    ctx,
+                }),
+                ..Default::default()
+            },
         }
     }
 }
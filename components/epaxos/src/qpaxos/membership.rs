@@ -0,0 +1,160 @@
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use prost::Message;
+
+use crate::qpaxos::quorum;
+use crate::qpaxos::Command;
+use crate::qpaxos::Membership;
+use crate::qpaxos::OpCode;
+use crate::qpaxos::ReplicaId;
+
+impl Membership {
+    pub fn new(voters: HashMap<ReplicaId, String>) -> Self {
+        Self {
+            voters,
+            joint: HashMap::new(),
+            learners: HashMap::new(),
+        }
+    }
+
+    /// is_joint returns true if the membership is changing from `voters` to `joint`.
+    pub fn is_joint(&self) -> bool {
+        self.joint.len() > 0
+    }
+
+    /// contains returns true if a replica votes in the current or the new config.
+    pub fn contains(&self, rid: ReplicaId) -> bool {
+        self.voters.contains_key(&rid) || self.joint.contains_key(&rid)
+    }
+
+    /// replica_ids returns replicas in the current or the new config, in ascending order.
+    pub fn replica_ids(&self) -> Vec<ReplicaId> {
+        let rids: BTreeSet<_> = self
+            .voters
+            .keys()
+            .chain(self.joint.keys())
+            .cloned()
+            .collect();
+        rids.into_iter().collect()
+    }
+
+    /// is_learner returns true if a replica is a learner: it receives and executes committed
+    /// instances but does not vote.
+    pub fn is_learner(&self, rid: ReplicaId) -> bool {
+        self.learners.contains_key(&rid)
+    }
+
+    /// learner_ids returns learners in ascending order.
+    pub fn learner_ids(&self) -> Vec<ReplicaId> {
+        let rids: BTreeSet<_> = self.learners.keys().cloned().collect();
+        rids.into_iter().collect()
+    }
+
+    /// get_addr returns the replication address of a replica in the current or the new config,
+    /// or of a learner.
+    pub fn get_addr(&self, rid: ReplicaId) -> Option<&String> {
+        self.joint
+            .get(&rid)
+            .or_else(|| self.voters.get(&rid))
+            .or_else(|| self.learners.get(&rid))
+    }
+
+    /// set_addr sets the replication address of a replica, e.g. after it migrates.
+    /// A replica that is not a learner is set as a voter.
+    pub fn set_addr(&mut self, rid: ReplicaId, addr: String) {
+        if let Some(a) = self.learners.get_mut(&rid) {
+            *a = addr;
+            return;
+        }
+        if let Some(a) = self.joint.get_mut(&rid) {
+            *a = addr.clone();
+        }
+        self.voters.insert(rid, addr);
+    }
+
+    /// quorum returns the least number of replicas a quorum has.
+    /// In a joint config it is the greater one of the two configs.
+    pub fn quorum(&self) -> i32 {
+        let q = quorum(self.voters.len() as i32);
+        if self.is_joint() {
+            q.max(quorum(self.joint.len() as i32))
+        } else {
+            q
+        }
+    }
+
+    /// is_quorum returns true if `rids` contains a quorum of every config.
+    /// Replicas that do not vote are not counted.
+    pub fn is_quorum(&self, rids: &HashSet<ReplicaId>) -> bool {
+        let is_q = |cfg: &HashMap<ReplicaId, String>| {
+            let n = rids.iter().filter(|rid| cfg.contains_key(rid)).count();
+            n as i32 >= quorum(cfg.len() as i32)
+        };
+
+        if self.is_joint() {
+            is_q(&self.voters) && is_q(&self.joint)
+        } else {
+            is_q(&self.voters)
+        }
+    }
+
+    /// enter_joint returns the joint config of the current voters and `voters`.
+    /// Learners are kept, except the ones in `voters`: they are promoted and vote in the joint
+    /// config.
+    pub fn enter_joint(&self, voters: HashMap<ReplicaId, String>) -> Self {
+        let learners = self
+            .learners
+            .iter()
+            .filter(|(rid, _)| !voters.contains_key(rid))
+            .map(|(rid, addr)| (*rid, addr.clone()))
+            .collect();
+
+        Self {
+            voters: self.voters.clone(),
+            joint: voters,
+            learners,
+        }
+    }
+
+    /// leave_joint returns the config in which only the new voters vote.
+    /// Learners are kept.
+    pub fn leave_joint(&self) -> Self {
+        Self {
+            voters: self.joint.clone(),
+            joint: HashMap::new(),
+            learners: self.learners.clone(),
+        }
+    }
+}
+
+impl From<&[ReplicaId]> for Membership {
+    /// from creates a Membership of replicas whose addresses are unknown.
+    fn from(rids: &[ReplicaId]) -> Self {
+        Self::new(rids.iter().map(|rid| (*rid, "".to_string())).collect())
+    }
+}
+
+impl From<&Membership> for Command {
+    fn from(m: &Membership) -> Command {
+        let mut value = vec![];
+        m.encode(&mut value).unwrap();
+        Command {
+            op: OpCode::Membership as i32,
+            key: vec![],
+            value,
+        }
+    }
+}
+
+impl Command {
+    /// get_membership returns the membership a Membership command changes to.
+    /// It returns None for other commands.
+    pub fn get_membership(&self) -> Option<Membership> {
+        if self.op != OpCode::Membership as i32 {
+            return None;
+        }
+        Membership::decode(self.value.as_slice()).ok()
+    }
+}
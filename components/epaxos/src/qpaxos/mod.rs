@@ -21,7 +21,10 @@ mod display;
 pub mod errors;
 mod instance_id_vec;
 mod instance_ids;
+mod membership;
 pub mod quorums;
+mod range;
+mod txn;
 
 pub use conflict::*;
 pub use deps::*;
@@ -30,9 +33,12 @@ pub use errors::*;
 pub use instance_id_vec::*;
 pub use instance_ids::*;
 pub use macros::*;
+pub use membership::*;
 pub use q_paxos_client::*;
 pub use q_paxos_server::*;
 pub use quorums::*;
+pub use range::*;
+pub use txn::*;
 pub use value::*;
 
 #[cfg(test)]
@@ -56,10 +62,16 @@ mod test_instance_ids;
 #[cfg(test)]
 mod test_macros;
 #[cfg(test)]
+mod test_membership;
+#[cfg(test)]
 mod test_quorums;
 #[cfg(test)]
+mod test_range;
+#[cfg(test)]
 mod test_record;
 #[cfg(test)]
+mod test_txn;
+#[cfg(test)]
 mod test_value;
 
 pub type InstanceIdx = i64;
@@ -67,9 +79,29 @@ pub type ReplicaId = i64;
 
 pub struct MakeRequest {}
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ReplicaStatus {
     Exec,
     MaxInstance,
+    /// A committed instance not yet delivered to a peer, by peer and instance id.
+    CommitPending(ReplicaId, InstanceId),
+    /// The greatest ballot a replica has seen, shared by all instances.
+    LastBallot,
+    /// Instances up to it are executed on every replica and are deleted.
+    GcWatermark,
+    /// The membership of the group, updated when a Membership command is executed.
+    Membership,
+    /// The last range change a replica has applied, updated when a Range command is executed.
+    Range,
+    /// Set when a replica is handed over to another node, to the instances it hands over after.
+    Frozen,
+    /// Set while a snapshot is being installed, to the exec status of the snapshot. A replica
+    /// does not execute until the installation finishes.
+    Installing,
+    /// The state of a transaction in the group, by transaction id.
+    Txn(Vec<u8>),
+    /// The transaction a key is locked by, by key.
+    TxnLock(Vec<u8>),
 }
 
 // TODO test
@@ -79,6 +111,17 @@ impl AsStorageKey for ReplicaStatus {
         match self {
             ReplicaStatus::Exec => "/exec".into(),
             ReplicaStatus::MaxInstance => "/max_inst".into(),
+            ReplicaStatus::CommitPending(rid, iid) => {
+                [commit_pending_prefix(*rid), iid.into_key()].concat()
+            }
+            ReplicaStatus::LastBallot => "/last_ballot".into(),
+            ReplicaStatus::GcWatermark => "/gc_watermark".into(),
+            ReplicaStatus::Membership => "/membership".into(),
+            ReplicaStatus::Range => "/range".into(),
+            ReplicaStatus::Frozen => "/frozen".into(),
+            ReplicaStatus::Installing => "/installing".into(),
+            ReplicaStatus::Txn(id) => [&b"/txn/"[..], id].concat(),
+            ReplicaStatus::TxnLock(key) => [&b"/txn_lock/"[..], key].concat(),
         }
     }
 
@@ -92,12 +135,38 @@ impl AsStorageKey for ReplicaStatus {
             ReplicaStatus::Exec
         } else if buf == "/max_inst".as_bytes() {
             ReplicaStatus::MaxInstance
+        } else if buf == "/last_ballot".as_bytes() {
+            ReplicaStatus::LastBallot
+        } else if buf.starts_with("/commit_pending/".as_bytes()) {
+            let buf = &buf["/commit_pending/".len()..];
+            let i = buf.iter().position(|c| *c == b'/').unwrap();
+            let rid = std::str::from_utf8(&buf[..i]).unwrap();
+            ReplicaStatus::CommitPending(rid.parse().unwrap(), InstanceId::from_key(&buf[i + 1..]))
+        } else if buf == "/gc_watermark".as_bytes() {
+            ReplicaStatus::GcWatermark
+        } else if buf == "/membership".as_bytes() {
+            ReplicaStatus::Membership
+        } else if buf == "/range".as_bytes() {
+            ReplicaStatus::Range
+        } else if buf == "/frozen".as_bytes() {
+            ReplicaStatus::Frozen
+        } else if buf == "/installing".as_bytes() {
+            ReplicaStatus::Installing
+        } else if buf.starts_with("/txn/".as_bytes()) {
+            ReplicaStatus::Txn(buf["/txn/".len()..].to_vec())
+        } else if buf.starts_with("/txn_lock/".as_bytes()) {
+            ReplicaStatus::TxnLock(buf["/txn_lock/".len()..].to_vec())
         } else {
             panic!("invalid")
         }
     }
 }
 
+/// commit_pending_prefix returns the prefix of keys of commits not yet delivered to a peer.
+pub fn commit_pending_prefix(rid: ReplicaId) -> Vec<u8> {
+    format!("/commit_pending/{}/", rid).into_bytes()
+}
+
 #[derive(Debug, Eq, PartialEq, enum_utils::FromStr)]
 pub enum Direction {
     Request,
@@ -125,13 +194,22 @@ impl From<(&str, &str)> for InvalidRequest {
 }
 
 impl Command {
-    /// kind returns one of there kinds of command: NoOp, Get, Set.
+    /// kind returns one of there kinds of command: NoOp, Get, Set, Membership, Range,
+    /// TxnPrepare, TxnDecide.
     /// In this way `Delete` is a `Set` kind command because it set the value to NULL.
     pub fn kind(&self) -> OpCode {
         if self.op == OpCode::NoOp as i32 {
             OpCode::NoOp
         } else if self.op == OpCode::Get as i32 {
             OpCode::Get
+        } else if self.op == OpCode::Membership as i32 {
+            OpCode::Membership
+        } else if self.op == OpCode::Range as i32 {
+            OpCode::Range
+        } else if self.op == OpCode::TxnPrepare as i32 {
+            OpCode::TxnPrepare
+        } else if self.op == OpCode::TxnDecide as i32 {
+            OpCode::TxnDecide
         } else {
             OpCode::Set
         }
@@ -145,6 +223,15 @@ impl Conflict for Command {
         match (self.kind(), with.kind()) {
             (OpCode::NoOp, _) => false,
             (_, OpCode::NoOp) => false,
+            // A membership change is ordered with every other command.
+            (OpCode::Membership, _) => true,
+            (_, OpCode::Membership) => true,
+            // So is a range change, it moves records between groups.
+            (OpCode::Range, _) => true,
+            (_, OpCode::Range) => true,
+            // So are transaction commands, a decision does not carry the keys it writes.
+            (OpCode::TxnPrepare, _) | (OpCode::TxnDecide, _) => true,
+            (_, OpCode::TxnPrepare) | (_, OpCode::TxnDecide) => true,
             (OpCode::Get, OpCode::Get) => false,
             _ => self.key == with.key,
         }
@@ -397,6 +484,7 @@ macro_rules! make_req {
             ballot: $inst.ballot,
             instance_id: $inst.instance_id,
             phase: Some($phase.into()),
+            committed: vec![],
         }
     };
 }
@@ -0,0 +1,62 @@
+use prost::Message;
+
+use crate::qpaxos::Command;
+use crate::qpaxos::KeyRange;
+use crate::qpaxos::OpCode;
+use crate::qpaxos::RangeChange;
+
+impl KeyRange {
+    /// contains returns true if `key` is in `[start, end)`.
+    pub fn contains(&self, key: &[u8]) -> bool {
+        self.start.as_bytes() <= key && key < self.end.as_bytes()
+    }
+
+    /// is_empty returns true if no key is in the range, e.g., the range of a retired group.
+    pub fn is_empty(&self) -> bool {
+        self.start >= self.end
+    }
+}
+
+impl From<(&str, &str)> for KeyRange {
+    fn from(t: (&str, &str)) -> KeyRange {
+        KeyRange {
+            start: t.0.into(),
+            end: t.1.into(),
+        }
+    }
+}
+
+impl RangeChange {
+    /// is_split returns true if the change moves a part of the range to a new group.
+    pub fn is_split(&self) -> bool {
+        self.split.is_some()
+    }
+
+    /// is_merge returns true if the change takes over records of a retired group.
+    pub fn is_merge(&self) -> bool {
+        self.split.is_none() && self.pairs.len() > 0
+    }
+}
+
+impl From<&RangeChange> for Command {
+    fn from(rc: &RangeChange) -> Command {
+        let mut value = vec![];
+        rc.encode(&mut value).unwrap();
+        Command {
+            op: OpCode::Range as i32,
+            key: vec![],
+            value,
+        }
+    }
+}
+
+impl Command {
+    /// get_range_change returns the change a Range command makes.
+    /// It returns None for other commands.
+    pub fn get_range_change(&self) -> Option<RangeChange> {
+        if self.op != OpCode::Range as i32 {
+            return None;
+        }
+        RangeChange::decode(self.value.as_slice()).ok()
+    }
+}
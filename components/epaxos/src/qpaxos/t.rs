@@ -86,6 +86,29 @@ fn test_replica_status_to_key() {
     assert_eq!("/max_inst", str::from_utf8(&k).unwrap());
 }
 
+#[test]
+fn test_replica_status_from_key() {
+    let cases = vec![
+        ReplicaStatus::Exec,
+        ReplicaStatus::MaxInstance,
+        ReplicaStatus::CommitPending(2, (1, 10).into()),
+        ReplicaStatus::LastBallot,
+        ReplicaStatus::GcWatermark,
+        ReplicaStatus::Membership,
+        ReplicaStatus::Range,
+        ReplicaStatus::Frozen,
+        ReplicaStatus::Installing,
+        ReplicaStatus::Txn(b"t1".to_vec()),
+        ReplicaStatus::Txn(vec![]),
+        ReplicaStatus::TxnLock(b"x".to_vec()),
+        ReplicaStatus::TxnLock(b"/txn/x".to_vec()),
+    ];
+
+    for st in cases {
+        assert_eq!(st, ReplicaStatus::from_key(&st.into_key()));
+    }
+}
+
 #[test]
 #[should_panic(expected = "idx can not be less than 0:-1")]
 fn test_instance_id_to_key_negative() {
@@ -210,6 +233,7 @@ fn test_replicate_reply_pb() {
             PrepareReply {
                 deps: Some(instidvec![(1, 2), (3, 4)].into()),
                 deps_committed: vec![true],
+                ..Default::default()
             }
             .into(),
         ),
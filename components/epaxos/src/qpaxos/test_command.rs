@@ -97,3 +97,24 @@ fn test_macro_cmdvec() {
     assert_eq!(cmds[2], ("Get", "a", "b").into());
     assert_eq!(3, cmds.len());
 }
+
+#[test]
+fn test_command_conflit_membership() {
+    let m = Membership::from(&[1, 2, 3][..]);
+    let cm = Command::from(&m);
+
+    assert_eq!(OpCode::Membership, cm.kind());
+    assert_eq!(Some(m), cm.get_membership());
+    assert_eq!(None, Command::from(("Set", "x", "1")).get_membership());
+
+    assert!(cm.conflict(&cm));
+    for c in vec![("Get", "x", "1"), ("Set", "y", "1"), ("Delete", "z", "1")] {
+        let c = Command::from(c);
+        assert!(cm.conflict(&c), "{:?}", c);
+        assert!(c.conflict(&cm), "{:?}", c);
+    }
+
+    let noop = Command::from(("NoOp", "x", "1"));
+    assert!(!cm.conflict(&noop));
+    assert!(!noop.conflict(&cm));
+}
@@ -155,6 +155,7 @@ fn test_display_replicate_reply_normal() {
         r.phase = Some(replicate_reply::Phase::Prepare(PrepareReply {
             deps: Some(instidvec![(1, 2), (3, 4)].into()),
             deps_committed: vec![true, false],
+            ..Default::default()
         }));
         let ph = "Prepare{deps[1]:[(1, 2, 0), (3, 4, 0)], c:[true, false]}";
 
@@ -0,0 +1,92 @@
+use std::collections::HashSet;
+
+use crate::qpaxos::*;
+
+#[test]
+fn test_membership_replica_ids() {
+    let m = Membership::from(&[3, 1, 2][..]);
+    assert!(!m.is_joint());
+    assert_eq!(vec![1, 2, 3], m.replica_ids());
+    assert!(m.contains(1));
+    assert!(!m.contains(4));
+
+    let j = m.enter_joint(hashmap! {2 => "b".to_string(), 4 => "d".to_string()});
+    assert!(j.is_joint());
+    assert_eq!(vec![1, 2, 3, 4], j.replica_ids());
+    assert!(j.contains(4));
+    assert_eq!(Some(&"d".to_string()), j.get_addr(4));
+    assert_eq!(Some(&"b".to_string()), j.get_addr(2));
+    assert_eq!(None, j.get_addr(5));
+
+    let n = j.leave_joint();
+    assert!(!n.is_joint());
+    assert_eq!(vec![2, 4], n.replica_ids());
+}
+
+#[test]
+fn test_membership_is_quorum() {
+    let m = Membership::from(&[1, 2, 3][..]);
+    let j = m.enter_joint(Membership::from(&[3, 4, 5, 6, 7][..]).voters);
+
+    assert_eq!(2, m.quorum());
+    assert_eq!(3, j.quorum());
+
+    let cases: Vec<(Vec<ReplicaId>, bool, bool)> = vec![
+        (vec![], false, false),
+        (vec![1], false, false),
+        (vec![1, 2], true, false),
+        // replicas not in any config are not counted.
+        (vec![1, 8, 9], false, false),
+        (vec![1, 2, 4, 5], true, false),
+        (vec![4, 5, 6], false, false),
+        (vec![1, 4, 5, 6], false, false),
+        (vec![1, 3, 4, 5], true, true),
+        (vec![1, 2, 3, 4, 5, 6, 7], true, true),
+    ];
+
+    for (rids, want, want_joint) in cases {
+        let rids: HashSet<_> = rids.into_iter().collect();
+        assert_eq!(want, m.is_quorum(&rids), "{:?}", rids);
+        assert_eq!(want_joint, j.is_quorum(&rids), "joint {:?}", rids);
+    }
+}
+
+#[test]
+fn test_membership_learners() {
+    let mut m = Membership::from(&[1, 2, 3][..]);
+    m.learners.insert(5, "e".to_string());
+    m.learners.insert(4, "d".to_string());
+
+    assert!(m.is_learner(4));
+    assert!(!m.is_learner(1));
+    assert_eq!(vec![4, 5], m.learner_ids());
+
+    // learners do not vote.
+    assert_eq!(vec![1, 2, 3], m.replica_ids());
+    assert!(!m.contains(4));
+    assert_eq!(2, m.quorum());
+    let rids: HashSet<_> = vec![1, 4, 5].into_iter().collect();
+    assert!(!m.is_quorum(&rids));
+
+    assert_eq!(Some(&"d".to_string()), m.get_addr(4));
+
+    m.set_addr(4, "dd".to_string());
+    m.set_addr(1, "a".to_string());
+    assert_eq!(Some(&"dd".to_string()), m.learners.get(&4));
+    assert_eq!(Some(&"a".to_string()), m.voters.get(&1));
+    assert!(!m.voters.contains_key(&4));
+
+    // learners are kept when the voters change.
+    let j = m.enter_joint(Membership::from(&[2, 3, 6][..]).voters);
+    assert_eq!(vec![4, 5], j.learner_ids());
+    assert_eq!(vec![4, 5], j.leave_joint().learner_ids());
+    assert_eq!(vec![2, 3, 6], j.leave_joint().replica_ids());
+
+    // a learner in the new voters is promoted.
+    let j = m.enter_joint(Membership::from(&[2, 3, 4][..]).voters);
+    assert!(!j.is_learner(4));
+    assert!(j.contains(4));
+    assert_eq!(vec![5], j.learner_ids());
+    assert_eq!(vec![2, 3, 4], j.leave_joint().replica_ids());
+    assert_eq!(vec![5], j.leave_joint().learner_ids());
+}
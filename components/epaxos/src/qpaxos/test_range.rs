@@ -0,0 +1,58 @@
+use crate::qpaxos::*;
+
+#[test]
+fn test_range_contains() {
+    let r = KeyRange::from(("b", "d"));
+    assert!(!r.is_empty());
+
+    for (key, want) in vec![
+        ("a", false),
+        ("b", true),
+        ("c", true),
+        ("cz", true),
+        ("d", false),
+    ] {
+        assert_eq!(want, r.contains(key.as_bytes()), "{}", key);
+    }
+
+    let r = KeyRange::from(("d", "d"));
+    assert!(r.is_empty());
+    assert!(!r.contains(b"d"));
+}
+
+#[test]
+fn test_range_change_command() {
+    let rc = RangeChange {
+        range: Some(("a", "c").into()),
+        split: Some(("c", "z").into()),
+        pairs: hashmap! {1=>4, 2=>5},
+    };
+    assert!(rc.is_split());
+    assert!(!rc.is_merge());
+
+    let cmd = Command::from(&rc);
+    assert_eq!(OpCode::Range, cmd.kind());
+    assert_eq!(Some(rc), cmd.get_range_change());
+    assert_eq!(None, Command::from(("Set", "x", "1")).get_range_change());
+    assert_eq!("Range:[a, c),split:[c, z)", cmd.tostr_ext());
+
+    for c in vec![("Get", "x", "1"), ("Set", "y", "1")] {
+        let c = Command::from(c);
+        assert!(cmd.conflict(&c), "{:?}", c);
+        assert!(c.conflict(&cmd), "{:?}", c);
+    }
+
+    let retire = RangeChange {
+        range: Some(("c", "c").into()),
+        ..Default::default()
+    };
+    assert!(!retire.is_split());
+    assert!(!retire.is_merge());
+
+    let merge = RangeChange {
+        range: Some(("a", "z").into()),
+        split: None,
+        pairs: hashmap! {1=>4},
+    };
+    assert!(merge.is_merge());
+}
@@ -0,0 +1,48 @@
+use crate::qpaxos::*;
+
+#[test]
+fn test_txn_command() {
+    let p = TxnPrepare {
+        txn_id: b"t1".to_vec(),
+        primary: b"x".to_vec(),
+        writes: vec![("Set", "x", "1").into(), ("Delete", "y", "").into()],
+    };
+
+    let cmd = Command::from(&p);
+    assert_eq!(OpCode::TxnPrepare, cmd.kind());
+    assert!(cmd.is_txn());
+    assert_eq!(Some(p), cmd.get_txn_prepare());
+    assert_eq!(None, cmd.get_txn_decide());
+    assert_eq!("TxnPrepare:t1,writes:2", cmd.tostr_ext());
+
+    let d = TxnDecide {
+        txn_id: b"t1".to_vec(),
+        commit: true,
+        end: false,
+    };
+
+    let dcmd = Command::from(&d);
+    assert_eq!(OpCode::TxnDecide, dcmd.kind());
+    assert!(dcmd.is_txn());
+    assert_eq!(Some(d), dcmd.get_txn_decide());
+    assert_eq!(None, Command::from(("Set", "x", "1")).get_txn_decide());
+    assert_eq!("TxnDecide:t1,commit:true", dcmd.tostr_ext());
+
+    // ordered with every command but NoOp.
+    for c in vec![("Get", "z", ""), ("Set", "z", "1")] {
+        let c = Command::from(c);
+        for t in [&cmd, &dcmd].iter() {
+            assert!(t.conflict(&c), "{:?}", c);
+            assert!(c.conflict(t), "{:?}", c);
+        }
+    }
+    assert!(!cmd.conflict(&Command::from(("NoOp", "", ""))));
+}
+
+#[test]
+fn test_txn_status_record() {
+    for st in vec![TxnStatus::Pending, TxnStatus::Committed, TxnStatus::Aborted] {
+        assert_eq!(Some(st), TxnStatus::from_record(&st.to_record()));
+    }
+    assert_eq!(None, TxnStatus::from_record(&Record::from("x")));
+}
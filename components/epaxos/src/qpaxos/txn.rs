@@ -0,0 +1,87 @@
+use prost::Message;
+
+use crate::qpaxos::Command;
+use crate::qpaxos::OpCode;
+use crate::qpaxos::Record;
+use crate::qpaxos::TxnDecide;
+use crate::qpaxos::TxnPrepare;
+use crate::qpaxos::TxnState;
+use crate::qpaxos::TxnStatus;
+
+impl From<&TxnPrepare> for Command {
+    fn from(p: &TxnPrepare) -> Command {
+        let mut value = vec![];
+        p.encode(&mut value).unwrap();
+        Command {
+            op: OpCode::TxnPrepare as i32,
+            key: vec![],
+            value,
+        }
+    }
+}
+
+impl From<&TxnDecide> for Command {
+    fn from(d: &TxnDecide) -> Command {
+        let mut value = vec![];
+        d.encode(&mut value).unwrap();
+        Command {
+            op: OpCode::TxnDecide as i32,
+            key: vec![],
+            value,
+        }
+    }
+}
+
+impl Command {
+    /// get_txn_prepare returns the prepare of a TxnPrepare command.
+    /// It returns None for other commands.
+    pub fn get_txn_prepare(&self) -> Option<TxnPrepare> {
+        if self.op != OpCode::TxnPrepare as i32 {
+            return None;
+        }
+        TxnPrepare::decode(self.value.as_slice()).ok()
+    }
+
+    /// get_txn_decide returns the decision of a TxnDecide command.
+    /// It returns None for other commands.
+    pub fn get_txn_decide(&self) -> Option<TxnDecide> {
+        if self.op != OpCode::TxnDecide as i32 {
+            return None;
+        }
+        TxnDecide::decode(self.value.as_slice()).ok()
+    }
+
+    /// is_txn returns true if it is a TxnPrepare or a TxnDecide command.
+    pub fn is_txn(&self) -> bool {
+        let k = self.kind();
+        k == OpCode::TxnPrepare || k == OpCode::TxnDecide
+    }
+}
+
+impl TxnStatus {
+    /// to_record returns the status as the result of executing a transaction command.
+    pub fn to_record(self) -> Record {
+        Record::from(format!("{:?}", self).as_str())
+    }
+
+    /// from_record parses the result of executing a transaction command.
+    pub fn from_record(rcd: &Record) -> Option<TxnStatus> {
+        match &rcd.to_vec()[..] {
+            b"Pending" => Some(TxnStatus::Pending),
+            b"Committed" => Some(TxnStatus::Committed),
+            b"Aborted" => Some(TxnStatus::Aborted),
+            _ => None,
+        }
+    }
+}
+
+impl TxnState {
+    /// is_primary returns true if it is the state in the primary group, which writes the primary
+    /// key.
+    pub fn is_primary(&self) -> bool {
+        match self.prepare.as_ref() {
+            Some(p) => p.writes.iter().any(|w| w.key == p.primary),
+            None => false,
+        }
+    }
+}
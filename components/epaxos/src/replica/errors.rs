@@ -1,5 +1,5 @@
 use crate::qpaxos::ReplicaId;
-use crate::qpaxos::{QError, StorageFailure};
+use crate::qpaxos::{InvalidRequest, QError, StorageFailure};
 use storage::StorageError;
 
 quick_error! {
@@ -14,6 +14,16 @@ quick_error! {
         ReplicaNotFound(rid: ReplicaId) {
             display("replica {:?} not found in cluster", rid)
         }
+
+        /// The replica has been handed over to another node and does not change any more.
+        Frozen(rid: ReplicaId) {
+            display("replica {} is frozen", rid)
+        }
+
+        /// The replica is a learner and does not propose or vote.
+        Learner(rid: ReplicaId) {
+            display("replica {} is a learner", rid)
+        }
     }
 }
 
@@ -35,6 +45,24 @@ impl Into<QError> for ReplicaError {
                 sto: Some(StorageFailure {}),
                 ..Default::default()
             },
+
+            Self::Frozen(rid) => QError {
+                req: Some(InvalidRequest {
+                    field: "to_replica_id".into(),
+                    problem: "Frozen".into(),
+                    ctx: format!("{}", rid),
+                }),
+                ..Default::default()
+            },
+
+            Self::Learner(rid) => QError {
+                req: Some(InvalidRequest {
+                    field: "to_replica_id".into(),
+                    problem: "Learner".into(),
+                    ctx: format!("{}", rid),
+                }),
+                ..Default::default()
+            },
         }
     }
 }
@@ -1,24 +1,61 @@
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
 use std::time::SystemTime;
 
-use crate::qpaxos::{Deps, Instance, InstanceId, InstanceIdVec, OpCode};
+use crate::conf::Executor;
+use crate::qpaxos::{Deps, Instance, InstanceId, InstanceIdVec, KeyRange, OpCode};
+use crate::replica::ConfigChange;
 use crate::replica::ExecRst;
 use crate::replica::Replica;
+use crate::replica::TxnExec;
+use crate::replication::recover;
 use crate::InstanceIds;
 use crate::Record;
 use crate::ReplicaStatus;
 use crate::StorageAPI;
+use storage::Storage;
 use storage::WriteEntry;
 use storage::{RawKV, StorageError};
 use tokio::sync::oneshot::Sender;
 
+/// The max time the executor sleeps when there is nothing to execute.
+/// It is woken up earlier when an instance is committed. Waking up periodically gives an
+/// instance that is not committed in time a chance to be recovered.
+pub const EXEC_IDLE_INTERVAL: Duration = Duration::from_millis(200);
+
 thread_local! {
     static PROBLEM_INSTS: RefCell<Vec<(InstanceId, SystemTime)>> = RefCell::new(vec![]);
 }
 
 impl Replica {
-    fn recover_instances(&self, _inst_ids: &InstanceIdVec) {}
+    /// recover_instances runs recovery for every instance in `inst_ids`, which is not committed in
+    /// time or is missing on this replica.
+    /// A failed recovery is just logged, the executor will retry it later.
+    /// A learner does not recover: it waits for voters to deliver the commits.
+    pub(crate) async fn recover_instances(&self, inst_ids: &InstanceIdVec) {
+        if self.is_learner() {
+            return;
+        }
+
+        for iid in inst_ids.iter() {
+            // it is executed on every replica.
+            if self.is_truncated(*iid) {
+                continue;
+            }
+
+            match recover(self, *iid).await {
+                Ok(inst) => {
+                    info!("recovered instance: {}", inst);
+                }
+                Err(e) => {
+                    error!("{:?} while recover instance {}", e, iid);
+                }
+            }
+        }
+    }
+
     // R1          R2
     // -------------
     // |           |
@@ -73,75 +110,145 @@ impl Replica {
         None
     }
 
-    async fn send_replies(&self, mut replies: Vec<(InstanceId, ExecRst)>) {
+    /// send_replies sends the result of every executed instance to the one waiting for it.
+    /// An instance without a result is not applied, the sender is dropped.
+    async fn send_replies(&self, mut replies: Vec<(InstanceId, Option<ExecRst>)>) {
         let mut wrpls = self.waiting_replies.lock().await;
         while let Some((iid, r)) = replies.pop() {
             let tx = match wrpls.remove(&iid) {
                 Some(t) => t,
                 None => continue,
             };
+            let r = match r {
+                Some(v) => v,
+                None => {
+                    info!("{} is not applied, drop the reply", iid);
+                    continue;
+                }
+            };
             if let Err(_) = tx.send(r) {
                 println!("the receiver dropped for {:?}", iid);
             }
         }
     }
 
+    /// insert_tx registers `tx` to receive the result of executing instance `iid`.
+    /// `tx` is dropped without a result if a command of the instance is not applied, because its
+    /// key is out of the range of this replica, e.g. it is ordered after a split.
     pub async fn insert_tx(&self, iid: InstanceId, tx: Sender<ExecRst>) {
         let mut wrpls = self.waiting_replies.lock().await;
         wrpls.insert(iid, tx);
     }
 
+    /// execute_commands applies instances and updates the executed status.
+    /// `insts` must be in the order they are executed.
+    ///
+    /// Instances are split into groups that do not conflict with each other, which are prepared
+    /// concurrently on the blocking worker pool. Changes of all groups and the executed status are
+    /// written in one write batch, thus an instance is never applied twice, even if the process
+    /// crashes during execution.
+    ///
+    /// A Membership command is applied by storing the membership in the same write batch.
+    ///
+    /// An instance with a Range command moves records between replicas. It is executed alone,
+    /// after the instances before it are applied. Instances after it are left to the next round,
+    /// thus only the ids of executed instances are returned.
+    /// A command on a key out of the range of this replica is not applied, and the instance has no
+    /// result, see `insert_tx`.
+    ///
+    /// Transaction commands lock and unlock keys, and apply the writes of a committed
+    /// transaction, see `TxnExec`. The result of one is the status of the transaction.
     pub async fn execute_commands(
         &self,
         mut insts: Vec<Instance>,
         mut executed: InstanceIds,
     ) -> Result<Vec<InstanceId>, StorageError> {
-        let mut rst = Vec::with_capacity(insts.len());
-        let mut entrys: Vec<WriteEntry> = Vec::with_capacity(insts.len());
-        let mut existed: HashMap<&Vec<u8>, Option<Record>> = HashMap::new();
-        let mut replies: Vec<(InstanceId, Vec<Option<Record>>)> = Vec::with_capacity(insts.len());
-
-        for inst in insts.iter() {
-            let iid = inst.instance_id.unwrap();
-            rst.push(iid);
-
-            let mut repl = Vec::with_capacity(inst.cmds.len());
-            for cmd in inst.cmds.iter() {
-                entrys.push(self.storage.make_cmd_entry(cmd));
-
-                if cmd.op == OpCode::Get as i32 {
-                    if !existed.contains_key(&cmd.key) {
-                        let v: Option<Record> = self.storage.get_kv(&cmd.key)?;
-                        existed.insert(&cmd.key, v);
-                    }
-                    let rcd: &Option<Record> = &existed[&cmd.key];
-                    repl.push(rcd.clone());
-                } else if cmd.op == OpCode::NoOp as i32 {
-                    repl.push(None);
-                } else {
-                    let v: Option<Record> = if cmd.op == OpCode::Delete as i32 {
-                        None
-                    } else {
-                        Some(cmd.value.clone().into())
-                    };
-                    existed.insert(&cmd.key, v);
-                    repl.push(None);
+        let is_range = |inst: &Instance| inst.cmds.iter().any(|c| c.kind() == OpCode::Range);
+        if let Some(i) = insts.iter().position(is_range) {
+            insts.truncate(i.max(1));
+        }
+
+        let rst: Vec<InstanceId> = insts.iter().map(|x| x.instance_id.unwrap()).collect();
+        for iid in rst.iter() {
+            executed.insert(iid.replica_id, iid.idx);
+        }
+
+        // the last one takes effect.
+        let membership = insts
+            .iter()
+            .flat_map(|inst| inst.cmds.iter())
+            .filter_map(|cmd| cmd.get_membership())
+            .last();
+
+        let range_change = insts
+            .iter()
+            .flat_map(|inst| inst.cmds.iter())
+            .filter_map(|cmd| cmd.get_range_change())
+            .last();
+
+        let groups = split_independent(insts);
+
+        let mut handles = Vec::with_capacity(groups.len());
+        for g in groups {
+            let sto = self.storage.clone();
+            let range = self.get_range();
+            handles.push(tokio::task::spawn_blocking(move || {
+                prepare_instances(&sto, range.as_ref(), g)
+            }));
+        }
+
+        let mut entrys = vec![];
+        let mut replies = Vec::with_capacity(rst.len());
+        let mut err = None;
+        for h in handles {
+            match h.await.expect("prepare instances panicked") {
+                Ok((es, r)) => {
+                    entrys.extend(es);
+                    replies.extend(r);
                 }
+                Err(e) => err = Some(e),
             }
+        }
 
-            executed.insert(iid.replica_id, iid.idx);
-            replies.push((iid, repl));
+        if let Some(e) = err {
+            return Err(e);
         }
 
-        // TODO: Since executed status is moved to ReplciaStatus::Exec, maybe no more instance update is required.
-        while let Some(inst) = insts.pop() {
-            entrys.push(self.storage.make_inst_entry(&inst));
+        if let Some(m) = membership.as_ref() {
+            entrys.push(self.storage.make_membership_entry(m));
         }
 
-        // TODO: use write batch to update exec-status
+        if let Some(rc) = range_change.as_ref() {
+            entrys.extend(self.make_range_entrys(rc)?);
+        }
 
+        entrys.push(
+            self.storage
+                .make_status_entry(&ReplicaStatus::Exec, &executed),
+        );
         self.storage.write_batch(&entrys)?;
-        self.storage.set_status(&ReplicaStatus::Exec, &executed)?;
+
+        // readers waiting for the exec status, there is always a receiver held by this replica.
+        let _ = self.exec_tx.broadcast(());
+
+        if let Some(m) = membership {
+            info!("replica {} applied membership: {:?}", self.replica_id, m);
+            self.set_membership(m.clone());
+            self.config_changes
+                .lock()
+                .unwrap()
+                .push(ConfigChange::Membership(m));
+        }
+
+        if let Some(rc) = range_change {
+            info!("replica {} applied range change: {:?}", self.replica_id, rc);
+            *self.range.lock().unwrap() = rc.range.clone();
+            self.config_changes
+                .lock()
+                .unwrap()
+                .push(ConfigChange::Range(rc));
+        }
+
         self.send_replies(replies).await;
         Ok(rst)
     }
@@ -216,7 +323,7 @@ impl Replica {
         rst
     }
 
-    pub fn get_insts_if_committed(
+    pub async fn get_insts_if_committed(
         &self,
         inst_ids: &Vec<InstanceId>,
     ) -> Result<Vec<Instance>, StorageError> {
@@ -241,13 +348,13 @@ impl Replica {
             }
         }
 
-        self.recover_instances(&recover_iids);
+        self.recover_instances(&recover_iids).await;
         Ok(rst)
     }
 
-    pub async fn execute(&self) -> Result<Vec<InstanceId>, StorageError> {
-        let mut smallest_inst_ids = InstanceIdVec::from([0; 0]);
-
+    /// get_executed returns the max executed instance index of every replica in the group.
+    /// It is -1 for a replica none of whose instances is executed.
+    pub fn get_executed(&self) -> Result<InstanceIds, StorageError> {
         let executed = self.storage.get_status(&ReplicaStatus::Exec)?;
         let mut executed = match executed {
             None => InstanceIds {
@@ -256,22 +363,59 @@ impl Replica {
             Some(v) => v,
         };
 
-        for rid in self.group_replica_ids.iter() {
+        for rid in self.get_group_replica_ids().iter() {
             if !executed.contains_key(rid) {
                 executed.insert(*rid, -1);
             }
+        }
+
+        Ok(executed)
+    }
+
+    /// execute executes committed instances with the executor this replica is configured with.
+    pub async fn execute(&self) -> Result<Vec<InstanceId>, StorageError> {
+        // a snapshot must not be installed while executing.
+        let _guard = self.exec_lock.lock().await;
+        self.exec_rounds.fetch_add(1, Ordering::Relaxed);
+
+        // records are incomplete until the snapshot is installed.
+        if self.is_installing()? {
+            return Ok(vec![]);
+        }
+
+        match self.executor {
+            Executor::LocalMin => self.execute_local_min().await,
+            Executor::Scc => self.execute_scc().await,
+        }
+    }
 
+    /// execute_local_min executes the smallest committed instances of every leader, see
+    /// `execute_instances`.
+    pub async fn execute_local_min(&self) -> Result<Vec<InstanceId>, StorageError> {
+        let mut smallest_inst_ids = InstanceIdVec::from([0; 0]);
+
+        let executed = self.get_executed()?;
+        let grids = self.get_group_replica_ids();
+
+        for rid in grids.iter() {
             smallest_inst_ids.push((*rid, executed[rid] + 1).into());
         }
 
-        let instances = self.get_insts_if_committed(&smallest_inst_ids)?;
+        let instances = self.get_insts_if_committed(&smallest_inst_ids).await?;
         if instances.len() == 0 {
             return Ok(vec![]);
         }
 
-        if instances.len() < self.group_replica_ids.len() {
+        if instances.len() < grids.len() {
             if let Some(iids) = self.find_missing_insts(&instances, &executed) {
-                self.recover_instances(&iids);
+                // give the leader of a missing instance a chance to commit it.
+                let iids: Vec<InstanceId> = iids
+                    .iter()
+                    .filter(|iid| self.timeout_to_committed(**iid))
+                    .cloned()
+                    .collect();
+
+                self.recover_instances(&iids.into()).await;
                 return Ok(vec![]);
             }
         }
@@ -279,3 +423,167 @@ impl Replica {
         self.execute_instances(instances, executed).await
     }
 }
+
+/// split_independent splits instances into groups so that no two instances in different groups
+/// conflict. The order of instances in a group is the same as in `insts`.
+pub fn split_independent(insts: Vec<Instance>) -> Vec<Vec<Instance>> {
+    // a transaction command conflicts with every other command.
+    if insts
+        .iter()
+        .any(|inst| inst.cmds.iter().any(|c| c.is_txn()))
+    {
+        return vec![insts];
+    }
+
+    let n = insts.len();
+    let mut parent: Vec<usize> = (0..n).collect();
+
+    fn root(parent: &mut Vec<usize>, mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+
+    fn union(parent: &mut Vec<usize>, a: usize, b: usize) {
+        let (ra, rb) = (root(parent, a), root(parent, b));
+        if ra != rb {
+            parent[rb] = ra;
+        }
+    }
+
+    // For every key, the last instance that writes it and the instances reading it since then.
+    // A write conflicts with every access to the same key, and a read conflicts with writes.
+    let mut writer: HashMap<&Vec<u8>, usize> = HashMap::new();
+    let mut readers: HashMap<&Vec<u8>, Vec<usize>> = HashMap::new();
+
+    for (i, inst) in insts.iter().enumerate() {
+        for cmd in inst.cmds.iter() {
+            match cmd.kind() {
+                OpCode::NoOp => {}
+                OpCode::Get => {
+                    if let Some(w) = writer.get(&cmd.key) {
+                        union(&mut parent, *w, i);
+                    }
+                    readers.entry(&cmd.key).or_default().push(i);
+                }
+                _ => {
+                    if let Some(w) = writer.get(&cmd.key) {
+                        union(&mut parent, *w, i);
+                    }
+                    if let Some(rs) = readers.remove(&cmd.key) {
+                        for r in rs {
+                            union(&mut parent, r, i);
+                        }
+                    }
+                    writer.insert(&cmd.key, i);
+                }
+            }
+        }
+    }
+
+    let roots: Vec<usize> = (0..n).map(|i| root(&mut parent, i)).collect();
+
+    let mut groups: Vec<Vec<Instance>> = vec![];
+    let mut group_of: HashMap<usize, usize> = HashMap::new();
+    for (i, inst) in insts.into_iter().enumerate() {
+        let gi = *group_of.entry(roots[i]).or_insert_with(|| {
+            groups.push(vec![]);
+            groups.len() - 1
+        });
+        groups[gi].push(inst);
+    }
+
+    groups
+}
+
+/// prepare_instances builds the write entries to apply commands of instances, and returns them
+/// along with the result of every instance.
+/// A command on a key out of `range` is skipped, and the instance has no result.
+/// The writes of a transaction committed in this batch are applied with the decision.
+fn prepare_instances(
+    sto: &Storage,
+    range: Option<&KeyRange>,
+    insts: Vec<Instance>,
+) -> Result<(Vec<WriteEntry>, Vec<(InstanceId, Option<ExecRst>)>), StorageError> {
+    let mut entrys: Vec<WriteEntry> = Vec::with_capacity(insts.len());
+    let mut existed: HashMap<Vec<u8>, Option<Record>> = HashMap::new();
+    let mut txns = TxnExec::new(sto);
+    let mut replies: Vec<(InstanceId, Option<ExecRst>)> = Vec::with_capacity(insts.len());
+
+    for inst in insts.iter() {
+        let iid = inst.instance_id.unwrap();
+
+        let mut repl = Vec::with_capacity(inst.cmds.len());
+        let mut applied = true;
+        for cmd in inst.cmds.iter() {
+            if let Some(p) = cmd.get_txn_prepare() {
+                let st = txns.prepare(p, range)?;
+                repl.push(Some(st.to_record()));
+                continue;
+            }
+
+            if let Some(d) = cmd.get_txn_decide() {
+                let (st, writes) = txns.decide(&d, iid)?;
+                for w in writes {
+                    entrys.push(sto.make_cmd_entry(&w));
+                    let v = if w.op == OpCode::Delete as i32 {
+                        None
+                    } else {
+                        Some(w.value.into())
+                    };
+                    existed.insert(w.key, v);
+                }
+                repl.push(Some(st.to_record()));
+                continue;
+            }
+
+            let k = cmd.kind();
+            let keyed = k == OpCode::Get || k == OpCode::Set || k == OpCode::Delete;
+            if keyed && !range.map_or(true, |r| r.contains(&cmd.key)) {
+                warn!(
+                    "skip {} of {}: key out of range {:?}",
+                    cmd,
+                    iid,
+                    range.unwrap()
+                );
+                applied = false;
+                repl.push(None);
+                continue;
+            }
+
+            entrys.push(sto.make_cmd_entry(cmd));
+
+            if cmd.op == OpCode::Get as i32 {
+                if !existed.contains_key(&cmd.key) {
+                    let v: Option<Record> = sto.get_kv(&cmd.key)?;
+                    existed.insert(cmd.key.clone(), v);
+                }
+                let rcd: &Option<Record> = &existed[&cmd.key];
+                repl.push(rcd.clone());
+            } else if !keyed {
+                repl.push(None);
+            } else {
+                let v: Option<Record> = if cmd.op == OpCode::Delete as i32 {
+                    None
+                } else {
+                    Some(cmd.value.clone().into())
+                };
+                existed.insert(cmd.key.clone(), v);
+                repl.push(None);
+            }
+        }
+
+        replies.push((iid, if applied { Some(repl) } else { None }));
+    }
+
+    entrys.extend(txns.into_entrys());
+
+    // TODO: Since executed status is moved to ReplciaStatus::Exec, maybe no more instance update is required.
+    for inst in insts.iter() {
+        entrys.push(sto.make_inst_entry(inst));
+    }
+
+    Ok((entrys, replies))
+}
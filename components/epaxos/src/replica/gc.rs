@@ -0,0 +1,149 @@
+use std::time::Duration;
+
+use prost::Message;
+
+use crate::qpaxos::InstanceId;
+use crate::qpaxos::InstanceIds;
+use crate::qpaxos::ReplicaStatus;
+use crate::qpaxos::TxnState;
+use crate::replica::status_iter;
+use crate::replica::Replica;
+use crate::StorageAPI;
+use storage::AsStorageKey;
+use storage::DBColumnFamily;
+use storage::RawKV;
+use storage::StorageError;
+use storage::WithNameSpace;
+use storage::WriteEntry;
+
+/// The max number of instances deleted in one write batch.
+pub const GC_BATCH_SIZE: i64 = 256;
+
+/// The interval between two rounds of gc.
+pub const GC_INTERVAL: Duration = Duration::from_secs(1);
+
+impl Replica {
+    /// get_gc_watermark returns the max instance id of every leader that has been deleted.
+    pub fn get_gc_watermark(&self) -> InstanceIds {
+        self.gc_watermark.lock().unwrap().clone()
+    }
+
+    /// is_truncated returns true if an instance is executed on every replica and has been
+    /// deleted.
+    pub fn is_truncated(&self, iid: InstanceId) -> bool {
+        let wm = self.gc_watermark.lock().unwrap();
+        match wm.get(&iid.replica_id) {
+            Some(idx) => iid.idx <= *idx,
+            None => false,
+        }
+    }
+
+    /// gc_target returns the instance ids executed on every replica in the group, by the exec
+    /// status of this replica and the ones peers and learners reported.
+    /// It returns None if a peer or a learner has not yet reported its exec status: a learner
+    /// receives commits too, an instance it has not executed must not be deleted.
+    pub fn gc_target(&self) -> Result<Option<InstanceIds>, StorageError> {
+        let mut target = self.get_executed()?;
+
+        for p in self.get_peers().iter().chain(self.get_learners().iter()) {
+            let executed = match self.detector.get(p.replica_id).executed {
+                Some(v) => v,
+                None => return Ok(None),
+            };
+
+            for rid in self.get_group_replica_ids().iter() {
+                let idx = *executed.get(rid).unwrap_or(&-1);
+                let t = target.entry(*rid).or_insert(idx);
+                if idx < *t {
+                    *t = idx;
+                }
+            }
+        }
+
+        Ok(Some(target))
+    }
+
+    /// gc deletes instances executed on every replica and returns the number of deleted ones.
+    /// Instances are deleted in batches of `GC_BATCH_SIZE`. The gc watermark is updated in the
+    /// same write batch, thus it never goes beyond an instance not deleted.
+    pub async fn gc(&self) -> Result<usize, StorageError> {
+        let target = match self.gc_target()? {
+            Some(v) => v,
+            None => return Ok(0),
+        };
+
+        let mut n = 0;
+
+        for rid in self.get_group_replica_ids().iter() {
+            let end = *target.get(rid).unwrap_or(&-1);
+
+            loop {
+                // handle_replicate must not see an instance being deleted.
+                let _guard = self.inst_lock.lock().await;
+
+                let mut wm = self.get_gc_watermark();
+                let start = *wm.get(rid).unwrap_or(&-1) + 1;
+                if start > end {
+                    break;
+                }
+
+                let last = end.min(start + GC_BATCH_SIZE - 1);
+
+                let mut entrys = Vec::with_capacity((last - start + 2) as usize);
+                for idx in start..=last {
+                    let iid = InstanceId::from((*rid, idx));
+                    entrys.push(WriteEntry::Delete(
+                        DBColumnFamily::Instance,
+                        self.storage.prepend_ns(&iid),
+                    ));
+                }
+
+                wm.insert(*rid, last);
+                entrys.push(
+                    self.storage
+                        .make_status_entry(&ReplicaStatus::GcWatermark, &wm),
+                );
+                self.storage.write_batch(&entrys)?;
+
+                *self.gc_watermark.lock().unwrap() = wm;
+                n += (last - start + 1) as usize;
+            }
+        }
+
+        Ok(n)
+    }
+
+    /// gc_txns deletes states of decided transactions whose `TxnState.done` instance is deleted,
+    /// and returns the number of deleted ones.
+    /// A state is never deleted before every replica in the group executed the instance that
+    /// decided it.
+    pub async fn gc_txns(&self) -> Result<usize, StorageError> {
+        // the executor must not update a state being deleted.
+        let _guard = self.exec_lock.lock().await;
+
+        let prefix = ReplicaStatus::Txn(vec![]).into_key();
+
+        let mut ids = vec![];
+        for kv in status_iter(&self.storage, &prefix) {
+            let (k, v) = kv?;
+            let st = TxnState::decode(v.as_slice())?;
+            match st.done {
+                Some(iid) if self.is_truncated(iid) => ids.push(k[prefix.len()..].to_vec()),
+                _ => {}
+            }
+        }
+
+        for chunk in ids.chunks(GC_BATCH_SIZE as usize) {
+            let entrys: Vec<WriteEntry> = chunk
+                .iter()
+                .map(|id| {
+                    let k = self.storage.prepend_ns(&ReplicaStatus::Txn(id.clone()));
+                    WriteEntry::Delete(DBColumnFamily::Status, k)
+                })
+                .collect();
+            self.storage.write_batch(&entrys)?;
+        }
+
+        Ok(ids.len())
+    }
+}
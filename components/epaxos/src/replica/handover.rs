@@ -0,0 +1,164 @@
+use crate::qpaxos::HandoverReply;
+use crate::qpaxos::InstanceId;
+use crate::qpaxos::InstanceIds;
+use crate::qpaxos::ProtocolError;
+use crate::qpaxos::RangeChange;
+use crate::qpaxos::ReplicaStatus;
+use crate::replica::Replica;
+use crate::replication::RpcHandlerError;
+use crate::Iter;
+use crate::StorageAPI;
+use storage::DBColumnFamily;
+use storage::RawKV;
+use storage::Storage;
+use storage::StorageError;
+use storage::WithNameSpace;
+use storage::WriteEntry;
+
+impl Replica {
+    /// is_frozen returns true if this replica has been handed over to a copy on another node.
+    pub fn is_frozen(&self) -> bool {
+        *self.frozen.read().unwrap()
+    }
+
+    /// hand_over freezes this replica and returns what a copy of it needs to take over besides a
+    /// snapshot: the last ballot, the membership, the range and all instances after `after`.
+    ///
+    /// A frozen replica neither votes nor stores any instance, thus the copy has every instance
+    /// this replica has accepted. Handing over again returns the same state.
+    ///
+    /// It fails without freezing if an instance after `after` has been deleted by gc.
+    pub async fn hand_over(&self, after: &InstanceIds) -> Result<HandoverReply, RpcHandlerError> {
+        // gc deletes instances with it held.
+        let _guard = self.inst_lock.lock().await;
+
+        let wm = self.get_gc_watermark();
+        for (rid, idx) in wm.iter() {
+            let start = *after.get(rid).unwrap_or(&-1);
+            if start < *idx {
+                return Err(ProtocolError::Truncated((*rid, start + 1).into()).into());
+            }
+        }
+
+        // a frozen replica stays frozen after restart.
+        self.storage.set_status(&ReplicaStatus::Frozen, after)?;
+        *self.frozen.write().unwrap() = true;
+
+        let mut instances = vec![];
+        for rid in self.get_group_replica_ids() {
+            let start = *after.get(&rid).unwrap_or(&-1) + 1;
+            let start_iid = InstanceId::from((rid, start));
+            instances.extend(self.storage.get_instance_iter(start_iid, true, false));
+        }
+
+        info!(
+            "replica {} is frozen, hand over {} instances after {}",
+            self.replica_id,
+            instances.len(),
+            after
+        );
+
+        Ok(HandoverReply {
+            err: None,
+            last_ballot: Some(self.get_last_ballot()),
+            membership: Some(self.get_membership()),
+            range: self.get_range(),
+            instances,
+        })
+    }
+
+    /// take_over stores what a frozen replica hands over, on a copy of it that has installed a
+    /// snapshot from it. Instances the copy has are overwritten.
+    ///
+    /// Only the range of the last range change is stored: a split or a merge has been applied
+    /// to the cluster config.
+    pub async fn take_over(&self, st: &HandoverReply) -> Result<(), RpcHandlerError> {
+        let m = st
+            .membership
+            .clone()
+            .ok_or(ProtocolError::LackOf("membership".into()))?;
+
+        let mut iids = vec![];
+        for inst in st.instances.iter() {
+            let iid = inst
+                .instance_id
+                .ok_or(ProtocolError::LackOf("instances.instance_id".into()))?;
+            iids.push(iid);
+        }
+
+        let _exec_guard = self.exec_lock.lock().await;
+        let _guard = self.inst_lock.lock().await;
+
+        let mut entrys = vec![];
+        for inst in st.instances.iter() {
+            entrys.push(self.storage.make_inst_entry(inst));
+        }
+        entrys.push(self.storage.make_membership_entry(&m));
+        entrys.push(self.storage.make_range_change_entry(&RangeChange {
+            range: st.range.clone(),
+            ..Default::default()
+        }));
+
+        self.storage.write_batch(&entrys)?;
+
+        if let Some(b) = st.last_ballot {
+            self.update_last_ballot(b)?;
+        }
+
+        {
+            let mut maxs = self.max_iids.lock().unwrap();
+            for iid in iids {
+                match maxs.get(iid.replica_id) {
+                    Some(max) if max.idx >= iid.idx => {}
+                    _ => {
+                        maxs.set(iid);
+                    }
+                }
+            }
+        }
+
+        self.set_membership(m);
+        *self.range.lock().unwrap() = st.range.clone();
+
+        // committed instances handed over may be executable.
+        self.commit_notify.notify();
+
+        Ok(())
+    }
+
+    /// purge deletes records, instances and status of a replica that has been handed over.
+    pub async fn purge(&self) -> Result<(), StorageError> {
+        let _guard = self.inst_lock.lock().await;
+
+        let mut entrys = vec![];
+        for cf in [
+            DBColumnFamily::Record,
+            DBColumnFamily::Instance,
+            DBColumnFamily::Status,
+        ]
+        .iter()
+        {
+            for k in ns_keys(&self.storage, *cf) {
+                entrys.push(WriteEntry::Delete(*cf, k?));
+            }
+        }
+
+        self.storage.write_batch(&entrys)
+    }
+}
+
+/// ns_keys iterates over keys in the namespace of a replica in a column family. Keys have the
+/// namespace.
+fn ns_keys(
+    sto: &Storage,
+    cf: DBColumnFamily,
+) -> impl Iterator<Item = Result<Vec<u8>, StorageError>> {
+    let prefix = sto.prepend_ns(&b""[..]);
+
+    sto.get_iter(prefix.clone(), true, false, cf)
+        .take_while(move |kv| match kv {
+            Ok((k, _)) => k.starts_with(&prefix),
+            Err(_) => true,
+        })
+        .map(|kv| kv.map(|(k, _)| k))
+}
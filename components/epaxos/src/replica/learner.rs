@@ -0,0 +1,49 @@
+use std::time::Duration;
+
+use crate::replica::Replica;
+use storage::StorageError;
+
+impl Replica {
+    /// is_learner returns true if this replica is a learner of the group: it receives and
+    /// executes committed instances, but never proposes or votes.
+    pub fn is_learner(&self) -> bool {
+        self.membership.lock().unwrap().is_learner(self.replica_id)
+    }
+
+    /// update_freshness compares the exec status of this replica with the ones peers reported in
+    /// the last pings. If this replica has executed every instance a peer had executed, its state
+    /// is at least as fresh as the peer was when the ping was sent.
+    pub fn update_freshness(&self) -> Result<(), StorageError> {
+        let executed = self.get_executed()?;
+
+        for p in self.get_peers().iter() {
+            let h = self.detector.get(p.replica_id);
+            let (peer_executed, at) = match (h.executed, h.executed_at) {
+                (Some(e), Some(t)) => (e, t),
+                _ => continue,
+            };
+
+            let caught_up = peer_executed
+                .iter()
+                .all(|(rid, idx)| *idx <= *executed.get(rid).unwrap_or(&-1));
+            if !caught_up {
+                continue;
+            }
+
+            let mut fresh_at = self.fresh_at.lock().unwrap();
+            match *fresh_at {
+                Some(t) if t >= at => {}
+                _ => *fresh_at = Some(at),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// staleness returns the bound of how stale a read from this replica is: every write a peer
+    /// had executed that long ago is visible. It returns None if this replica has never caught
+    /// up with a peer.
+    pub fn staleness(&self) -> Option<Duration> {
+        self.fresh_at.lock().unwrap().map(|t| t.elapsed())
+    }
+}
@@ -4,6 +4,27 @@ pub use exec::*;
 mod replica;
 pub use replica::*;
 
+mod scc;
+pub use scc::*;
+
+mod gc;
+pub use gc::*;
+
+mod snapshot;
+pub use snapshot::*;
+
+mod range;
+pub use range::*;
+
+mod handover;
+pub use handover::*;
+
+mod learner;
+pub use learner::*;
+
+mod txn;
+pub use txn::*;
+
 mod errors;
 pub use errors::*;
 
@@ -18,3 +39,24 @@ mod test_replica;
 
 #[cfg(test)]
 mod test_exec;
+
+#[cfg(test)]
+mod test_scc;
+
+#[cfg(test)]
+mod test_gc;
+
+#[cfg(test)]
+mod test_snapshot;
+
+#[cfg(test)]
+mod test_range;
+
+#[cfg(test)]
+mod test_handover;
+
+#[cfg(test)]
+mod test_learner;
+
+#[cfg(test)]
+mod test_txn;
@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+
+use crate::qpaxos::KeyRange;
+use crate::qpaxos::Membership;
+use crate::qpaxos::RangeChange;
+use crate::qpaxos::ReplicaId;
+use crate::replica::record_iter;
+use crate::replica::Replica;
+use crate::StorageAPI;
+use storage::DBColumnFamily;
+use storage::Storage;
+use storage::StorageError;
+use storage::WithNameSpace;
+use storage::WriteEntry;
+
+impl Replica {
+    /// get_range returns the key range this replica serves.
+    /// It returns None if this replica serves every key.
+    pub fn get_range(&self) -> Option<KeyRange> {
+        self.range.lock().unwrap().clone()
+    }
+
+    /// owns returns true if `key` is in the range this replica serves.
+    pub fn owns(&self, key: &[u8]) -> bool {
+        match self.range.lock().unwrap().as_ref() {
+            Some(r) => r.contains(key),
+            None => true,
+        }
+    }
+
+    /// make_range_entrys builds the write entries to apply a range change on this replica:
+    ///
+    /// A split moves records in the split range to the paired replica of the new group, and
+    /// initializes its membership and range. A merge moves all records of the paired replica of
+    /// the retired group to this replica.
+    ///
+    /// The paired replica is on the same node and shares the storage engine with this replica.
+    pub(crate) fn make_range_entrys(
+        &self,
+        rc: &RangeChange,
+    ) -> Result<Vec<WriteEntry>, StorageError> {
+        let mut entrys = vec![self.storage.make_range_change_entry(rc)];
+
+        let prid = match rc.pairs.get(&self.replica_id) {
+            Some(v) => *v,
+            None => {
+                if rc.pairs.len() > 0 {
+                    warn!(
+                        "replica {} has no paired replica in range change",
+                        self.replica_id
+                    );
+                }
+                return Ok(entrys);
+            }
+        };
+
+        let psto = Storage::new(prid, self.storage.get_inner().clone());
+
+        if let Some(split) = rc.split.as_ref() {
+            for kv in record_iter(&self.storage) {
+                let (k, v) = kv?;
+                if !split.contains(&k) {
+                    continue;
+                }
+                entrys.push(WriteEntry::Delete(
+                    DBColumnFamily::Record,
+                    self.storage.prepend_ns(&k),
+                ));
+                entrys.push(WriteEntry::Set(
+                    DBColumnFamily::Record,
+                    psto.prepend_ns(&k),
+                    v,
+                ));
+            }
+
+            // every replica of the new group is on the same node as the paired one.
+            let m = self.get_membership();
+            let paired = |rs: &HashMap<ReplicaId, String>| {
+                let mut prs = HashMap::new();
+                for (rid, addr) in rs.iter() {
+                    if let Some(p) = rc.pairs.get(rid) {
+                        prs.insert(*p, addr.clone());
+                    }
+                }
+                prs
+            };
+
+            let mut pm = Membership::new(paired(&m.voters));
+            pm.learners = paired(&m.learners);
+            entrys.push(psto.make_membership_entry(&pm));
+            entrys.push(psto.make_range_change_entry(&RangeChange {
+                range: Some(split.clone()),
+                ..Default::default()
+            }));
+        } else {
+            for kv in record_iter(&psto) {
+                let (k, v) = kv?;
+                entrys.push(WriteEntry::Delete(
+                    DBColumnFamily::Record,
+                    psto.prepend_ns(&k),
+                ));
+                entrys.push(WriteEntry::Set(
+                    DBColumnFamily::Record,
+                    self.storage.prepend_ns(&k),
+                    v,
+                ));
+            }
+        }
+
+        Ok(entrys)
+    }
+}
@@ -1,10 +1,12 @@
 use std::i64;
 
 use crate::conf::ClusterInfo;
+use crate::conf::Executor;
 use crate::qpaxos::replicate_reply;
 use crate::qpaxos::replicate_request::Phase;
 use crate::qpaxos::AcceptReply;
 use crate::qpaxos::AcceptRequest;
+use crate::qpaxos::BallotNum;
 use crate::qpaxos::Command;
 use crate::qpaxos::CommitReply;
 use crate::qpaxos::CommitRequest;
@@ -12,23 +14,36 @@ use crate::qpaxos::Conflict;
 use crate::qpaxos::Instance;
 use crate::qpaxos::InstanceId;
 use crate::qpaxos::InstanceIdVec;
+use crate::qpaxos::InstanceIds;
+use crate::qpaxos::KeyRange;
+use crate::qpaxos::Membership;
 use crate::qpaxos::PrepareReply;
 use crate::qpaxos::PrepareRequest;
 use crate::qpaxos::ProtocolError;
+use crate::qpaxos::RangeChange;
 use crate::qpaxos::ReplicaId;
+use crate::qpaxos::ReplicaStatus;
 use crate::qpaxos::ReplicateReply;
 use crate::qpaxos::ReplicateRequest;
+use crate::replica::InstanceStatus;
 use crate::replica::ReplicaError;
+use crate::replication::CommitTracker;
+use crate::replication::ConnPool;
+use crate::replication::FailureDetector;
 use crate::replication::RpcHandlerError;
 use crate::Iter;
 use crate::Record;
 use crate::StorageAPI;
 use std::collections::HashMap;
+use std::sync::atomic::AtomicU64;
 use std::sync::Arc;
+use std::time::Instant;
 use storage::Storage;
 use storage::{RawKV, StorageError};
 use tokio::sync::oneshot::Sender;
+use tokio::sync::watch;
 use tokio::sync::Mutex;
+use tokio::sync::Notify;
 
 /// ref_or_bug extracts a immutable ref from an Option.
 /// If the Option is None a bug handler is triggered.
@@ -82,14 +97,66 @@ impl From<(ReplicaId, String, bool)> for ReplicaPeer {
 
 pub type ExecRst = Vec<Option<Record>>;
 
+/// ConfigChange is an executed command that the server applies to the cluster config.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigChange {
+    Membership(Membership),
+    Range(RangeChange),
+}
+
 /// structure to represent a replica
 pub struct Replica {
     pub replica_id: ReplicaId,
-    pub group_replica_ids: Vec<ReplicaId>,
-    pub peers: Vec<ReplicaPeer>,
+    /// the replicas of the group and their addresses. Peers are the replicas other than this one.
+    /// It is a cache of `ReplicaStatus::Membership`, or is built from the config if the group
+    /// never changed its membership.
+    pub membership: std::sync::Mutex<Membership>,
+    /// the key range this replica serves, None if it serves every key.
+    /// It is a cache of the range in `ReplicaStatus::Range`, or is the range of the group in the
+    /// config if the range never changed.
+    pub range: std::sync::Mutex<Option<KeyRange>>,
+    /// membership and range changes executed but not yet applied to the cluster config, see
+    /// `take_config_changes`.
+    pub config_changes: std::sync::Mutex<Vec<ConfigChange>>,
     pub storage: Storage,
     pub committed_timeout: i32,
     pub waiting_replies: Mutex<HashMap<InstanceId, Sender<ExecRst>>>,
+    /// long-lived connections to peers, shared by all requests sent by this replica.
+    pub conns: Arc<ConnPool>,
+    /// tracks liveness of peers.
+    pub detector: Arc<FailureDetector>,
+    /// commits not yet delivered to peers.
+    pub commits: CommitTracker,
+    /// the greatest ballot this replica has seen, shared by all instances.
+    pub last_ballot: std::sync::Mutex<BallotNum>,
+    /// serializes allocating and updating instances on this replica.
+    /// It must be acquired before `last_ballot`.
+    pub inst_lock: Mutex<()>,
+    /// the max instance id of every replica in the group that is stored on this replica.
+    /// It is loaded from storage when starting and updated when an instance is stored.
+    pub max_iids: std::sync::Mutex<InstanceIdVec>,
+    /// the algorithm to find out the order to execute instances.
+    pub executor: Executor,
+    /// notified when an instance is committed on this replica, to wake up the executor.
+    pub commit_notify: Notify,
+    /// the number of times the executor ran on this replica, to tell how often it wakes up.
+    pub exec_rounds: AtomicU64,
+    /// broadcasts when the exec status of this replica is updated, to wake up readers waiting
+    /// for it. A reader clones `exec_rx`, see `wait_executed`.
+    pub exec_tx: watch::Sender<()>,
+    pub exec_rx: watch::Receiver<()>,
+    /// instances up to it are executed on every replica and are deleted.
+    /// It is a cache of `ReplicaStatus::GcWatermark`.
+    pub gc_watermark: std::sync::Mutex<InstanceIds>,
+    /// serializes executing instances and reading or installing a snapshot.
+    /// It must be acquired before `inst_lock`.
+    pub exec_lock: Mutex<()>,
+    /// set when this replica is handed over to a copy on another node. No instance is stored
+    /// since then. Storing an instance holds the read lock.
+    pub frozen: std::sync::RwLock<bool>,
+    /// this replica has executed every instance a peer had executed at this time, see
+    /// `update_freshness`. It bounds the staleness of a read served by a learner.
+    pub fresh_at: std::sync::Mutex<Option<Instant>>,
 }
 
 impl Replica {
@@ -102,46 +169,196 @@ impl Replica {
         let group = cinfo
             .get_group(rid)
             .ok_or(ReplicaError::ReplicaNotFound(rid))?;
-        let mut peers = vec![];
-
-        for prid in group.replicas.keys() {
+        let addr_of = |prid: ReplicaId| -> Result<String, ReplicaError> {
             let node = cinfo
-                .get_replica_node(*prid)
-                .ok_or(ReplicaError::ReplicaNotFound(*prid))?;
+                .get_replica_node(prid)
+                .ok_or(ReplicaError::ReplicaNotFound(prid))?;
 
-            if *prid == rid {
-                continue;
-            }
+            // liveness is tracked by FailureDetector, see Replica::get_peers().
+            Ok(format!("http://{}", node.replication.to_string()))
+        };
+
+        let mut voters = HashMap::new();
+        for prid in group.replicas.keys() {
+            voters.insert(*prid, addr_of(*prid)?);
+        }
 
-            // TODO check the replica is alive or not
-            let addr = format!("http://{}", node.replication.to_string());
-            peers.push((*prid, addr, true).into());
+        let mut learners = HashMap::new();
+        for prid in group.learners.keys() {
+            learners.insert(*prid, addr_of(*prid)?);
         }
 
+        let mut membership = Membership::new(voters);
+        membership.learners = learners;
+
+        let range = Some(KeyRange::from((
+            group.range.0.as_str(),
+            group.range.1.as_str(),
+        )));
+
+        Replica::with_config(
+            rid,
+            Storage::new(rid, sto),
+            membership,
+            range,
+            cinfo.executor,
+        )
+    }
+
+    /// with_config creates a replica on `storage`, with the membership and the range it is
+    /// configured with. They are overridden by the ones stored, since the group may have changed
+    /// its membership or range.
+    pub fn with_config(
+        rid: ReplicaId,
+        storage: Storage,
+        membership: Membership,
+        range: Option<KeyRange>,
+        executor: Executor,
+    ) -> Result<Replica, ReplicaError> {
+        let membership = storage.get_membership()?.unwrap_or(membership);
+
+        let range = match storage.get_range_change()? {
+            Some(rc) => rc.range,
+            None => range,
+        };
+
+        // commits are delivered to learners too.
+        let group_replica_ids = membership.replica_ids();
+        let prids: Vec<_> = group_replica_ids
+            .iter()
+            .chain(membership.learners.keys())
+            .filter(|x| **x != rid)
+            .cloned()
+            .collect();
+        let commits = CommitTracker::new(storage.clone(), &prids)?;
+        let last_ballot = storage.get_ballot_status(&ReplicaStatus::LastBallot)?;
+        let max_iids = load_max_instance_ids(&storage, &group_replica_ids);
+        let gc_watermark = storage.get_status(&ReplicaStatus::GcWatermark)?;
+        let frozen = storage.get_status(&ReplicaStatus::Frozen)?;
+        let (exec_tx, exec_rx) = watch::channel(());
+
         Ok(Replica {
             replica_id: rid,
-            group_replica_ids: group.replicas.keys().cloned().collect(),
-            peers,
-            storage: Storage::new(rid, sto),
+            membership: std::sync::Mutex::new(membership),
+            range: std::sync::Mutex::new(range),
+            config_changes: std::sync::Mutex::new(vec![]),
+            storage,
             // TODO get from conf
             committed_timeout: 10000,
             waiting_replies: Mutex::new(HashMap::new()),
+            conns: Arc::new(ConnPool::new()),
+            detector: Arc::new(FailureDetector::default()),
+            commits,
+            last_ballot: std::sync::Mutex::new(last_ballot.unwrap_or_default()),
+            inst_lock: Mutex::new(()),
+            max_iids: std::sync::Mutex::new(max_iids),
+            executor,
+            commit_notify: Notify::new(),
+            exec_rounds: AtomicU64::new(0),
+            exec_tx,
+            exec_rx,
+            gc_watermark: std::sync::Mutex::new(gc_watermark.unwrap_or_default()),
+            exec_lock: Mutex::new(()),
+            frozen: std::sync::RwLock::new(frozen.is_some()),
+            fresh_at: std::sync::Mutex::new(None),
         })
     }
 
+    /// get_last_ballot returns the greatest ballot this replica has seen.
+    pub fn get_last_ballot(&self) -> BallotNum {
+        *self.last_ballot.lock().unwrap()
+    }
+
+    /// update_last_ballot updates and persists the last seen ballot if `b` is greater.
+    pub fn update_last_ballot(&self, b: BallotNum) -> Result<(), StorageError> {
+        let mut lb = self.last_ballot.lock().unwrap();
+        if b > *lb {
+            self.storage
+                .set_ballot_status(&ReplicaStatus::LastBallot, &b)?;
+            *lb = b;
+        }
+        Ok(())
+    }
+
+    /// get_membership returns the membership of the group this replica has applied.
+    pub fn get_membership(&self) -> Membership {
+        self.membership.lock().unwrap().clone()
+    }
+
+    /// set_membership updates the in-memory membership, after it is stored.
+    /// Commits are delivered to a replica or a learner added to the group since then.
+    pub fn set_membership(&self, m: Membership) {
+        for rid in m.replica_ids().into_iter().chain(m.learner_ids()) {
+            if rid != self.replica_id {
+                self.commits.add_peer(rid);
+            }
+        }
+        *self.membership.lock().unwrap() = m;
+    }
+
+    /// take_config_changes returns the membership and range changes executed since the last
+    /// call. They are applied to the cluster config by the server.
+    pub fn take_config_changes(&self) -> Vec<ConfigChange> {
+        std::mem::replace(&mut *self.config_changes.lock().unwrap(), vec![])
+    }
+
+    /// get_group_replica_ids returns the replicas of the group in ascending order, including the
+    /// ones being added or removed.
+    pub fn get_group_replica_ids(&self) -> Vec<ReplicaId> {
+        self.membership.lock().unwrap().replica_ids()
+    }
+
+    /// get_learners returns learners of the group other than this replica, with `alive` updated
+    /// by the failure detector. Learners are not peers: they only receive commits.
+    pub fn get_learners(&self) -> Vec<ReplicaPeer> {
+        let m = self.get_membership();
+
+        let mut learners = vec![];
+        for rid in m.learner_ids() {
+            if rid == self.replica_id {
+                continue;
+            }
+            let addr = m.learners[&rid].clone();
+            learners.push((rid, addr, self.detector.is_alive(rid)).into());
+        }
+        learners
+    }
+
+    /// get_peers returns peers of this replica, with `alive` updated by the failure detector.
+    /// Peers are the replicas that vote, in the current or the new config.
+    pub fn get_peers(&self) -> Vec<ReplicaPeer> {
+        let m = self.get_membership();
+
+        let mut peers = vec![];
+        for rid in m.replica_ids() {
+            if rid == self.replica_id {
+                continue;
+            }
+            let addr = m.get_addr(rid).unwrap().clone();
+            peers.push((rid, addr, self.detector.is_alive(rid)).into());
+        }
+        peers
+    }
+
     /// new_instance creates a new instance with deps initialized and stores it in
     /// replica storage.
     /// deps could contains (x, -1) if a leader has not yet propose any instance.
-    pub fn new_instance(&self, cmds: &[Command]) -> Result<Instance, StorageError> {
-        // TODO locking
-        // TODO do not need to store max instance id, store it in replica and when starting, scan
-        // backward to find the max
+    pub async fn new_instance(&self, cmds: &[Command]) -> Result<Instance, ReplicaError> {
+        // Reading the max instance id and storing the new instance must be atomic, or two
+        // concurrent proposals get the same instance id.
+        let _guard = self.inst_lock.lock().await;
+
+        // a learner only executes instances committed by voters.
+        if self.is_learner() {
+            return Err(ReplicaError::Learner(self.replica_id));
+        }
+
         // TODO test storage error
 
         // TODO ensure replica_ids are sorted
 
         let rid = self.replica_id;
-        let maxs = self.get_max_instance_ids(&self.group_replica_ids);
+        let maxs = self.get_max_instance_ids(&self.get_group_replica_ids());
 
         let this_iid = maxs.get(rid).unwrap();
         let iid = (rid, this_iid.idx + 1).into();
@@ -151,38 +368,66 @@ impl Replica {
             deps.push(did.into());
         }
 
-        let mut inst = Instance::of(cmds, (0, rid).into(), &deps);
+        // propose in the latest round this replica has seen.
+        let num = self.get_last_ballot().num;
+        let mut inst = Instance::of(cmds, (num, rid).into(), &deps);
         inst.instance_id = Some(iid);
 
-        self.storage
-            .set_instance(&inst.instance_id.unwrap(), &inst)?;
+        self.store_instance(&inst)?;
 
         Ok(inst)
     }
 
     /// get_max_instance_ids returns the max instance-id for every specified replica.
     /// If there is no instance at all by a replica, a `(rid, -1)` is filled.
+    /// It does not access storage.
     pub fn get_max_instance_ids(&self, rids: &[ReplicaId]) -> InstanceIdVec {
-        // TODO move the max-ids into replica. it does not need to be in storage.
-        // This way, every time the server starts, a replica need to load the max ids from storage.
+        let maxs = self.max_iids.lock().unwrap();
 
         let mut iids = Vec::with_capacity(rids.len());
-
         for rid in rids.iter() {
-            let start_iid = (*rid, i64::MAX).into();
-            let mut it = self.storage.get_instance_iter(start_iid, true, true);
-            let inst = it.next();
-            let max = match inst {
-                Some(v) => v.instance_id.unwrap(),
-                None => (*rid, -1).into(),
-            };
-
+            let max = maxs.get(*rid).unwrap_or_else(|| (*rid, -1).into());
             iids.push(max);
         }
         iids.into()
     }
 
-    pub fn handle_replicate(
+    /// set_instance stores an instance and updates the in-memory max instance ids.
+    /// The executor is notified if the instance is committed.
+    /// It fails if this replica is frozen.
+    ///
+    /// It holds `inst_lock`, thus a commit is never overwritten by a concurrent request that has
+    /// read the instance before it.
+    pub async fn set_instance(&self, inst: &Instance) -> Result<(), ReplicaError> {
+        let _guard = self.inst_lock.lock().await;
+        self.store_instance(inst)
+    }
+
+    /// store_instance is `set_instance` with `inst_lock` held by the caller.
+    fn store_instance(&self, inst: &Instance) -> Result<(), ReplicaError> {
+        let frozen = self.frozen.read().unwrap();
+        if *frozen {
+            return Err(ReplicaError::Frozen(self.replica_id));
+        }
+
+        let iid = ref_or_bug!(inst.instance_id);
+        self.storage.set_instance(iid, inst)?;
+
+        if inst.committed {
+            self.commit_notify.notify();
+        }
+
+        let mut maxs = self.max_iids.lock().unwrap();
+        match maxs.get(iid.replica_id) {
+            Some(max) if max.idx >= iid.idx => {}
+            _ => {
+                maxs.set(*iid);
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn handle_replicate(
         &self,
         req: ReplicateRequest,
     ) -> Result<ReplicateReply, RpcHandlerError> {
@@ -193,6 +438,16 @@ impl Replica {
             .instance_id
             .ok_or(ProtocolError::LackOf("instance_id".into()))?;
 
+        // read-modify-write of an instance must not interleave with another request.
+        let _guard = self.inst_lock.lock().await;
+
+        // A frozen replica does not vote any more, a copy of it does.
+        if self.is_frozen() {
+            return Err(ReplicaError::Frozen(self.replica_id).into());
+        }
+
+        self.apply_committed(&req.committed)?;
+
         let mut inst = self.get_instance(iid)?;
         let last_ballot = inst.ballot;
 
@@ -203,10 +458,45 @@ impl Replica {
             .as_ref()
             .ok_or(ProtocolError::LackOf("phase".into()))?;
 
+        // A learner never votes.
+        if self.is_learner() {
+            match phase {
+                Phase::Commit(_) => {}
+                _ => return Err(ReplicaError::Learner(self.replica_id).into()),
+            }
+        }
+
+        // An instance below the gc watermark is executed on every replica and has been deleted.
+        if self.is_truncated(iid) {
+            if let Phase::Commit(_) = phase {
+                return Ok(ReplicateReply {
+                    err: None,
+                    last_ballot,
+                    instance_id: Some(iid),
+                    phase: Some(CommitReply {}.into()),
+                });
+            }
+            return Err(ProtocolError::Truncated(iid).into());
+        }
+
         // TODO: Prepare does not need to be rejected when ballot is smaller.
         // Because recovering a FastCommit-ed value does not rely on ballot.
         match phase {
             Phase::Prepare(_) | Phase::Accept(_) => {
+                let rb = req.ballot.unwrap();
+                let mut lb = self.last_ballot.lock().unwrap();
+
+                // A request of an earlier round than the replica has seen is rejected, as well
+                // as a smaller ballot than the instance has seen.
+                if rb.num < lb.num {
+                    return Ok(ReplicateReply {
+                        err: None,
+                        last_ballot: last_ballot.max(Some(*lb)),
+                        instance_id: Some(iid),
+                        phase: None,
+                    });
+                }
+
                 if req.ballot < inst.ballot {
                     return Ok(ReplicateReply {
                         err: None,
@@ -215,6 +505,12 @@ impl Replica {
                         phase: None,
                     });
                 }
+
+                if rb > *lb {
+                    self.storage
+                        .set_ballot_status(&ReplicaStatus::LastBallot, &rb)?;
+                    *lb = rb;
+                }
                 inst.ballot = req.ballot;
             }
             Phase::Commit(_) => {}
@@ -226,8 +522,10 @@ impl Replica {
             Phase::Commit(r) => self.handle_commit(r, &mut inst)?.into(),
         };
 
-        self.storage
-            .set_instance(&inst.instance_id.unwrap(), &inst)?;
+        // A Prepare from a recovery process does not create an instance this replica never saw.
+        if inst.get_status() != InstanceStatus::Na {
+            self.store_instance(&inst)?;
+        }
 
         Ok(ReplicateReply {
             err: None,
@@ -243,6 +541,22 @@ impl Replica {
         inst: &mut Instance,
     ) -> Result<PrepareReply, RpcHandlerError> {
         let iid = ref_or_bug!(inst.instance_id);
+
+        // A committed instance never changes.
+        // An accepted value is only changed by another Accept.
+        // A Prepare without deps is sent by a recovery process. It only reads what this replica
+        // has.
+        if inst.committed || inst.vballot.is_some() || req.deps.is_none() {
+            let n = inst.deps.as_ref().map_or(0, |d| d.len());
+            return Ok(PrepareReply {
+                cmds: inst.cmds.clone(),
+                deps: inst.deps.clone(),
+                deps_committed: vec![inst.committed; n],
+                vballot: inst.vballot,
+                committed: inst.committed,
+            });
+        }
+
         let req_deps = ref_or_bug!(req.deps);
 
         inst.cmds = req.cmds.clone();
@@ -251,7 +565,7 @@ impl Replica {
         // TODO update local commited status by deps_committed[i] is true
         let mut deps_committed = req.deps_committed.clone();
 
-        for rid in self.group_replica_ids.iter() {
+        for rid in self.get_group_replica_ids().iter() {
             let start_iid = (*rid, i64::MAX).into();
 
             for local_inst in self.storage.get_instance_iter(start_iid, true, true) {
@@ -292,9 +606,20 @@ impl Replica {
             }
         }
 
+        // deps below the gc watermark are executed on every replica.
+        for (i, d) in inst.deps.as_ref().unwrap().iter().enumerate() {
+            if i < deps_committed.len() && self.is_truncated((d.replica_id, d.idx).into()) {
+                deps_committed[i] = true;
+            }
+        }
+
         Ok(PrepareReply {
+            // cmds of an instance never change and the requester already has them.
+            cmds: vec![],
             deps: inst.deps.clone(),
             deps_committed: deps_committed,
+            vballot: None,
+            committed: false,
         })
     }
 
@@ -303,9 +628,10 @@ impl Replica {
         req: &AcceptRequest,
         inst: &mut Instance,
     ) -> Result<AcceptReply, RpcHandlerError> {
-        // TODO locking
         // TODO check instance status if committed or executed
         inst.deps = req.deps.clone();
+        // the ballot has been updated to the one in request.
+        inst.vballot = inst.ballot;
         Ok(AcceptReply {})
     }
 
@@ -321,6 +647,28 @@ impl Replica {
         Ok(CommitReply {})
     }
 
+    /// apply_committed stores piggybacked committed instances that are not yet committed on this
+    /// replica.
+    fn apply_committed(&self, insts: &[Instance]) -> Result<(), RpcHandlerError> {
+        for inst in insts.iter() {
+            let iid = inst
+                .instance_id
+                .ok_or(ProtocolError::LackOf("committed.instance_id".into()))?;
+
+            if !inst.committed || self.is_truncated(iid) {
+                continue;
+            }
+
+            let local = self.get_instance(iid)?;
+            if local.committed {
+                continue;
+            }
+
+            self.store_instance(inst)?;
+        }
+        Ok(())
+    }
+
     pub fn get_instance(&self, iid: InstanceId) -> Result<Instance, ReplicaError> {
         let inst = self.storage.get_instance(&iid)?;
 
@@ -340,3 +688,29 @@ impl Replica {
         }
     }
 }
+
+/// load_max_instance_ids finds the max instance-id for every specified replica by seeking
+/// backward in storage.
+/// If all instances by a replica are deleted by gc, the gc watermark is used.
+/// If there is no instance at all by a replica, a `(rid, -1)` is filled.
+pub fn load_max_instance_ids(sto: &Storage, rids: &[ReplicaId]) -> InstanceIdVec {
+    let mut iids = Vec::with_capacity(rids.len());
+    // a storage error is treated as no instance has been deleted.
+    let gc_watermark = sto
+        .get_status(&ReplicaStatus::GcWatermark)
+        .unwrap_or(None)
+        .unwrap_or_default();
+
+    for rid in rids.iter() {
+        let start_iid = (*rid, i64::MAX).into();
+        let mut it = sto.get_instance_iter(start_iid, true, true);
+        let inst = it.next();
+        let max = match inst {
+            Some(v) => v.instance_id.unwrap(),
+            None => (*rid, *gc_watermark.get(rid).unwrap_or(&-1)).into(),
+        };
+
+        iids.push(max);
+    }
+    iids.into()
+}
@@ -0,0 +1,231 @@
+use std::cmp::min;
+use std::collections::HashMap;
+
+use crate::qpaxos::Instance;
+use crate::qpaxos::InstanceId;
+use crate::qpaxos::InstanceIdVec;
+use crate::replica::Replica;
+use crate::InstanceIds;
+use crate::StorageAPI;
+use storage::StorageError;
+
+/// The max number of committed instances of a leader loaded in one round of execution.
+pub const SCC_MAX_LOAD: i64 = 1024;
+
+const UNVISITED: usize = std::usize::MAX;
+
+/// tarjan finds strongly connected components in a graph of vertices `0..edges.len()`.
+/// `edges[v]` are the vertices `v` points to.
+///
+/// Components are returned in reverse topological order: a component is returned after all of the
+/// components it reaches. It does not recurse thus a long dependency chain does not overflow the
+/// stack.
+pub fn tarjan(edges: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    let n = edges.len();
+
+    let mut index = vec![UNVISITED; n];
+    let mut low = vec![0; n];
+    let mut on_stack = vec![false; n];
+    let mut stack = vec![];
+    let mut next = 0;
+
+    let mut sccs = vec![];
+
+    // a DFS path of (vertex, the next edge to visit).
+    let mut path: Vec<(usize, usize)> = vec![];
+
+    for root in 0..n {
+        if index[root] != UNVISITED {
+            continue;
+        }
+
+        index[root] = next;
+        low[root] = next;
+        next += 1;
+        stack.push(root);
+        on_stack[root] = true;
+        path.push((root, 0));
+
+        while let Some((v, ei)) = path.pop() {
+            if ei < edges[v].len() {
+                path.push((v, ei + 1));
+
+                let w = edges[v][ei];
+                if index[w] == UNVISITED {
+                    index[w] = next;
+                    low[w] = next;
+                    next += 1;
+                    stack.push(w);
+                    on_stack[w] = true;
+                    path.push((w, 0));
+                } else if on_stack[w] {
+                    low[v] = min(low[v], index[w]);
+                }
+                continue;
+            }
+
+            // v is the root of a component
+            if low[v] == index[v] {
+                let mut scc = vec![];
+                loop {
+                    let w = stack.pop().unwrap();
+                    on_stack[w] = false;
+                    scc.push(w);
+                    if w == v {
+                        break;
+                    }
+                }
+                sccs.push(scc);
+            }
+
+            if let Some(&(parent, _)) = path.last() {
+                low[parent] = min(low[parent], low[v]);
+            }
+        }
+    }
+
+    sccs
+}
+
+impl Replica {
+    /// load_committed_after loads committed instances of every leader following the executed
+    /// ones, at most `SCC_MAX_LOAD` for a leader.
+    /// It returns the loaded instances and, for every leader, the first instance not loaded
+    /// because it is not committed or not found.
+    fn load_committed_after(
+        &self,
+        executed: &InstanceIds,
+    ) -> Result<(Vec<Instance>, InstanceIdVec), StorageError> {
+        let mut insts = vec![];
+        let mut missing = InstanceIdVec::from([0; 0]);
+
+        for rid in self.get_group_replica_ids().iter() {
+            let start = executed[rid] + 1;
+            for idx in start..start + SCC_MAX_LOAD {
+                let iid = InstanceId::from((*rid, idx));
+                match self.storage.get_instance(&iid)? {
+                    Some(inst) if inst.committed => insts.push(inst),
+                    _ => {
+                        missing.push(iid);
+                        break;
+                    }
+                }
+            }
+        }
+
+        Ok((insts, missing))
+    }
+
+    /// execute_scc executes committed instances in the order of the strongly connected components
+    /// of their dependency graph. Instances in a component are executed in instance-id order.
+    ///
+    /// An instance depends on every instance of its leader with a smaller index, and on every
+    /// instance up to a dep that is not yet executed.
+    /// A component can not be executed if it depends, directly or not, on an instance that is not
+    /// committed on this replica. Such instances are recovered if they are not committed in time.
+    pub async fn execute_scc(&self) -> Result<Vec<InstanceId>, StorageError> {
+        let executed = self.get_executed()?;
+
+        let (mut insts, missing) = self.load_committed_after(&executed)?;
+        insts.sort_by(|a, b| a.instance_id.cmp(&b.instance_id));
+
+        let vertices: HashMap<InstanceId, usize> = insts
+            .iter()
+            .enumerate()
+            .map(|(i, inst)| (inst.instance_id.unwrap(), i))
+            .collect();
+
+        let mut edges = vec![vec![]; insts.len()];
+        let mut blocked = vec![false; insts.len()];
+        let mut waiting = InstanceIdVec::from([0; 0]);
+
+        for (i, inst) in insts.iter().enumerate() {
+            let iid = inst.instance_id.unwrap();
+
+            // the previous instance by the same leader.
+            if let Some(j) = vertices.get(&(iid.replica_id, iid.idx - 1).into()) {
+                edges[i].push(*j);
+            }
+
+            for dep in inst.deps.as_ref().unwrap().iter() {
+                let exec_idx = match executed.get(&dep.replica_id) {
+                    Some(v) => *v,
+                    // not a replica of this group
+                    None => continue,
+                };
+
+                if dep.idx <= exec_idx {
+                    continue;
+                }
+
+                match vertices.get(&(dep.replica_id, dep.idx).into()) {
+                    Some(j) => edges[i].push(*j),
+                    None => {
+                        blocked[i] = true;
+                        if let Some(m) = missing.get(dep.replica_id) {
+                            if m.idx <= dep.idx && waiting.get(m.replica_id).is_none() {
+                                waiting.push(m);
+                            }
+                        }
+                    }
+                }
+            }
+
+            edges[i].sort();
+            edges[i].dedup();
+        }
+
+        let sccs = tarjan(&edges);
+
+        let mut scc_of = vec![0; insts.len()];
+        let mut scc_blocked = vec![false; sccs.len()];
+
+        for (k, scc) in sccs.iter().enumerate() {
+            for v in scc.iter() {
+                scc_of[*v] = k;
+            }
+
+            // Every component this one reaches is returned earlier and has been checked.
+            let b = scc.iter().any(|v| {
+                blocked[*v]
+                    || edges[*v]
+                        .iter()
+                        .any(|w| scc_of[*w] != k && scc_blocked[scc_of[*w]])
+            });
+            scc_blocked[k] = b;
+        }
+
+        let mut insts: Vec<Option<Instance>> = insts.into_iter().map(Some).collect();
+        let mut to_exec = vec![];
+
+        for (k, scc) in sccs.iter().enumerate() {
+            if scc_blocked[k] {
+                continue;
+            }
+
+            let mut scc = scc.clone();
+            // vertices are numbered in instance-id order.
+            scc.sort();
+            for v in scc {
+                to_exec.push(insts[v].take().unwrap());
+            }
+        }
+
+        if waiting.len() > 0 {
+            // give the leader of a missing instance a chance to commit it.
+            let iids: Vec<InstanceId> = waiting
+                .iter()
+                .filter(|iid| self.timeout_to_committed(**iid))
+                .cloned()
+                .collect();
+
+            self.recover_instances(&iids.into()).await;
+        }
+
+        if to_exec.len() == 0 {
+            return Ok(vec![]);
+        }
+
+        self.execute_commands(to_exec, executed).await
+    }
+}
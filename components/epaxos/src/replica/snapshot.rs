@@ -0,0 +1,356 @@
+use crate::qpaxos::InstanceId;
+use crate::qpaxos::InstanceIds;
+use crate::qpaxos::ReplicaStatus;
+use crate::qpaxos::SnapshotRecord;
+use crate::replica::txn_iter;
+use crate::replica::Replica;
+use crate::replica::ReplicaPeer;
+use crate::replica::GC_BATCH_SIZE;
+use crate::Iter;
+use crate::StorageAPI;
+use std::time::Duration;
+use storage::DBColumnFamily;
+use storage::RawKV;
+use storage::Storage;
+use storage::StorageError;
+use storage::WithNameSpace;
+use storage::WriteEntry;
+
+/// The max number of records in one snapshot chunk. A snapshot is also installed in write batches
+/// of at most this many entries.
+pub const SNAPSHOT_CHUNK_SIZE: usize = 1024;
+
+/// A replica that has executed nothing catches up with a snapshot if a peer has executed more
+/// than this number of instances, instead of replaying all of them.
+pub const CATCH_UP_LAG: i64 = 1024;
+
+/// A source gives up streaming a snapshot if a chunk can not be sent in this time, and releases
+/// the storage snapshot it streams from.
+pub const SNAPSHOT_SEND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// SnapshotInstall replaces records and transactions of a replica with a snapshot received in
+/// chunks, see `Replica::start_install`.
+/// Chunks are staged in a separate namespace, the replica keeps replicating and executing until
+/// `finish`.
+pub struct SnapshotInstall<'a> {
+    r: &'a Replica,
+    executed: InstanceIds,
+    staging: Storage,
+}
+
+impl Replica {
+    /// open_snapshot returns the exec status and a storage snapshot taken at the same time.
+    /// `exec_lock` is held only to take the snapshot, thus records in it are exactly the result of
+    /// executing instances up to the exec status.
+    pub async fn open_snapshot(&self) -> Result<(InstanceIds, Storage), StorageError> {
+        let _guard = self.exec_lock.lock().await;
+
+        let executed = self.get_executed()?;
+        let sto = self.storage.get_snapshot()?;
+
+        Ok((executed, sto))
+    }
+
+    /// make_snapshot returns the exec status and all records of this replica, see
+    /// `open_snapshot`.
+    /// The snapshot is held in memory, it is only for a small replica.
+    pub async fn make_snapshot(&self) -> Result<(InstanceIds, Vec<SnapshotRecord>), StorageError> {
+        let (executed, sto) = self.open_snapshot().await?;
+        let records = snapshot_records(&sto).collect::<Result<Vec<_>, _>>()?;
+
+        Ok((executed, records))
+    }
+
+    /// install_snapshot replaces records, transactions and the exec status of this replica with a
+    /// snapshot held in memory, see `start_install`.
+    /// It returns false if the snapshot is discarded.
+    pub async fn install_snapshot(
+        &self,
+        executed: &InstanceIds,
+        records: &[SnapshotRecord],
+    ) -> Result<bool, StorageError> {
+        let mut inst = self.start_install(executed)?;
+        inst.add(records)?;
+        inst.finish().await
+    }
+
+    /// start_install starts to install a snapshot with exec status `executed`: it clears the
+    /// staging namespace of this replica, which a previous installation may have left.
+    /// Records are then staged with `SnapshotInstall::add`, and `SnapshotInstall::finish` replaces
+    /// the live data with them.
+    /// Only one installation on a replica runs at a time.
+    pub fn start_install(
+        &self,
+        executed: &InstanceIds,
+    ) -> Result<SnapshotInstall<'_>, StorageError> {
+        let staging = self.staging();
+        clear(&staging)?;
+
+        Ok(SnapshotInstall {
+            r: self,
+            executed: executed.clone(),
+            staging,
+        })
+    }
+
+    /// staging returns the namespace a snapshot is staged in before it is installed.
+    pub(crate) fn staging(&self) -> Storage {
+        Storage::new(
+            format!("snapshot/{}", self.replica_id),
+            self.storage.get_inner().clone(),
+        )
+    }
+
+    /// is_installing returns true if a snapshot installation on this replica did not finish.
+    pub fn is_installing(&self) -> Result<bool, StorageError> {
+        Ok(self
+            .storage
+            .get_status(&ReplicaStatus::Installing)?
+            .is_some())
+    }
+
+    /// snapshot_source returns a peer to install a snapshot from, if this replica can not catch up
+    /// by replaying instances, or replaying is too slow:
+    /// - the peer has deleted an instance this replica has not executed;
+    /// - this replica has executed nothing, e.g. it is new or empty, and the peer has executed more
+    ///   than `CATCH_UP_LAG` instances;
+    /// - a previous installation on this replica did not finish.
+    pub fn snapshot_source(&self) -> Result<Option<ReplicaPeer>, StorageError> {
+        let executed = self.get_executed()?;
+        let installing = self.is_installing()?;
+        let empty = executed.iter().all(|(_, idx)| *idx < 0);
+
+        for p in self.get_peers() {
+            if !p.alive {
+                continue;
+            }
+
+            if installing {
+                return Ok(Some(p));
+            }
+
+            let h = self.detector.get(p.replica_id);
+
+            if let Some(wm) = h.gc_watermark {
+                let behind = wm
+                    .iter()
+                    .any(|(rid, idx)| *idx > *executed.get(rid).unwrap_or(&-1));
+
+                if behind {
+                    return Ok(Some(p));
+                }
+            }
+
+            if let Some(pe) = h.executed {
+                let n: i64 = pe.iter().map(|(_, idx)| *idx + 1).sum();
+                if empty && n > CATCH_UP_LAG {
+                    return Ok(Some(p));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+impl<'a> SnapshotInstall<'a> {
+    /// add stages a chunk of records of the snapshot, in batches of at most
+    /// `SNAPSHOT_CHUNK_SIZE` records.
+    pub fn add(&mut self, records: &[SnapshotRecord]) -> Result<(), StorageError> {
+        let sto = &self.staging;
+
+        for chunk in records.chunks(SNAPSHOT_CHUNK_SIZE) {
+            let mut entrys = Vec::with_capacity(chunk.len());
+            for rcd in chunk.iter() {
+                let cf = if rcd.txn {
+                    DBColumnFamily::Status
+                } else {
+                    DBColumnFamily::Record
+                };
+                entrys.push(WriteEntry::Set(
+                    cf,
+                    sto.prepend_ns(&rcd.key),
+                    rcd.value.clone(),
+                ));
+            }
+            sto.write_batch(&entrys)?;
+        }
+
+        Ok(())
+    }
+
+    /// finish swaps the staged records in, with `exec_lock` and `inst_lock` held.
+    /// The snapshot is discarded and false is returned if the replica has executed an instance
+    /// the snapshot does not cover while it was being transferred.
+    ///
+    /// The swap marks the installation with `ReplicaStatus::Installing`, deletes records and
+    /// transactions of the replica and moves the staged ones in, in batches.
+    /// Then instances covered by the snapshot are deleted and the gc watermark is raised to the
+    /// exec status, in batches of at most `GC_BATCH_SIZE` instances, like gc does.
+    /// At last, the exec status is written and the installation mark is removed, in one batch.
+    /// A replica that crashed during the swap does not execute, and installs a snapshot again,
+    /// see `snapshot_source`.
+    pub async fn finish(self) -> Result<bool, StorageError> {
+        let r = self.r;
+        let sto = &r.storage;
+        let staging = &self.staging;
+        let executed = &self.executed;
+
+        let _exec_guard = r.exec_lock.lock().await;
+        let _inst_guard = r.inst_lock.lock().await;
+
+        let local = r.get_executed()?;
+        let newer = local
+            .iter()
+            .any(|(rid, idx)| *idx > *executed.get(rid).unwrap_or(&-1));
+
+        if newer && !r.is_installing()? {
+            clear(staging)?;
+            return Ok(false);
+        }
+
+        sto.set_status(&ReplicaStatus::Installing, executed)?;
+        clear(sto)?;
+
+        let staged = snapshot_records(staging);
+        let mut entrys = Vec::with_capacity(SNAPSHOT_CHUNK_SIZE * 2);
+        for rcd in staged {
+            let rcd = rcd?;
+            let cf = if rcd.txn {
+                DBColumnFamily::Status
+            } else {
+                DBColumnFamily::Record
+            };
+            entrys.push(WriteEntry::Set(cf, sto.prepend_ns(&rcd.key), rcd.value));
+            entrys.push(WriteEntry::Delete(cf, staging.prepend_ns(&rcd.key)));
+
+            if entrys.len() >= SNAPSHOT_CHUNK_SIZE * 2 {
+                sto.write_batch(&entrys)?;
+                entrys.clear();
+            }
+        }
+        sto.write_batch(&entrys)?;
+
+        let mut wm = r.get_gc_watermark();
+        let mut max_iids = vec![];
+        for rid in r.get_group_replica_ids().iter() {
+            let end = *executed.get(rid).unwrap_or(&-1);
+            let start = *wm.get(rid).unwrap_or(&-1) + 1;
+            if start > end {
+                continue;
+            }
+
+            let start_iid = InstanceId::from((*rid, start));
+            let mut entrys = vec![];
+            for inst in sto.get_instance_iter(start_iid, true, false) {
+                let iid = inst.instance_id.unwrap();
+                if iid.idx > end || iid.replica_id != *rid {
+                    break;
+                }
+                entrys.push(WriteEntry::Delete(
+                    DBColumnFamily::Instance,
+                    sto.prepend_ns(&iid),
+                ));
+
+                if entrys.len() as i64 >= GC_BATCH_SIZE {
+                    wm.insert(*rid, iid.idx);
+                    entrys.push(sto.make_status_entry(&ReplicaStatus::GcWatermark, &wm));
+                    sto.write_batch(&entrys)?;
+                    entrys.clear();
+                }
+            }
+
+            wm.insert(*rid, end);
+            entrys.push(sto.make_status_entry(&ReplicaStatus::GcWatermark, &wm));
+            sto.write_batch(&entrys)?;
+
+            *r.gc_watermark.lock().unwrap() = wm.clone();
+            max_iids.push(InstanceId::from((*rid, end)));
+        }
+
+        let entrys = vec![
+            sto.make_status_entry(&ReplicaStatus::Exec, executed),
+            WriteEntry::Delete(
+                DBColumnFamily::Status,
+                sto.prepend_ns(&ReplicaStatus::Installing),
+            ),
+        ];
+        sto.write_batch(&entrys)?;
+
+        {
+            let mut maxs = r.max_iids.lock().unwrap();
+            for iid in max_iids {
+                match maxs.get(iid.replica_id) {
+                    Some(max) if max.idx >= iid.idx => {}
+                    _ => {
+                        maxs.set(iid);
+                    }
+                }
+            }
+        }
+
+        // instances following the snapshot may be executable.
+        r.commit_notify.notify();
+        let _ = r.exec_tx.broadcast(());
+
+        Ok(true)
+    }
+}
+
+/// snapshot_records iterates over all records of a replica, followed by states and locks of
+/// transactions.
+pub fn snapshot_records(
+    sto: &Storage,
+) -> impl Iterator<Item = Result<SnapshotRecord, StorageError>> {
+    let records = record_iter(sto).map(|kv| {
+        kv.map(|(key, value)| SnapshotRecord {
+            key,
+            value,
+            txn: false,
+        })
+    });
+
+    let txns = txn_iter(sto).map(|kv| {
+        kv.map(|(key, value)| SnapshotRecord {
+            key,
+            value,
+            txn: true,
+        })
+    });
+
+    records.chain(txns)
+}
+
+/// clear deletes all records and transactions in the namespace of `sto`, in batches.
+fn clear(sto: &Storage) -> Result<(), StorageError> {
+    let olds = record_iter(sto)
+        .map(|kv| kv.map(|(k, _)| (DBColumnFamily::Record, k)))
+        .chain(txn_iter(sto).map(|kv| kv.map(|(k, _)| (DBColumnFamily::Status, k))));
+
+    let mut entrys = Vec::with_capacity(SNAPSHOT_CHUNK_SIZE);
+    for kv in olds {
+        let (cf, k) = kv?;
+        entrys.push(WriteEntry::Delete(cf, sto.prepend_ns(&k)));
+
+        if entrys.len() >= SNAPSHOT_CHUNK_SIZE {
+            sto.write_batch(&entrys)?;
+            entrys.clear();
+        }
+    }
+    sto.write_batch(&entrys)
+}
+
+/// record_iter iterates over records of a replica, with the namespace stripped.
+pub(crate) fn record_iter(
+    sto: &Storage,
+) -> impl Iterator<Item = Result<(Vec<u8>, Vec<u8>), StorageError>> {
+    let prefix = sto.prepend_ns(&b""[..]);
+    let n = prefix.len();
+
+    sto.get_iter(prefix.clone(), true, false, DBColumnFamily::Record)
+        .take_while(move |kv| match kv {
+            Ok((k, _)) => k.starts_with(&prefix),
+            Err(_) => true,
+        })
+        .map(move |kv| kv.map(|(k, v)| (k[n..].to_vec(), v)))
+}
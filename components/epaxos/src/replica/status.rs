@@ -54,7 +54,12 @@ pub struct DepStatus {
 /// Status tracks replication status during fast-accept, accept and commit phase.
 #[derive(Debug, Default)]
 pub struct ReplicationStatus {
-    // TODO: to work with cluster membership updating, a single number quorum is not enough in future.
+    /// the replicas whose replies count, when the replication started.
+    pub membership: Membership,
+
+    /// fast_quorum and quorum are the least number of replies required.
+    /// In a joint config, whether there is a quorum is decided by `membership`, and the fast path
+    /// is disabled.
     pub fast_quorum: i32,
     pub quorum: i32,
 
@@ -75,10 +80,19 @@ pub struct ReplicationStatus {
 impl ReplicationStatus {
     /// new creates a ReplicationStatus with initial deps filled, as if it already Prepare-ed from the
     /// instnace it serves.
-    pub fn new(n_replica: i32, instance: Instance) -> Self {
+    /// Quorums are those of `membership`.
+    pub fn new(membership: &Membership, instance: Instance) -> Self {
+        let n_replica = membership.voters.len() as i32;
+        let fq = if membership.is_joint() {
+            membership.replica_ids().len() as i32
+        } else {
+            fast_quorum(n_replica)
+        };
+
         let mut st = Self {
-            quorum: quorum(n_replica),
-            fast_quorum: fast_quorum(n_replica),
+            membership: membership.clone(),
+            quorum: membership.quorum(),
+            fast_quorum: fq,
 
             instance,
 
@@ -127,9 +141,19 @@ impl ReplicationStatus {
         self
     }
 
+    /// is_accepted returns true if a quorum of the membership has accepted the instance.
+    pub fn is_accepted(&self) -> bool {
+        self.membership.is_quorum(&self.accepted)
+    }
+
     /// get_fastpath_deps returns a Dep Vec if current status satisfies FastPath
     /// condition. Otherwise it returns None.
+    /// There is no fast path while the membership is changing.
     pub fn get_fastpath_deps(&mut self, cluster: &[ReplicaId]) -> Option<Vec<Dep>> {
+        if self.membership.is_joint() {
+            return None;
+        }
+
         let mut rst: Vec<Dep> = Vec::with_capacity(cluster.len());
         for rid in cluster.iter() {
             // TODO do not need to sort every time calling this function.
@@ -145,15 +169,34 @@ impl ReplicationStatus {
 
     /// get_slowpath_deps returns a Dep Vec for accept request.
     /// If current status accumulated enough fast-accept-replies. Otherwise it returns None.
+    ///
+    /// In a joint config, it waits for a quorum of both configs and chooses the greatest replied
+    /// dep, because a new replica may not have seen the instances the old ones have.
     pub fn get_slowpath_deps(&mut self, cluster: &[ReplicaId]) -> Option<Vec<Dep>> {
+        let joint = self.membership.is_joint();
+
         let mut rst: Vec<Dep> = Vec::with_capacity(cluster.len());
         for rid in cluster.iter() {
+            let pre = self.prepared.get_mut(rid)?;
+            if joint && !self.membership.is_quorum(&pre.replied) {
+                return None;
+            }
+
             // TODO do not need to sort every time calling this function.
-            let deps = &mut self.prepared.get_mut(rid)?.rdeps;
+            let deps = &mut pre.rdeps;
 
             deps.sort();
 
-            let fdep = get_slowpath_dep(*rid, deps, self.quorum)?;
+            let fdep = if joint {
+                let d = deps.last()?;
+                Dep {
+                    replica_id: *rid,
+                    idx: d.idx,
+                    seq: d.seq,
+                }
+            } else {
+                get_slowpath_dep(*rid, deps, self.quorum)?
+            };
             rst.push(fdep);
         }
         Some(rst)
@@ -14,7 +14,16 @@ use crate::ReplicaId;
 use crate::ReplicaStatus;
 use crate::StorageAPI;
 use std::collections::HashMap;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use storage::DBColumnFamily;
 use storage::MemEngine;
+use storage::RawKV;
+use storage::RocksDBEngine;
+use storage::StorageError;
+use storage::WriteEntry;
+use tempfile::Builder;
 use tokio::sync::oneshot;
 
 fn new_replica() -> Replica {
@@ -126,6 +135,309 @@ async fn test_execute_commands() {
     }
 }
 
+#[test]
+fn test_split_independent() {
+    let ids = |groups: &Vec<Vec<Instance>>| -> Vec<Vec<InstanceId>> {
+        groups
+            .iter()
+            .map(|g| g.iter().map(|x| x.instance_id.unwrap()).collect())
+            .collect()
+    };
+
+    let cases: Vec<(Vec<Instance>, Vec<Vec<InstanceId>>)> = vec![
+        (vec![], vec![]),
+        (
+            vec![inst!((1, 1), [(x = a)]), inst!((2, 1), [(y = b)])],
+            vec![instidvec![(1, 1)], instidvec![(2, 1)]],
+        ),
+        // read-read does not conflict
+        (
+            vec![inst!((1, 1), [(x)]), inst!((2, 1), [(x)])],
+            vec![instidvec![(1, 1)], instidvec![(2, 1)]],
+        ),
+        // NoOp does not conflict with anything
+        (
+            vec![inst!((1, 1), [()]), inst!((2, 1), [(x = a)])],
+            vec![instidvec![(1, 1)], instidvec![(2, 1)]],
+        ),
+        // read-write and write-write conflict, the order is kept.
+        (
+            vec![
+                inst!((2, 1), [(x)]),
+                inst!((1, 1), [(y = a)]),
+                inst!((3, 1), [(x = a)]),
+                inst!((1, 2), [(y = b)]),
+                inst!((2, 2), [(z = b)]),
+            ],
+            vec![
+                instidvec![(2, 1), (3, 1)],
+                instidvec![(1, 1), (1, 2)],
+                instidvec![(2, 2)],
+            ],
+        ),
+        // connected by an instance with several commands
+        (
+            vec![
+                inst!((1, 1), [(x = a)]),
+                inst!((2, 1), [(y = a)]),
+                inst!((3, 1), [(x), (y)]),
+            ],
+            vec![instidvec![(1, 1), (2, 1), (3, 1)]],
+        ),
+    ];
+
+    for (insts, want) in cases.into_iter() {
+        let groups = split_independent(insts);
+        assert_eq!(want, ids(&groups));
+    }
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_execute_commands_parallel() {
+    let rp = new_replica();
+
+    let insts = vec![
+        inst!((1, 1), [(x = a)]),
+        inst!((2, 1), [(y = b)]),
+        inst!((1, 2), [(x), (x = c)]),
+        inst!((2, 2), [(y)]),
+        inst!((3, 1), [(z = d), (x)]),
+    ];
+
+    let mut rxs = vec![];
+    for inst in insts.iter() {
+        let (tx, rx) = oneshot::channel();
+        rp.insert_tx(inst.instance_id.unwrap(), tx).await;
+        rxs.push(rx);
+    }
+
+    let iids = rp
+        .execute_commands(insts.clone(), instids![(1, 0), (2, 0), (3, 0)])
+        .await
+        .unwrap();
+    assert_eq!(instidvec![(1, 1), (2, 1), (1, 2), (2, 2), (3, 1)], iids);
+
+    assert_eq!(instids![(1, 2), (2, 2), (3, 1)], rp.get_executed().unwrap());
+
+    let want: Vec<Vec<Option<Record>>> = vec![
+        vec![None],
+        vec![None],
+        vec![Some("a".into()), None],
+        vec![Some("b".into())],
+        vec![None, Some("c".into())],
+    ];
+    for (rx, w) in rxs.into_iter().zip(want.iter()) {
+        assert_eq!(w, &rx.await.unwrap());
+    }
+
+    for (k, v) in vec![("x", "c"), ("y", "b"), ("z", "d")] {
+        let got = rp.storage.get_kv(&k.as_bytes().to_vec()).unwrap();
+        assert_eq!(Some(Record::from(v)), got);
+    }
+}
+
+/// CrashEngine fails every write after `crash_at` writes, as if the process crashed.
+/// It counts how many times every record is written.
+struct CrashEngine {
+    inner: Arc<dyn RawKV>,
+    writes: AtomicUsize,
+    crash_at: AtomicUsize,
+    applied: Mutex<HashMap<Vec<u8>, usize>>,
+}
+
+impl CrashEngine {
+    fn new(inner: Arc<dyn RawKV>) -> Self {
+        Self {
+            inner,
+            writes: AtomicUsize::new(0),
+            crash_at: AtomicUsize::new(std::usize::MAX),
+            applied: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// crash_after makes the engine crash after `n` more writes.
+    fn crash_after(&self, n: usize) {
+        let w = self.writes.load(Ordering::SeqCst);
+        self.crash_at.store(w.saturating_add(n), Ordering::SeqCst);
+    }
+
+    fn write(&self) -> Result<(), StorageError> {
+        let w = self.writes.fetch_add(1, Ordering::SeqCst);
+        if w >= self.crash_at.load(Ordering::SeqCst) {
+            return Err(StorageError::DBError("crashed".into()));
+        }
+        Ok(())
+    }
+
+    fn applied(&self, key: &[u8]) -> usize {
+        let applied = self.applied.lock().unwrap();
+        applied
+            .iter()
+            .filter(|(k, _)| k.ends_with(key))
+            .map(|(_, n)| *n)
+            .sum()
+    }
+}
+
+impl RawKV for CrashEngine {
+    fn set_raw(&self, cf: DBColumnFamily, key: &[u8], value: &[u8]) -> Result<(), StorageError> {
+        self.write()?;
+        self.inner.set_raw(cf, key, value)
+    }
+
+    fn get_raw(&self, cf: DBColumnFamily, key: &[u8]) -> Result<Option<Vec<u8>>, StorageError> {
+        self.inner.get_raw(cf, key)
+    }
+
+    fn delete_raw(&self, cf: DBColumnFamily, key: &[u8]) -> Result<(), StorageError> {
+        self.write()?;
+        self.inner.delete_raw(cf, key)
+    }
+
+    fn next_raw(
+        &self,
+        cf: DBColumnFamily,
+        key: &[u8],
+        forward: bool,
+        include: bool,
+    ) -> Result<Option<(Vec<u8>, Vec<u8>)>, StorageError> {
+        self.inner.next_raw(cf, key, forward, include)
+    }
+
+    fn write_batch(&self, entrys: &Vec<WriteEntry>) -> Result<(), StorageError> {
+        self.write()?;
+        self.inner.write_batch(entrys)?;
+
+        let mut applied = self.applied.lock().unwrap();
+        for e in entrys.iter() {
+            if let WriteEntry::Set(DBColumnFamily::Record, k, _) = e {
+                *applied.entry(k.clone()).or_insert(0) += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn snapshot(&self) -> Result<Arc<dyn RawKV>, StorageError> {
+        self.inner.snapshot()
+    }
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_execute_commands_parallel_atomic() {
+    // 3 independent groups, applied in one write with the executed status.
+    let insts = vec![
+        inst!((1, 1), [(x = a)]),
+        inst!((2, 1), [(y = b)]),
+        inst!((3, 1), [(z = c)]),
+    ];
+    assert_eq!(3, split_independent(insts.clone()).len());
+
+    for crash_at in 0..2 {
+        let eng = Arc::new(CrashEngine::new(Arc::new(MemEngine::new().unwrap())));
+        let rp = testutil::new_replica(1, vec![1, 2, 3], vec![], eng.clone());
+        let executed = instids![(1, 0), (2, 0), (3, 0)];
+
+        eng.crash_after(crash_at);
+        let rst = rp.execute_commands(insts.clone(), executed.clone()).await;
+        assert_eq!(crash_at == 1, rst.is_ok(), "crash at: {}", crash_at);
+
+        let applied = crash_at == 1;
+        for k in vec!["x", "y", "z"] {
+            let got = rp.storage.get_kv(&k.as_bytes().to_vec()).unwrap();
+            assert_eq!(applied, got.is_some(), "crash at: {}", crash_at);
+        }
+
+        let want = if applied {
+            instids![(1, 1), (2, 1), (3, 1)]
+        } else {
+            executed
+        };
+        let got = rp.storage.get_status(&ReplicaStatus::Exec).unwrap();
+        assert_eq!(applied, got == Some(want), "crash at: {}", crash_at);
+    }
+}
+
+async fn crash_and_restart(new_engine: &dyn Fn() -> Arc<dyn RawKV>) {
+    // (3, 1)→(2, 1)→(1, 1), executed one by one.
+    let mut insts = vec![
+        inst!((1, 1), [(x = a)], (1, [0, 0, 0])),
+        inst!((2, 1), [(y = b)], (1, [1, 0, 0])),
+        inst!((3, 1), [(z = c)], (1, [1, 1, 0])),
+    ];
+    for inst in insts.iter_mut() {
+        inst.committed = true;
+    }
+
+    for crash_at in 0..4 {
+        let eng = Arc::new(CrashEngine::new(new_engine()));
+
+        let rp = testutil::new_replica(1, vec![1, 2, 3], vec![], eng.clone());
+        for inst in insts.iter() {
+            rp.set_instance(inst).await.unwrap();
+        }
+        rp.storage
+            .set_status(&ReplicaStatus::Exec, &instids![(1, 0), (2, 0), (3, 0)])
+            .unwrap();
+
+        eng.crash_after(crash_at);
+        loop {
+            match rp.execute().await {
+                Ok(iids) if iids.len() > 0 => {}
+                _ => break,
+            }
+        }
+
+        // restart: execute with the same storage.
+        eng.crash_after(std::usize::MAX);
+        let rp = testutil::new_replica(1, vec![1, 2, 3], vec![], eng.clone());
+
+        // records and the executed status are written atomically.
+        let executed = rp.get_executed().unwrap();
+        for (k, rid) in vec![("x", 1), ("y", 2), ("z", 3)] {
+            let got = rp.storage.get_kv(&k.as_bytes().to_vec()).unwrap();
+            assert_eq!(executed[&rid] == 1, got.is_some(), "crash at: {}", crash_at);
+        }
+
+        loop {
+            match rp.execute().await {
+                Ok(iids) if iids.len() > 0 => {}
+                _ => break,
+            }
+        }
+
+        assert_eq!(
+            instids![(1, 1), (2, 1), (3, 1)],
+            rp.get_executed().unwrap(),
+            "crash at: {}",
+            crash_at
+        );
+
+        for (k, v) in vec![("x", "a"), ("y", "b"), ("z", "c")] {
+            let got = rp.storage.get_kv(&k.as_bytes().to_vec()).unwrap();
+            assert_eq!(Some(Record::from(v)), got, "crash at: {}", crash_at);
+            assert_eq!(1, eng.applied(k.as_bytes()), "crash at: {}", crash_at);
+        }
+    }
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_execute_exactly_once_mem() {
+    crash_and_restart(&|| Arc::new(MemEngine::new().unwrap())).await;
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_execute_exactly_once_rocks() {
+    let tmp_root = Builder::new().tempdir().unwrap();
+    let n = AtomicUsize::new(0);
+
+    crash_and_restart(&|| {
+        let i = n.fetch_add(1, Ordering::SeqCst);
+        let db_path = format!("{}/test-{}", tmp_root.path().display(), i);
+        Arc::new(RocksDBEngine::new(&db_path).unwrap())
+    })
+    .await;
+}
+
 #[tokio::test(threaded_scheduler)]
 async fn test_execute_instances() {
     let rp = new_replica();
@@ -0,0 +1,163 @@
+use std::sync::Arc;
+
+use crate::inst;
+use crate::instids;
+use crate::qpaxos::{Instance, InstanceId, MakeRequest, ProtocolError};
+use crate::replica::*;
+use crate::testutil;
+use crate::InstanceIds;
+use crate::ReplicaStatus;
+use crate::StorageAPI;
+use storage::MemEngine;
+use storage::RawKV;
+
+fn new_replica(sto: Arc<dyn RawKV>) -> Replica {
+    testutil::new_replica(
+        1,
+        vec![1, 2, 3],
+        vec![
+            (2, "http://127.0.0.1:1", true).into(),
+            (3, "http://127.0.0.1:1", true).into(),
+        ],
+        sto,
+    )
+}
+
+async fn set_committed(r: &Replica, rid: i64, n: i64) {
+    for idx in 0..n {
+        let mut inst = inst!((rid, idx), (0, _), [(x = y)]);
+        inst.committed = true;
+        r.set_instance(&inst).await.unwrap();
+    }
+}
+
+#[test]
+fn test_gc_target() {
+    let r = new_replica(Arc::new(MemEngine::new().unwrap()));
+    r.storage
+        .set_status(&ReplicaStatus::Exec, &instids![(1, 9), (2, 2), (3, -1)])
+        .unwrap();
+
+    // a peer has not reported
+    r.detector
+        .set_executed(2, instids![(1, 5), (2, 2), (3, -1)]);
+    assert_eq!(None, r.gc_target().unwrap());
+
+    r.detector.set_executed(3, instids![(1, 7), (2, 1)]);
+    assert_eq!(
+        Some(instids![(1, 5), (2, 1), (3, -1)]),
+        r.gc_target().unwrap()
+    );
+}
+
+#[test]
+fn test_gc_target_learner() {
+    let r = new_replica(Arc::new(MemEngine::new().unwrap()));
+    r.membership
+        .lock()
+        .unwrap()
+        .learners
+        .insert(4, "http://127.0.0.1:1".into());
+    r.storage
+        .set_status(&ReplicaStatus::Exec, &instids![(1, 9), (2, 2), (3, 3)])
+        .unwrap();
+
+    r.detector.set_executed(2, instids![(1, 9), (2, 2), (3, 3)]);
+    r.detector.set_executed(3, instids![(1, 9), (2, 2), (3, 3)]);
+
+    // the learner has not reported
+    assert_eq!(None, r.gc_target().unwrap());
+
+    r.detector.set_executed(4, instids![(1, 4), (2, 2)]);
+    assert_eq!(
+        Some(instids![(1, 4), (2, 2), (3, -1)]),
+        r.gc_target().unwrap()
+    );
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_gc() {
+    let sto: Arc<dyn RawKV> = Arc::new(MemEngine::new().unwrap());
+    let r = new_replica(sto.clone());
+
+    // more than one batch
+    set_committed(&r, 1, GC_BATCH_SIZE + 10).await;
+    set_committed(&r, 2, 3).await;
+
+    // nothing to delete before peers report.
+    assert_eq!(0, r.gc().await.unwrap());
+
+    let max1 = GC_BATCH_SIZE + 9;
+    r.storage
+        .set_status(&ReplicaStatus::Exec, &instids![(1, max1), (2, 2)])
+        .unwrap();
+    r.detector.set_executed(2, instids![(1, max1), (2, 2)]);
+    r.detector.set_executed(3, instids![(1, max1 - 3), (2, 1)]);
+
+    assert_eq!((max1 - 3 + 1 + 2) as usize, r.gc().await.unwrap());
+    assert_eq!(0, r.gc().await.unwrap());
+
+    let wm = instids![(1, max1 - 3), (2, 1)];
+    assert_eq!(wm, r.get_gc_watermark());
+    assert_eq!(
+        Some(wm.clone()),
+        r.storage.get_status(&ReplicaStatus::GcWatermark).unwrap()
+    );
+
+    for (iid, deleted) in vec![
+        ((1, 0), true),
+        ((1, max1 - 3), true),
+        ((1, max1 - 2), false),
+        ((1, max1), false),
+        ((2, 1), true),
+        ((2, 2), false),
+    ] {
+        let iid = InstanceId::from(iid);
+        let got = r.storage.get_instance(&iid).unwrap();
+        assert_eq!(deleted, got.is_none(), "{}", iid);
+        assert_eq!(deleted, r.is_truncated(iid), "{}", iid);
+    }
+
+    // delete all instances by replica 2
+    r.detector.set_executed(3, instids![(1, max1 - 3), (2, 2)]);
+    assert_eq!(1, r.gc().await.unwrap());
+
+    // restart
+    let r = new_replica(sto.clone());
+    assert_eq!(instids![(1, max1 - 3), (2, 2)], r.get_gc_watermark());
+    assert_eq!(
+        vec![InstanceId::from((1, max1)), (2, 2).into(), (3, -1).into()],
+        r.get_max_instance_ids(&[1, 2, 3]).to_vec()
+    );
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_gc_handle_replicate_truncated() {
+    let r = new_replica(Arc::new(MemEngine::new().unwrap()));
+    set_committed(&r, 2, 3).await;
+
+    r.storage
+        .set_status(&ReplicaStatus::Exec, &instids![(2, 2)])
+        .unwrap();
+    r.detector.set_executed(2, instids![(2, 2)]);
+    r.detector.set_executed(3, instids![(2, 1)]);
+    assert_eq!(2, r.gc().await.unwrap());
+
+    let inst = inst!((2, 1), (0, _), [(x = z)]);
+
+    // a commit of a deleted instance does nothing.
+    let repl = r
+        .handle_replicate(MakeRequest::commit(1, &inst))
+        .await
+        .unwrap();
+    assert!(repl.err.is_none());
+    assert_eq!(None, r.storage.get_instance(&(2, 1).into()).unwrap());
+
+    for req in vec![
+        MakeRequest::prepare(1, &inst, &[]),
+        MakeRequest::accept(1, &inst),
+    ] {
+        let err = r.handle_replicate(req).await.unwrap_err();
+        assert_eq!(err, ProtocolError::Truncated((2, 1).into()).into());
+    }
+}
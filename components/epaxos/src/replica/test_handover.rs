@@ -0,0 +1,109 @@
+use std::sync::Arc;
+
+use crate::inst;
+use crate::instids;
+use crate::instidvec;
+use crate::qpaxos::*;
+use crate::replica::*;
+use crate::replication::RpcHandlerError;
+use crate::testutil;
+use crate::StorageAPI;
+use storage::MemEngine;
+use storage::RawKV;
+
+fn new_replica(rid: ReplicaId, sto: Arc<dyn RawKV>) -> Replica {
+    testutil::new_replica(rid, vec![1, 2, 3], vec![], sto)
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_hand_over_take_over() {
+    let sto: Arc<dyn RawKV> = Arc::new(MemEngine::new().unwrap());
+    let r = new_replica(1, sto.clone());
+    let r2 = new_replica(2, sto.clone());
+
+    let mut insts = vec![
+        inst!((1, 0), (0, _), [(x = a)]),
+        inst!((1, 1), (0, _), [(y = b)]),
+        inst!((2, 0), (0, _), [(z = c)]),
+    ];
+    insts[0].committed = true;
+    for inst in insts.iter() {
+        r.set_instance(inst).await.unwrap();
+    }
+    r.update_last_ballot((3, 2).into()).unwrap();
+    r.storage.set_kv(b"x", &"a".into()).unwrap();
+    r2.storage.set_kv(b"w", &"d".into()).unwrap();
+
+    let st = r.hand_over(&instids![(1, 0)]).await.unwrap();
+    assert!(r.is_frozen());
+    assert_eq!(insts[1..].to_vec(), st.instances);
+    assert_eq!(Some((3, 2).into()), st.last_ballot);
+    assert_eq!(Some(r.get_membership()), st.membership);
+    assert_eq!(None, st.range);
+
+    // a frozen replica stores nothing.
+    let err = r
+        .set_instance(&inst!((1, 2), (0, _), [(x = b)]))
+        .await
+        .unwrap_err();
+    assert_eq!(ReplicaError::Frozen(1), err);
+
+    let err = r
+        .handle_replicate(MakeRequest::accept(2, &insts[2]))
+        .await
+        .unwrap_err();
+    assert_eq!(RpcHandlerError::from(ReplicaError::Frozen(1)), err);
+
+    // hand over again
+    assert_eq!(st, r.hand_over(&instids![(1, 0)]).await.unwrap());
+
+    // it stays frozen after restart.
+    assert!(new_replica(1, sto.clone()).is_frozen());
+
+    // a copy on another node
+    let c = new_replica(1, Arc::new(MemEngine::new().unwrap()));
+    c.take_over(&st).await.unwrap();
+
+    for inst in insts[1..].iter() {
+        assert_eq!(*inst, c.get_instance(inst.instance_id.unwrap()).unwrap());
+    }
+    assert_eq!(
+        InstanceIdVec::from(instidvec![(1, 1), (2, 0), (3, -1)]),
+        c.get_max_instance_ids(&[1, 2, 3])
+    );
+    assert_eq!(BallotNum::from((3, 2)), c.get_last_ballot());
+    assert!(!c.is_frozen());
+
+    // purge
+    r.purge().await.unwrap();
+    assert_eq!(None, r.storage.get_kv(b"x").unwrap());
+    assert_eq!(None, r.storage.get_instance(&(1, 0).into()).unwrap());
+    assert_eq!(
+        None,
+        r.storage
+            .get_ballot_status(&ReplicaStatus::LastBallot)
+            .unwrap()
+    );
+    assert!(!new_replica(1, sto.clone()).is_frozen());
+    assert_eq!(Some("d".into()), r2.storage.get_kv(b"w").unwrap());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_hand_over_truncated() {
+    let sto: Arc<dyn RawKV> = Arc::new(MemEngine::new().unwrap());
+    let r = new_replica(1, sto.clone());
+    r.storage
+        .set_status(&ReplicaStatus::GcWatermark, &instids![(2, 3)])
+        .unwrap();
+    let r = new_replica(1, sto.clone());
+
+    let err = r.hand_over(&instids![(2, 1)]).await.unwrap_err();
+    assert_eq!(
+        RpcHandlerError::from(ProtocolError::Truncated((2, 2).into())),
+        err
+    );
+    assert!(!r.is_frozen());
+
+    r.hand_over(&instids![(2, 3)]).await.unwrap();
+    assert!(r.is_frozen());
+}
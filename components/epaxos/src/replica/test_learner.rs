@@ -0,0 +1,119 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::inst;
+use crate::instids;
+use crate::qpaxos::*;
+use crate::replica::*;
+use crate::replication::RpcHandlerError;
+use crate::testutil;
+use crate::StorageAPI;
+use storage::MemEngine;
+use storage::RawKV;
+
+/// new_replica creates a replica of group 1, 2, 3 with learner 4.
+fn new_replica(rid: ReplicaId, sto: Arc<dyn RawKV>) -> Replica {
+    let peers = vec![1, 2, 3, 4]
+        .into_iter()
+        .filter(|x| *x != rid)
+        .map(|x| (x, format!("http://127.0.0.1:{}", x), true).into())
+        .collect();
+
+    let r = testutil::new_replica(rid, vec![1, 2, 3], peers, sto);
+
+    let mut m = r.get_membership();
+    let addr = m.voters.remove(&4).unwrap_or("http://127.0.0.1:4".into());
+    m.learners.insert(4, addr);
+    r.set_membership(m);
+    r
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_learner_does_not_vote() {
+    let r = new_replica(4, Arc::new(MemEngine::new().unwrap()));
+    assert!(r.is_learner());
+    assert_eq!(vec![1, 2, 3], r.get_group_replica_ids());
+    assert_eq!(
+        vec![1, 2, 3],
+        r.get_peers()
+            .iter()
+            .map(|p| p.replica_id)
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(0, r.get_learners().len());
+
+    assert_eq!(
+        ReplicaError::Learner(4),
+        r.new_instance(&[("Set", "x", "1").into()])
+            .await
+            .unwrap_err()
+    );
+
+    let mut inst = inst!((1, 0), (0, _), [(x = a)]);
+    let err = r
+        .handle_replicate(MakeRequest::accept(4, &inst))
+        .await
+        .unwrap_err();
+    assert_eq!(RpcHandlerError::from(ReplicaError::Learner(4)), err);
+
+    // a learner receives commits.
+    inst.committed = true;
+    r.handle_replicate(MakeRequest::commit(4, &inst))
+        .await
+        .unwrap();
+    assert!(r.get_instance((1, 0).into()).unwrap().committed);
+}
+
+#[test]
+fn test_learner_receives_commits() {
+    let r = new_replica(1, Arc::new(MemEngine::new().unwrap()));
+    assert!(!r.is_learner());
+    assert_eq!(
+        vec![4],
+        r.get_learners()
+            .iter()
+            .map(|p| p.replica_id)
+            .collect::<Vec<_>>()
+    );
+
+    r.commits.add((1, 0).into()).unwrap();
+    for rid in 2..=4 {
+        assert_eq!(vec![InstanceId::from((1, 0))], r.commits.pending(rid, 10));
+    }
+}
+
+#[test]
+fn test_learner_staleness() {
+    let r = new_replica(4, Arc::new(MemEngine::new().unwrap()));
+    r.storage
+        .set_status(&ReplicaStatus::Exec, &instids![(1, 3), (2, 2), (3, -1)])
+        .unwrap();
+
+    // no peer reported
+    r.update_freshness().unwrap();
+    assert_eq!(None, r.staleness());
+
+    // not caught up
+    let t0 = Instant::now();
+    r.detector.set_executed(1, instids![(1, 4), (2, 2)]);
+    r.detector.set_executed_at(1, t0);
+    r.update_freshness().unwrap();
+    assert_eq!(None, r.staleness());
+
+    r.detector.set_executed(2, instids![(1, 3), (2, 1)]);
+    r.detector.set_executed_at(2, t0);
+    r.update_freshness().unwrap();
+    assert_eq!(Some(t0), *r.fresh_at.lock().unwrap());
+    assert!(r.staleness().is_some());
+
+    // freshness never goes back.
+    let t1 = Instant::now();
+    r.detector.set_executed_at(3, t1);
+    r.detector.set_executed(3, instids![(1, 2)]);
+    r.update_freshness().unwrap();
+    assert_eq!(Some(t1), *r.fresh_at.lock().unwrap());
+
+    r.detector.set_executed_at(2, t0);
+    r.update_freshness().unwrap();
+    assert_eq!(Some(t1), *r.fresh_at.lock().unwrap());
+}
@@ -0,0 +1,123 @@
+use std::sync::Arc;
+
+use crate::inst;
+use crate::instids;
+use crate::instidvec;
+use crate::qpaxos::*;
+use crate::replica::*;
+use crate::testutil;
+use crate::StorageAPI;
+use storage::MemEngine;
+use storage::RawKV;
+use storage::Storage;
+use tokio::sync::oneshot;
+
+fn range_inst(iid: (ReplicaId, i64), rc: &RangeChange) -> Instance {
+    Instance {
+        instance_id: Some(iid.into()),
+        cmds: vec![rc.into()],
+        ..Default::default()
+    }
+}
+
+fn get(sto: &Storage, key: &str) -> Option<Record> {
+    sto.get_kv(key.as_bytes()).unwrap()
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_range_split_merge() {
+    let sto: Arc<dyn RawKV> = Arc::new(MemEngine::new().unwrap());
+    let r = testutil::new_replica(1, vec![1, 2, 3], vec![], sto.clone());
+    for k in vec!["a", "b", "m", "y"] {
+        r.storage.set_kv(k.as_bytes(), &k.into()).unwrap();
+    }
+
+    let pairs = hashmap! {1=>11, 2=>12, 3=>13};
+    let split = RangeChange {
+        range: Some(("a", "m").into()),
+        split: Some(("m", "z").into()),
+        pairs: pairs.clone(),
+    };
+
+    let insts = vec![
+        inst!((2, 0), [(b = b2)]),
+        range_inst((2, 1), &split),
+        inst!((2, 2), [(n = n2), (del y)]),
+    ];
+
+    // a range change is executed alone.
+    let iids = r.execute_commands(insts.clone(), instids![]).await.unwrap();
+    assert_eq!(instidvec![(2, 0)], iids);
+    assert_eq!(None, r.get_range());
+    assert!(r.take_config_changes().is_empty());
+
+    let iids = r
+        .execute_commands(insts[1..].to_vec(), r.get_executed().unwrap())
+        .await
+        .unwrap();
+    assert_eq!(instidvec![(2, 1)], iids);
+
+    assert_eq!(Some(("a", "m").into()), r.get_range());
+    assert!(r.owns(b"b"));
+    assert!(!r.owns(b"m"));
+    assert_eq!(
+        vec![ConfigChange::Range(split.clone())],
+        r.take_config_changes()
+    );
+    assert!(r.take_config_changes().is_empty());
+
+    // records in the split range are moved to the paired replica.
+    let psto = Storage::new(11, sto.clone());
+    for (k, want, pwant) in vec![
+        ("a", Some("a"), None),
+        ("b", Some("b2"), None),
+        ("m", None, Some("m")),
+        ("y", None, Some("y")),
+    ] {
+        assert_eq!(want.map(Record::from), get(&r.storage, k), "{}", k);
+        assert_eq!(pwant.map(Record::from), get(&psto, k), "{}", k);
+    }
+
+    let p = testutil::new_replica(11, vec![11, 12, 13], vec![], sto.clone());
+    assert_eq!(Some(("m", "z").into()), p.get_range());
+    assert_eq!(Membership::from(&[11, 12, 13][..]), p.get_membership());
+
+    // a command out of the range is not applied, and no result is sent.
+    let (tx, rx) = oneshot::channel();
+    r.insert_tx((2, 2).into(), tx).await;
+
+    let iids = r
+        .execute_commands(insts[2..].to_vec(), r.get_executed().unwrap())
+        .await
+        .unwrap();
+    assert_eq!(instidvec![(2, 2)], iids);
+    assert_eq!(None, get(&r.storage, "n"));
+    assert_eq!(None, get(&psto, "n"));
+    assert_eq!(Some(Record::from("y")), get(&psto, "y"));
+    assert!(rx.await.is_err());
+
+    // retire the new group then merge it back.
+    let retire = RangeChange {
+        range: Some(("z", "z").into()),
+        ..Default::default()
+    };
+    p.execute_commands(vec![range_inst((11, 0), &retire)], instids![])
+        .await
+        .unwrap();
+    assert!(!p.owns(b"m"));
+
+    let merge = RangeChange {
+        range: Some(("a", "z").into()),
+        split: None,
+        pairs,
+    };
+    r.execute_commands(vec![range_inst((2, 3), &merge)], r.get_executed().unwrap())
+        .await
+        .unwrap();
+
+    assert!(r.owns(b"y"));
+    for k in vec!["a", "m", "y"] {
+        assert_eq!(Some(Record::from(k)), get(&r.storage, k), "{}", k);
+        assert_eq!(None, get(&psto, k), "{}", k);
+    }
+}
@@ -1,5 +1,6 @@
 use std::sync::Arc;
 
+use crate::cmdvec;
 use crate::inst;
 
 use crate::conf::ClusterInfo;
@@ -9,6 +10,7 @@ use crate::testutil;
 use crate::StorageAPI;
 use storage::DBColumnFamily;
 use storage::ObjectKV;
+use storage::Storage;
 use storage::{MemEngine, RawKV};
 
 use pretty_assertions::assert_eq;
@@ -74,18 +76,19 @@ fn new_foo_replica(
     engine: Arc<dyn RawKV>,
     insts: &[((i64, i64), Instance)],
 ) -> Replica {
-    let r = testutil::new_replica(replica_id, vec![0, 1, 2], vec![], engine);
-
+    // instances are stored before creating the replica, which loads max instance ids from
+    // storage.
+    let sto = Storage::new(replica_id, engine.clone());
     for (iid, inst) in insts.iter() {
         let iid = InstanceId::from(iid);
-        r.storage.set(DBColumnFamily::Instance, &iid, inst).unwrap();
+        sto.set(DBColumnFamily::Instance, &iid, inst).unwrap();
     }
 
-    r
+    testutil::new_replica(replica_id, vec![0, 1, 2], vec![], engine)
 }
 
-#[test]
-fn test_new_instance() {
+#[tokio::test(threaded_scheduler)]
+async fn test_new_instance() {
     let rid1 = 1;
 
     let cmds = cmdvec![("Set", "x", "1")];
@@ -94,7 +97,7 @@ fn test_new_instance() {
         // initial
         let r1 = new_foo_replica(rid1, eng.clone(), &[]);
         // R1: (1, 0) -> []
-        let i10 = r1.new_instance(&cmds).unwrap();
+        let i10 = r1.new_instance(&cmds).await.unwrap();
         assert_eq!(
             i10,
             inst!((rid1, 0), (0, _), [(x = "1")], (0, [-1, -1, -1]))
@@ -125,7 +128,7 @@ fn test_new_instance() {
             ],
         );
 
-        let i20 = r2.new_instance(&cmds).unwrap();
+        let i20 = r2.new_instance(&cmds).await.unwrap();
         assert_eq!(i20, inst!((2, 3), (0, _), [(x = "1")], (0, [0, -1, 2])));
         assert_eq!(
             i20,
@@ -134,22 +137,158 @@ fn test_new_instance() {
     }
 }
 
-#[test]
-fn test_get_max_instance_ids() {
+#[tokio::test(threaded_scheduler)]
+async fn test_new_instance_concurrent() {
+    let (n_tasks, n_per_task) = (16, 200);
+
+    let r = Arc::new(new_foo_replica(1, new_mem_sto(), &[]));
+
+    let mut handles = vec![];
+    for t in 0..n_tasks {
+        let r = r.clone();
+        handles.push(tokio::spawn(async move {
+            let mut insts = vec![];
+            for i in 0..n_per_task {
+                let key = format!("k{}-{}", t, i);
+                let cmds = cmdvec![("Set", key.as_str(), "v")];
+                insts.push(r.new_instance(&cmds).await.unwrap());
+            }
+            insts
+        }));
+    }
+
+    let mut insts = vec![];
+    for h in handles {
+        insts.extend(h.await.unwrap());
+    }
+
+    // every proposal gets a distinct instance id and none is overwritten.
+    let mut idxs: Vec<_> = insts.iter().map(|x| x.instance_id.unwrap().idx).collect();
+    idxs.sort();
+    let want: Vec<_> = (0..(n_tasks * n_per_task) as i64).collect();
+    assert_eq!(want, idxs);
+
+    for inst in insts.iter() {
+        let got = r.storage.get_instance(&inst.instance_id.unwrap()).unwrap();
+        assert_eq!(Some(inst.clone()), got);
+    }
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_handle_replicate_concurrent() {
+    let (n_tasks, n_per_task) = (16, 100);
+
+    let r = Arc::new(new_foo_replica(1, new_mem_sto(), &[]));
+
+    let mut handles = vec![];
+    for t in 0..n_tasks {
+        let r = r.clone();
+        handles.push(tokio::spawn(async move {
+            for i in 0..n_per_task {
+                let num = (i * n_tasks + t + 1) as i64;
+                let inst = inst!((0, 0), (num, _), [("Set", "x", "1")], [(0, -1)]);
+                r.handle_replicate(MakeRequest::accept(0, &inst))
+                    .await
+                    .unwrap();
+            }
+        }));
+    }
+
+    for h in handles {
+        h.await.unwrap();
+    }
+
+    // the greatest ballot wins, no matter in which order the requests are handled.
+    let max = BallotNum::from(((n_tasks * n_per_task) as i64, 0));
+    let got = r.get_instance((0, 0).into()).unwrap();
+    assert_eq!(Some(max), got.ballot);
+    assert_eq!(Some(max), got.vballot);
+    assert_eq!(max, r.get_last_ballot());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_commit_concurrent_with_prepare() {
+    let n = 200;
+
+    let r = Arc::new(new_foo_replica(1, new_mem_sto(), &[]));
+
+    let mut handles = vec![];
+    for idx in 0..n {
+        let mut inst = inst!((0, idx), (1, _), [("Set", "x", "1")], [(0, -1)]);
+        inst.vballot = inst.ballot;
+        r.set_instance(&inst).await.unwrap();
+
+        // a recovery process prepares it while the leader commits it.
+        let mut prepare = inst.clone();
+        prepare.ballot = Some((2, 2).into());
+        let req = MakeRequest::prepare(0, &prepare, &[false]);
+
+        let mut committed = inst.clone();
+        committed.committed = true;
+
+        let rr = r.clone();
+        handles.push(tokio::spawn(async move {
+            rr.handle_replicate(req).await.unwrap();
+        }));
+        let rr = r.clone();
+        handles.push(tokio::spawn(async move {
+            rr.set_instance(&committed).await.unwrap();
+        }));
+    }
+
+    for h in handles {
+        h.await.unwrap();
+    }
+
+    // a commit is never overwritten.
+    for idx in 0..n {
+        let got = r.get_instance((0, idx).into()).unwrap();
+        assert!(got.committed, "instance (0, {})", idx);
+    }
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_get_max_instance_ids() {
     let (i12, i13, i34) = (inst!((1, 2)), inst!((1, 3)), inst!((3, 4)));
 
     let insts = vec![((1, 2), i12), ((1, 3), i13), ((3, 4), i34)];
 
     let r = new_foo_replica(3, new_mem_sto(), &insts);
-    let maxs = r.get_max_instance_ids(&[1, 3, 5]);
+
+    let maxs = load_max_instance_ids(&r.storage, &[1, 3, 5]);
     assert_eq!(
         maxs,
         InstanceIdVec::from(instidvec![(1, 3), (3, 4), (5, -1)])
     );
+
+    // only replicas in the group are loaded when starting.
+    let maxs = r.get_max_instance_ids(&[0, 1, 3]);
+    assert_eq!(
+        maxs,
+        InstanceIdVec::from(instidvec![(0, -1), (1, 3), (3, -1)])
+    );
+
+    // updated by storing an instance, but never decreases.
+    r.set_instance(&inst!((1, 5))).await.unwrap();
+    r.set_instance(&inst!((1, 4))).await.unwrap();
+    r.set_instance(&inst!((3, 6))).await.unwrap();
+    let maxs = r.get_max_instance_ids(&[1, 3]);
+    assert_eq!(maxs, InstanceIdVec::from(instidvec![(1, 5), (3, 6)]));
+
+    // a new instance does not read storage
+    r.storage
+        .set(
+            DBColumnFamily::Instance,
+            &InstanceId::from((3, 10)),
+            &inst!((3, 10)),
+        )
+        .unwrap();
+    let inst = r.new_instance(&cmdvec![("Set", "x", "1")]).await.unwrap();
+    assert_eq!(Some(InstanceId::from((3, 7))), inst.instance_id);
 }
 
-#[test]
-fn test_handle_replicate_request_invalid() {
+#[tokio::test(threaded_scheduler)]
+async fn test_handle_replicate_request_invalid() {
     let replica_id = 2;
     let replica = new_foo_replica(replica_id, new_mem_sto(), &vec![]);
 
@@ -184,14 +323,14 @@ fn test_handle_replicate_request_invalid() {
     ];
 
     for (req, estr) in cases.clone() {
-        let repl = replica.handle_replicate(req);
+        let repl = replica.handle_replicate(req).await;
         let err = repl.err().unwrap();
         assert_eq!(err, ProtocolError::LackOf(estr.into()).into());
     }
 }
 
-#[test]
-fn test_handle_replicate_ballot_check() {
+#[tokio::test(threaded_scheduler)]
+async fn test_handle_replicate_ballot_check() {
     let replica_id = 2;
     let replica = new_foo_replica(replica_id, new_mem_sto(), &vec![]);
 
@@ -209,7 +348,7 @@ fn test_handle_replicate_ballot_check() {
     ];
 
     for req in reqs {
-        let repl = replica.handle_replicate(req);
+        let repl = replica.handle_replicate(req).await;
         assert!(repl.is_ok());
 
         let repl = repl.unwrap();
@@ -225,7 +364,7 @@ fn test_handle_replicate_ballot_check() {
         // commit does not check ballot
         let req = MakeRequest::commit(0, &inst);
 
-        let repl = replica.handle_replicate(req);
+        let repl = replica.handle_replicate(req).await;
         assert!(repl.is_ok());
 
         let repl = repl.unwrap();
@@ -236,6 +375,65 @@ fn test_handle_replicate_ballot_check() {
     }
 }
 
+#[tokio::test(threaded_scheduler)]
+async fn test_handle_replicate_last_ballot() {
+    let replica_id = 2;
+    let eng = new_mem_sto();
+    let replica = new_foo_replica(replica_id, eng.clone(), &vec![]);
+    assert_eq!(BallotNum::default(), replica.get_last_ballot());
+
+    let a = inst!((3, 4), (2, _), [("Set", "x", "1")]);
+    let req = MakeRequest::accept(0, &a);
+    let repl = replica.handle_replicate(req).await.unwrap();
+    assert!(repl.phase.is_some());
+    assert_eq!(BallotNum::from((2, 3)), replica.get_last_ballot());
+
+    let b = inst!((1, 5), (1, _), [("Set", "y", "1")]);
+
+    for req in vec![MakeRequest::prepare(0, &b, &[]), MakeRequest::accept(0, &b)] {
+        // an earlier round is rejected, even though instance `b` has not seen any ballot.
+        let repl = replica.handle_replicate(req).await.unwrap();
+        assert!(repl.err.is_none());
+        assert!(repl.phase.is_none());
+        assert_eq!(Some(BallotNum::from((2, 3))), repl.last_ballot);
+
+        let notupdated = replica.get_instance((1, 5).into()).unwrap();
+        assert_eq!(None, notupdated.ballot);
+    }
+
+    {
+        // a ballot of another leader in the same round is accepted
+        let b = inst!((1, 5), (2, _), [("Set", "y", "1")]);
+        let repl = replica
+            .handle_replicate(MakeRequest::accept(0, &b))
+            .await
+            .unwrap();
+        assert!(repl.phase.is_some());
+        assert_eq!(BallotNum::from((2, 3)), replica.get_last_ballot());
+    }
+
+    {
+        // persisted
+        let replica = new_foo_replica(replica_id, eng.clone(), &vec![]);
+        assert_eq!(BallotNum::from((2, 3)), replica.get_last_ballot());
+
+        // new instance is proposed in the latest round
+        let inst = replica
+            .new_instance(&cmdvec![("Set", "z", "1")])
+            .await
+            .unwrap();
+        assert_eq!(Some(BallotNum::from((2, replica_id))), inst.ballot);
+    }
+
+    {
+        // update_last_ballot only increases it
+        replica.update_last_ballot((1, 5).into()).unwrap();
+        assert_eq!(BallotNum::from((2, 3)), replica.get_last_ballot());
+        replica.update_last_ballot((3, 1).into()).unwrap();
+        assert_eq!(BallotNum::from((3, 1)), replica.get_last_ballot());
+    }
+}
+
 #[test]
 #[should_panic(expected = "inst.deps is unexpected to be None")]
 fn test_handle_prepare_request_panic_local_deps_none() {
@@ -368,8 +566,8 @@ fn test_handle_prepare_normal() {
     }
 }
 
-#[test]
-fn test_handle_accept_request() {
+#[tokio::test(threaded_scheduler)]
+async fn test_handle_accept_request() {
     let replica_id = 2;
     let inst = new_foo_inst(replica_id);
     let iid = inst.instance_id.unwrap();
@@ -415,9 +613,22 @@ fn test_handle_accept_request() {
         assert!(repl.is_ok());
 
         assert_eq!(fdeps, local_inst.deps, "deps");
+        assert_eq!(blt, local_inst.vballot, "vballot");
+        assert_eq!(InstanceStatus::Accepted, local_inst.get_status());
         _test_updated_inst(&local_inst, vec![], false, false);
     }
 
+    {
+        // vballot is persisted by handle_replicate
+        let req = MakeRequest::accept(replica_id, &inst);
+        let repl = replica.handle_replicate(req).await.unwrap();
+        assert!(repl.phase.is_some());
+
+        let got = replica.get_instance(iid).unwrap();
+        assert_eq!(blt, got.vballot, "vballot");
+        assert_eq!(fdeps, got.deps, "deps");
+    }
+
     // TODO test higher ballot
 
     // TODO test storage error
@@ -445,6 +656,43 @@ fn test_handle_commit_request() {
     _test_updated_inst(&inst, cmds.clone(), true, false);
 }
 
+#[tokio::test(threaded_scheduler)]
+async fn test_handle_replicate_piggybacked_commits() {
+    let replica_id = 2;
+    let replica = new_foo_replica(replica_id, new_mem_sto(), &vec![]);
+
+    let mut committed = inst!((1, 0), (1, _), [(x = y)], [(1, -1), (2, -1)]);
+    committed.committed = true;
+
+    let uncommitted = inst!((1, 1), (1, _), [(x = z)], [(1, 0), (2, -1)]);
+
+    // a locally committed instance is not overridden
+    let mut local = inst!((1, 2), (1, _), [(a = b)], [(1, 1), (2, -1)]);
+    local.committed = true;
+    replica
+        .storage
+        .set_instance(&local.instance_id.unwrap(), &local)
+        .unwrap();
+    let mut other = local.clone();
+    other.cmds = cmdvec![(c = d)];
+
+    let inst = new_foo_inst(replica_id);
+    let mut req = MakeRequest::commit(replica_id, &inst);
+    req.committed = vec![committed.clone(), uncommitted.clone(), other];
+
+    let repl = replica.handle_replicate(req).await.unwrap();
+    assert_eq!(None, repl.err);
+
+    let got = replica.get_instance((1, 0).into()).unwrap();
+    assert_eq!(committed, got);
+
+    let got = replica.get_instance((1, 1).into()).unwrap();
+    assert_eq!(InstanceStatus::Na, got.get_status());
+
+    let got = replica.get_instance((1, 2).into()).unwrap();
+    assert_eq!(local, got);
+}
+
 fn _test_updated_inst(got: &Instance, cmds: Vec<Command>, committed: bool, executed: bool) {
     assert_eq!(cmds, got.cmds, "cmds");
     assert_eq!(committed, got.committed, "committed");
@@ -473,21 +721,20 @@ groups:
 
     let ci = ClusterInfo::from_str(cont).unwrap();
 
-    let mut rp = Replica::new(1, &ci, new_mem_sto()).unwrap();
+    let rp = Replica::new(1, &ci, new_mem_sto()).unwrap();
     assert_eq!(1, rp.replica_id);
 
-    rp.group_replica_ids.sort();
-    assert_eq!(rp.group_replica_ids, [1, 2, 3]);
+    assert_eq!(rp.get_group_replica_ids(), [1, 2, 3]);
 
-    rp.peers.sort_by(|x, y| x.replica_id.cmp(&y.replica_id));
-    assert_eq!(2, rp.peers.len());
+    let peers = rp.get_peers();
+    assert_eq!(2, peers.len());
     assert_eq!(
         ReplicaPeer {
             replica_id: 2,
             addr: "http://192.168.0.1:4442".to_string(),
             alive: true
         },
-        rp.peers[0]
+        peers[0]
     );
     assert_eq!(
         ReplicaPeer {
@@ -495,7 +742,7 @@ groups:
             addr: "http://192.168.0.1:4442".to_string(),
             alive: true
         },
-        rp.peers[1]
+        peers[1]
     );
 
     let rp = Replica::new(4, &ci, new_mem_sto());
@@ -0,0 +1,273 @@
+use std::sync::Arc;
+
+use crate::inst;
+use crate::instidvec;
+
+use crate::conf::Executor;
+use crate::qpaxos::{Command, Instance, InstanceId};
+use crate::replica::*;
+use crate::testutil;
+use crate::InstanceIds;
+use crate::Record;
+use crate::ReplicaId;
+use crate::ReplicaStatus;
+use crate::StorageAPI;
+use storage::MemEngine;
+
+fn new_replica(executor: Executor) -> Replica {
+    let mut r = testutil::new_replica(
+        1,
+        vec![1, 2, 3],
+        vec![],
+        Arc::new(MemEngine::new().unwrap()),
+    );
+    r.executor = executor;
+    r
+}
+
+#[test]
+fn test_tarjan() {
+    let cases: Vec<(Vec<Vec<usize>>, Vec<Vec<usize>>)> = vec![
+        (vec![], vec![]),
+        (vec![vec![]], vec![vec![0]]),
+        (vec![vec![0]], vec![vec![0]]),
+        // 0 → 1 → 2
+        (
+            vec![vec![1], vec![2], vec![]],
+            vec![vec![2], vec![1], vec![0]],
+        ),
+        // 0 ⇄ 1 → 2
+        (vec![vec![1], vec![0, 2], vec![]], vec![vec![2], vec![1, 0]]),
+        // the example in wiki/Epaxos-execution.md, ins1 is vertex 0:
+        //
+        // ins1---------->ins3---------->ins5
+        //  ^              |              |
+        //  |              V              V
+        // ins2<----------ins4---------->ins6
+        (
+            vec![vec![2], vec![0], vec![4, 3], vec![1, 5], vec![5], vec![]],
+            vec![vec![5], vec![4], vec![1, 3, 2, 0]],
+        ),
+    ];
+
+    for (edges, want) in cases.iter() {
+        assert_eq!(want, &tarjan(edges), "edges: {:?}", edges);
+    }
+}
+
+#[test]
+fn test_tarjan_long_chain() {
+    // a deep graph does not overflow the stack.
+    let n = 100_000;
+    let mut edges: Vec<Vec<usize>> = (0..n).map(|i| vec![i + 1]).collect();
+    edges[n - 1] = vec![0];
+
+    let sccs = tarjan(&edges);
+    assert_eq!(1, sccs.len());
+    assert_eq!(n, sccs[0].len());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_execute_scc() {
+    let rp = new_replica(Executor::Scc);
+
+    let cases: Vec<(Vec<Instance>, Vec<(ReplicaId, i64)>, Vec<InstanceId>)> = vec![
+        // (1, 1)
+        (
+            vec![inst!((1, 1), deps:(1, [0, 0, 0]), committed:true)],
+            vec![(1, 0), (2, 0), (3, 0)],
+            instidvec![(1, 1)],
+        ),
+        // (3, 2)->(2, 2)->(1, 2)
+        (
+            vec![
+                inst!((1, 2), deps:(1, [1, 1, 1]), committed:true),
+                inst!((2, 2), deps:(1, [2, 1, 1]), committed:true),
+                inst!((3, 2), deps:(1, [2, 2, 1]), committed:true),
+            ],
+            vec![(1, 1), (2, 1), (3, 1)],
+            instidvec![(1, 2), (2, 2), (3, 2)],
+        ),
+        // (1, 3)~(2, 3)<-(3, 3)
+        (
+            vec![
+                inst!((1, 3), deps:(1, [2, 3, 2]), committed:true),
+                inst!((2, 3), deps:(1, [3, 2, 2]), committed:true),
+                inst!((3, 3), deps:(1, [3, 2, 2]), committed:true),
+            ],
+            vec![(1, 2), (2, 2), (3, 2)],
+            instidvec![(1, 3), (2, 3), (3, 3)],
+        ),
+        // (1, 4)->(2, 4)->(3, 4)->(1, 4)
+        (
+            vec![
+                inst!((1, 4), deps:(1, [3, 4, 4]), committed:true),
+                inst!((2, 4), deps:(1, [3, 3, 4]), committed:true),
+                inst!((3, 4), deps:(1, [4, 4, 3]), committed:true),
+            ],
+            vec![(1, 3), (2, 3), (3, 3)],
+            instidvec![(1, 4), (2, 4), (3, 4)],
+        ),
+        // (1, 5)[NotFound]<-(2, 5)~(3, 5)
+        (
+            vec![
+                inst!((2, 5), deps:(1, [5, 4, 5]), committed:true),
+                inst!((3, 5), deps:(1, [4, 5, 4]), committed:true),
+            ],
+            vec![(1, 4), (2, 4), (3, 4)],
+            instidvec![],
+        ),
+        // (1, 6)[NotCommitted]<-(2, 6)  (3, 6)
+        (
+            vec![
+                inst!((1, 6), deps:(1, [5, 5, 5]), committed:false),
+                inst!((2, 6), deps:(1, [6, 5, 5]), committed:true),
+                inst!((3, 6), deps:(1, [5, 5, 5]), committed:true),
+            ],
+            vec![(1, 5), (2, 5), (3, 5)],
+            instidvec![(3, 6)],
+        ),
+    ];
+
+    for (insts, exec_ref, rst) in cases.iter() {
+        insts.iter().for_each(|inst| {
+            rp.storage
+                .set_instance(&inst.instance_id.unwrap(), &inst)
+                .unwrap();
+        });
+
+        let mut executed = InstanceIds {
+            ..Default::default()
+        };
+        for (rid, idx) in exec_ref.iter() {
+            executed.insert(*rid, *idx);
+        }
+        rp.storage
+            .set_status(&ReplicaStatus::Exec, &executed)
+            .unwrap();
+
+        let r = rp.execute().await.unwrap();
+        assert_eq!(rst, &r);
+        for iid in r.iter() {
+            assert_eq!(iid.idx, rp.get_executed().unwrap()[&iid.replica_id]);
+        }
+    }
+}
+
+/// Rand is a tiny xorshift generator to build reproducible histories.
+struct Rand(u64);
+
+impl Rand {
+    fn next(&mut self, n: u64) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % n
+    }
+}
+
+/// gen_history builds committed instances proposed in `rounds` rounds.
+/// In a round, every replica proposes at most one instance, which depends on all instances of
+/// earlier rounds. Instances in one round that set the same key depend on each other, as the
+/// Prepare of one of them sees the other.
+fn gen_history(seed: u64, rounds: usize) -> Vec<Instance> {
+    let rids: Vec<ReplicaId> = vec![1, 2, 3];
+    let keys = ["a", "b", "c", "d"];
+
+    let mut rnd = Rand(seed);
+    let mut maxs: Vec<i64> = vec![-1; rids.len()];
+    let mut insts = vec![];
+
+    for round in 0..rounds {
+        let mut proposed = vec![];
+        for (i, rid) in rids.iter().enumerate() {
+            if rnd.next(3) == 0 {
+                continue;
+            }
+            let key = keys[rnd.next(keys.len() as u64) as usize];
+            proposed.push((i, *rid, key));
+        }
+
+        let mut newmaxs = maxs.clone();
+        for (i, rid, key) in proposed.iter() {
+            let mut deps = maxs.clone();
+            for (j, _, k) in proposed.iter() {
+                if j != i && k == key {
+                    deps[*j] = maxs[*j] + 1;
+                }
+            }
+
+            let deps: Vec<InstanceId> = rids
+                .iter()
+                .zip(deps.iter())
+                .map(|(r, d)| (*r, *d).into())
+                .collect();
+
+            let value = format!("{}-{}", round, rid);
+            let cmds: Vec<Command> = vec![("Set", *key, value.as_str()).into()];
+
+            let mut inst = Instance::of(&cmds, (0, *rid).into(), &[]);
+            inst.instance_id = Some((*rid, maxs[*i] + 1).into());
+            inst.deps = Some(deps.into());
+            inst.committed = true;
+            insts.push(inst);
+
+            newmaxs[*i] = maxs[*i] + 1;
+        }
+        maxs = newmaxs;
+    }
+
+    insts
+}
+
+async fn execute_all(rp: &Replica) {
+    for _ in 0..1000 {
+        let iids = rp.execute().await.unwrap();
+        if iids.len() == 0 {
+            return;
+        }
+    }
+    panic!("too many rounds to execute all instances");
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_execute_scc_differential() {
+    for seed in 1..=20 {
+        let insts = gen_history(seed * 7919, 30);
+
+        let local_min = new_replica(Executor::LocalMin);
+        let scc = new_replica(Executor::Scc);
+
+        for rp in [&local_min, &scc].iter() {
+            for inst in insts.iter() {
+                rp.storage
+                    .set_instance(&inst.instance_id.unwrap(), inst)
+                    .unwrap();
+            }
+            execute_all(rp).await;
+        }
+
+        let mut want = InstanceIds {
+            ..Default::default()
+        };
+        for inst in insts.iter() {
+            let iid = inst.instance_id.unwrap();
+            want.insert(iid.replica_id, iid.idx);
+        }
+        for rid in 1..=3 {
+            if !want.contains_key(&rid) {
+                want.insert(rid, -1);
+            }
+        }
+
+        assert_eq!(want, local_min.get_executed().unwrap(), "seed: {}", seed);
+        assert_eq!(want, scc.get_executed().unwrap(), "seed: {}", seed);
+
+        for key in ["a", "b", "c", "d"].iter() {
+            let k = key.as_bytes().to_vec();
+            let x: Option<Record> = local_min.storage.get_kv(&k).unwrap();
+            let y: Option<Record> = scc.storage.get_kv(&k).unwrap();
+            assert_eq!(x, y, "seed: {}, key: {}", seed, key);
+        }
+    }
+}
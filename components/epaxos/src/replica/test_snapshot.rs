@@ -0,0 +1,227 @@
+use std::sync::Arc;
+
+use crate::inst;
+use crate::instids;
+use crate::qpaxos::{Instance, InstanceId, SnapshotRecord};
+use crate::replica::*;
+use crate::testutil;
+use crate::InstanceIds;
+use crate::Record;
+use crate::ReplicaStatus;
+use crate::StorageAPI;
+use prost::Message;
+use storage::MemEngine;
+use storage::RawKV;
+
+fn new_replica(rid: i64, sto: Arc<dyn RawKV>) -> Replica {
+    testutil::new_replica(rid, vec![1, 2, 3], vec![], sto)
+}
+
+fn rcd(k: &str, v: &str) -> SnapshotRecord {
+    let mut value = vec![];
+    Record::from(v).encode(&mut value).unwrap();
+    SnapshotRecord {
+        key: k.as_bytes().to_vec(),
+        value,
+        txn: false,
+    }
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_make_snapshot() {
+    let sto: Arc<dyn RawKV> = Arc::new(MemEngine::new().unwrap());
+    let r1 = new_replica(1, sto.clone());
+    let r2 = new_replica(2, sto.clone());
+
+    for (k, v) in vec![("y", "b"), ("x", "a"), ("z", "c")] {
+        r1.storage
+            .set_kv(&k.as_bytes().to_vec(), &Record::from(v))
+            .unwrap();
+    }
+    // records of another replica
+    r2.storage
+        .set_kv(&"w".as_bytes().to_vec(), &Record::from("d"))
+        .unwrap();
+
+    r1.storage
+        .set_status(&ReplicaStatus::Exec, &instids![(1, 5), (2, 3)])
+        .unwrap();
+
+    let (executed, records) = r1.make_snapshot().await.unwrap();
+    assert_eq!(instids![(1, 5), (2, 3), (3, -1)], executed);
+    assert_eq!(vec![rcd("x", "a"), rcd("y", "b"), rcd("z", "c")], records);
+
+    let (executed, records) = r2.make_snapshot().await.unwrap();
+    assert_eq!(instids![(1, -1), (2, -1), (3, -1)], executed);
+    assert_eq!(vec![rcd("w", "d")], records);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_install_snapshot() {
+    let r = new_replica(3, Arc::new(MemEngine::new().unwrap()));
+
+    r.storage
+        .set_kv(&"old".as_bytes().to_vec(), &Record::from("o"))
+        .unwrap();
+    for (rid, n) in vec![(1, 8), (2, 2)] {
+        for idx in 0..n {
+            let inst = inst!((rid, idx), (0, _), [(x = y)]);
+            r.set_instance(&inst).await.unwrap();
+        }
+    }
+
+    let executed = instids![(1, 5), (2, 3), (3, -1)];
+    let records = vec![rcd("x", "a"), rcd("y", "b")];
+    assert!(r.install_snapshot(&executed, &records).await.unwrap());
+
+    assert_eq!(executed, r.get_executed().unwrap());
+
+    let (_, got) = r.make_snapshot().await.unwrap();
+    assert_eq!(records, got);
+
+    for (iid, deleted) in vec![
+        ((1, 0), true),
+        ((1, 5), true),
+        ((1, 6), false),
+        ((2, 1), true),
+    ] {
+        let iid = InstanceId::from(iid);
+        let got = r.storage.get_instance(&iid).unwrap();
+        assert_eq!(deleted, got.is_none(), "{}", iid);
+    }
+
+    assert_eq!(instids![(1, 5), (2, 3)], r.get_gc_watermark());
+    assert!(r.is_truncated((2, 3).into()));
+    assert!(!r.is_truncated((3, 0).into()));
+
+    assert_eq!(
+        vec![InstanceId::from((1, 7)), (2, 3).into(), (3, -1).into()],
+        r.get_max_instance_ids(&[1, 2, 3]).to_vec()
+    );
+}
+
+fn new_replica_with_peers(sto: Arc<dyn RawKV>) -> Replica {
+    testutil::new_replica(
+        3,
+        vec![1, 2, 3],
+        vec![
+            (1, "http://127.0.0.1:1", true).into(),
+            (2, "http://127.0.0.1:1", true).into(),
+        ],
+        sto,
+    )
+}
+
+#[test]
+fn test_snapshot_source() {
+    let r = new_replica_with_peers(Arc::new(MemEngine::new().unwrap()));
+    assert_eq!(None, r.snapshot_source().unwrap().map(|p| p.replica_id));
+
+    // an empty replica replays a few instances.
+    r.detector.set_executed(
+        1,
+        instids![(1, CATCH_UP_LAG / 2 - 1), (2, CATCH_UP_LAG / 2 - 1)],
+    );
+    assert_eq!(None, r.snapshot_source().unwrap().map(|p| p.replica_id));
+
+    // but not too many.
+    r.detector.set_executed(
+        1,
+        instids![(1, CATCH_UP_LAG / 2 - 1), (2, CATCH_UP_LAG / 2)],
+    );
+    assert_eq!(Some(1), r.snapshot_source().unwrap().map(|p| p.replica_id));
+
+    // a replica that has executed something replays.
+    r.storage
+        .set_status(&ReplicaStatus::Exec, &instids![(1, 0)])
+        .unwrap();
+    assert_eq!(None, r.snapshot_source().unwrap().map(|p| p.replica_id));
+
+    // unless instances it needs are deleted.
+    r.detector.set_gc_watermark(2, instids![(1, 1)]);
+    assert_eq!(Some(2), r.snapshot_source().unwrap().map(|p| p.replica_id));
+}
+
+fn staged(r: &Replica) -> usize {
+    snapshot_records(&r.staging()).count()
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_install_snapshot_interrupted() {
+    let r = new_replica_with_peers(Arc::new(MemEngine::new().unwrap()));
+
+    r.storage
+        .set_kv(&"old".as_bytes().to_vec(), &Record::from("o"))
+        .unwrap();
+    let mut inst = inst!((1, 0), (0, _), [(x = y)]);
+    inst.committed = true;
+    r.set_instance(&inst).await.unwrap();
+
+    let executed = instids![(1, 5), (2, 3), (3, -1)];
+
+    // the transfer breaks after the first chunk.
+    {
+        let mut install = r.start_install(&executed).unwrap();
+        install.add(&[rcd("x", "a")]).unwrap();
+
+        // the replica is not locked while chunks are staged.
+        let _exec_guard = r.exec_lock.lock().await;
+        let _inst_guard = r.inst_lock.lock().await;
+    }
+
+    assert_eq!(1, staged(&r));
+    assert!(!r.is_installing().unwrap());
+    assert_eq!(Some(Record::from("o")), r.storage.get_kv(b"old").unwrap());
+    assert_eq!(None, r.storage.get_kv(b"x").unwrap());
+
+    // a crash during the swap leaves the mark: it does not execute on incomplete records, but
+    // installs a snapshot again.
+    r.storage
+        .set_status(&ReplicaStatus::Installing, &executed)
+        .unwrap();
+    assert_eq!(Vec::<InstanceId>::new(), r.execute().await.unwrap());
+    assert!(r.snapshot_source().unwrap().is_some());
+
+    let records: Vec<_> = (0..SNAPSHOT_CHUNK_SIZE * 2 + 1)
+        .map(|i| rcd(&format!("k{:05}", i), "v"))
+        .collect();
+
+    // records staged by the broken transfer are cleared.
+    let mut install = r.start_install(&executed).unwrap();
+    for chunk in records.chunks(SNAPSHOT_CHUNK_SIZE) {
+        install.add(chunk).unwrap();
+    }
+    assert!(install.finish().await.unwrap());
+
+    assert!(!r.is_installing().unwrap());
+    assert_eq!(executed, r.get_executed().unwrap());
+    assert_eq!(None, r.storage.get_instance(&(1, 0).into()).unwrap());
+    assert_eq!(0, staged(&r));
+
+    let (_, got) = r.make_snapshot().await.unwrap();
+    assert_eq!(records, got);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_install_snapshot_discarded() {
+    let r = new_replica(3, Arc::new(MemEngine::new().unwrap()));
+
+    r.storage
+        .set_kv(&"old".as_bytes().to_vec(), &Record::from("o"))
+        .unwrap();
+
+    let executed = instids![(1, 5), (2, 3), (3, -1)];
+    let mut install = r.start_install(&executed).unwrap();
+    install.add(&[rcd("x", "a")]).unwrap();
+
+    // the replica executes an instance the snapshot does not cover, during the transfer.
+    let local = instids![(1, 6)];
+    r.storage.set_status(&ReplicaStatus::Exec, &local).unwrap();
+
+    assert!(!install.finish().await.unwrap());
+
+    assert_eq!(Some(Record::from("o")), r.storage.get_kv(b"old").unwrap());
+    assert_eq!(None, r.storage.get_kv(b"x").unwrap());
+    assert_eq!(0, staged(&r));
+    assert!(!r.is_installing().unwrap());
+}
@@ -23,7 +23,8 @@ macro_rules! get {
 fn test_status_new() {
     let inst = inst!((1, 2), (4, _), [("Set", "x", "1")], [(1, 1), (2, 0)],);
 
-    let st = ReplicationStatus::new(7, inst.clone());
+    let m = Membership::from(&[0, 1, 2, 3, 4, 5, 6][..]);
+    let st = ReplicationStatus::new(&m, inst.clone());
 
     assert_eq!(4, st.quorum);
     assert_eq!(5, st.fast_quorum);
@@ -57,7 +58,8 @@ fn test_status_new() {
 #[test]
 fn test_status_start_accept() {
     let inst = inst!((1, 2), (4, _), [("Set", "x", "1")], [(1, 1), (2, 0)],);
-    let mut st = ReplicationStatus::new(7, inst.clone());
+    let m = Membership::from(&[0, 1, 2, 3, 4, 5, 6][..]);
+    let mut st = ReplicationStatus::new(&m, inst.clone());
 
     get!(st.accepted, &1, None);
 
@@ -302,3 +304,57 @@ fn test_status_get_accept_deps() {
         assert_eq!(*want, adep, "deps:{:?}, n:{}, q:{}", deps, n, q);
     }
 }
+
+#[test]
+fn test_status_joint() {
+    let inst = inst!((1, 2), (4, _), [("Set", "x", "1")], [(1, 1), (2, 0)],);
+
+    // change from {1, 2, 3} to {3, 4, 5}
+    let m = Membership::from(&[1, 2, 3][..]);
+    let m = m.enter_joint(Membership::from(&[3, 4, 5][..]).voters);
+
+    let mut st = ReplicationStatus::new(&m, inst.clone());
+    assert_eq!(2, st.quorum);
+    assert_eq!(5, st.fast_quorum);
+
+    let cluster = vec![1, 2];
+
+    let reply = |st: &mut ReplicationStatus, from: ReplicaId, idx: i64| {
+        for rid in cluster.iter() {
+            let pre = st.prepared.get_mut(rid).unwrap();
+            pre.replied.insert(from);
+            pre.rdeps.push(RepliedDep {
+                idx,
+                seq: 0,
+                committed: true,
+            });
+        }
+    };
+
+    // a quorum of the old config.
+    reply(&mut st, 2, 1);
+    assert_eq!(None, st.get_fastpath_deps(&cluster));
+    assert_eq!(None, st.get_slowpath_deps(&cluster));
+
+    // and a quorum of the new config. The greatest dep is chosen.
+    reply(&mut st, 3, 5);
+    assert_eq!(None, st.get_slowpath_deps(&cluster));
+    reply(&mut st, 4, 1);
+    assert_eq!(
+        Some(depvec![(1, 5), (2, 5)]),
+        st.get_slowpath_deps(&cluster)
+    );
+
+    // all replied identical deps but there is no fast path.
+    reply(&mut st, 5, 5);
+    assert_eq!(None, st.get_fastpath_deps(&cluster));
+
+    st.start_accept();
+    assert!(!st.is_accepted());
+    st.accepted.insert(2);
+    assert!(!st.is_accepted());
+    st.accepted.insert(4);
+    assert!(!st.is_accepted());
+    st.accepted.insert(5);
+    assert!(st.is_accepted());
+}
@@ -0,0 +1,250 @@
+use std::sync::Arc;
+
+use tokio::sync::oneshot;
+
+use crate::instids;
+use crate::qpaxos::*;
+use crate::replica::*;
+use crate::testutil;
+use crate::StorageAPI;
+use storage::MemEngine;
+use storage::RawKV;
+
+fn new_replica(rid: ReplicaId, sto: Arc<dyn RawKV>) -> Replica {
+    testutil::new_replica(rid, vec![1, 2, 3], vec![], sto)
+}
+
+fn prepare(id: &str, writes: &[(&str, &str)]) -> Command {
+    let p = TxnPrepare {
+        txn_id: id.as_bytes().to_vec(),
+        primary: writes[0].0.as_bytes().to_vec(),
+        writes: writes
+            .iter()
+            .map(|(k, v)| Command::from(("Set", *k, *v)))
+            .collect(),
+    };
+    Command::from(&p)
+}
+
+fn decide(id: &str, commit: bool) -> Command {
+    let d = TxnDecide {
+        txn_id: id.as_bytes().to_vec(),
+        commit,
+        end: false,
+    };
+    Command::from(&d)
+}
+
+/// end ends a committed transaction after every group is decided.
+fn end(id: &str) -> Command {
+    let d = TxnDecide {
+        txn_id: id.as_bytes().to_vec(),
+        commit: true,
+        end: true,
+    };
+    Command::from(&d)
+}
+
+fn st(s: TxnStatus) -> Option<Record> {
+    Some(s.to_record())
+}
+
+/// exec executes instances of replica 1 from `idx`, one for every element of `cmds`, in one
+/// batch, and returns their results.
+async fn exec(r: &Replica, idx: i64, cmds: Vec<Vec<Command>>) -> Vec<ExecRst> {
+    let mut insts = vec![];
+    let mut rxs = vec![];
+    for (i, cs) in cmds.into_iter().enumerate() {
+        let inst = Instance {
+            instance_id: Some((1, idx + i as i64).into()),
+            cmds: cs,
+            ..Default::default()
+        };
+        let (tx, rx) = oneshot::channel();
+        r.insert_tx(inst.instance_id.unwrap(), tx).await;
+        rxs.push(rx);
+        insts.push(inst);
+    }
+
+    let executed = r.get_executed().unwrap();
+    r.execute_commands(insts, executed).await.unwrap();
+
+    let mut rst = vec![];
+    for rx in rxs {
+        rst.push(rx.await.unwrap());
+    }
+    rst
+}
+
+fn pending_ids(r: &Replica) -> Vec<Vec<u8>> {
+    r.pending_txns()
+        .unwrap()
+        .into_iter()
+        .map(|s| s.prepare.unwrap().txn_id)
+        .collect()
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_txn_prepare_decide() {
+    let r = new_replica(1, Arc::new(MemEngine::new().unwrap()));
+
+    let got = exec(&r, 0, vec![vec![prepare("t1", &[("x", "1"), ("y", "2")])]]).await;
+    assert_eq!(vec![vec![st(TxnStatus::Pending)]], got);
+    assert_eq!(vec![b"t1".to_vec()], pending_ids(&r));
+    assert_eq!(
+        Some(b"t1".to_vec()),
+        r.storage.get_txn_lock(b"x").unwrap().map(|l| l.txn_id)
+    );
+    // nothing is written before commit.
+    assert_eq!(None, r.storage.get_kv(b"x").unwrap());
+
+    // y is locked by t1, t2 is aborted and a commit of it changes nothing.
+    let got = exec(
+        &r,
+        1,
+        vec![
+            vec![prepare("t2", &[("z", "3"), ("y", "3")])],
+            vec![decide("t2", true)],
+        ],
+    )
+    .await;
+    assert_eq!(
+        vec![vec![st(TxnStatus::Aborted)], vec![st(TxnStatus::Aborted)]],
+        got
+    );
+    assert_eq!(None, r.storage.get_txn_lock(b"z").unwrap());
+    assert_eq!(None, r.storage.get_kv(b"z").unwrap());
+
+    // the writes are visible to commands after the commit in the same batch.
+    let got = exec(
+        &r,
+        3,
+        vec![vec![decide("t1", true), Command::from(("Get", "x", ""))]],
+    )
+    .await;
+    assert_eq!(vec![vec![st(TxnStatus::Committed), Some("1".into())]], got);
+    assert_eq!(Some("2".into()), r.storage.get_kv(b"y").unwrap());
+    assert_eq!(None, r.storage.get_txn_lock(b"x").unwrap());
+    assert_eq!(Vec::<Vec<u8>>::new(), pending_ids(&r));
+
+    // the first decision wins.
+    let got = exec(&r, 4, vec![vec![decide("t1", false)]]).await;
+    assert_eq!(vec![vec![st(TxnStatus::Committed)]], got);
+    assert_eq!(Some("1".into()), r.storage.get_kv(b"x").unwrap());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_txn_decide_before_prepare() {
+    let r = new_replica(1, Arc::new(MemEngine::new().unwrap()));
+
+    let got = exec(
+        &r,
+        0,
+        vec![
+            vec![decide("t1", false)],
+            vec![prepare("t1", &[("x", "1")])],
+        ],
+    )
+    .await;
+    assert_eq!(
+        vec![vec![st(TxnStatus::Aborted)], vec![st(TxnStatus::Aborted)]],
+        got
+    );
+    assert_eq!(None, r.storage.get_txn_lock(b"x").unwrap());
+    assert_eq!(Vec::<Vec<u8>>::new(), pending_ids(&r));
+
+    // prepared in one batch.
+    let got = exec(
+        &r,
+        2,
+        vec![
+            vec![prepare("t2", &[("x", "2")])],
+            vec![prepare("t3", &[("x", "3")])],
+        ],
+    )
+    .await;
+    assert_eq!(
+        vec![vec![st(TxnStatus::Pending)], vec![st(TxnStatus::Aborted)]],
+        got
+    );
+    assert_eq!(vec![b"t2".to_vec()], pending_ids(&r));
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_txn_snapshot() {
+    let r = new_replica(1, Arc::new(MemEngine::new().unwrap()));
+    exec(
+        &r,
+        0,
+        vec![
+            vec![prepare("t1", &[("x", "1")])],
+            vec![decide("t2", false)],
+        ],
+    )
+    .await;
+
+    let (executed, records) = r.make_snapshot().await.unwrap();
+
+    let c = new_replica(1, Arc::new(MemEngine::new().unwrap()));
+    assert!(c.install_snapshot(&executed, &records).await.unwrap());
+
+    assert_eq!(vec![b"t1".to_vec()], pending_ids(&c));
+    assert_eq!(
+        Some(TxnStatus::Aborted),
+        c.storage.get_txn_state(b"t2").unwrap().map(|s| s.status())
+    );
+
+    // a committed transaction is applied on the copy.
+    exec(&c, 2, vec![vec![decide("t1", true)]]).await;
+    assert_eq!(Some("1".into()), c.storage.get_kv(b"x").unwrap());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_txn_gc() {
+    let r = new_replica(1, Arc::new(MemEngine::new().unwrap()));
+
+    // t2 is prepared in a group other than the primary group.
+    let p2 = TxnPrepare {
+        txn_id: b"t2".to_vec(),
+        primary: b"a".to_vec(),
+        writes: vec![Command::from(("Set", "y", "2"))],
+    };
+
+    exec(
+        &r,
+        0,
+        vec![
+            vec![prepare("t1", &[("x", "1")])],
+            vec![Command::from(&p2)],
+            vec![prepare("t3", &[("z", "3")])],
+            vec![decide("t1", true)],
+            vec![decide("t2", true)],
+            vec![decide("t3", false)],
+        ],
+    )
+    .await;
+
+    let state = |id: &str| r.storage.get_txn_state(id.as_bytes()).unwrap();
+
+    *r.gc_watermark.lock().unwrap() = instids![(1, 4)];
+    assert_eq!(1, r.gc_txns().await.unwrap());
+    assert_eq!(None, state("t2"));
+    assert!(state("t3").is_some());
+
+    // an aborted one is deleted in the primary group too.
+    *r.gc_watermark.lock().unwrap() = instids![(1, 5)];
+    assert_eq!(1, r.gc_txns().await.unwrap());
+    assert_eq!(None, state("t3"));
+
+    // a committed one is kept in the primary group until it is ended.
+    assert_eq!(None, state("t1").unwrap().done);
+    let got = exec(&r, 6, vec![vec![end("t1")]]).await;
+    assert_eq!(vec![vec![st(TxnStatus::Committed)]], got);
+    assert_eq!(Some((1, 6).into()), state("t1").unwrap().done);
+    assert_eq!(0, r.gc_txns().await.unwrap());
+
+    *r.gc_watermark.lock().unwrap() = instids![(1, 6)];
+    assert_eq!(1, r.gc_txns().await.unwrap());
+    assert_eq!(None, state("t1"));
+    assert_eq!(Some("1".into()), r.storage.get_kv(b"x").unwrap());
+}
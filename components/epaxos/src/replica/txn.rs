@@ -0,0 +1,229 @@
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+
+use prost::Message;
+
+use crate::qpaxos::Command;
+use crate::qpaxos::InstanceId;
+use crate::qpaxos::KeyRange;
+use crate::qpaxos::ReplicaStatus;
+use crate::qpaxos::TxnDecide;
+use crate::qpaxos::TxnLock;
+use crate::qpaxos::TxnPrepare;
+use crate::qpaxos::TxnState;
+use crate::qpaxos::TxnStatus;
+use crate::replica::Replica;
+use crate::Iter;
+use crate::StorageAPI;
+use storage::AsStorageKey;
+use storage::DBColumnFamily;
+use storage::Storage;
+use storage::StorageError;
+use storage::WithNameSpace;
+use storage::WriteEntry;
+
+impl Replica {
+    /// pending_txns returns transactions prepared in the group of this replica but not yet
+    /// decided, in the order of transaction id.
+    pub fn pending_txns(&self) -> Result<Vec<TxnState>, StorageError> {
+        let prefix = ReplicaStatus::TxnLock(vec![]).into_key();
+
+        let mut ids = BTreeSet::new();
+        for kv in status_iter(&self.storage, &prefix) {
+            let (_, v) = kv?;
+            let lock = TxnLock::decode(v.as_slice())?;
+            ids.insert(lock.txn_id);
+        }
+
+        let mut rst = vec![];
+        for id in ids {
+            match self.storage.get_txn_state(&id)? {
+                Some(st) if st.status() == TxnStatus::Pending => rst.push(st),
+                _ => {}
+            }
+        }
+
+        Ok(rst)
+    }
+}
+
+/// TxnExec executes transaction commands of a batch of instances, upon the storage and the
+/// changes made by commands before them in the batch.
+pub(crate) struct TxnExec<'a> {
+    sto: &'a Storage,
+    states: HashMap<Vec<u8>, TxnState>,
+    /// key to the transaction it is locked by, None if it is unlocked in the batch.
+    locks: HashMap<Vec<u8>, Option<Vec<u8>>>,
+}
+
+impl<'a> TxnExec<'a> {
+    pub fn new(sto: &'a Storage) -> Self {
+        TxnExec {
+            sto,
+            states: HashMap::new(),
+            locks: HashMap::new(),
+        }
+    }
+
+    fn get_state(&self, txn_id: &[u8]) -> Result<Option<TxnState>, StorageError> {
+        match self.states.get(txn_id) {
+            Some(st) => Ok(Some(st.clone())),
+            None => self.sto.get_txn_state(txn_id),
+        }
+    }
+
+    fn get_lock(&self, key: &[u8]) -> Result<Option<Vec<u8>>, StorageError> {
+        match self.locks.get(key) {
+            Some(l) => Ok(l.clone()),
+            None => Ok(self.sto.get_txn_lock(key)?.map(|l| l.txn_id)),
+        }
+    }
+
+    /// prepare locks the keys `p` writes and returns Pending, i.e., a vote to commit.
+    /// If a key is locked by another transaction or is out of `range`, it returns Aborted and
+    /// the transaction is aborted in this group.
+    /// A transaction decided before it is prepared is not locked, its decision is returned.
+    pub fn prepare(
+        &mut self,
+        p: TxnPrepare,
+        range: Option<&KeyRange>,
+    ) -> Result<TxnStatus, StorageError> {
+        if let Some(st) = self.get_state(&p.txn_id)? {
+            return Ok(st.status());
+        }
+
+        let mut vote = TxnStatus::Pending;
+        for w in p.writes.iter() {
+            if !range.map_or(true, |r| r.contains(&w.key)) {
+                vote = TxnStatus::Aborted;
+                break;
+            }
+            match self.get_lock(&w.key)? {
+                Some(id) if id != p.txn_id => {
+                    vote = TxnStatus::Aborted;
+                    break;
+                }
+                _ => {}
+            }
+        }
+
+        if vote == TxnStatus::Pending {
+            for w in p.writes.iter() {
+                self.locks.insert(w.key.clone(), Some(p.txn_id.clone()));
+            }
+        }
+
+        let mut st = TxnState {
+            prepare: Some(p),
+            ..Default::default()
+        };
+        st.set_status(vote);
+        self.states
+            .insert(st.prepare.as_ref().unwrap().txn_id.clone(), st);
+
+        Ok(vote)
+    }
+
+    /// decide applies the first decision of a transaction, made by instance `iid`, and returns the
+    /// status of it along with the writes to apply. Later decisions return the first one and
+    /// change nothing, except that an ended one lets the state be deleted.
+    ///
+    /// A decided state is deleted by gc after `TxnState.done`. A group without the state of a
+    /// transaction takes it as aborted, thus only the state of a committed transaction in the
+    /// primary group is kept until it is ended: other groups may still ask it for the decision.
+    pub fn decide(
+        &mut self,
+        d: &TxnDecide,
+        iid: InstanceId,
+    ) -> Result<(TxnStatus, Vec<Command>), StorageError> {
+        let mut st = match self.get_state(&d.txn_id)? {
+            Some(mut st) if st.status() != TxnStatus::Pending => {
+                if d.end && st.done.is_none() {
+                    st.done = Some(iid);
+                    self.states.insert(d.txn_id.clone(), st.clone());
+                }
+                return Ok((st.status(), vec![]));
+            }
+            Some(st) => st,
+            None => TxnState::default(),
+        };
+
+        let decision = if d.commit {
+            TxnStatus::Committed
+        } else {
+            TxnStatus::Aborted
+        };
+
+        let mut writes = vec![];
+        if let Some(p) = st.prepare.as_ref() {
+            for w in p.writes.iter() {
+                self.locks.insert(w.key.clone(), None);
+            }
+            if decision == TxnStatus::Committed {
+                writes = p.writes.clone();
+            }
+        }
+
+        st.set_status(decision);
+        if d.end || decision == TxnStatus::Aborted || !st.is_primary() {
+            st.done = Some(iid);
+        }
+        self.states.insert(d.txn_id.clone(), st);
+
+        Ok((decision, writes))
+    }
+
+    /// into_entrys returns the write entries to store the changes of states and locks.
+    pub fn into_entrys(self) -> Vec<WriteEntry> {
+        let mut entrys = vec![];
+
+        for (id, st) in self.states {
+            let mut v = vec![];
+            st.encode(&mut v).unwrap();
+            let k = self.sto.prepend_ns(&ReplicaStatus::Txn(id));
+            entrys.push(WriteEntry::Set(DBColumnFamily::Status, k, v));
+        }
+
+        for (key, l) in self.locks {
+            let k = self.sto.prepend_ns(&ReplicaStatus::TxnLock(key));
+            match l {
+                Some(txn_id) => {
+                    let mut v = vec![];
+                    TxnLock { txn_id }.encode(&mut v).unwrap();
+                    entrys.push(WriteEntry::Set(DBColumnFamily::Status, k, v));
+                }
+                None => entrys.push(WriteEntry::Delete(DBColumnFamily::Status, k)),
+            }
+        }
+
+        entrys
+    }
+}
+
+/// status_iter iterates over status entries of a replica whose keys start with `prefix`, with the
+/// namespace stripped.
+pub(crate) fn status_iter(
+    sto: &Storage,
+    prefix: &[u8],
+) -> impl Iterator<Item = Result<(Vec<u8>, Vec<u8>), StorageError>> {
+    let prefix = sto.prepend_ns(prefix);
+    let n = sto.prepend_ns(&b""[..]).len();
+
+    sto.get_iter(prefix.clone(), true, false, DBColumnFamily::Status)
+        .take_while(move |kv| match kv {
+            Ok((k, _)) => k.starts_with(&prefix),
+            Err(_) => true,
+        })
+        .map(move |kv| kv.map(|(k, v)| (k[n..].to_vec(), v)))
+}
+
+/// txn_iter iterates over states and locks of transactions of a replica, with the namespace
+/// stripped.
+pub(crate) fn txn_iter(
+    sto: &Storage,
+) -> impl Iterator<Item = Result<(Vec<u8>, Vec<u8>), StorageError>> {
+    let states = ReplicaStatus::Txn(vec![]).into_key();
+    let locks = ReplicaStatus::TxnLock(vec![]).into_key();
+
+    status_iter(sto, &states).chain(status_iter(sto, &locks))
+}
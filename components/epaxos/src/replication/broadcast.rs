@@ -1,39 +1,88 @@
-use tonic::Response;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+use tokio::time::timeout;
 
-use crate::qpaxos::QPaxosClient;
 use crate::qpaxos::ReplicaId;
 use crate::qpaxos::ReplicateReply;
 use crate::qpaxos::ReplicateRequest;
 use crate::replica::ReplicaPeer;
+use crate::replication::ConnPool;
+use crate::replication::FailureDetector;
+
+/// The default deadline of a single request sent by bcast_msg, including connecting.
+pub const RPC_TIMEOUT: Duration = Duration::from_millis(1000);
 
-pub async fn bcast_msg(
+/// bcast_msg sends `req` to all peers concurrently and returns a receiver of replies.
+/// Connections to peers are taken from `conns`.
+/// Peers that `fd` considers dead are skipped, and the result of every request is fed to `fd`.
+///
+/// Replies are delivered in the order they arrive. A peer that fails or does not reply within
+/// `tmout` is just skipped.
+/// The receiver yields `None` when every peer has replied or timed out, thus a caller could
+/// stop reading as soon as it has collected enough replies.
+pub fn bcast_msg(
+    conns: &Arc<ConnPool>,
+    fd: &Arc<FailureDetector>,
     peers: &[ReplicaPeer],
     req: ReplicateRequest,
-) -> Vec<(ReplicaId, Response<ReplicateReply>)> {
-    let mut rst = Vec::with_capacity(peers.len());
+    tmout: Duration,
+) -> mpsc::UnboundedReceiver<(ReplicaId, ReplicateReply)> {
+    let (tx, rx) = mpsc::unbounded_channel();
+
     for p in peers.iter() {
-        let mut client = match QPaxosClient::connect(p.addr.clone()).await {
-            Ok(c) => c,
-            // TODO just ignore the err
-            Err(e) => {
-                println!("{:?} while connect to {:?}", e, &p.addr);
-                continue;
-            }
-        };
+        if !fd.is_alive(p.replica_id) {
+            continue;
+        }
 
         let mut r = req.clone();
         r.to_replica_id = p.replica_id;
-        let repl = match client.replicate(r).await {
-            Ok(r) => r,
-            // TODO just ignore the err
-            Err(e) => {
-                println!("{:?} while request to {:?}", e, &p.addr);
-                continue;
-            }
-        };
 
-        rst.push((p.replica_id, repl));
+        let rid = p.replica_id;
+        let addr = p.addr.clone();
+        let tx = tx.clone();
+        let conns = conns.clone();
+        let fd = fd.clone();
+
+        tokio::spawn(async move {
+            match timeout(tmout, send_msg(&conns, &addr, r)).await {
+                Ok(Some(repl)) => {
+                    fd.on_success(rid);
+                    // the receiver may have been dropped because the caller has had enough
+                    // replies.
+                    let _ = tx.send((rid, repl));
+                }
+                Ok(None) => {
+                    fd.on_failure(rid);
+                }
+                Err(_) => {
+                    fd.on_failure(rid);
+                    warn!("timeout({:?}) while request to {:?}", tmout, addr);
+                }
+            }
+        });
     }
 
-    return rst;
+    rx
+}
+
+async fn send_msg(conns: &ConnPool, addr: &str, req: ReplicateRequest) -> Option<ReplicateReply> {
+    let mut client = match conns.get(addr).await {
+        Ok(c) => c,
+        Err(e) => {
+            warn!("{} while request to {:?}", e, addr);
+            return None;
+        }
+    };
+
+    match client.replicate(req).await {
+        Ok(r) => Some(r.into_inner()),
+        Err(e) => {
+            warn!("{:?} while request to {:?}", e, addr);
+            // the channel may be broken, reconnect next time.
+            conns.reset(addr);
+            None
+        }
+    }
 }
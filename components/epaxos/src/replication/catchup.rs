@@ -0,0 +1,66 @@
+use crate::qpaxos::ProtocolError;
+use crate::qpaxos::SnapshotRequest;
+use crate::replica::Replica;
+use crate::replica::ReplicaPeer;
+use crate::replication::ReplicationError;
+use crate::replication::RpcHandlerError;
+
+/// catch_up installs a snapshot from `peer` on a replica that is too far behind to catch up by
+/// replaying instances, e.g., a new replica, or one that has been down for a long time, whose
+/// missing instances are deleted by gc on its peers.
+///
+/// Chunks of the snapshot are staged as they are received, the snapshot is never held in memory
+/// as a whole. The replica keeps executing until the staged snapshot is swapped in. See
+/// `Replica::start_install`.
+pub async fn catch_up(r: &Replica, peer: &ReplicaPeer) -> Result<(), ReplicationError> {
+    let mut client = r.conns.get(&peer.addr).await?;
+
+    let req = SnapshotRequest {
+        from_replica_id: r.replica_id,
+        to_replica_id: peer.replica_id,
+    };
+
+    let mut stream = client.snapshot(req).await?.into_inner();
+
+    // the first chunk carries the exec status.
+    let first = stream
+        .message()
+        .await?
+        .ok_or_else(|| RpcHandlerError::from(ProtocolError::LackOf("executed".into())))?;
+
+    if let Some(e) = first.err {
+        return Err(RpcHandlerError::RemoteError(e).into());
+    }
+
+    let executed = first
+        .executed
+        .ok_or_else(|| RpcHandlerError::from(ProtocolError::LackOf("executed".into())))?;
+
+    let mut inst = r.start_install(&executed)?;
+    inst.add(&first.records)?;
+    let mut n = first.records.len();
+
+    while let Some(chunk) = stream.message().await? {
+        if let Some(e) = chunk.err {
+            return Err(RpcHandlerError::RemoteError(e).into());
+        }
+
+        inst.add(&chunk.records)?;
+        n += chunk.records.len();
+    }
+
+    if !inst.finish().await? {
+        info!(
+            "discarded snapshot from {} on {}: executed: {}, replica executed more",
+            peer.replica_id, r.replica_id, executed
+        );
+        return Ok(());
+    }
+
+    info!(
+        "installed snapshot from {} on {}: executed: {}, {} records",
+        peer.replica_id, r.replica_id, executed, n
+    );
+
+    Ok(())
+}
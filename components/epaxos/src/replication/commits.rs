@@ -0,0 +1,233 @@
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use prost::Message;
+
+use storage::DBColumnFamily;
+use storage::RawKV;
+use storage::Storage;
+use storage::StorageError;
+use storage::WithNameSpace;
+use storage::WriteEntry;
+
+use crate::qpaxos::commit_pending_prefix;
+use crate::qpaxos::Instance;
+use crate::qpaxos::InstanceId;
+use crate::qpaxos::MakeRequest;
+use crate::qpaxos::ReplicaId;
+use crate::qpaxos::ReplicaStatus;
+use crate::replica::status_iter;
+use crate::replica::Replica;
+use crate::replication::backoff;
+use crate::replication::bcast_msg;
+use crate::replication::RPC_TIMEOUT;
+use crate::StorageAPI;
+
+/// The interval to check and resend undelivered commits.
+pub const COMMIT_RETRY_INTERVAL: Duration = Duration::from_millis(200);
+
+/// The max number of committed instances sent to a peer in one request.
+pub const MAX_COMMITS_PER_REQ: usize = 64;
+
+/// PeerCommits tracks commits not yet delivered to a peer.
+#[derive(Debug, Default)]
+struct PeerCommits {
+    pending: BTreeSet<InstanceId>,
+
+    /// number of consecutive failed deliveries.
+    fails: u32,
+
+    /// no delivery is attempted before this time.
+    retry_at: Option<Instant>,
+}
+
+/// CommitTracker tracks, for every peer, the committed instances that the peer has not yet
+/// acknowledged.
+///
+/// Pending commits are persisted under `ReplicaStatus::CommitPending`, one key per peer and
+/// instance, thus they are resent after restart and a commit costs one write per peer, no matter
+/// how many commits are pending. A pending commit is removed only when a peer replied a request
+/// carrying it.
+pub struct CommitTracker {
+    storage: Storage,
+    peers: Mutex<HashMap<ReplicaId, PeerCommits>>,
+}
+
+impl CommitTracker {
+    /// new creates a CommitTracker for `peers` and loads pending commits from `storage`.
+    pub fn new(storage: Storage, peers: &[ReplicaId]) -> Result<Self, StorageError> {
+        let mut ps = HashMap::new();
+        for rid in peers.iter() {
+            let mut pc = PeerCommits::default();
+            for kv in status_iter(&storage, &commit_pending_prefix(*rid)) {
+                let (_, v) = kv?;
+                pc.pending.insert(InstanceId::decode(v.as_slice())?);
+            }
+            ps.insert(*rid, pc);
+        }
+
+        Ok(Self {
+            storage,
+            peers: Mutex::new(ps),
+        })
+    }
+
+    /// add_peer starts tracking commits of a peer added to the group.
+    /// Commits added before it are not delivered to the peer.
+    pub fn add_peer(&self, rid: ReplicaId) {
+        let mut peers = self.peers.lock().unwrap();
+        peers.entry(rid).or_default();
+    }
+
+    /// add adds a committed instance to be delivered to every peer.
+    pub fn add(&self, iid: InstanceId) -> Result<(), StorageError> {
+        let mut peers = self.peers.lock().unwrap();
+
+        let mut v = vec![];
+        iid.encode(&mut v).unwrap();
+
+        let mut entrys = vec![];
+        for (rid, pc) in peers.iter_mut() {
+            if pc.pending.insert(iid) {
+                let k = self
+                    .storage
+                    .prepend_ns(&ReplicaStatus::CommitPending(*rid, iid));
+                entrys.push(WriteEntry::Set(DBColumnFamily::Status, k, v.clone()));
+            }
+        }
+
+        if entrys.len() > 0 {
+            self.storage.write_batch(&entrys)?;
+        }
+        Ok(())
+    }
+
+    /// ack removes commits that are delivered to a peer and resets its backoff.
+    pub fn ack(&self, rid: ReplicaId, iids: &[InstanceId]) -> Result<(), StorageError> {
+        let mut peers = self.peers.lock().unwrap();
+        let pc = match peers.get_mut(&rid) {
+            Some(pc) => pc,
+            None => return Ok(()),
+        };
+
+        pc.fails = 0;
+        pc.retry_at = None;
+
+        let mut entrys = vec![];
+        for iid in iids.iter() {
+            if pc.pending.remove(iid) {
+                let k = self
+                    .storage
+                    .prepend_ns(&ReplicaStatus::CommitPending(rid, *iid));
+                entrys.push(WriteEntry::Delete(DBColumnFamily::Status, k));
+            }
+        }
+
+        if entrys.len() > 0 {
+            self.storage.write_batch(&entrys)?;
+        }
+        Ok(())
+    }
+
+    /// on_failure records a failed delivery to a peer. The next delivery to it is delayed.
+    pub fn on_failure(&self, rid: ReplicaId) {
+        let mut peers = self.peers.lock().unwrap();
+        if let Some(pc) = peers.get_mut(&rid) {
+            pc.retry_at = Some(Instant::now() + backoff(pc.fails));
+            pc.fails += 1;
+        }
+    }
+
+    /// pending returns at most `limit` undelivered commits of a peer.
+    pub fn pending(&self, rid: ReplicaId, limit: usize) -> Vec<InstanceId> {
+        let peers = self.peers.lock().unwrap();
+        match peers.get(&rid) {
+            Some(pc) => pc.pending.iter().take(limit).cloned().collect(),
+            None => vec![],
+        }
+    }
+
+    /// piggyback returns at most `limit` commits that are undelivered to any peer.
+    /// It is used to piggyback commits on a request sent to all peers.
+    pub fn piggyback(&self, limit: usize) -> Vec<InstanceId> {
+        let peers = self.peers.lock().unwrap();
+        let mut iids = BTreeSet::new();
+        for pc in peers.values() {
+            iids.extend(pc.pending.iter().take(limit));
+        }
+        iids.into_iter().take(limit).collect()
+    }
+
+    /// is_ready returns true if the peer has undelivered commits and is not backing off.
+    pub fn is_ready(&self, rid: ReplicaId) -> bool {
+        let peers = self.peers.lock().unwrap();
+        match peers.get(&rid) {
+            Some(pc) => {
+                if pc.pending.len() == 0 {
+                    return false;
+                }
+                match pc.retry_at {
+                    Some(t) => Instant::now() >= t,
+                    None => true,
+                }
+            }
+            None => false,
+        }
+    }
+}
+
+/// load_committed loads committed instances from storage.
+/// Instances not found or not committed are ignored.
+pub fn load_committed(r: &Replica, iids: &[InstanceId]) -> Result<Vec<Instance>, StorageError> {
+    let mut insts = Vec::with_capacity(iids.len());
+    for iid in iids.iter() {
+        if let Some(inst) = r.storage.get_instance(iid)? {
+            if inst.committed {
+                insts.push(inst);
+            }
+        }
+    }
+    Ok(insts)
+}
+
+/// deliver_commits sends undelivered commits to every peer and learner that is not backing off,
+/// and waits for the replies.
+///
+/// Commits to a peer are sent in one request: the first one as a Commit and the others
+/// piggybacked.
+pub async fn deliver_commits(r: &Replica) -> Result<(), StorageError> {
+    let mut rxs = vec![];
+
+    for p in r.get_peers().iter().chain(r.get_learners().iter()) {
+        if !r.commits.is_ready(p.replica_id) {
+            continue;
+        }
+
+        let iids = r.commits.pending(p.replica_id, MAX_COMMITS_PER_REQ);
+        let mut insts = load_committed(r, &iids)?;
+        if insts.len() == 0 {
+            // nothing can be sent, they were lost.
+            r.commits.ack(p.replica_id, &iids)?;
+            continue;
+        }
+
+        let first = insts.remove(0);
+        let mut req = MakeRequest::commit(p.replica_id, &first);
+        req.committed = insts;
+
+        let rx = bcast_msg(&r.conns, &r.detector, &[p.clone()], req, RPC_TIMEOUT);
+        rxs.push((p.replica_id, iids, rx));
+    }
+
+    for (rid, iids, mut rx) in rxs {
+        match rx.recv().await {
+            Some((_, repl)) if repl.err.is_none() => r.commits.ack(rid, &iids)?,
+            _ => r.commits.on_failure(rid),
+        }
+    }
+
+    Ok(())
+}
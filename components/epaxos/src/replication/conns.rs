@@ -0,0 +1,109 @@
+use std::cmp::min;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use tonic::transport::Channel;
+
+use crate::qpaxos::QPaxosClient;
+use crate::replication::ConnError;
+
+/// The backoff after the first failed connect. It doubles on every following failure.
+pub const CONN_BACKOFF_MIN: Duration = Duration::from_millis(50);
+
+/// The max backoff between two connects to a peer.
+pub const CONN_BACKOFF_MAX: Duration = Duration::from_millis(3000);
+
+/// PeerConn is the cached connection to a peer, or the state of reconnecting to it.
+#[derive(Debug, Default)]
+struct PeerConn {
+    client: Option<QPaxosClient<Channel>>,
+
+    /// number of consecutive failures.
+    fails: u32,
+
+    /// no connect is attempted before this time.
+    retry_at: Option<Instant>,
+}
+
+/// ConnPool caches a long-lived connection for every peer address.
+///
+/// A `QPaxosClient` is cheap to clone and all clones share one underlying HTTP/2 channel.
+/// When a channel breaks, the user calls `reset()` and the next `get()` reconnects, with an
+/// exponential backoff if connecting keeps failing.
+#[derive(Debug, Default)]
+pub struct ConnPool {
+    conns: Mutex<HashMap<String, PeerConn>>,
+}
+
+impl ConnPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// get returns a cached client to `addr`, or connects to it if there is no cached one.
+    pub async fn get(&self, addr: &str) -> Result<QPaxosClient<Channel>, ConnError> {
+        {
+            let conns = self.conns.lock().unwrap();
+            if let Some(pc) = conns.get(addr) {
+                if let Some(ref c) = pc.client {
+                    return Ok(c.clone());
+                }
+
+                if let Some(t) = pc.retry_at {
+                    let now = Instant::now();
+                    if now < t {
+                        return Err(ConnError::Backoff(addr.into(), t - now));
+                    }
+                }
+            }
+        }
+
+        // Do not hold the lock when connecting. Concurrent connecting to one peer is allowed and
+        // the last one wins.
+        let rst = QPaxosClient::connect(addr.to_string()).await;
+
+        let mut conns = self.conns.lock().unwrap();
+        let pc = conns.entry(addr.into()).or_default();
+
+        match rst {
+            Ok(c) => {
+                pc.client = Some(c.clone());
+                pc.fails = 0;
+                pc.retry_at = None;
+                Ok(c)
+            }
+            Err(e) => {
+                pc.client = None;
+                pc.retry_at = Some(Instant::now() + backoff(pc.fails));
+                pc.fails += 1;
+                Err(ConnError::Connect(addr.into(), format!("{:?}", e)))
+            }
+        }
+    }
+
+    /// reset drops the cached connection to `addr` so that the next `get()` reconnects.
+    /// It should be called when a request through the connection failed.
+    pub fn reset(&self, addr: &str) {
+        let mut conns = self.conns.lock().unwrap();
+        if let Some(pc) = conns.get_mut(addr) {
+            pc.client = None;
+        }
+    }
+
+    /// is_connected returns true if there is a cached connection to `addr`.
+    pub fn is_connected(&self, addr: &str) -> bool {
+        let conns = self.conns.lock().unwrap();
+        match conns.get(addr) {
+            Some(pc) => pc.client.is_some(),
+            None => false,
+        }
+    }
+}
+
+/// backoff returns the time to wait before next connect, after `fails` consecutive failures.
+pub fn backoff(fails: u32) -> Duration {
+    let n = min(fails, 16);
+    min(CONN_BACKOFF_MIN * (1 << n), CONN_BACKOFF_MAX)
+}
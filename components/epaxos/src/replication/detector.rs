@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use tokio::time::timeout;
+
+use crate::qpaxos::InstanceIds;
+use crate::qpaxos::PingReply;
+use crate::qpaxos::PingRequest;
+use crate::qpaxos::ReplicaId;
+use crate::replica::ReplicaPeer;
+use crate::replication::ConnPool;
+
+/// A peer is considered dead after this number of consecutive failed requests.
+pub const DEAD_AFTER_FAILS: u32 = 3;
+
+/// The interval between two rounds of pings to peers.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_millis(500);
+
+/// PeerHealth is what a FailureDetector knows about a peer.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PeerHealth {
+    /// number of consecutive failed requests to the peer.
+    pub fails: u32,
+
+    /// the last time a request to the peer succeeded.
+    pub last_seen: Option<Instant>,
+
+    /// the executed instance ids the peer replied in the last ping.
+    pub executed: Option<InstanceIds>,
+
+    /// the time the last ping with `executed` replied was sent. The peer had executed at least
+    /// `executed` since then.
+    pub executed_at: Option<Instant>,
+
+    /// the gc watermark the peer replied in the last ping.
+    pub gc_watermark: Option<InstanceIds>,
+}
+
+/// FailureDetector is a heartbeat failure detector.
+/// It is fed with the result of every request sent to a peer, e.g., replication requests and
+/// periodic pings, and considers a peer dead after `dead_after` consecutive failures.
+///
+/// A peer that has never been contacted is considered alive.
+#[derive(Debug)]
+pub struct FailureDetector {
+    dead_after: u32,
+    peers: Mutex<HashMap<ReplicaId, PeerHealth>>,
+}
+
+impl Default for FailureDetector {
+    fn default() -> Self {
+        Self::new(DEAD_AFTER_FAILS)
+    }
+}
+
+impl FailureDetector {
+    pub fn new(dead_after: u32) -> Self {
+        Self {
+            dead_after,
+            peers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// on_success records a successful request to a peer.
+    pub fn on_success(&self, rid: ReplicaId) {
+        let mut peers = self.peers.lock().unwrap();
+        let h = peers.entry(rid).or_default();
+        h.fails = 0;
+        h.last_seen = Some(Instant::now());
+    }
+
+    /// on_failure records a failed or timed out request to a peer.
+    pub fn on_failure(&self, rid: ReplicaId) {
+        let mut peers = self.peers.lock().unwrap();
+        let h = peers.entry(rid).or_default();
+        h.fails += 1;
+    }
+
+    /// set_executed records the executed instance ids reported by a peer.
+    pub fn set_executed(&self, rid: ReplicaId, executed: InstanceIds) {
+        let mut peers = self.peers.lock().unwrap();
+        let h = peers.entry(rid).or_default();
+        h.executed = Some(executed);
+    }
+
+    /// set_executed_at records the time the request `executed` is replied to was sent.
+    pub fn set_executed_at(&self, rid: ReplicaId, at: Instant) {
+        let mut peers = self.peers.lock().unwrap();
+        let h = peers.entry(rid).or_default();
+        h.executed_at = Some(at);
+    }
+
+    /// set_gc_watermark records the gc watermark reported by a peer.
+    pub fn set_gc_watermark(&self, rid: ReplicaId, wm: InstanceIds) {
+        let mut peers = self.peers.lock().unwrap();
+        let h = peers.entry(rid).or_default();
+        h.gc_watermark = Some(wm);
+    }
+
+    pub fn is_alive(&self, rid: ReplicaId) -> bool {
+        self.get(rid).fails < self.dead_after
+    }
+
+    /// get returns what the detector knows about a peer.
+    pub fn get(&self, rid: ReplicaId) -> PeerHealth {
+        let peers = self.peers.lock().unwrap();
+        peers.get(&rid).cloned().unwrap_or_default()
+    }
+}
+
+/// ping_peers sends a ping to every peer, including dead ones, and feeds the results, along with
+/// the executed instance ids and the gc watermark of the peer, to `fd`.
+/// It returns after all pings finished or timed out.
+pub async fn ping_peers(
+    from_rid: ReplicaId,
+    conns: &Arc<ConnPool>,
+    fd: &Arc<FailureDetector>,
+    peers: &[ReplicaPeer],
+    tmout: Duration,
+) {
+    let mut handles = Vec::with_capacity(peers.len());
+
+    for p in peers.iter() {
+        let req = PingRequest {
+            from_replica_id: from_rid,
+            to_replica_id: p.replica_id,
+        };
+        let rid = p.replica_id;
+        let addr = p.addr.clone();
+        let conns = conns.clone();
+        let fd = fd.clone();
+
+        let h = tokio::spawn(async move {
+            let sent = Instant::now();
+            match timeout(tmout, ping(&conns, &addr, req)).await {
+                Ok(Some(reply)) => {
+                    fd.on_success(rid);
+                    if let Some(executed) = reply.executed {
+                        fd.set_executed(rid, executed);
+                        fd.set_executed_at(rid, sent);
+                    }
+                    if let Some(wm) = reply.gc_watermark {
+                        fd.set_gc_watermark(rid, wm);
+                    }
+                }
+                Ok(None) | Err(_) => fd.on_failure(rid),
+            }
+        });
+        handles.push(h);
+    }
+
+    for h in handles {
+        let _ = h.await;
+    }
+}
+
+/// ping sends a ping to `addr` and returns the reply, or None if it failed.
+pub(crate) async fn ping(conns: &ConnPool, addr: &str, req: PingRequest) -> Option<PingReply> {
+    let mut client = match conns.get(addr).await {
+        Ok(c) => c,
+        Err(_) => return None,
+    };
+
+    match client.ping(req).await {
+        Ok(r) => {
+            let reply = r.into_inner();
+            // the node is up but the replica is not there.
+            if reply.err.is_some() {
+                return None;
+            }
+            Some(reply)
+        }
+        Err(e) => {
+            warn!("{:?} while ping {:?}", e, addr);
+            conns.reset(addr);
+            None
+        }
+    }
+}
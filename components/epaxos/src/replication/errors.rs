@@ -1,12 +1,17 @@
 use crate::qpaxos::BallotNum;
 use crate::qpaxos::Direction;
 use crate::qpaxos::InstanceId;
+use crate::qpaxos::InstanceIds;
+use crate::qpaxos::InvalidRequest;
+use crate::qpaxos::Membership;
 use crate::qpaxos::ProtocolError;
 use crate::qpaxos::QError;
 use crate::qpaxos::ReplicaId;
 use crate::qpaxos::StorageFailure;
 use crate::replica::InstanceStatus;
 use crate::replica::ReplicaError;
+use crate::RangeLookupError;
+use std::time::Duration;
 use storage::StorageError;
 
 quick_error! {
@@ -94,9 +99,91 @@ quick_error! {
         }
         RpcHandler(e: RpcHandlerError) {
             from(e: RpcHandlerError) -> (e)
+            from(e: ReplicaError) -> (e.into())
         }
         Storage(e: StorageError) {
             from(e: StorageError) -> (e)
         }
+        Conn(e: ConnError) {
+            from(e: ConnError) -> (e)
+        }
+        Grpc(msg: String) {
+            from(s: tonic::Status) -> (format!("{:?}", s))
+            display("grpc error: {}", msg)
+        }
+        /// Another membership change has not finished.
+        MembershipChanging(m: Membership) {
+            display("membership is changing: {:?}", m)
+        }
+        /// A replica to promote is not a learner of the group.
+        NotLearner(rid: ReplicaId) {
+            display("replica {} is not a learner", rid)
+        }
+        /// An instance is committed but the result of executing it is lost.
+        NotExecuted(iid: InstanceId) {
+            display("{} is not executed", iid)
+        }
+        /// A range change does not fit the range or the membership of the group.
+        BadRangeChange(reason: String) {
+            display("bad range change: {}", reason)
+        }
+        /// Some peer has not yet executed instances up to the exec status.
+        Lagging(executed: InstanceIds) {
+            display("peers have not executed up to {}", executed)
+        }
+        /// The replica has not executed instances up to a read index in time.
+        ReadTimeout(index: InstanceIds) {
+            display("not executed up to read index {}", index)
+        }
+        /// The replica does not serve the key, e.g., it has been split away.
+        KeyOutOfRange(key: String) {
+            display("key {} is out of range", key)
+        }
+        /// The key is locked by a pending transaction that is not decided in time.
+        KeyLocked(key: String) {
+            display("key {} is locked by a pending transaction", key)
+        }
+        RangeLookup(e: RangeLookupError) {
+            from(e: RangeLookupError) -> (e)
+        }
+    }
+}
+
+impl Into<QError> for ReplicationError {
+    fn into(self) -> QError {
+        match self {
+            Self::RpcHandler(e) => e.into(),
+
+            Self::Storage(_) => QError {
+                sto: Some(StorageFailure {}),
+                ..Default::default()
+            },
+
+            // TODO impl
+            e => QError {
+                req: Some(InvalidRequest {
+                    field: "".into(),
+                    problem: "Replication".into(),
+                    ctx: format!("{}", e),
+                }),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+quick_error! {
+    /// ConnError is an error encountered when getting a connection to a peer from ConnPool.
+    #[derive(Debug, Eq, PartialEq)]
+    pub enum ConnError {
+        /// Failed to establish a connection.
+        Connect(addr: String, e: String) {
+            display("{} while connect to {}", e, addr)
+        }
+
+        /// The last connect failed recently, it is not retried until backoff expires.
+        Backoff(addr: String, remain: Duration) {
+            display("connect to {} is backing off, remain:{:?}", addr, remain)
+        }
     }
 }
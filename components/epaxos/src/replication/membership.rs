@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::time::Duration;
+use std::time::Instant;
+
+use tokio::sync::oneshot;
+
+use crate::qpaxos::Command;
+use crate::qpaxos::InstanceId;
+use crate::qpaxos::Membership;
+use crate::qpaxos::ReplicaId;
+use crate::replica::ExecRst;
+use crate::replica::Replica;
+use crate::replication::commit;
+use crate::replication::ping_peers;
+use crate::replication::replicate;
+use crate::replication::ReplicationError;
+use crate::replication::HEARTBEAT_INTERVAL;
+use crate::replication::RPC_TIMEOUT;
+
+/// The max time to wait for a quorum of both configs to execute the joint config.
+pub const JOINT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// change_membership changes the voters of the group `r` belongs to, to `voters`: a map of
+/// replica id to replication address.
+///
+/// It takes two steps, each of them is an instance replicated through qpaxos. The group first
+/// enters a joint config of the current voters and `voters`, in which a quorum has to be a quorum
+/// of both; then it leaves the joint config and only `voters` vote.
+/// A membership takes effect on a replica when the instance carrying it is executed. Every step
+/// waits until it is executed on `r`. Before leaving the joint config, it also waits until a quorum
+/// of both configs have executed the joint config: otherwise, replicas still using the old config
+/// and the ones using only the new config could form disjoint quorums.
+///
+/// If a previous change to the same voters did not finish, it goes on with the second step.
+pub async fn change_membership(
+    r: &Replica,
+    voters: HashMap<ReplicaId, String>,
+) -> Result<Membership, ReplicationError> {
+    let cur = r.get_membership();
+
+    if cur.is_joint() {
+        if cur.joint != voters {
+            return Err(ReplicationError::MembershipChanging(cur));
+        }
+    } else {
+        propose_and_execute(r, &Command::from(&cur.enter_joint(voters))).await?;
+    }
+
+    wait_quorum_executed(r, JOINT_TIMEOUT).await?;
+
+    let m = r.get_membership().leave_joint();
+    propose_and_execute(r, &Command::from(&m)).await?;
+
+    Ok(m)
+}
+
+/// add_learner adds learner `rid` with replication address `addr` to the group `r` belongs to.
+/// Voters do not change, nor do quorums, thus it takes one instance. Commits after it are
+/// delivered to the learner.
+///
+/// Adding a learner or a voter of the group does nothing.
+pub async fn add_learner(
+    r: &Replica,
+    rid: ReplicaId,
+    addr: String,
+) -> Result<Membership, ReplicationError> {
+    let mut m = r.get_membership();
+
+    if m.is_joint() {
+        return Err(ReplicationError::MembershipChanging(m));
+    }
+
+    if m.contains(rid) || m.is_learner(rid) {
+        return Ok(m);
+    }
+
+    m.learners.insert(rid, addr);
+    propose_and_execute(r, &Command::from(&m)).await?;
+
+    Ok(m)
+}
+
+/// promote_learner makes learner `rid` a voter in place of voter `replaced`, in one change of the
+/// voters through a joint config. See `change_membership`.
+///
+/// If a previous promotion did not finish, it goes on with it. Promoting a promoted learner does
+/// nothing.
+pub async fn promote_learner(
+    r: &Replica,
+    rid: ReplicaId,
+    replaced: ReplicaId,
+) -> Result<Membership, ReplicationError> {
+    let cur = r.get_membership();
+
+    let voters = if cur.is_joint() {
+        if !cur.joint.contains_key(&rid) || cur.joint.contains_key(&replaced) {
+            return Err(ReplicationError::MembershipChanging(cur));
+        }
+        cur.joint.clone()
+    } else {
+        if cur.voters.contains_key(&rid) && !cur.voters.contains_key(&replaced) {
+            return Ok(cur);
+        }
+
+        let addr = cur
+            .learners
+            .get(&rid)
+            .ok_or(ReplicationError::NotLearner(rid))?;
+
+        let mut voters = cur.voters.clone();
+        voters.remove(&replaced);
+        voters.insert(rid, addr.clone());
+        voters
+    };
+
+    change_membership(r, voters).await
+}
+
+/// wait_quorum_executed waits until replicas that have executed the instances `r` has executed
+/// form a quorum of the membership of `r`, a quorum of both configs if it is a joint one.
+/// Peers are pinged to report their exec status.
+async fn wait_quorum_executed(r: &Replica, timeout: Duration) -> Result<(), ReplicationError> {
+    let target = r.get_executed()?;
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        let peers = r.get_peers();
+        ping_peers(r.replica_id, &r.conns, &r.detector, &peers, RPC_TIMEOUT).await;
+
+        let mut done = HashSet::new();
+        done.insert(r.replica_id);
+
+        for p in peers.iter() {
+            let executed = match r.detector.get(p.replica_id).executed {
+                Some(v) => v,
+                None => continue,
+            };
+
+            let ok = target
+                .iter()
+                .all(|(rid, idx)| *executed.get(rid).unwrap_or(&-1) >= *idx);
+            if ok {
+                done.insert(p.replica_id);
+            }
+        }
+
+        if r.get_membership().is_quorum(&done) {
+            return Ok(());
+        }
+
+        if Instant::now() >= deadline {
+            return Err(ReplicationError::Lagging(target));
+        }
+
+        tokio::time::delay_for(HEARTBEAT_INTERVAL).await;
+    }
+}
+
+/// propose_and_execute commits an admin command and waits for it to be executed on `r`.
+/// It returns the instance id and the result of executing it.
+pub(crate) async fn propose_and_execute(
+    r: &Replica,
+    cmd: &Command,
+) -> Result<(InstanceId, ExecRst), ReplicationError> {
+    let mut st = replicate(&[cmd.clone()], r).await?;
+    st.instance.committed = true;
+
+    let iid = st.instance.instance_id.unwrap();
+
+    let (tx, rx) = oneshot::channel();
+    r.insert_tx(iid, tx).await;
+
+    commit(r, st.instance).await?;
+
+    let rst = rx.await.or(Err(ReplicationError::NotExecuted(iid)))?;
+
+    Ok((iid, rst))
+}
@@ -0,0 +1,192 @@
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::qpaxos::HandoverReply;
+use crate::qpaxos::HandoverRequest;
+use crate::qpaxos::KeyRange;
+use crate::qpaxos::Membership;
+use crate::qpaxos::MembershipRequest;
+use crate::qpaxos::ProtocolError;
+use crate::qpaxos::ReplicaId;
+use crate::replica::Replica;
+use crate::replica::ReplicaError;
+use crate::replica::ReplicaPeer;
+use crate::replication::catch_up;
+use crate::replication::ping_peers;
+use crate::replication::ConnPool;
+use crate::replication::ReplicationError;
+use crate::replication::RpcHandlerError;
+use crate::replication::HEARTBEAT_INTERVAL;
+use crate::replication::RPC_TIMEOUT;
+use crate::ServerData;
+use storage::Storage;
+
+/// The max time to wait for a learner to execute what the replica it replaces has executed.
+pub const CATCH_UP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// migrate_replica moves replica `rid` from the node it is on to the node of `sd`, as replica
+/// `to_rid`, e.g. to retire a machine. It takes four steps:
+///
+/// 1. The source replica adds `to_rid` on this node to the group as a learner. Thus this node
+///    does not need a voter of the group.
+/// 2. The learner installs a snapshot from the source, and executes the commits delivered to it,
+///    until it has executed every instance the source had. The source keeps voting.
+/// 3. The source promotes the learner in place of itself, in one change of the voters through a
+///    joint config. When it is executed, every node updates the group in its cluster config.
+/// 4. The source is released: it is removed from its node and its data is deleted.
+///
+/// The group never has fewer voters than before. If a previous migration did not finish, the
+/// steps it has done are skipped.
+///
+/// A learner can not be migrated, because it does not propose.
+pub async fn migrate_replica(
+    sd: &ServerData,
+    rid: ReplicaId,
+    to_rid: ReplicaId,
+) -> Result<Arc<Replica>, ReplicationError> {
+    let cluster = sd.get_cluster();
+    let node = cluster
+        .get_replica_node(rid)
+        .ok_or(ReplicaError::ReplicaNotFound(rid))?;
+
+    if node.node_id == sd.node_id {
+        return Err(ReplicaError::Existed {}.into());
+    }
+
+    let g = cluster.get_group(rid).unwrap();
+    if g.learners.contains_key(&rid) {
+        return Err(ReplicaError::Learner(rid).into());
+    }
+
+    // `to_rid` is new, or the learner of a previous migration.
+    if let Some(x) = cluster.get_replica(to_rid) {
+        if x.node_id != sd.node_id || cluster.get_group(to_rid) != Some(g) {
+            return Err(ReplicaError::Existed {}.into());
+        }
+    }
+
+    let src = ReplicaPeer::new(rid, format!("http://{}", node.replication), true);
+    let addr = format!("http://{}", sd.node.replication);
+
+    let m = request_membership(&sd.conns, &src, to_rid, &addr, false).await?;
+
+    let r = match sd.get_local_replica(to_rid) {
+        Some(r) => r,
+        None => {
+            let range = Some(KeyRange::from((g.range.0.as_str(), g.range.1.as_str())));
+            let sto = Storage::new(to_rid, sd.storage.clone());
+            let learner = Replica::with_config(to_rid, sto, m, range, cluster.executor)?;
+
+            // commits delivered before the snapshot is installed are kept.
+            sd.add_local_replica(learner)
+        }
+    };
+
+    if r.is_learner() {
+        catch_up_learner(&r, &src).await?;
+        info!("learner {} caught up with {}", to_rid, rid);
+    }
+
+    request_membership(&sd.conns, &src, to_rid, &addr, true).await?;
+    info!("learner {} is promoted in place of {}", to_rid, rid);
+
+    handover(&r, &src, &sd.node_id, true).await?;
+
+    info!("replica {} migrated from {} as {}", rid, src.addr, to_rid);
+    Ok(r)
+}
+
+/// catch_up_learner installs a snapshot from `peer` on learner `r`, and waits until `r` has
+/// executed every instance `peer` had executed when it is pinged. Instances executed after the
+/// snapshot are committed after the learner is added, thus they are delivered to it.
+async fn catch_up_learner(r: &Replica, peer: &ReplicaPeer) -> Result<(), ReplicationError> {
+    catch_up(r, peer).await?;
+
+    let deadline = Instant::now() + CATCH_UP_TIMEOUT;
+
+    loop {
+        let peers = vec![peer.clone()];
+        ping_peers(r.replica_id, &r.conns, &r.detector, &peers, RPC_TIMEOUT).await;
+
+        if let Some(target) = r.detector.get(peer.replica_id).executed {
+            let executed = r.get_executed()?;
+            let caught_up = target
+                .iter()
+                .all(|(rid, idx)| *idx <= *executed.get(rid).unwrap_or(&-1));
+            if caught_up {
+                return Ok(());
+            }
+
+            if Instant::now() >= deadline {
+                return Err(ReplicationError::Lagging(target));
+            }
+        }
+
+        tokio::time::delay_for(HEARTBEAT_INTERVAL).await;
+    }
+}
+
+/// request_membership asks voter `peer` to add learner `rid` at `addr` to its group, or to
+/// promote the learner in place of itself if `promote` is true.
+async fn request_membership(
+    conns: &ConnPool,
+    peer: &ReplicaPeer,
+    rid: ReplicaId,
+    addr: &str,
+    promote: bool,
+) -> Result<Membership, ReplicationError> {
+    let mut client = conns.get(&peer.addr).await?;
+
+    let req = MembershipRequest {
+        to_replica_id: peer.replica_id,
+        learner_id: rid,
+        learner_addr: addr.into(),
+        promote,
+    };
+
+    let reply = match client.membership(req).await {
+        Ok(v) => v.into_inner(),
+        Err(e) => {
+            conns.reset(&peer.addr);
+            return Err(e.into());
+        }
+    };
+
+    if let Some(e) = reply.err {
+        return Err(RpcHandlerError::RemoteError(e).into());
+    }
+
+    let m = reply
+        .membership
+        .ok_or_else(|| RpcHandlerError::from(ProtocolError::LackOf("membership".into())))?;
+
+    Ok(m)
+}
+
+/// handover asks the source replica `peer` to hand over to its copy `r` on node `to_node`, or to
+/// release itself if `release` is true, e.g. after `r` is promoted in place of it.
+/// Instances after the exec status of `r` are handed over.
+pub async fn handover(
+    r: &Replica,
+    peer: &ReplicaPeer,
+    to_node: &str,
+    release: bool,
+) -> Result<HandoverReply, ReplicationError> {
+    let mut client = r.conns.get(&peer.addr).await?;
+
+    let req = HandoverRequest {
+        from_replica_id: r.replica_id,
+        to_replica_id: peer.replica_id,
+        to_node: to_node.into(),
+        release,
+        after: Some(r.get_executed()?),
+    };
+
+    let reply = client.handover(req).await?.into_inner();
+    if let Some(e) = reply.err {
+        return Err(RpcHandlerError::RemoteError(e).into());
+    }
+
+    Ok(reply)
+}
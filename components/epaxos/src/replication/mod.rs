@@ -10,8 +10,53 @@ pub use replication::*;
 mod broadcast;
 pub use broadcast::*;
 
+mod conns;
+pub use conns::*;
+
+mod detector;
+pub use detector::*;
+
+mod commits;
+pub use commits::*;
+
+mod recovery;
+pub use recovery::*;
+
+mod catchup;
+pub use catchup::*;
+
+mod membership;
+pub use membership::*;
+
+mod range;
+pub use range::*;
+
+mod migrate;
+pub use migrate::*;
+
+mod read;
+pub use read::*;
+
+mod txn;
+pub use txn::*;
+
 #[cfg(test)]
 mod test_hdlreply;
 
 #[cfg(test)]
 mod test_broadcast;
+
+#[cfg(test)]
+mod test_conns;
+
+#[cfg(test)]
+mod test_detector;
+
+#[cfg(test)]
+mod test_commits;
+
+#[cfg(test)]
+mod test_recovery;
+
+#[cfg(test)]
+mod test_read;
@@ -0,0 +1,209 @@
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::qpaxos::Command;
+use crate::qpaxos::KeyRange;
+use crate::qpaxos::RangeChange;
+use crate::qpaxos::ReplicaId;
+use crate::replica::Replica;
+use crate::replication::propose_and_execute;
+use crate::replication::ReplicationError;
+use crate::replication::HEARTBEAT_INTERVAL;
+
+/// The max time to wait for every replica of a group to execute a retire.
+pub const RETIRE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// split_range splits the range of the group `r` belongs to at key `at`: the group keeps
+/// `[start, at)` and a new group serves `[at, end)`.
+/// `pairs` maps every replica of the group, including learners, to the replica of the new group
+/// on the same node.
+///
+/// The split is an instance replicated through qpaxos. It takes effect on a replica when it is
+/// executed: records in `[at, end)` are moved to the paired replica, and the server adds the
+/// new group to the cluster config and creates the paired replica. It waits until it is executed
+/// on `r`.
+///
+/// A write to the split range that is ordered after the split is not applied on this group. Its
+/// client receives an error instead of OK, because a write is replied after it is executed, see
+/// `Replica::insert_tx`.
+///
+/// It fails if a transaction is pending in the group, see `check_no_pending_txn`.
+pub async fn split_range(
+    r: &Replica,
+    at: &str,
+    pairs: HashMap<ReplicaId, ReplicaId>,
+) -> Result<RangeChange, ReplicationError> {
+    let m = r.get_membership();
+    if m.is_joint() {
+        return Err(ReplicationError::MembershipChanging(m));
+    }
+
+    let range = serving_range(r)?;
+    if at <= range.start.as_str() || at >= range.end.as_str() {
+        return Err(ReplicationError::BadRangeChange(format!(
+            "{} is not in range {:?}",
+            at, range
+        )));
+    }
+
+    let rids: Vec<_> = m.replica_ids().into_iter().chain(m.learner_ids()).collect();
+    check_pairs(&rids, &pairs)?;
+    check_no_pending_txn(r)?;
+
+    let rc = RangeChange {
+        range: Some((range.start.as_str(), at).into()),
+        split: Some((at, range.end.as_str()).into()),
+        pairs,
+    };
+    propose_and_execute(r, &Command::from(&rc)).await?;
+
+    Ok(rc)
+}
+
+/// merge_range merges the group `h` belongs to into the group `g` belongs to. The range of `h`
+/// must follow the one of `g`.
+/// `pairs` maps every replica of the group of `g` to the replica of the group of `h` on the same
+/// node.
+///
+/// It takes two steps, each of them is an instance replicated through qpaxos. The group of `h`
+/// first retires: it serves an empty range since then. After the retire is executed on every
+/// replica of it, the group of `g` merges: every replica moves records of the paired replica to
+/// itself, and the server removes the retired group from the cluster config.
+/// Every step waits until it is executed on `g` or `h`.
+///
+/// If a previous merge retired `h` but did not finish, it goes on with the second step.
+///
+/// Groups with learners can not be merged: a learner may not have executed the retire when the
+/// merge is executed. Neither can groups with a pending transaction.
+pub async fn merge_range(
+    g: &Replica,
+    h: &Replica,
+    pairs: HashMap<ReplicaId, ReplicaId>,
+) -> Result<RangeChange, ReplicationError> {
+    for x in [g, h].iter() {
+        let m = x.get_membership();
+        if m.is_joint() {
+            return Err(ReplicationError::MembershipChanging(m));
+        }
+        if m.learners.len() > 0 {
+            return Err(ReplicationError::BadRangeChange(format!(
+                "group of replica {} has learners {:?}",
+                x.replica_id,
+                m.learner_ids()
+            )));
+        }
+    }
+
+    check_pairs(&g.get_membership().replica_ids(), &pairs)?;
+    check_no_pending_txn(g)?;
+    check_no_pending_txn(h)?;
+
+    let hrids: BTreeSet<_> = h.get_group_replica_ids().into_iter().collect();
+    let paired: BTreeSet<_> = pairs.values().cloned().collect();
+    if hrids != paired {
+        return Err(ReplicationError::BadRangeChange(format!(
+            "paired replicas {:?} are not the group {:?}",
+            paired, hrids
+        )));
+    }
+
+    let grange = serving_range(g)?;
+    let mut hrange = serving_range(h)?;
+
+    if !hrange.is_empty() {
+        if grange.end != hrange.start {
+            return Err(ReplicationError::BadRangeChange(format!(
+                "{:?} does not follow {:?}",
+                hrange, grange
+            )));
+        }
+
+        let retire = RangeChange {
+            range: Some((hrange.end.as_str(), hrange.end.as_str()).into()),
+            ..Default::default()
+        };
+        propose_and_execute(h, &Command::from(&retire)).await?;
+        hrange = serving_range(h)?;
+    }
+
+    wait_peers_executed(h, RETIRE_TIMEOUT).await?;
+
+    let rc = RangeChange {
+        range: Some((grange.start.as_str(), hrange.end.as_str()).into()),
+        split: None,
+        pairs,
+    };
+    propose_and_execute(g, &Command::from(&rc)).await?;
+
+    Ok(rc)
+}
+
+/// check_no_pending_txn returns an error if a transaction is pending in the group of `r`: its
+/// locks and writes are not moved with records.
+/// A transaction prepared after the check but before the range change is executed, is not
+/// detected.
+fn check_no_pending_txn(r: &Replica) -> Result<(), ReplicationError> {
+    let pending = r.pending_txns()?;
+    if pending.len() > 0 {
+        return Err(ReplicationError::BadRangeChange(format!(
+            "{} transactions are pending on replica {}",
+            pending.len(),
+            r.replica_id
+        )));
+    }
+    Ok(())
+}
+
+/// serving_range returns the range `r` serves, or an error if it serves every key.
+fn serving_range(r: &Replica) -> Result<KeyRange, ReplicationError> {
+    r.get_range().ok_or_else(|| {
+        ReplicationError::BadRangeChange(format!("replica {} has no range", r.replica_id))
+    })
+}
+
+/// check_pairs checks that every replica of a group has a paired replica.
+fn check_pairs(
+    rids: &[ReplicaId],
+    pairs: &HashMap<ReplicaId, ReplicaId>,
+) -> Result<(), ReplicationError> {
+    for rid in rids.iter() {
+        if !pairs.contains_key(rid) {
+            return Err(ReplicationError::BadRangeChange(format!(
+                "replica {} has no paired replica",
+                rid
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// wait_peers_executed waits until every peer of `r` reports it has executed the instances `r`
+/// has executed. Peers report the exec status in reply to a ping from the failure detector.
+async fn wait_peers_executed(r: &Replica, timeout: Duration) -> Result<(), ReplicationError> {
+    let target = r.get_executed()?;
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        let done = r.get_peers().iter().all(|p| {
+            let executed = match r.detector.get(p.replica_id).executed {
+                Some(v) => v,
+                None => return false,
+            };
+            target
+                .iter()
+                .all(|(rid, idx)| *executed.get(rid).unwrap_or(&-1) >= *idx)
+        });
+
+        if done {
+            return Ok(());
+        }
+
+        if Instant::now() >= deadline {
+            return Err(ReplicationError::Lagging(target));
+        }
+
+        tokio::time::delay_for(HEARTBEAT_INTERVAL).await;
+    }
+}
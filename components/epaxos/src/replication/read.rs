@@ -0,0 +1,230 @@
+use std::collections::HashSet;
+use std::time::Duration;
+use std::time::Instant;
+
+use tokio::sync::mpsc;
+use tokio::sync::watch;
+use tokio::time::timeout;
+
+use crate::qpaxos::InstanceIds;
+use crate::qpaxos::PingRequest;
+use crate::qpaxos::Record;
+use crate::replica::InstanceStatus;
+use crate::replica::Replica;
+use crate::replication::ping;
+use crate::replication::ReplicationError;
+use crate::replication::RPC_TIMEOUT;
+use crate::StorageAPI;
+
+/// The max time to wait for a replica to execute up to a read index.
+pub const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// read_linearizable reads `key` from the executed state of `r`, without replicating the read.
+/// Every write completed before the read is visible, see `sync_read_index`. Writes are still
+/// ordered by qpaxos.
+/// A key locked by a pending transaction is read after the transaction is decided, see
+/// `wait_unlocked`.
+pub async fn read_linearizable(
+    r: &Replica,
+    key: &[u8],
+) -> Result<Option<Record>, ReplicationError> {
+    sync_read_index(r).await?;
+    wait_unlocked(r, key, READ_TIMEOUT).await?;
+    read_local(r, key)
+}
+
+/// read_local reads `key` from the executed state of `r`, without contacting any other replica.
+/// The value may be stale.
+pub fn read_local(r: &Replica, key: &[u8]) -> Result<Option<Record>, ReplicationError> {
+    // the key may have been split away.
+    if let Some(range) = r.get_range() {
+        if !range.contains(key) {
+            return Err(ReplicationError::KeyOutOfRange(
+                String::from_utf8_lossy(key).into(),
+            ));
+        }
+    }
+
+    Ok(r.storage.get_kv(key)?)
+}
+
+/// read_local_executed is `read_local` that also returns the exec status the value is read at.
+/// Executing is blocked while reading.
+pub async fn read_local_executed(
+    r: &Replica,
+    key: &[u8],
+) -> Result<(Option<Record>, InstanceIds), ReplicationError> {
+    let _guard = r.exec_lock.lock().await;
+
+    let executed = r.get_executed()?;
+    let v = read_local(r, key)?;
+    Ok((v, executed))
+}
+
+/// sync_read_index collects a read index from a quorum of voters, see `read_index`, and waits
+/// until `r` has executed up to it. Every write completed before it is called is executed on `r`
+/// then.
+pub async fn sync_read_index(r: &Replica) -> Result<InstanceIds, ReplicationError> {
+    let index = read_index(r).await?;
+    wait_executed(r, &index, READ_TIMEOUT).await?;
+    Ok(index)
+}
+
+/// read_index returns the greatest max instance id of every leader, among the ones stored on a
+/// quorum of voters of the group `r` belongs to.
+///
+/// A completed write is stored on a quorum, which intersects the quorum replied. Thus it is not
+/// greater than the read index.
+/// `r` counts if it is a voter. Other voters are asked with a ping.
+pub async fn read_index(r: &Replica) -> Result<InstanceIds, ReplicationError> {
+    let m = r.get_membership();
+    let grids = m.replica_ids();
+
+    let mut index = InstanceIds::default();
+    let mut replied = HashSet::new();
+
+    let merge = |index: &mut InstanceIds, maxs: &InstanceIds| {
+        for rid in grids.iter() {
+            let idx = *maxs.get(rid).unwrap_or(&-1);
+            let x = index.entry(*rid).or_insert(idx);
+            if idx > *x {
+                *x = idx;
+            }
+        }
+    };
+
+    if m.contains(r.replica_id) {
+        let maxs = r.get_max_instance_ids(&grids);
+        merge(&mut index, &InstanceIds::from(&maxs[..]));
+        replied.insert(r.replica_id);
+    }
+
+    if m.is_quorum(&replied) {
+        return Ok(index);
+    }
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+
+    for p in r.get_peers() {
+        if !p.alive {
+            continue;
+        }
+
+        let req = PingRequest {
+            from_replica_id: r.replica_id,
+            to_replica_id: p.replica_id,
+        };
+        let conns = r.conns.clone();
+        let fd = r.detector.clone();
+        let tx = tx.clone();
+
+        tokio::spawn(async move {
+            match timeout(RPC_TIMEOUT, ping(&conns, &p.addr, req)).await {
+                Ok(Some(reply)) => {
+                    fd.on_success(p.replica_id);
+                    // the receiver is dropped once a quorum replied.
+                    let _ = tx.send((p.replica_id, reply));
+                }
+                Ok(None) | Err(_) => fd.on_failure(p.replica_id),
+            }
+        });
+    }
+
+    // the receiver yields None when every ping finished.
+    drop(tx);
+
+    while let Some((rid, reply)) = rx.recv().await {
+        let maxs = match reply.max_instance_ids {
+            Some(v) => v,
+            None => continue,
+        };
+
+        merge(&mut index, &maxs);
+        replied.insert(rid);
+
+        if m.is_quorum(&replied) {
+            return Ok(index);
+        }
+    }
+
+    Err(ReplicationError::NotEnoughQuorum(
+        InstanceStatus::Na,
+        m.quorum(),
+        replied.len() as i32,
+    ))
+}
+
+/// wait_executed waits until `r` has executed every instance up to `index`.
+/// Instances not committed in time are recovered by the executor.
+/// It is woken up when the exec status of `r` is updated, see `Replica::exec_tx`.
+pub async fn wait_executed(
+    r: &Replica,
+    index: &InstanceIds,
+    tmout: Duration,
+) -> Result<(), ReplicationError> {
+    // subscribe before checking, not to miss an update.
+    let mut rx = r.exec_rx.clone();
+
+    let done = wait_until(&mut rx, tmout, || {
+        let executed = r.get_executed()?;
+        Ok(index
+            .iter()
+            .all(|(rid, idx)| *executed.get(rid).unwrap_or(&-1) >= *idx))
+    })
+    .await?;
+
+    if !done {
+        return Err(ReplicationError::ReadTimeout(index.clone()));
+    }
+    Ok(())
+}
+
+/// wait_unlocked waits until `key` is not locked by a pending transaction on `r`, thus a read does
+/// not miss a transaction that is being committed.
+/// A lock is released when the decision of the transaction is executed.
+pub async fn wait_unlocked(
+    r: &Replica,
+    key: &[u8],
+    tmout: Duration,
+) -> Result<(), ReplicationError> {
+    let mut rx = r.exec_rx.clone();
+
+    let done = wait_until(&mut rx, tmout, || {
+        Ok(r.storage.get_txn_lock(key)?.is_none())
+    })
+    .await?;
+
+    if !done {
+        return Err(ReplicationError::KeyLocked(
+            String::from_utf8_lossy(key).into(),
+        ));
+    }
+    Ok(())
+}
+
+/// wait_until checks `cond` every time `rx` is notified, until it is true or `tmout` is over.
+/// It returns false if it times out.
+async fn wait_until<F>(
+    rx: &mut watch::Receiver<()>,
+    tmout: Duration,
+    cond: F,
+) -> Result<bool, ReplicationError>
+where
+    F: Fn() -> Result<bool, ReplicationError>,
+{
+    let deadline = Instant::now() + tmout;
+
+    loop {
+        if cond()? {
+            return Ok(true);
+        }
+
+        let remain = deadline.saturating_duration_since(Instant::now());
+        match timeout(remain, rx.recv()).await {
+            Ok(Some(_)) => {}
+            // the replica is dropped.
+            Ok(None) => return Ok(false),
+            Err(_) => return cond(),
+        }
+    }
+}
@@ -0,0 +1,248 @@
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+
+use crate::qpaxos::BallotNum;
+use crate::qpaxos::Command;
+use crate::qpaxos::Dep;
+use crate::qpaxos::Instance;
+use crate::qpaxos::InstanceId;
+use crate::qpaxos::MakeRequest;
+use crate::qpaxos::OpCode;
+use crate::qpaxos::PrepareReply;
+use crate::qpaxos::ProtocolError;
+use crate::qpaxos::ReplicaId;
+use crate::qpaxos::ReplicateReply;
+use crate::qpaxos::TryInto;
+use crate::replica::InstanceStatus;
+use crate::replica::Replica;
+use crate::replica::ReplicationStatus;
+use crate::replication::bcast_msg;
+use crate::replication::check_repl_common;
+use crate::replication::deliver_commits;
+use crate::replication::handle_accept_reply;
+use crate::replication::ReplicationError;
+use crate::replication::RpcHandlerError;
+use crate::replication::RPC_TIMEOUT;
+
+/// recover takes over an instance whose leader failed to commit it in time, and commits a safe
+/// value of it.
+///
+/// It Prepare-s with a higher ballot to read what a quorum knows about the instance, chooses a
+/// value, Accept-s it, then broadcasts Commit.
+/// If no replica in the quorum has seen the instance, a NoOp is committed in place of it.
+///
+/// See: https://github.com/openacid/celeritasdb/wiki/replication-algo#def-recovery
+pub async fn recover(r: &Replica, iid: InstanceId) -> Result<Instance, ReplicationError> {
+    let inst = r.get_instance(iid)?;
+    if inst.committed {
+        return Ok(inst);
+    }
+
+    let membership = r.get_membership();
+    let grids = &membership.replica_ids();
+    let q = membership.quorum();
+
+    // Recovery-1: take leadership.
+    // Prepare with what this replica knows. Without deps, acceptors just reply what they have.
+    let mut pinst = inst;
+    pinst.ballot = Some(next_ballot(&pinst, r.get_last_ballot(), r.replica_id));
+
+    let n = pinst.deps.as_ref().map_or(0, |d| d.len());
+    let deps_committed = vec![false; n];
+    let req = MakeRequest::prepare(r.replica_id, &pinst, &deps_committed);
+
+    let mut prepared = Vec::with_capacity(grids.len());
+    let mut replied = HashSet::new();
+
+    let repl = r.handle_replicate(req.clone()).await?;
+    prepared.push(check_prepare_reply(&pinst, repl)?);
+    replied.insert(r.replica_id);
+
+    let mut repls = bcast_msg(&r.conns, &r.detector, &r.get_peers(), req, RPC_TIMEOUT);
+    while let Some((from_rid, repl)) = repls.recv().await {
+        match check_prepare_reply(&pinst, repl) {
+            Ok(p) => {
+                prepared.push(p);
+                replied.insert(from_rid);
+            }
+            Err(e) => {
+                warn!("{:?} while recover {} prepare from {}", e, iid, from_rid);
+            }
+        }
+        if membership.is_quorum(&replied) {
+            break;
+        }
+    }
+
+    if !membership.is_quorum(&replied) {
+        return Err(ReplicationError::NotEnoughQuorum(
+            InstanceStatus::Prepared,
+            q,
+            prepared.len() as i32,
+        ));
+    }
+
+    let chosen = choose_recovery_value(&pinst, &prepared, grids, q);
+    if chosen.committed {
+        return commit(r, chosen).await;
+    }
+
+    // Recovery-2..4: make the chosen value safe on SlowPath.
+    let mut st = ReplicationStatus::new(&membership, chosen);
+    st.instance.vballot = st.instance.ballot;
+
+    let req = MakeRequest::accept(r.replica_id, &st.instance);
+    let repl = r.handle_replicate(req.clone()).await?;
+    check_repl_common(&st.instance, repl)?;
+    st.start_accept();
+
+    let mut repls = bcast_msg(&r.conns, &r.detector, &r.get_peers(), req, RPC_TIMEOUT);
+    while let Some((from_rid, repl)) = repls.recv().await {
+        if let Err(e) = handle_accept_reply(&mut st, from_rid, repl) {
+            warn!("{:?} while recover {} accept from {}", e, iid, from_rid);
+        }
+        if st.is_accepted() {
+            break;
+        }
+    }
+
+    if !st.is_accepted() {
+        return Err(ReplicationError::NotEnoughQuorum(
+            InstanceStatus::Accepted,
+            st.quorum,
+            st.accepted.len() as i32,
+        ));
+    }
+
+    commit(r, st.instance).await
+}
+
+/// choose_recovery_value chooses the value to commit for an instance being recovered, from
+/// the prepare-replies of a quorum.
+///
+/// - A committed value is always chosen.
+/// - Otherwise the accepted value with the highest vballot is chosen, as classic paxos does.
+///   See: wiki/epaxos-bug-lackof-vballot.md
+/// - If no replica has seen the instance, it can not have been committed: choose a NoOp.
+/// - Otherwise for every leader, choose the dep that occupies more than half of a quorum, which
+///   is possibly FastCommit-ed. If there is no such dep, choose the greatest one.
+pub fn choose_recovery_value(
+    inst: &Instance,
+    prepared: &[PrepareReply],
+    group: &[ReplicaId],
+    quorum: i32,
+) -> Instance {
+    let iid = inst.instance_id.unwrap();
+
+    let mut chosen = Instance {
+        instance_id: inst.instance_id,
+        ballot: inst.ballot,
+        cmds: inst.cmds.clone(),
+        ..Default::default()
+    };
+
+    for p in prepared.iter() {
+        if p.committed {
+            chosen.cmds = p.cmds.clone();
+            chosen.deps = p.deps.clone();
+            chosen.committed = true;
+            return chosen;
+        }
+    }
+
+    let accepted = prepared
+        .iter()
+        .filter(|p| p.vballot.is_some())
+        .max_by_key(|p| p.vballot);
+
+    if let Some(p) = accepted {
+        chosen.cmds = p.cmds.clone();
+        chosen.deps = p.deps.clone();
+        return chosen;
+    }
+
+    let seen: Vec<&PrepareReply> = prepared.iter().filter(|p| p.deps.is_some()).collect();
+
+    if seen.is_empty() {
+        // A NoOp depends on nothing except the preceding instance by the same leader.
+        let mut deps = Vec::with_capacity(group.len());
+        for rid in group.iter() {
+            if *rid == iid.replica_id {
+                deps.push(Dep::from((*rid, iid.idx - 1)));
+            } else {
+                deps.push(Dep::from((*rid, -1)));
+            }
+        }
+
+        chosen.cmds = vec![Command::from((OpCode::NoOp, "", ""))];
+        chosen.deps = Some(deps.into());
+        return chosen;
+    }
+
+    if inst.deps.is_none() {
+        // This replica has not seen the instance. Learn cmds from others.
+        chosen.cmds = seen[0].cmds.clone();
+    }
+
+    let mut counts: BTreeMap<ReplicaId, BTreeMap<Dep, i32>> = BTreeMap::new();
+    for p in seen.iter() {
+        for d in p.deps.as_ref().unwrap().iter() {
+            let cnt = counts.entry(d.replica_id).or_insert_with(BTreeMap::new);
+            *cnt.entry(*d).or_insert(0) += 1;
+        }
+    }
+
+    let mut deps = Vec::with_capacity(counts.len());
+    for cnt in counts.values() {
+        let major = cnt.iter().find(|(_, c)| **c * 2 > quorum);
+        let d = match major {
+            Some((d, _)) => *d,
+            None => *cnt.keys().next_back().unwrap(),
+        };
+        deps.push(d);
+    }
+
+    chosen.deps = Some(deps.into());
+    chosen
+}
+
+/// next_ballot returns a ballot of a new round, greater than the one the instance has seen and
+/// the last ballot the replica has seen, owned by `rid`.
+fn next_ballot(inst: &Instance, last: BallotNum, rid: ReplicaId) -> BallotNum {
+    let num = match inst.ballot {
+        Some(b) => b.num,
+        None => 0,
+    };
+    (num.max(last.num) + 1, rid).into()
+}
+
+fn check_prepare_reply(
+    inst: &Instance,
+    repl: ReplicateReply,
+) -> Result<PrepareReply, RpcHandlerError> {
+    if let Some(ref e) = repl.err {
+        return Err(RpcHandlerError::RemoteError(e.clone()));
+    }
+
+    let phase = check_repl_common(inst, repl)?;
+
+    let p: PrepareReply = phase
+        .try_into()
+        .map_err(|_| ProtocolError::LackOf("phase::Prepare".into()))?;
+
+    Ok(p)
+}
+
+/// commit commits the instance on local replica and delivers it to peers.
+pub(crate) async fn commit(r: &Replica, inst: Instance) -> Result<Instance, ReplicationError> {
+    let iid = inst.instance_id.unwrap();
+
+    let req = MakeRequest::commit(r.replica_id, &inst);
+    r.handle_replicate(req).await?;
+
+    // Peers that miss the commit receive it by a later retry.
+    r.commits.add(iid)?;
+    deliver_commits(r).await?;
+
+    Ok(r.get_instance(iid)?)
+}
@@ -1,4 +1,3 @@
-use crate::conf::GroupInfo;
 use crate::qpaxos::Command;
 use crate::qpaxos::MakeRequest;
 use crate::replica::InstanceStatus;
@@ -7,26 +6,29 @@ use crate::replica::ReplicationStatus;
 use crate::replication::bcast_msg;
 use crate::replication::handle_accept_reply;
 use crate::replication::handle_prepare_reply;
+use crate::replication::load_committed;
+use crate::replication::MAX_COMMITS_PER_REQ;
+use crate::replication::RPC_TIMEOUT;
 use crate::ReplicationError;
-use crate::StorageAPI;
 
 /// replicate runs replication algo to forward instance to other replica in leader's group.
 /// An OK return value indicate the instance becomes safe, but not yet committed.
 /// An Err return value means the instance could be unsafe yet.
 ///
 /// On success it returns the status containing an instance and replication status.
+///
+/// Quorums are those of the membership `r` has applied when the replication starts.
 pub async fn replicate(
     cmds: &[Command],
-    g: &GroupInfo,
     r: &Replica,
 ) -> Result<ReplicationStatus, ReplicationError> {
-    let grids: Vec<_> = g.replicas.keys().cloned().collect();
+    let membership = r.get_membership();
+    let grids = membership.replica_ids();
     println!("grids:{:?}", grids);
 
-    let inst = r.new_instance(cmds)?;
+    let inst = r.new_instance(cmds).await?;
 
-    let n = grids.len();
-    let mut st = ReplicationStatus::new(n as i32, inst);
+    let mut st = ReplicationStatus::new(&membership, inst);
     println!("st:{:?}", st);
 
     // a special path for n = 1
@@ -44,19 +46,32 @@ pub async fn replicate(
 
     // TODO not impl yet.
     let mut deps_committed = vec![];
-    for _ in 0..g.replicas.len() {
+    for _ in 0..grids.len() {
         deps_committed.push(false);
     }
 
-    let req = MakeRequest::prepare(0, &st.instance, &deps_committed);
-    let repls = bcast_msg(&r.peers, req).await;
+    let mut req = MakeRequest::prepare(0, &st.instance, &deps_committed);
+
+    // piggyback undelivered commits to let followers converge.
+    let piggybacked = r.commits.piggyback(MAX_COMMITS_PER_REQ);
+    req.committed = load_committed(r, &piggybacked)?;
 
-    println!("got {} replies", repls.len());
+    let mut repls = bcast_msg(&r.conns, &r.detector, &r.get_peers(), req, RPC_TIMEOUT);
+
+    // the leader itself is counted.
+    let mut n_replied = 1;
+    while let Some((from_rid, repl)) = repls.recv().await {
+        println!("fast-reply from:{} {}", from_rid, repl);
+        if repl.err.is_none() {
+            r.commits.ack(from_rid, &piggybacked)?;
+        }
+        // learn the latest round, thus the next proposal won't be rejected.
+        if let Some(b) = repl.last_ballot {
+            r.update_last_ballot(b)?;
+        }
+        handle_prepare_reply(&mut st, from_rid, repl)?;
+        n_replied += 1;
 
-    for (from_rid, repl) in repls.iter() {
-        println!("fast-reply from:{} {}", from_rid, repl.get_ref());
-        // TODO  consume repl do not clone
-        handle_prepare_reply(&mut st, *from_rid, repl.get_ref().clone())?;
         let fast = st.get_fastpath_deps(&grids);
         match fast {
             Some(fdeps) => {
@@ -68,6 +83,12 @@ pub async fn replicate(
                 // not enough fast replies, continue
             }
         };
+
+        // A fast-quorum replied but fast path can not be chosen. Do not wait for the slow
+        // peers if slow path is ready.
+        if n_replied >= st.fast_quorum && st.get_slowpath_deps(&grids).is_some() {
+            break;
+        }
     }
 
     let adeps = st.get_slowpath_deps(&grids);
@@ -83,16 +104,20 @@ pub async fn replicate(
     // slow path
 
     st.instance.deps = Some(adeps.into());
+    // the leader accepts it at its own ballot.
+    st.instance.vballot = st.instance.ballot;
     st.start_accept();
-    r.storage
-        .set_instance(&st.instance.instance_id.unwrap(), &st.instance)?;
+    r.set_instance(&st.instance).await?;
 
     let req = MakeRequest::accept(0, &st.instance);
-    let repls = bcast_msg(&r.peers, req).await;
+    let mut repls = bcast_msg(&r.conns, &r.detector, &r.get_peers(), req, RPC_TIMEOUT);
 
-    for (from_rid, repl) in repls.iter() {
-        handle_accept_reply(&mut st, *from_rid, repl.get_ref().clone())?;
-        if st.accepted.len() as i32 >= st.quorum {
+    while let Some((from_rid, repl)) = repls.recv().await {
+        if let Some(b) = repl.last_ballot {
+            r.update_last_ballot(b)?;
+        }
+        handle_accept_reply(&mut st, from_rid, repl)?;
+        if st.is_accepted() {
             // instance is safe to commit.
             return Ok(st);
         }
@@ -1,10 +1,16 @@
+use std::net::TcpListener;
+use std::time::Duration;
+use std::time::Instant;
+
 use crate::inst;
 use crate::qpaxos::Command;
 use crate::qpaxos::Dep;
 use crate::qpaxos::Instance;
 use crate::qpaxos::InstanceId;
 use crate::qpaxos::MakeRequest;
+use crate::replica::ReplicaPeer;
 use crate::replication::bcast_msg;
+use crate::replication::RPC_TIMEOUT;
 use crate::testutil::TestCluster;
 
 #[tokio::test(threaded_scheduler)]
@@ -14,9 +20,55 @@ async fn test_bcast_replicate_request() {
     let inst = inst!((0, 1), [(x = x)], [(0, 0), (1, 0), (2, 0)]);
     let req = MakeRequest::prepare(0, &inst, &[true, true, true]);
 
-    let r = bcast_msg(&tc.replicas[0].peers, req).await;
+    let mut rx = bcast_msg(
+        &tc.replicas[0].conns,
+        &tc.replicas[0].detector,
+        &tc.replicas[0].get_peers(),
+        req,
+        RPC_TIMEOUT,
+    );
+
+    let mut r = vec![];
+    while let Some(repl) = rx.recv().await {
+        r.push(repl);
+    }
 
     println!("receive prepare replys: {:?}", r);
     // not contain self
     assert_eq!(2, r.len());
+
+    {
+        // a slow or dead peer does not block replies from others.
+
+        let mut peers = tc.replicas[0].get_peers();
+        let mut want: Vec<_> = peers.iter().map(|p| p.replica_id).collect();
+        want.sort();
+
+        // A peer that accepts connections but never replies.
+        let black_hole = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = format!("http://{}", black_hole.local_addr().unwrap());
+        peers.push(ReplicaPeer::new(5, addr, true));
+        // A peer that is down.
+        peers.push((6, "http://127.0.0.1:1", true).into());
+
+        let req = MakeRequest::prepare(0, &inst, &[true, true, true]);
+
+        let start = Instant::now();
+        let mut rx = bcast_msg(
+            &tc.replicas[0].conns,
+            &tc.replicas[0].detector,
+            &peers,
+            req,
+            Duration::from_millis(200),
+        );
+
+        let mut rids = vec![];
+        while let Some((rid, _)) = rx.recv().await {
+            rids.push(rid);
+        }
+        rids.sort();
+
+        assert_eq!(want, rids);
+        assert!(start.elapsed() < Duration::from_millis(1000));
+    }
 }
@@ -0,0 +1,92 @@
+use std::sync::Arc;
+
+use crate::inst;
+use crate::instidvec;
+use crate::qpaxos::*;
+use crate::replica::ReplicaPeer;
+use crate::replication::deliver_commits;
+use crate::replication::CommitTracker;
+use crate::testutil;
+use crate::StorageAPI;
+use storage::DBColumnFamily;
+use storage::MemEngine;
+use storage::ObjectKV;
+use storage::Storage;
+
+#[test]
+fn test_commit_tracker() {
+    let eng = Arc::new(MemEngine::new().unwrap());
+    let sto = Storage::new(1, eng.clone());
+
+    let ct = CommitTracker::new(sto.clone(), &[2, 3]).unwrap();
+    assert!(!ct.is_ready(2));
+    assert_eq!(instidvec![], ct.piggyback(10));
+
+    ct.add((1, 0).into()).unwrap();
+    ct.add((1, 1).into()).unwrap();
+    ct.add((1, 2).into()).unwrap();
+
+    assert!(ct.is_ready(2));
+    assert!(ct.is_ready(3));
+    assert_eq!(instidvec![(1, 0), (1, 1)], ct.pending(2, 2));
+
+    ct.ack(2, &instidvec![(1, 0), (1, 1)]).unwrap();
+    assert_eq!(instidvec![(1, 2)], ct.pending(2, 10));
+    assert_eq!(instidvec![(1, 0), (1, 1), (1, 2)], ct.pending(3, 10));
+    assert_eq!(instidvec![(1, 0), (1, 1)], ct.piggyback(2));
+
+    // a pending commit is stored in its own key, and is deleted when acked.
+    let get = |rid: ReplicaId, iid: (i64, i64)| -> Option<InstanceId> {
+        let k = ReplicaStatus::CommitPending(rid, iid.into());
+        sto.get(DBColumnFamily::Status, &k).unwrap()
+    };
+    assert_eq!(None, get(2, (1, 0)));
+    assert_eq!(Some((1, 2).into()), get(2, (1, 2)));
+    assert_eq!(Some((1, 0).into()), get(3, (1, 0)));
+
+    // unknown peer
+    ct.ack(5, &instidvec![(1, 2)]).unwrap();
+    assert!(!ct.is_ready(5));
+
+    {
+        // pending commits are reloaded
+        let ct = CommitTracker::new(sto.clone(), &[2, 3]).unwrap();
+        assert_eq!(instidvec![(1, 2)], ct.pending(2, 10));
+        assert_eq!(instidvec![(1, 0), (1, 1), (1, 2)], ct.pending(3, 10));
+    }
+
+    {
+        // other replica does not see them
+        let ct = CommitTracker::new(Storage::new(2, eng.clone()), &[1, 3]).unwrap();
+        assert_eq!(instidvec![], ct.pending(3, 10));
+    }
+
+    // backoff after failure
+    ct.on_failure(2);
+    assert!(!ct.is_ready(2));
+    assert!(ct.is_ready(3));
+
+    // ack resets backoff
+    ct.ack(2, &[]).unwrap();
+    assert!(ct.is_ready(2));
+
+    ct.ack(2, &instidvec![(1, 2)]).unwrap();
+    assert!(!ct.is_ready(2));
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_deliver_commits_peer_down() {
+    let peers: Vec<ReplicaPeer> = vec![(2, "http://127.0.0.1:1", true).into()];
+    let r = testutil::new_replica(1, vec![1, 2], peers, Arc::new(MemEngine::new().unwrap()));
+
+    let mut inst = inst!((1, 0), (1, _), [(x = y)], [(1, -1), (2, -1)]);
+    inst.committed = true;
+    r.storage.set_instance(&(1, 0).into(), &inst).unwrap();
+
+    r.commits.add((1, 0).into()).unwrap();
+    deliver_commits(&r).await.unwrap();
+
+    // still pending and backing off
+    assert_eq!(instidvec![(1, 0)], r.commits.pending(2, 10));
+    assert!(!r.commits.is_ready(2));
+}
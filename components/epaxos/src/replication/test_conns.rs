@@ -0,0 +1,107 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::time::delay_for;
+use tonic::transport::Server;
+
+use crate::qpaxos::*;
+use crate::replication::backoff;
+use crate::replication::ConnError;
+use crate::replication::ConnPool;
+use crate::replication::CONN_BACKOFF_MAX;
+use crate::replication::CONN_BACKOFF_MIN;
+use crate::testutil;
+use crate::QPaxosImpl;
+
+#[test]
+fn test_conns_backoff() {
+    assert_eq!(CONN_BACKOFF_MIN, backoff(0));
+    assert_eq!(CONN_BACKOFF_MIN * 2, backoff(1));
+    assert_eq!(CONN_BACKOFF_MIN * 4, backoff(2));
+    assert_eq!(CONN_BACKOFF_MAX, backoff(10));
+    assert_eq!(CONN_BACKOFF_MAX, backoff(1000));
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_conns_reuse_and_reset() {
+    let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+
+    let qp = QPaxosImpl::new(Arc::new(testutil::new_inmem_server_data("az_1")));
+    let s = Server::builder().add_service(QPaxosServer::new(qp));
+
+    tokio::spawn(async move {
+        s.serve_with_shutdown("127.0.0.1:5661".parse().unwrap(), async {
+            rx.await.ok();
+        })
+        .await
+        .unwrap();
+    });
+
+    delay_for(Duration::from_millis(1_000)).await;
+
+    let conns = ConnPool::new();
+    let addr = "http://127.0.0.1:5661";
+
+    assert!(!conns.is_connected(addr));
+
+    {
+        // connect and cache it
+        let mut c = conns.get(addr).await.unwrap();
+        assert!(conns.is_connected(addr));
+
+        let inst = Instance::default();
+        c.replicate(MakeRequest::accept(0, &inst)).await.unwrap();
+    }
+
+    {
+        // a cached client works
+        let mut c = conns.get(addr).await.unwrap();
+        let inst = Instance::default();
+        c.replicate(MakeRequest::accept(0, &inst)).await.unwrap();
+    }
+
+    {
+        // reconnect after reset
+        conns.reset(addr);
+        assert!(!conns.is_connected(addr));
+
+        let mut c = conns.get(addr).await.unwrap();
+        assert!(conns.is_connected(addr));
+
+        let inst = Instance::default();
+        c.replicate(MakeRequest::accept(0, &inst)).await.unwrap();
+    }
+
+    let _ = tx.send(());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_conns_connect_failure() {
+    let conns = ConnPool::new();
+    let addr = "http://127.0.0.1:1";
+
+    let rst = conns.get(addr).await;
+    match rst.err().unwrap() {
+        ConnError::Connect(a, _) => assert_eq!(addr, a),
+        e => panic!("unexpected error: {:?}", e),
+    }
+    assert!(!conns.is_connected(addr));
+
+    // retried only after backoff
+    let rst = conns.get(addr).await;
+    match rst.err().unwrap() {
+        ConnError::Backoff(a, remain) => {
+            assert_eq!(addr, a);
+            assert!(remain <= backoff(0));
+        }
+        e => panic!("unexpected error: {:?}", e),
+    }
+
+    delay_for(backoff(0)).await;
+
+    let rst = conns.get(addr).await;
+    match rst.err().unwrap() {
+        ConnError::Connect(a, _) => assert_eq!(addr, a),
+        e => panic!("unexpected error: {:?}", e),
+    }
+}
@@ -0,0 +1,60 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::replica::ReplicaPeer;
+use crate::replication::ping_peers;
+use crate::replication::ConnPool;
+use crate::replication::FailureDetector;
+use crate::replication::DEAD_AFTER_FAILS;
+
+#[test]
+fn test_detector() {
+    let fd = FailureDetector::new(2);
+
+    // never contacted
+    assert!(fd.is_alive(1));
+    assert_eq!(0, fd.get(1).fails);
+    assert_eq!(None, fd.get(1).last_seen);
+
+    fd.on_failure(1);
+    assert!(fd.is_alive(1));
+
+    fd.on_failure(1);
+    assert!(!fd.is_alive(1));
+    assert_eq!(2, fd.get(1).fails);
+
+    // other peers are not affected
+    assert!(fd.is_alive(2));
+
+    fd.on_success(1);
+    assert!(fd.is_alive(1));
+    assert_eq!(0, fd.get(1).fails);
+    assert!(fd.get(1).last_seen.is_some());
+}
+
+#[test]
+fn test_detector_default() {
+    let fd = FailureDetector::default();
+    for _ in 0..DEAD_AFTER_FAILS - 1 {
+        fd.on_failure(1);
+    }
+    assert!(fd.is_alive(1));
+
+    fd.on_failure(1);
+    assert!(!fd.is_alive(1));
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_ping_peers_down() {
+    let conns = Arc::new(ConnPool::new());
+    let fd = Arc::new(FailureDetector::new(2));
+    let peers: Vec<ReplicaPeer> = vec![(2, "http://127.0.0.1:1", true).into()];
+
+    ping_peers(1, &conns, &fd, &peers, Duration::from_millis(200)).await;
+    assert_eq!(1, fd.get(2).fails);
+    assert!(fd.is_alive(2));
+
+    ping_peers(1, &conns, &fd, &peers, Duration::from_millis(200)).await;
+    assert_eq!(2, fd.get(2).fails);
+    assert!(!fd.is_alive(2));
+}
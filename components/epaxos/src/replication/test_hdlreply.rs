@@ -149,7 +149,7 @@ fn test_handle_prepare_reply_err() {
     ];
 
     for (repl, want) in cases.iter() {
-        let mut st = ReplicationStatus::new(3, inst.clone());
+        let mut st = ReplicationStatus::new(&Membership::from(&[1, 2, 3][..]), inst.clone());
         let r = handle_prepare_reply(&mut st, 3, repl.clone());
         assert_eq!(r.err().unwrap(), *want, "Prepare-reply: {:?}", repl);
     }
@@ -158,7 +158,7 @@ fn test_handle_prepare_reply_err() {
 #[test]
 fn test_handle_prepare_reply() {
     let inst = inst!((1, 2), (0, _), [(x = "1")], []);
-    let mut st = ReplicationStatus::new(3, inst.clone());
+    let mut st = ReplicationStatus::new(&Membership::from(&[1, 2, 3][..]), inst.clone());
 
     {
         // positive reply updates the Status.
@@ -208,7 +208,7 @@ fn test_handle_prepare_reply() {
         // duplicated message
 
         let inst = inst!((1, 2), (0, _), [(x = "1")], []);
-        let mut st = ReplicationStatus::new(3, inst.clone());
+        let mut st = ReplicationStatus::new(&Membership::from(&[1, 2, 3][..]), inst.clone());
 
         let repl: ReplicateReply = frepl!(((0, 1), (1, 2)), ([(3, 4)], vec![true]));
         let from_rid = 4;
@@ -259,11 +259,11 @@ fn test_handle_accept_reply() {
     rp.storage
         .set_instance(&inst.instance_id.unwrap(), &inst)
         .unwrap();
-    let n = rp.group_replica_ids.len() as i32;
+    let m = rp.get_membership();
 
     {
         // with high ballot num
-        let mut st = ReplicationStatus::new(n, inst.clone());
+        let mut st = ReplicationStatus::new(&m, inst.clone());
         st.start_accept();
         let repl = ReplicateReply {
             last_ballot: Some((10, replica_id).into()),
@@ -273,13 +273,13 @@ fn test_handle_accept_reply() {
         println!("{:?}", r);
         assert!(r.is_err());
 
-        assert_eq!(st.get_slowpath_deps(&rp.group_replica_ids), None);
+        assert_eq!(st.get_slowpath_deps(&rp.get_group_replica_ids()), None);
         assert_eq!(1, st.accepted.len());
     }
 
     {
         // with reply err
-        let mut st = ReplicationStatus::new(n, inst.clone());
+        let mut st = ReplicationStatus::new(&m, inst.clone());
         st.start_accept();
         let repl = ReplicateReply {
             err: Some(ProtocolError::LackOf("test".to_string()).into()),
@@ -289,7 +289,7 @@ fn test_handle_accept_reply() {
         println!("{:?}", r);
         assert!(r.is_err());
 
-        assert_eq!(st.get_slowpath_deps(&rp.group_replica_ids), None);
+        assert_eq!(st.get_slowpath_deps(&rp.get_group_replica_ids()), None);
 
         assert_eq!(1, st.accepted.len());
     }
@@ -297,7 +297,7 @@ fn test_handle_accept_reply() {
     {
         // success
         inst.vballot = Some((2, 3).into());
-        let mut st = ReplicationStatus::new(n, inst.clone());
+        let mut st = ReplicationStatus::new(&m, inst.clone());
         st.start_accept();
         let repl = ReplicateReply {
             err: None,
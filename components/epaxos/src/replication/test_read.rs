@@ -0,0 +1,139 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::inst;
+use crate::instids;
+use crate::qpaxos::*;
+use crate::replica::Replica;
+use crate::replication::read_index;
+use crate::replication::read_linearizable;
+use crate::replication::read_local_executed;
+use crate::replication::wait_executed;
+use crate::replication::ReplicationError;
+use crate::testutil;
+use crate::StorageAPI;
+use storage::MemEngine;
+
+#[cfg(test)]
+use pretty_assertions::assert_eq;
+
+fn new_replica(group: Vec<ReplicaId>) -> Replica {
+    let peers = group
+        .iter()
+        .filter(|x| **x != 1)
+        .map(|x| (*x, "http://127.0.0.1:1", true).into())
+        .collect();
+    testutil::new_replica(1, group, peers, Arc::new(MemEngine::new().unwrap()))
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_read_index_local() {
+    let r = new_replica(vec![1]);
+    assert_eq!(instids![(1, -1)], read_index(&r).await.unwrap());
+
+    for idx in 0..3 {
+        r.set_instance(&inst!((1, idx), (0, _), [(x = y)]))
+            .await
+            .unwrap();
+    }
+    assert_eq!(instids![(1, 2)], read_index(&r).await.unwrap());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_read_index_no_quorum() {
+    let r = new_replica(vec![1, 2, 3]);
+
+    let err = read_index(&r).await.unwrap_err();
+    match err {
+        ReplicationError::NotEnoughQuorum(_, want, got) => {
+            assert_eq!(2, want);
+            assert_eq!(1, got);
+        }
+        _ => panic!("unexpected error: {:?}", err),
+    }
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_wait_executed() {
+    let r = new_replica(vec![1, 2]);
+    r.storage
+        .set_status(&ReplicaStatus::Exec, &instids![(1, 3), (2, 1)])
+        .unwrap();
+
+    let tmout = Duration::from_millis(20);
+
+    wait_executed(&r, &instids![(1, 3), (2, -1)], tmout)
+        .await
+        .unwrap();
+
+    let err = wait_executed(&r, &instids![(1, 3), (2, 2)], tmout)
+        .await
+        .unwrap_err();
+    match err {
+        ReplicationError::ReadTimeout(index) => assert_eq!(instids![(1, 3), (2, 2)], index),
+        _ => panic!("unexpected error: {:?}", err),
+    }
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_wait_executed_notified() {
+    let r = Arc::new(new_replica(vec![1, 2]));
+    r.storage
+        .set_status(&ReplicaStatus::Exec, &instids![(1, 3), (2, 1)])
+        .unwrap();
+
+    let r1 = r.clone();
+    tokio::spawn(async move {
+        tokio::time::delay_for(Duration::from_millis(10)).await;
+        r1.storage
+            .set_status(&ReplicaStatus::Exec, &instids![(1, 3), (2, 2)])
+            .unwrap();
+        r1.exec_tx.broadcast(()).unwrap();
+    });
+
+    // woken up by the broadcast long before the timeout.
+    let start = std::time::Instant::now();
+    wait_executed(&r, &instids![(1, 3), (2, 2)], Duration::from_secs(5))
+        .await
+        .unwrap();
+    assert!(start.elapsed() < Duration::from_secs(1));
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_read_linearizable() {
+    let r = new_replica(vec![1]);
+    r.set_instance(&inst!((1, 0), (0, _), [(x = y)]))
+        .await
+        .unwrap();
+    r.storage.set_kv(b"x", &"y".into()).unwrap();
+    r.storage
+        .set_status(&ReplicaStatus::Exec, &instids![(1, 0)])
+        .unwrap();
+
+    assert_eq!(
+        Some(Record::from("y")),
+        read_linearizable(&r, b"x").await.unwrap()
+    );
+    assert_eq!(None, read_linearizable(&r, b"z").await.unwrap());
+
+    *r.range.lock().unwrap() = Some(("a", "b").into());
+    let err = read_linearizable(&r, b"x").await.unwrap_err();
+    match err {
+        ReplicationError::KeyOutOfRange(k) => assert_eq!("x", k),
+        _ => panic!("unexpected error: {:?}", err),
+    }
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_read_local_executed() {
+    let r = new_replica(vec![1, 2, 3]);
+    r.storage.set_kv(b"x", &"y".into()).unwrap();
+    r.storage
+        .set_status(&ReplicaStatus::Exec, &instids![(1, 2), (2, 1)])
+        .unwrap();
+
+    // no other replica is contacted.
+    let (v, executed) = read_local_executed(&r, b"x").await.unwrap();
+    assert_eq!(Some(Record::from("y")), v);
+    assert_eq!(instids![(1, 2), (2, 1), (3, -1)], executed);
+}
@@ -0,0 +1,177 @@
+use std::sync::Arc;
+
+use crate::qpaxos::*;
+use crate::replica::Replica;
+use crate::replication::choose_recovery_value;
+use crate::testutil;
+use crate::StorageAPI;
+use storage::MemEngine;
+
+#[cfg(test)]
+use pretty_assertions::assert_eq;
+
+fn prepared(cmds: Vec<Command>, deps: Option<Deps>, committed: bool) -> PrepareReply {
+    PrepareReply {
+        cmds,
+        deps,
+        deps_committed: vec![],
+        vballot: None,
+        committed,
+    }
+}
+
+fn accepted(cmds: Vec<Command>, deps: Option<Deps>, vballot: (i32, i64)) -> PrepareReply {
+    PrepareReply {
+        vballot: Some(vballot.into()),
+        ..prepared(cmds, deps, false)
+    }
+}
+
+#[test]
+fn test_choose_recovery_value() {
+    let group = vec![1, 2, 3];
+    let quorum = 2;
+    let known = inst!((1, 2), (3, _), [(x = y)], [(1, 1), (2, 5), (3, 6)]);
+    let unknown = inst!((1, 2), (3, _), []);
+
+    {
+        // a committed value is always chosen
+        let repls = vec![
+            prepared(vec![], optdeps!([(1, 1), (2, 7), (3, 6)]), false),
+            prepared(cmdvec![(x = y)], optdeps!([(1, 1), (2, 5), (3, 9)]), true),
+        ];
+        let got = choose_recovery_value(&unknown, &repls, &group, quorum);
+        assert_eq!(
+            inst!((1, 2), (3, _), [(x = y)], [(1, 1), (2, 5), (3, 9)]),
+            Instance {
+                committed: false,
+                ..got.clone()
+            }
+        );
+        assert!(got.committed);
+    }
+
+    {
+        // no replica has seen it: NoOp
+        let repls = vec![prepared(vec![], None, false), prepared(vec![], None, false)];
+        let got = choose_recovery_value(&unknown, &repls, &group, quorum);
+        assert_eq!(inst!((1, 2), (3, _), [()], [(1, 1), (2, -1), (3, -1)]), got);
+    }
+
+    {
+        // learn cmds from other replica, choose the greatest dep
+        let repls = vec![
+            prepared(vec![], None, false),
+            prepared(cmdvec![(x = y)], optdeps!([(1, 1), (2, 5), (3, 6)]), false),
+        ];
+        let got = choose_recovery_value(&unknown, &repls, &group, quorum);
+        assert_eq!(known, got);
+    }
+
+    {
+        // dep that occupies more than half of a quorum is chosen.
+        let quorum = 3;
+        let repls = vec![
+            prepared(vec![], optdeps!([(1, 1), (2, 5), (3, 6)]), false),
+            prepared(vec![], optdeps!([(1, 1), (2, 5), (3, 8)]), false),
+            prepared(vec![], optdeps!([(1, 1), (2, 9), (3, 7)]), false),
+        ];
+        let got = choose_recovery_value(&known, &repls, &group, quorum);
+        assert_eq!(
+            inst!((1, 2), (3, _), [(x = y)], [(1, 1), (2, 5), (3, 8)]),
+            got
+        );
+    }
+
+    {
+        // the accepted value with the highest vballot is chosen.
+        let repls = vec![
+            prepared(vec![], optdeps!([(1, 1), (2, 9), (3, 9)]), false),
+            accepted(cmdvec![(x = y)], optdeps!([(1, 1), (2, 5), (3, 8)]), (2, 2)),
+            accepted(cmdvec![(x = y)], optdeps!([(1, 1), (2, 5), (3, 6)]), (1, 3)),
+        ];
+        let got = choose_recovery_value(&unknown, &repls, &group, quorum);
+        assert_eq!(
+            inst!((1, 2), (3, _), [(x = y)], [(1, 1), (2, 5), (3, 8)]),
+            got
+        );
+    }
+}
+
+/// new_a creates instance `a` by leader 6, which depends on `b` by leader 5.
+fn new_a(ballot: (i32, i64), b_idx: i64) -> Instance {
+    Instance {
+        ballot: Some(ballot.into()),
+        ..inst!((6, 0), [(x = y)], [(5, b_idx)])
+    }
+}
+
+/// The scenario in wiki/epaxos-bug-lackof-vballot.md, with 5 replicas:
+///
+/// - Recovery with ballot=1 accepted `a→b₂` on R1.
+/// - Recovery with ballot=2 accepted `a→b₃` on R2..R5, i.e., `a→b₃` is committed.
+/// - Recovery with ballot=3 prepared on R1.
+/// - Recovery with ballot=4 prepared on R1..R3. It must choose `a→b₃`.
+#[tokio::test(threaded_scheduler)]
+async fn test_recovery_choose_highest_vballot() {
+    let group = vec![1, 2, 3, 4, 5];
+    let quorum = 3;
+
+    let rs: Vec<Replica> = group
+        .iter()
+        .map(|rid| {
+            let sto = Arc::new(MemEngine::new().unwrap());
+            testutil::new_replica(*rid, group.clone(), vec![], sto)
+        })
+        .collect();
+
+    let a = new_a((0, 6), 1);
+    let iid = a.instance_id.unwrap();
+    for r in rs.iter() {
+        r.storage.set_instance(&iid, &a).unwrap();
+    }
+
+    let a_b2 = new_a((1, 1), 2);
+    let req = MakeRequest::accept(1, &a_b2);
+    let repl = rs[0].handle_replicate(req).await.unwrap();
+    assert!(repl.phase.is_some());
+
+    let a_b3 = new_a((2, 2), 3);
+    for r in rs[1..].iter() {
+        let req = MakeRequest::accept(r.replica_id, &a_b3);
+        let repl = r.handle_replicate(req).await.unwrap();
+        assert!(repl.phase.is_some());
+
+        let got = r.get_instance(iid).unwrap();
+        assert_eq!(Some(BallotNum::from((2, 2))), got.vballot);
+    }
+
+    // a Prepare does not change an accepted value
+    let p3 = new_a((3, 1), 4);
+    let req = MakeRequest::prepare(1, &p3, &[false]);
+    let repl = rs[0].handle_replicate(req).await.unwrap();
+    assert!(repl.phase.is_some());
+
+    let got = rs[0].get_instance(iid).unwrap();
+    assert_eq!(Some(BallotNum::from((3, 1))), got.ballot);
+    assert_eq!(Some(BallotNum::from((1, 1))), got.vballot);
+    assert_eq!(a_b2.deps, got.deps);
+
+    let p4 = new_a((4, 1), 1);
+    let mut repls = vec![];
+    for r in rs[..3].iter() {
+        let req = MakeRequest::prepare(r.replica_id, &p4, &[false]);
+        let repl = r.handle_replicate(req).await.unwrap();
+        let p: PrepareReply = repl.phase.unwrap().try_into().unwrap();
+        repls.push(p);
+    }
+
+    // R1 has the highest ballot but the lowest vballot
+    assert_eq!(Some(BallotNum::from((1, 1))), repls[0].vballot);
+    assert_eq!(Some(BallotNum::from((2, 2))), repls[1].vballot);
+
+    let got = choose_recovery_value(&p4, &repls, &group, quorum);
+    assert_eq!(a_b3.cmds, got.cmds);
+    assert_eq!(a_b3.deps, got.deps);
+    assert!(!got.committed);
+}
@@ -0,0 +1,364 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use crate::conf::GroupInfo;
+use crate::qpaxos::Command;
+use crate::qpaxos::ReplicaId;
+use crate::qpaxos::TxnDecide;
+use crate::qpaxos::TxnPrepare;
+use crate::qpaxos::TxnRequest;
+use crate::qpaxos::TxnStatus;
+use crate::replica::Replica;
+use crate::replication::propose_and_execute;
+use crate::replication::ConnPool;
+use crate::replication::ReplicationError;
+use crate::replication::RpcHandlerError;
+use crate::RangeLookupError;
+use crate::ServerData;
+
+/// The time a transaction may be pending in a group before it is aborted by recovery, i.e., the
+/// time a coordinator has to finish it.
+pub const TXN_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// The interval to look for transactions abandoned by their coordinators.
+pub const TXN_RECOVERY_INTERVAL: Duration = Duration::from_millis(500);
+
+lazy_static! {
+    static ref TXN_SEQ: AtomicU64 = AtomicU64::new(0);
+    static ref TXN_EPOCH: u128 = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+}
+
+/// new_txn_id returns a transaction id unique in the cluster: the node, the time the process
+/// started and a sequence number.
+pub fn new_txn_id(node_id: &str) -> Vec<u8> {
+    let seq = TXN_SEQ.fetch_add(1, Ordering::SeqCst);
+    format!("{}/{}/{}", node_id, *TXN_EPOCH, seq).into_bytes()
+}
+
+/// TxnGroup is a group a transaction writes: a local voter proposes to it, or a voter on another
+/// node does, through the `txn` RPC.
+#[derive(Clone)]
+pub enum TxnGroup {
+    Local(Arc<Replica>),
+    /// voters on other nodes and their replication addresses. They are tried in order until one
+    /// replies, proposing a transaction command twice is harmless.
+    Remote(Vec<(ReplicaId, String)>),
+}
+
+impl TxnGroup {
+    /// propose proposes a TxnPrepare or TxnDecide command to the group and returns the result of
+    /// executing it, see `propose_txn_cmd`.
+    pub async fn propose(
+        &self,
+        conns: &ConnPool,
+        cmd: &Command,
+    ) -> Result<TxnStatus, ReplicationError> {
+        let voters = match self {
+            TxnGroup::Local(r) => return propose_txn_cmd(r, cmd).await,
+            TxnGroup::Remote(voters) => voters,
+        };
+
+        let mut err = None;
+        for (rid, addr) in voters.iter() {
+            match send_txn_cmd(conns, *rid, addr, cmd).await {
+                Ok(st) => return Ok(st),
+                Err(e) => {
+                    warn!("{:?} while send txn cmd to {} at {}", e, rid, addr);
+                    err = Some(e);
+                }
+            }
+        }
+
+        Err(err.unwrap())
+    }
+}
+
+/// txn_group returns the group serving `key` and how to propose to it: with a local voter if
+/// there is one, otherwise with voters on other nodes.
+pub fn txn_group(sd: &ServerData, key: &[u8]) -> Result<(GroupInfo, TxnGroup), ReplicationError> {
+    match sd.get_local_replica_for_key(key) {
+        Ok((g, r)) if !r.is_learner() => return Ok((g, TxnGroup::Local(r))),
+        Ok(_) => {}
+        Err(RangeLookupError::NoLocalReplicaForKey(_)) => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    let (g, voters) = sd.get_remote_voters_for_key(key)?;
+    let voters = voters
+        .into_iter()
+        .map(|(rid, n)| (rid, format!("http://{}", n.replication)))
+        .collect();
+
+    Ok((g, TxnGroup::Remote(voters)))
+}
+
+/// send_txn_cmd asks voter `rid` at `addr` to propose a transaction command to its group.
+async fn send_txn_cmd(
+    conns: &ConnPool,
+    rid: ReplicaId,
+    addr: &str,
+    cmd: &Command,
+) -> Result<TxnStatus, ReplicationError> {
+    let mut client = conns.get(addr).await?;
+
+    let req = TxnRequest {
+        to_replica_id: rid,
+        cmd: Some(cmd.clone()),
+    };
+
+    let reply = match client.txn(req).await {
+        Ok(v) => v.into_inner(),
+        Err(e) => {
+            conns.reset(addr);
+            return Err(e.into());
+        }
+    };
+
+    if let Some(e) = reply.err {
+        return Err(RpcHandlerError::RemoteError(e).into());
+    }
+
+    Ok(reply.status())
+}
+
+/// run_txn writes Set or Delete commands `writes` atomically, with a two-phase commit across the
+/// groups serving the keys. The group of the first key is the primary group.
+///
+/// 1. Every group prepares the writes to its keys with a TxnPrepare instance. It locks the keys
+///    and votes to commit, or aborts if a key is locked by another transaction.
+/// 2. The decision is proposed to the primary group with a TxnDecide instance. The first one it
+///    executes is the decision of the transaction, which is the commit point.
+/// 3. The decision is proposed to the other groups, which apply the writes if it is committed.
+/// 4. If it is committed and every group is decided, an ended decision is proposed to the primary
+///    group, after which its state of the transaction is deleted by gc.
+///
+/// If the coordinator quits before a group is decided, `recover_txns` finishes it.
+/// It returns the decision. A transaction that fails to prepare is aborted and the error is
+/// returned.
+///
+/// A group without a local voter is proposed to by a voter on another node, see `TxnGroup`.
+/// Only keys it writes are locked: a write that is not in a transaction is not blocked.
+pub async fn run_txn(
+    sd: &ServerData,
+    txn_id: &[u8],
+    writes: Vec<Command>,
+) -> Result<TxnStatus, ReplicationError> {
+    if writes.len() == 0 {
+        return Ok(TxnStatus::Committed);
+    }
+
+    let primary = writes[0].key.clone();
+    let (pg, pr) = txn_group(sd, &primary)?;
+
+    // groups are identified by the start of their ranges.
+    let mut groups: BTreeMap<String, (TxnGroup, Vec<Command>)> = BTreeMap::new();
+    for w in writes {
+        let (g, tg) = txn_group(sd, &w.key)?;
+        groups
+            .entry(g.range.0)
+            .or_insert_with(|| (tg, vec![]))
+            .1
+            .push(w);
+    }
+
+    let mut handles = Vec::with_capacity(groups.len());
+    for (tg, ws) in groups.values() {
+        let p = TxnPrepare {
+            txn_id: txn_id.to_vec(),
+            primary: primary.clone(),
+            writes: ws.clone(),
+        };
+        let tg = tg.clone();
+        let conns = sd.conns.clone();
+        handles.push(tokio::spawn(async move {
+            tg.propose(&conns, &Command::from(&p)).await
+        }));
+    }
+
+    let mut commit = true;
+    let mut err = None;
+    for h in handles {
+        match h.await.expect("prepare txn panicked") {
+            Ok(TxnStatus::Pending) => {}
+            Ok(_) => commit = false,
+            Err(e) => {
+                commit = false;
+                err = Some(e);
+            }
+        }
+    }
+
+    // with only the primary group, the transaction ends once it is decided.
+    let d = TxnDecide {
+        txn_id: txn_id.to_vec(),
+        commit,
+        end: groups.len() == 1,
+    };
+    let st = pr.propose(&sd.conns, &Command::from(&d)).await?;
+
+    let mut d = TxnDecide {
+        txn_id: txn_id.to_vec(),
+        commit: st == TxnStatus::Committed,
+        end: false,
+    };
+
+    let mut ended = true;
+    let mut handles = vec![];
+    for (start, (tg, _)) in groups.iter() {
+        if *start == pg.range.0 {
+            continue;
+        }
+        let tg = tg.clone();
+        let conns = sd.conns.clone();
+        let cmd = Command::from(&d);
+        handles.push(tokio::spawn(async move { tg.propose(&conns, &cmd).await }));
+    }
+
+    for h in handles {
+        if let Err(e) = h.await.expect("decide txn panicked") {
+            // recovery decides it later.
+            warn!(
+                "{:?} while decide txn {}",
+                e,
+                String::from_utf8_lossy(txn_id)
+            );
+            ended = false;
+        }
+    }
+
+    if ended && groups.len() > 1 && st == TxnStatus::Committed {
+        // the decision is not returned by the primary group any more, after it is gc'ed.
+        d.end = true;
+        let conns = sd.conns.clone();
+        tokio::spawn(async move {
+            if let Err(e) = pr.propose(&conns, &Command::from(&d)).await {
+                warn!(
+                    "{:?} while end txn {}",
+                    e,
+                    String::from_utf8_lossy(&d.txn_id)
+                );
+            }
+        });
+    }
+
+    match err {
+        Some(e) => Err(e),
+        None => Ok(st),
+    }
+}
+
+/// prepare_txn prepares a transaction in the group of `r` and returns the vote: Pending to
+/// commit, or Aborted.
+pub async fn prepare_txn(r: &Replica, p: &TxnPrepare) -> Result<TxnStatus, ReplicationError> {
+    propose_txn_cmd(r, &Command::from(p)).await
+}
+
+/// decide_txn proposes a decision of a transaction to the group of `r` and returns the first
+/// decision the group executed.
+pub async fn decide_txn(r: &Replica, d: &TxnDecide) -> Result<TxnStatus, ReplicationError> {
+    propose_txn_cmd(r, &Command::from(d)).await
+}
+
+/// propose_txn_cmd proposes a TxnPrepare or TxnDecide command to the group of `r` and returns the
+/// result of executing it.
+pub async fn propose_txn_cmd(r: &Replica, cmd: &Command) -> Result<TxnStatus, ReplicationError> {
+    let (iid, rst) = propose_and_execute(r, cmd).await?;
+    rst.get(0)
+        .and_then(|x| x.as_ref())
+        .and_then(TxnStatus::from_record)
+        .ok_or(ReplicationError::NotExecuted(iid))
+}
+
+/// recover_txns finishes transactions pending longer than TXN_TIMEOUT on local voters, see
+/// `recover_txn`.
+/// `seen` is the time a pending transaction is first seen on a replica. It is kept by the caller
+/// across calls, and transactions no longer pending are removed from it.
+pub async fn recover_txns(sd: &ServerData, seen: &mut HashMap<(ReplicaId, Vec<u8>), Instant>) {
+    let now = Instant::now();
+    let mut pending = HashSet::new();
+
+    for r in sd.get_local_replicas() {
+        if r.is_learner() || r.is_frozen() {
+            continue;
+        }
+
+        let states = match r.pending_txns() {
+            Ok(v) => v,
+            Err(e) => {
+                error!("{:?} while list pending txns of {}", e, r.replica_id);
+                continue;
+            }
+        };
+
+        for p in states.into_iter().filter_map(|st| st.prepare) {
+            let k = (r.replica_id, p.txn_id.clone());
+            let first = *seen.entry(k.clone()).or_insert(now);
+            pending.insert(k);
+
+            if now - first < TXN_TIMEOUT {
+                continue;
+            }
+
+            if let Err(e) = recover_txn(sd, &r, &p).await {
+                warn!(
+                    "{:?} while recover txn {} on {}",
+                    e,
+                    String::from_utf8_lossy(&p.txn_id),
+                    r.replica_id
+                );
+            }
+        }
+    }
+
+    seen.retain(|k, _| pending.contains(k));
+}
+
+/// recover_txn finishes a transaction prepared in the group of `r`, whose coordinator may have
+/// quit. It proposes to abort it to the primary group, which returns the first decision it
+/// executed, then applies that decision to the group of `r`.
+/// A decision of the coordinator that wins in the primary group is kept.
+///
+/// The primary group is proposed to by a voter on another node if this node has no voter of it,
+/// see `TxnGroup`.
+pub async fn recover_txn(
+    sd: &ServerData,
+    r: &Replica,
+    p: &TxnPrepare,
+) -> Result<TxnStatus, ReplicationError> {
+    let (pg, pr) = txn_group(sd, &p.primary)?;
+
+    let abort = TxnDecide {
+        txn_id: p.txn_id.clone(),
+        commit: false,
+        end: false,
+    };
+    let st = pr.propose(&sd.conns, &Command::from(&abort)).await?;
+
+    if !pg.replicas.contains_key(&r.replica_id) {
+        let d = TxnDecide {
+            txn_id: p.txn_id.clone(),
+            commit: st == TxnStatus::Committed,
+            end: false,
+        };
+        decide_txn(r, &d).await?;
+    }
+
+    info!(
+        "recovered txn {} on {}: {:?}",
+        String::from_utf8_lossy(&p.txn_id),
+        r.replica_id,
+        st
+    );
+    Ok(st)
+}
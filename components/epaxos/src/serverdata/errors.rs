@@ -1,3 +1,8 @@
+use crate::conf::ConfError;
+use crate::qpaxos::{InvalidRequest, QError, ReplicaId, StorageFailure};
+use crate::replica::ReplicaError;
+use storage::StorageError;
+
 quick_error! {
     /// RangeLookupError defines all error occurs at server level.
     /// It also wraps lower level errors.
@@ -5,5 +10,51 @@ quick_error! {
     pub enum RangeLookupError {
         NoGroupForKey(k: String) {}
         NoLocalReplicaForKey(k: String) {}
+        /// A key is not valid UTF-8, thus it is not in any range.
+        InvalidKey(k: String) {}
+    }
+}
+
+quick_error! {
+    /// ConfigChangeError occurs when applying a range change, a membership change or a replica
+    /// migration to the cluster config.
+    #[derive(Debug)]
+    pub enum ConfigChangeError {
+        Conf(e: ConfError) {
+            from(e: ConfError) -> (e)
+        }
+        Replica(e: ReplicaError) {
+            from(e: ReplicaError) -> (e)
+        }
+        Storage(e: StorageError) {
+            from(e: StorageError) -> (e)
+        }
+        /// A replica to release has not been handed over.
+        NotFrozen(rid: ReplicaId) {
+            display("replica {} is not frozen", rid)
+        }
+    }
+}
+
+impl Into<QError> for ConfigChangeError {
+    fn into(self) -> QError {
+        match self {
+            Self::Replica(e) => e.into(),
+
+            Self::NotFrozen(rid) => QError {
+                req: Some(InvalidRequest {
+                    field: "to_replica_id".into(),
+                    problem: "NotFrozen".into(),
+                    ctx: format!("{}", rid),
+                }),
+                ..Default::default()
+            },
+
+            // TODO impl
+            _ => QError {
+                sto: Some(StorageFailure {}),
+                ..Default::default()
+            },
+        }
     }
 }
@@ -1,64 +1,372 @@
+use storage::DBColumnFamily;
 use storage::RawKV;
 
 use crate::conf::ClusterInfo;
+use crate::conf::ConfError;
 use crate::conf::GroupInfo;
 use crate::conf::Node;
 use crate::conf::NodeId;
+use crate::conf::Routing;
+use crate::qpaxos::Membership;
+use crate::qpaxos::RangeChange;
 use crate::qpaxos::ReplicaId;
+use crate::replica::ConfigChange;
 use crate::replica::Replica;
+use crate::replica::ReplicaError;
+use crate::replication::ConnPool;
+use crate::ConfigChangeError;
 use crate::RangeLookupError;
+use crate::StorageAPI;
+use std::sync::RwLock;
 use std::{collections::BTreeMap, sync::Arc};
 
+/// The key of the cluster config a node has applied, in `DBColumnFamily::Status`.
+pub const CLUSTER_KEY: &[u8] = b"/cluster";
+
 /// ServerData is shared between threads or coroutine.
 /// TODO: Storage does not need to be shared with Arc any more.
 // #[derive(Debug)]
 pub struct ServerData {
-    pub cluster: ClusterInfo,
+    /// the cluster config this node has applied. It changes when a range is split or merged, or a
+    /// replica migrates, and is stored in `CLUSTER_KEY`, which takes precedence over the config
+    /// file.
+    cluster: RwLock<ClusterInfo>,
     pub node_id: NodeId,
     pub node: Node,
-    pub local_replicas: BTreeMap<ReplicaId, Replica>,
+    /// replicas on this node. A split adds replicas and a merge removes the retired ones.
+    /// A replica migrating to this node is added when it takes over, and a replica migrating
+    /// away is removed when it is released.
+    local_replicas: RwLock<BTreeMap<ReplicaId, Arc<Replica>>>,
     pub storage: Arc<dyn RawKV>,
+    /// connections to other nodes for requests not sent by a local replica, e.g., to a group a
+    /// transaction writes that has no replica on this node.
+    pub conns: Arc<ConnPool>,
 }
 
 impl ServerData {
     pub fn new(sto: Arc<dyn RawKV>, cluster: ClusterInfo, node_id: NodeId) -> ServerData {
+        let cluster = match sto.get_raw(DBColumnFamily::Status, CLUSTER_KEY).unwrap() {
+            Some(v) => ClusterInfo::from_str(&String::from_utf8(v).unwrap()).unwrap(),
+            None => cluster,
+        };
+
         let n = cluster.get(&node_id).unwrap().clone();
 
         let mut rs = BTreeMap::new();
         for (rid, rinfo) in cluster.replicas.iter() {
             if rinfo.node_id == node_id {
                 let rp = Replica::new(*rid, &cluster, sto.clone()).unwrap();
-                rs.insert(*rid, rp);
+                rs.insert(*rid, Arc::new(rp));
             }
         }
 
-        ServerData {
-            cluster,
+        let sd = ServerData {
+            cluster: RwLock::new(cluster),
             node_id,
             node: n,
-            local_replicas: rs,
+            local_replicas: RwLock::new(rs),
             storage: sto,
+            conns: Arc::new(ConnPool::new()),
+        };
+
+        // The process may crash after executing a range change and before applying it.
+        for r in sd.get_local_replicas() {
+            if let Some(rc) = r.storage.get_range_change().unwrap() {
+                sd.apply_range_change(r.replica_id, &rc).unwrap();
+            }
         }
+
+        sd
+    }
+
+    /// get_cluster returns a copy of the cluster config this node has applied.
+    pub fn get_cluster(&self) -> ClusterInfo {
+        self.cluster.read().unwrap().clone()
+    }
+
+    /// get_local_replica returns a replica on this node.
+    pub fn get_local_replica(&self, rid: ReplicaId) -> Option<Arc<Replica>> {
+        self.local_replicas.read().unwrap().get(&rid).cloned()
+    }
+
+    /// get_local_replicas returns all replicas on this node, in ascending order of replica id.
+    pub fn get_local_replicas(&self) -> Vec<Arc<Replica>> {
+        self.local_replicas
+            .read()
+            .unwrap()
+            .values()
+            .cloned()
+            .collect()
     }
 
+    /// get_local_replica_for_key returns the group serving `key` and a replica of it on this
+    /// node. A voter is preferred to a learner.
     pub fn get_local_replica_for_key(
         &self,
         key: &[u8],
-    ) -> Result<(&GroupInfo, &Replica), RangeLookupError> {
-        let k = String::from_utf8(key.to_vec()).unwrap();
+    ) -> Result<(GroupInfo, Arc<Replica>), RangeLookupError> {
+        let k = key_to_str(key)?;
 
-        let g = self
-            .cluster
+        let cluster = self.cluster.read().unwrap();
+        let g = cluster
             .get_group_for_key(&k)
             .ok_or(RangeLookupError::NoGroupForKey(k.clone()))?;
 
-        for (rid, _) in g.replicas.iter() {
-            let replica = self.local_replicas.get(rid);
+        for (rid, nid) in g.replicas.iter().chain(g.learners.iter()) {
+            // a replica removed from this node does not serve, before it is released.
+            if *nid != self.node_id {
+                continue;
+            }
+            let replica = self.get_local_replica(*rid);
             if let Some(v) = replica {
-                return Ok((g, v));
+                return Ok((g.clone(), v));
             }
         }
 
         Err(RangeLookupError::NoLocalReplicaForKey(k.clone()))
     }
+
+    /// get_remote_node_for_key returns the group serving `key` and another node that has a voter
+    /// of it, for a key this node has no replica for. A learner is never returned, because it does
+    /// not serve writes.
+    pub fn get_remote_node_for_key(
+        &self,
+        key: &[u8],
+    ) -> Result<(GroupInfo, Node), RangeLookupError> {
+        let (g, voters) = self.get_remote_voters_for_key(key)?;
+        let n = voters[0].1.clone();
+        Ok((g, n))
+    }
+
+    /// get_remote_voters_for_key returns the group serving `key` and its voters on other nodes,
+    /// with the nodes they are on. It returns an error if there is none.
+    pub fn get_remote_voters_for_key(
+        &self,
+        key: &[u8],
+    ) -> Result<(GroupInfo, Vec<(ReplicaId, Node)>), RangeLookupError> {
+        let k = key_to_str(key)?;
+
+        let cluster = self.cluster.read().unwrap();
+        let g = cluster
+            .get_group_for_key(&k)
+            .ok_or(RangeLookupError::NoGroupForKey(k.clone()))?;
+
+        let mut voters = vec![];
+        for (rid, nid) in g.replicas.iter() {
+            if *nid == self.node_id {
+                continue;
+            }
+            if let Some(n) = cluster.get(nid) {
+                voters.push((*rid, n.clone()));
+            }
+        }
+
+        if voters.len() == 0 {
+            return Err(RangeLookupError::NoLocalReplicaForKey(k.clone()));
+        }
+
+        Ok((g.clone(), voters))
+    }
+
+    /// get_routing returns how this node serves a command on a key it has no replica for.
+    pub fn get_routing(&self) -> Routing {
+        self.cluster.read().unwrap().routing
+    }
+
+    /// add_local_replica adds a replica migrating to this node, after it takes over.
+    /// It returns the local replica with the same id if there is one.
+    pub fn add_local_replica(&self, r: Replica) -> Arc<Replica> {
+        let mut rs = self.local_replicas.write().unwrap();
+        rs.entry(r.replica_id)
+            .or_insert_with(|| Arc::new(r))
+            .clone()
+    }
+
+    /// apply_config_change applies a config change executed by local replica `rid`.
+    pub fn apply_config_change(
+        &self,
+        rid: ReplicaId,
+        cc: &ConfigChange,
+    ) -> Result<(), ConfigChangeError> {
+        match cc {
+            ConfigChange::Membership(m) => self.apply_membership(rid, m),
+            ConfigChange::Range(rc) => self.apply_range_change(rid, rc),
+        }
+    }
+
+    /// apply_membership sets the voters and learners of the group in the cluster config to the
+    /// ones in a membership executed by local replica `rid`, on the nodes they have the
+    /// replication addresses of, e.g. after a learner is added or promoted, or a replica migrates.
+    pub fn apply_membership(
+        &self,
+        rid: ReplicaId,
+        m: &Membership,
+    ) -> Result<(), ConfigChangeError> {
+        let mut cluster = self.cluster.write().unwrap();
+
+        if !set_members_of(&mut cluster, m)? {
+            return Ok(());
+        }
+        self.store_cluster(&cluster)?;
+
+        info!("replica {} applied membership to cluster: {:?}", rid, m);
+        Ok(())
+    }
+
+    /// release_replica removes a local replica and deletes its data, after it has been handed
+    /// over to a copy on node `to_node`, or after it is removed from its group, e.g. a learner on
+    /// `to_node` is promoted in place of it.
+    /// A replica handed over is moved to `to_node` in the cluster config.
+    /// Releasing a released replica does nothing.
+    pub async fn release_replica(
+        &self,
+        rid: ReplicaId,
+        to_node: &str,
+    ) -> Result<(), ConfigChangeError> {
+        let r = {
+            let mut cluster = self.cluster.write().unwrap();
+            let mut rs = self.local_replicas.write().unwrap();
+
+            let r = match rs.get(&rid) {
+                Some(r) => r.clone(),
+                None => {
+                    let released = match cluster.get_replica(rid) {
+                        Some(x) => x.node_id == to_node,
+                        None => true,
+                    };
+                    if released {
+                        return Ok(());
+                    }
+                    return Err(ReplicaError::ReplicaNotFound(rid).into());
+                }
+            };
+
+            let m = r.get_membership();
+            if m.contains(rid) || m.is_learner(rid) {
+                if !r.is_frozen() {
+                    return Err(ConfigChangeError::NotFrozen(rid));
+                }
+                cluster.move_replica(rid, to_node)?;
+            } else {
+                // the membership removing it may not have been applied yet.
+                set_members_of(&mut cluster, &m)?;
+            }
+
+            self.store_cluster(&cluster)?;
+            rs.remove(&rid);
+            r
+        };
+
+        // no request reaches it after it is removed, and it does not propose or vote any more.
+        r.purge().await?;
+
+        info!("replica {} is released to {}", rid, to_node);
+        Ok(())
+    }
+
+    /// apply_range_change applies a range change executed by local replica `rid` to the cluster
+    /// config, and stores the config.
+    ///
+    /// A split creates the replica of the new group paired with `rid`, and a merge removes the
+    /// paired replica of the retired group. Replicas of a group on the same node apply the same
+    /// change, thus it is applied to the config only once.
+    pub fn apply_range_change(
+        &self,
+        rid: ReplicaId,
+        rc: &RangeChange,
+    ) -> Result<(), ConfigChangeError> {
+        if !rc.is_split() && !rc.is_merge() {
+            return Ok(());
+        }
+
+        let mut cluster = self.cluster.write().unwrap();
+        let mut rs = self.local_replicas.write().unwrap();
+
+        if let Some(split) = rc.split.as_ref() {
+            let applied = rc.pairs.values().any(|p| cluster.get_replica(*p).is_some());
+            if !applied {
+                cluster.split_group(rid, &split.start, &rc.pairs)?;
+            }
+
+            if let Some(prid) = rc.pairs.get(&rid) {
+                if !rs.contains_key(prid) {
+                    let r = Replica::new(*prid, &cluster, self.storage.clone())?;
+                    rs.insert(*prid, Arc::new(r));
+                }
+            }
+        } else {
+            let applied = rc.pairs.values().all(|p| cluster.get_replica(*p).is_none());
+            if !applied {
+                cluster.merge_groups(rid, &rc.pairs)?;
+            }
+
+            if let Some(prid) = rc.pairs.get(&rid) {
+                rs.remove(prid);
+            }
+        }
+
+        self.store_cluster(&cluster)?;
+
+        info!("replica {} applied range change to cluster: {:?}", rid, rc);
+        Ok(())
+    }
+
+    /// store_cluster stores the cluster config this node has applied in `CLUSTER_KEY`.
+    fn store_cluster(&self, cluster: &ClusterInfo) -> Result<(), ConfigChangeError> {
+        let yaml = serde_yaml::to_string(cluster).map_err(ConfError::from)?;
+        self.storage
+            .set_raw(DBColumnFamily::Status, CLUSTER_KEY, yaml.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// key_to_str returns a key as a string to look up the range it is in.
+fn key_to_str(key: &[u8]) -> Result<String, RangeLookupError> {
+    String::from_utf8(key.to_vec())
+        .map_err(|_| RangeLookupError::InvalidKey(String::from_utf8_lossy(key).into()))
+}
+
+/// set_members_of sets the voters and learners of the group in `cluster` to the ones in `m`, on
+/// the nodes they have the replication addresses of. A replica on an unknown address stays on the
+/// node it is on in `cluster`, or is left out if it is not in `cluster`.
+/// It returns false if nothing changes.
+fn set_members_of(cluster: &mut ClusterInfo, m: &Membership) -> Result<bool, ConfError> {
+    let node_of = |rid: ReplicaId| -> Option<NodeId> {
+        let by_addr = m
+            .get_addr(rid)
+            .and_then(|addr| cluster.get_node_by_replication(addr));
+        match by_addr {
+            Some(n) => Some(n.node_id.clone()),
+            None => cluster.get_replica(rid).map(|x| x.node_id.clone()),
+        }
+    };
+
+    let members = |rids: Vec<ReplicaId>| -> BTreeMap<ReplicaId, NodeId> {
+        rids.into_iter()
+            .filter_map(|rid| node_of(rid).map(|nid| (rid, nid)))
+            .collect()
+    };
+
+    let replicas = members(m.replica_ids());
+    let learners = members(m.learner_ids());
+
+    // the group is found by any replica of it in `cluster`: a learner just added is not.
+    let grid = replicas
+        .keys()
+        .chain(learners.keys())
+        .find(|rid| cluster.get_replica(**rid).is_some())
+        .cloned();
+
+    let grid = match grid {
+        Some(v) => v,
+        None => return Ok(false),
+    };
+
+    let g = cluster.get_group(grid).unwrap();
+    if g.replicas == replicas && g.learners == learners {
+        return Ok(false);
+    }
+
+    cluster.set_members(grid, replicas, learners)?;
+    Ok(true)
 }
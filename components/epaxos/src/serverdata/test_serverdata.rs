@@ -1,6 +1,13 @@
 use crate::conf::ClusterInfo;
+use crate::conf::Routing;
+use crate::qpaxos::InstanceIds;
+use crate::qpaxos::RangeChange;
+use crate::replica::ConfigChange;
+use crate::replica::Replica;
+use crate::testutil;
 use crate::RangeLookupError;
 use crate::ServerData;
+use crate::StorageAPI;
 use std::sync::Arc;
 use storage::MemEngine;
 
@@ -31,8 +38,8 @@ groups:
         let sd = ServerData::new(sto.clone(), ci.clone(), node_id.into());
 
         let (g, r) = sd.get_local_replica_for_key("b".as_bytes()).unwrap();
-        assert_eq!(g, &ci.groups[0]);
-        assert_eq!(r.replica_id, sd.local_replicas.get(&1).unwrap().replica_id);
+        assert_eq!(g, ci.groups[0]);
+        assert_eq!(r.replica_id, sd.get_local_replica(1).unwrap().replica_id);
         let rst = sd.get_local_replica_for_key("z".as_bytes());
         assert_eq!(
             RangeLookupError::NoGroupForKey("z".into()),
@@ -48,5 +55,206 @@ groups:
             RangeLookupError::NoLocalReplicaForKey("b".into()),
             sd.get_local_replica_for_key("b".as_bytes()).err().unwrap()
         );
+
+        let (g, n) = sd.get_remote_node_for_key("b".as_bytes()).unwrap();
+        assert_eq!(ci.groups[0], g);
+        assert_eq!(ci.get("192.168.0.1:4442"), Some(&n));
+        assert_eq!(
+            RangeLookupError::NoGroupForKey("z".into()),
+            sd.get_remote_node_for_key("z".as_bytes()).err().unwrap()
+        );
+        assert_eq!(Routing::Forward, sd.get_routing());
     }
 }
+
+#[test]
+fn test_serverdata_remote_node() {
+    let ci = testutil::new_cluster("az_3_learner_1");
+    let sto = Arc::new(MemEngine::new().unwrap());
+
+    // a learner does not serve writes and is not a remote node for a key.
+    let sd = ServerData::new(sto.clone(), ci.clone(), "127.0.0.1:4441".into());
+    assert_eq!(
+        RangeLookupError::NoLocalReplicaForKey("b".into()),
+        sd.get_remote_node_for_key(b"b").err().unwrap()
+    );
+
+    let sd = ServerData::new(sto.clone(), ci.clone(), "127.0.0.1:4442".into());
+    let (_, n) = sd.get_remote_node_for_key(b"b").unwrap();
+    assert_eq!(ci.get("127.0.0.1:4441"), Some(&n));
+
+    let (g, voters) = sd.get_remote_voters_for_key(b"b").unwrap();
+    assert_eq!(ci.groups[0], g);
+    assert_eq!(
+        vec![1, 2, 3],
+        voters.iter().map(|(rid, _)| *rid).collect::<Vec<_>>()
+    );
+
+    // a key that is not UTF-8 is an error, not a panic.
+    let key = [b'b', 0xff];
+    let want = RangeLookupError::InvalidKey(String::from_utf8_lossy(&key).into());
+    assert_eq!(want, sd.get_remote_node_for_key(&key).err().unwrap());
+    assert_eq!(want, sd.get_local_replica_for_key(&key).err().unwrap());
+}
+
+#[test]
+fn test_serverdata_range_change() {
+    let ci = testutil::new_cluster("az_3");
+    let node_id = "127.0.0.1:4441";
+    let sto = Arc::new(MemEngine::new().unwrap());
+    let sd = ServerData::new(sto.clone(), ci.clone(), node_id.into());
+
+    let pairs = hashmap! {1=>11, 2=>12, 3=>13};
+    let split = RangeChange {
+        range: Some(("a", "m").into()),
+        split: Some(("m", "z").into()),
+        pairs: pairs.clone(),
+    };
+
+    sd.apply_range_change(1, &split).unwrap();
+
+    let (g, r) = sd.get_local_replica_for_key(b"n").unwrap();
+    assert_eq!(("m".to_string(), "z".to_string()), g.range);
+    assert_eq!(11, r.replica_id);
+    assert!(sd.get_local_replica(12).is_none());
+
+    let (g, r) = sd.get_local_replica_for_key(b"b").unwrap();
+    assert_eq!(("a".to_string(), "m".to_string()), g.range);
+    assert_eq!(1, r.replica_id);
+
+    // applied only once
+    sd.apply_range_change(2, &split).unwrap();
+    sd.apply_range_change(3, &split).unwrap();
+    assert_eq!(2, sd.get_cluster().groups.len());
+    assert_eq!(6, sd.get_local_replicas().len());
+
+    // the config is stored
+    let sd2 = ServerData::new(sto.clone(), ci.clone(), node_id.into());
+    assert_eq!(sd.get_cluster(), sd2.get_cluster());
+    assert_eq!(6, sd2.get_local_replicas().len());
+
+    let merge = RangeChange {
+        range: Some(("a", "z").into()),
+        split: None,
+        pairs,
+    };
+    for rid in 1..=3 {
+        sd.apply_range_change(rid, &merge).unwrap();
+    }
+    assert_eq!(ci, sd.get_cluster());
+    assert_eq!(3, sd.get_local_replicas().len());
+    assert_eq!(1, sd.get_local_replica_for_key(b"n").unwrap().1.replica_id);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_serverdata_migrate() {
+    let ci = testutil::new_cluster("az_3_remote_1");
+    let (na, nb) = ("127.0.0.1:4442", "127.0.0.1:4441");
+    let sto_a = Arc::new(MemEngine::new().unwrap());
+    let sto_b = Arc::new(MemEngine::new().unwrap());
+    let sda = ServerData::new(sto_a.clone(), ci.clone(), na.into());
+    let sdb = ServerData::new(sto_b.clone(), ci.clone(), nb.into());
+
+    // a copy of replica 1 on node b does not serve until the cluster config is switched.
+    let copy = Replica::new(1, &ci, sto_b.clone()).unwrap();
+    sdb.add_local_replica(copy);
+    assert!(sdb.get_local_replica(1).is_some());
+    assert_eq!(2, sdb.get_local_replica_for_key(b"b").unwrap().1.replica_id);
+
+    let mut m = sdb.get_local_replica(2).unwrap().get_membership();
+    m.voters.insert(1, format!("http://{}", nb));
+    for rid in 1..=3 {
+        sdb.apply_config_change(rid, &ConfigChange::Membership(m.clone()))
+            .unwrap();
+    }
+    assert_eq!(nb, sdb.get_cluster().get_replica(1).unwrap().node_id);
+    assert_eq!(1, sdb.get_local_replica_for_key(b"b").unwrap().1.replica_id);
+
+    // release the source
+    let err = sda.release_replica(1, nb).await.unwrap_err();
+    assert_eq!("replica 1 is not frozen", err.to_string());
+
+    let r1 = sda.get_local_replica(1).unwrap();
+    r1.storage.set_kv(b"x", &"a".into()).unwrap();
+    r1.hand_over(&InstanceIds::default()).await.unwrap();
+
+    sda.release_replica(1, nb).await.unwrap();
+    assert!(sda.get_local_replica(1).is_none());
+    assert_eq!(nb, sda.get_cluster().get_replica(1).unwrap().node_id);
+    assert_eq!(None, r1.storage.get_kv(b"x").unwrap());
+
+    // released again
+    sda.release_replica(1, nb).await.unwrap();
+
+    // the config is stored
+    let sda2 = ServerData::new(sto_a.clone(), ci.clone(), na.into());
+    assert_eq!(0, sda2.get_local_replicas().len());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_serverdata_promote_learner() {
+    let ci = testutil::new_cluster("az_3_remote_1");
+    let (na, nb) = ("127.0.0.1:4442", "127.0.0.1:4441");
+    let sto_a = Arc::new(MemEngine::new().unwrap());
+    let sto_b = Arc::new(MemEngine::new().unwrap());
+    let sda = ServerData::new(sto_a.clone(), ci.clone(), na.into());
+    let sdb = ServerData::new(sto_b.clone(), ci.clone(), nb.into());
+
+    let r1 = sda.get_local_replica(1).unwrap();
+    let cur = r1.get_membership();
+
+    // add learner 4 on node b
+    let mut m = cur.clone();
+    m.learners.insert(4, format!("http://{}", nb));
+    for sd in vec![&sda, &sdb] {
+        sd.apply_config_change(1, &ConfigChange::Membership(m.clone()))
+            .unwrap();
+        let c = sd.get_cluster();
+        assert_eq!(nb, c.groups[0].learners[&4]);
+        assert_eq!(&c.groups[0], c.get_group(4).unwrap());
+    }
+
+    // promote learner 4 in place of replica 1
+    let mut voters = cur.voters.clone();
+    voters.remove(&1);
+    voters.insert(4, format!("http://{}", nb));
+    let j = m.enter_joint(voters);
+
+    sdb.apply_membership(2, &j).unwrap();
+    let c = sdb.get_cluster();
+    assert_eq!(
+        vec![1, 2, 3, 4],
+        c.groups[0].replicas.keys().cloned().collect::<Vec<_>>()
+    );
+    assert_eq!(0, c.groups[0].learners.len());
+
+    let m = j.leave_joint();
+    sdb.apply_membership(2, &m).unwrap();
+    let c = sdb.get_cluster();
+    assert_eq!(
+        vec![2, 3, 4],
+        c.groups[0].replicas.keys().cloned().collect::<Vec<_>>()
+    );
+    assert!(c.get_replica(1).is_none());
+
+    // the source is released after it is removed
+    let err = sda.release_replica(1, nb).await.unwrap_err();
+    assert_eq!("replica 1 is not frozen", err.to_string());
+
+    r1.storage.set_kv(b"x", &"a".into()).unwrap();
+    r1.set_membership(m.clone());
+
+    sda.release_replica(1, nb).await.unwrap();
+    assert!(sda.get_local_replica(1).is_none());
+    assert!(sda.get_cluster().get_replica(1).is_none());
+    assert_eq!(nb, sda.get_cluster().get_replica(4).unwrap().node_id);
+    assert_eq!(None, r1.storage.get_kv(b"x").unwrap());
+
+    // released again
+    sda.release_replica(1, nb).await.unwrap();
+
+    // the config is stored
+    let sdb2 = ServerData::new(sto_b.clone(), ci.clone(), nb.into());
+    assert_eq!(sdb.get_cluster(), sdb2.get_cluster());
+    assert!(sdb2.get_local_replica(4).is_some());
+}
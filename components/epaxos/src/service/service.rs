@@ -1,11 +1,36 @@
+use crate::qpaxos::HandoverReply;
+use crate::qpaxos::HandoverRequest;
+use crate::qpaxos::InstanceIds;
+use crate::qpaxos::Membership;
+use crate::qpaxos::MembershipReply;
+use crate::qpaxos::MembershipRequest;
+use crate::qpaxos::OpCode;
+use crate::qpaxos::PingReply;
+use crate::qpaxos::PingRequest;
 use crate::qpaxos::ProtocolError;
+use crate::qpaxos::QError;
 use crate::qpaxos::QPaxos;
 use crate::qpaxos::ReplicateReply;
 use crate::qpaxos::ReplicateRequest;
+use crate::qpaxos::SnapshotChunk;
+use crate::qpaxos::SnapshotRequest;
+use crate::qpaxos::TxnReply;
+use crate::qpaxos::TxnRequest;
+use crate::qpaxos::TxnStatus;
+use crate::replica::snapshot_records;
+use crate::replica::ReplicaError;
+use crate::replica::SNAPSHOT_CHUNK_SIZE;
+use crate::replica::SNAPSHOT_SEND_TIMEOUT;
+use crate::replication::add_learner;
+use crate::replication::promote_learner;
+use crate::replication::propose_txn_cmd;
 use crate::replication::RpcHandlerError;
 use crate::ServerData;
+use std::mem::replace;
 use std::sync::Arc;
 
+use tokio::sync::mpsc;
+use tokio::time::timeout;
 use tonic;
 use tonic::{Request, Response, Status};
 
@@ -30,7 +55,7 @@ impl QPaxos for QPaxosImpl {
 
         println!("Got a ReplicateRequest: {}", req);
 
-        let reply = handle_replicate_request(self, req);
+        let reply = handle_replicate_request(self, req).await;
         let reply = match reply {
             Ok(v) => v,
             Err(e) => ReplicateReply {
@@ -40,16 +65,261 @@ impl QPaxos for QPaxosImpl {
         };
         Ok(Response::new(reply))
     }
+
+    async fn ping(&self, request: Request<PingRequest>) -> Result<Response<PingReply>, Status> {
+        let req = request.into_inner();
+        let rid = req.to_replica_id;
+
+        let reply = match self.server_data.get_local_replica(rid) {
+            Some(r) => {
+                // a frozen replica does not store instances any more, its copy does.
+                let maxs = if r.is_frozen() {
+                    None
+                } else {
+                    let maxs = r.get_max_instance_ids(&r.get_group_replica_ids());
+                    Some(InstanceIds::from(&maxs[..]))
+                };
+
+                PingReply {
+                    err: None,
+                    executed: r.get_executed().ok(),
+                    gc_watermark: Some(r.get_gc_watermark()),
+                    max_instance_ids: maxs,
+                }
+            }
+            None => {
+                let e: RpcHandlerError = ProtocolError::NoSuchReplica(rid, 0).into();
+                PingReply {
+                    err: Some(e.into()),
+                    executed: None,
+                    gc_watermark: None,
+                    max_instance_ids: None,
+                }
+            }
+        };
+        Ok(Response::new(reply))
+    }
+
+    type SnapshotStream = mpsc::Receiver<Result<SnapshotChunk, Status>>;
+
+    /// snapshot streams records of a replica in chunks of at most SNAPSHOT_CHUNK_SIZE records.
+    /// The first chunk carries the exec status.
+    /// Records are read from a storage snapshot while sending, no lock of the replica is held.
+    /// Streaming stops if a chunk is not sent in SNAPSHOT_SEND_TIMEOUT.
+    async fn snapshot(
+        &self,
+        request: Request<SnapshotRequest>,
+    ) -> Result<Response<Self::SnapshotStream>, Status> {
+        let req = request.into_inner();
+        let rid = req.to_replica_id;
+        let sd = self.server_data.clone();
+
+        let (mut tx, rx) = mpsc::channel(4);
+
+        tokio::spawn(async move {
+            let r = match sd.get_local_replica(rid) {
+                Some(r) => r,
+                None => {
+                    let e: RpcHandlerError = ProtocolError::NoSuchReplica(rid, 0).into();
+                    let _ = tx.send(Ok(snapshot_err(e))).await;
+                    return;
+                }
+            };
+
+            let (executed, sto) = match r.open_snapshot().await {
+                Ok(v) => v,
+                Err(e) => {
+                    let _ = tx.send(Ok(snapshot_err(e.into()))).await;
+                    return;
+                }
+            };
+
+            let mut chunk = SnapshotChunk {
+                err: None,
+                executed: Some(executed),
+                records: vec![],
+            };
+
+            for rcd in snapshot_records(&sto) {
+                let rcd = match rcd {
+                    Ok(v) => v,
+                    Err(e) => {
+                        let _ = tx.send(Ok(snapshot_err(e.into()))).await;
+                        return;
+                    }
+                };
+
+                chunk.records.push(rcd);
+                if chunk.records.len() >= SNAPSHOT_CHUNK_SIZE {
+                    let c = replace(&mut chunk, SnapshotChunk::default());
+                    match timeout(SNAPSHOT_SEND_TIMEOUT, tx.send(Ok(c))).await {
+                        Ok(Ok(_)) => {}
+                        // the receiver quit or stalled
+                        _ => return,
+                    }
+                }
+            }
+
+            let _ = timeout(SNAPSHOT_SEND_TIMEOUT, tx.send(Ok(chunk))).await;
+        });
+
+        Ok(Response::new(rx))
+    }
+
+    /// handover freezes a local replica and returns what a copy of it on another node needs to
+    /// take over, or releases a replica the copy has taken over.
+    async fn handover(
+        &self,
+        request: Request<HandoverRequest>,
+    ) -> Result<Response<HandoverReply>, Status> {
+        let req = request.into_inner();
+
+        let reply = match handle_handover_request(self, req).await {
+            Ok(v) => v,
+            Err(e) => HandoverReply {
+                err: Some(e),
+                ..Default::default()
+            },
+        };
+        Ok(Response::new(reply))
+    }
+
+    /// txn proposes a transaction command to the group of a local voter, for a node without a
+    /// voter of the group.
+    async fn txn(&self, request: Request<TxnRequest>) -> Result<Response<TxnReply>, Status> {
+        let req = request.into_inner();
+
+        let reply = match handle_txn_request(self, req).await {
+            Ok(status) => TxnReply {
+                err: None,
+                status: status as i32,
+            },
+            Err(e) => TxnReply {
+                err: Some(e),
+                ..Default::default()
+            },
+        };
+        Ok(Response::new(reply))
+    }
+
+    /// membership adds a learner to the group of a local voter, or promotes the learner in place
+    /// of the voter, for a replica migrating to another node.
+    async fn membership(
+        &self,
+        request: Request<MembershipRequest>,
+    ) -> Result<Response<MembershipReply>, Status> {
+        let req = request.into_inner();
+
+        let reply = match handle_membership_request(self, req).await {
+            Ok(m) => MembershipReply {
+                err: None,
+                membership: Some(m),
+            },
+            Err(e) => MembershipReply {
+                err: Some(e),
+                ..Default::default()
+            },
+        };
+        Ok(Response::new(reply))
+    }
 }
 
-pub fn handle_replicate_request(
+pub async fn handle_replicate_request(
     sv: &QPaxosImpl,
     req: ReplicateRequest,
 ) -> Result<ReplicateReply, RpcHandlerError> {
     // TODO test replica not found
     let rid = req.to_replica_id;
-    let r = sv.server_data.local_replicas.get(&rid);
+    let r = sv.server_data.get_local_replica(rid);
     let r = r.ok_or(ProtocolError::NoSuchReplica(rid, 0))?;
 
-    r.handle_replicate(req)
+    r.handle_replicate(req).await
+}
+
+pub async fn handle_handover_request(
+    sv: &QPaxosImpl,
+    req: HandoverRequest,
+) -> Result<HandoverReply, QError> {
+    let rid = req.to_replica_id;
+    let sd = &sv.server_data;
+
+    if req.release {
+        sd.release_replica(rid, &req.to_node)
+            .await
+            .map_err(|e| -> QError { e.into() })?;
+        return Ok(HandoverReply::default());
+    }
+
+    let after = req
+        .after
+        .ok_or_else(|| -> QError { ProtocolError::LackOf("after".into()).into() })?;
+
+    let r = sd
+        .get_local_replica(rid)
+        .ok_or_else(|| -> QError { ProtocolError::NoSuchReplica(rid, 0).into() })?;
+
+    r.hand_over(&after).await.map_err(|e| e.into())
+}
+
+pub async fn handle_txn_request(sv: &QPaxosImpl, req: TxnRequest) -> Result<TxnStatus, QError> {
+    let rid = req.to_replica_id;
+
+    let cmd = req
+        .cmd
+        .ok_or_else(|| -> QError { ProtocolError::LackOf("cmd".into()).into() })?;
+
+    match cmd.op() {
+        OpCode::TxnPrepare | OpCode::TxnDecide => {}
+        op => {
+            let e = ProtocolError::NotMatch(
+                "cmd.op".into(),
+                "TxnPrepare|TxnDecide".into(),
+                format!("{:?}", op),
+            );
+            return Err(e.into());
+        }
+    }
+
+    let r = sv
+        .server_data
+        .get_local_replica(rid)
+        .ok_or_else(|| -> QError { ProtocolError::NoSuchReplica(rid, 0).into() })?;
+
+    if r.is_learner() {
+        return Err(ReplicaError::Learner(rid).into());
+    }
+
+    propose_txn_cmd(&r, &cmd).await.map_err(|e| e.into())
+}
+
+pub async fn handle_membership_request(
+    sv: &QPaxosImpl,
+    req: MembershipRequest,
+) -> Result<Membership, QError> {
+    let rid = req.to_replica_id;
+
+    let r = sv
+        .server_data
+        .get_local_replica(rid)
+        .ok_or_else(|| -> QError { ProtocolError::NoSuchReplica(rid, 0).into() })?;
+
+    if r.is_learner() {
+        return Err(ReplicaError::Learner(rid).into());
+    }
+
+    let rst = if req.promote {
+        promote_learner(&r, req.learner_id, rid).await
+    } else {
+        add_learner(&r, req.learner_id, req.learner_addr).await
+    };
+
+    rst.map_err(|e| e.into())
+}
+
+/// snapshot_err builds a snapshot chunk that carries only an error.
+fn snapshot_err(e: RpcHandlerError) -> SnapshotChunk {
+    SnapshotChunk {
+        err: Some(e.into()),
+        ..Default::default()
+    }
 }
@@ -4,6 +4,7 @@ use std::sync::Arc;
 use std::thread::sleep;
 use std::time::Duration;
 
+use crate::conf::Executor;
 use crate::qpaxos::*;
 use crate::replica::{Replica, ReplicaPeer};
 use crate::QPaxosImpl;
@@ -12,7 +13,6 @@ use storage::RawKV;
 use storage::Storage;
 
 use tokio::sync::oneshot;
-use tokio::sync::Mutex;
 use tokio::time::delay_for;
 use tonic::transport::Server;
 
@@ -55,14 +55,23 @@ pub fn new_replica(
     peers: Vec<ReplicaPeer>,
     sto: Arc<dyn RawKV>,
 ) -> Replica {
-    Replica {
-        replica_id: rid,
-        group_replica_ids: group,
-        peers,
-        storage: Storage::new(rid, sto),
-        committed_timeout: 1000,
-        waiting_replies: Mutex::new(HashMap::new()),
+    // replicas in `group` that are not peers have no address.
+    let mut membership = Membership::from(&group[..]);
+    for p in peers.iter() {
+        membership.voters.insert(p.replica_id, p.addr.clone());
     }
+
+    // a replica serves every key unless its range has been changed.
+    let mut r = Replica::with_config(
+        rid,
+        Storage::new(rid, sto),
+        membership,
+        None,
+        Executor::default(),
+    )
+    .unwrap();
+    r.committed_timeout = 1000;
+    r
 }
 
 pub struct TestCluster {
@@ -37,6 +37,112 @@ groups:
         3: 127.0.0.1:4441
 ");
 
+        h.insert("az_5", "
+nodes:
+    127.0.0.1:4441:
+        api_addr: 127.0.0.1:6379
+        replication: 127.0.0.1:4441
+groups:
+-   range:
+    -   a
+    -   z
+    replicas:
+        1: 127.0.0.1:4441
+        2: 127.0.0.1:4441
+        3: 127.0.0.1:4441
+        4: 127.0.0.1:4441
+        5: 127.0.0.1:4441
+");
+
+        h.insert("az_3_remote_1", "
+nodes:
+    127.0.0.1:4441:
+        api_addr: 127.0.0.1:6379
+        replication: 127.0.0.1:4441
+    127.0.0.1:4442:
+        api_addr: 127.0.0.1:6380
+        replication: 127.0.0.1:4442
+groups:
+-   range:
+    -   a
+    -   z
+    replicas:
+        1: 127.0.0.1:4442
+        2: 127.0.0.1:4441
+        3: 127.0.0.1:4441
+");
+
+        h.insert("az_3_learner_1", "
+nodes:
+    127.0.0.1:4441:
+        api_addr: 127.0.0.1:6379
+        replication: 127.0.0.1:4441
+    127.0.0.1:4442:
+        api_addr: 127.0.0.1:6380
+        replication: 127.0.0.1:4442
+groups:
+-   range:
+    -   a
+    -   z
+    replicas:
+        1: 127.0.0.1:4441
+        2: 127.0.0.1:4441
+        3: 127.0.0.1:4441
+    learners:
+        4: 127.0.0.1:4442
+");
+
+        h.insert("an_nz_2", "
+nodes:
+    127.0.0.1:4441:
+        api_addr: 127.0.0.1:6379
+        replication: 127.0.0.1:4441
+    127.0.0.1:4442:
+        api_addr: 127.0.0.1:6380
+        replication: 127.0.0.1:4442
+groups:
+-   range:
+    -   a
+    -   n
+    replicas:
+        1: 127.0.0.1:4441
+        2: 127.0.0.1:4441
+        3: 127.0.0.1:4441
+-   range:
+    -   n
+    -   z
+    replicas:
+        4: 127.0.0.1:4442
+        5: 127.0.0.1:4442
+        6: 127.0.0.1:4442
+");
+
+        h.insert("an_nz_2_redirect", "
+routing: redirect
+nodes:
+    127.0.0.1:4441:
+        api_addr: 127.0.0.1:6379
+        replication: 127.0.0.1:4441
+    127.0.0.1:4442:
+        api_addr: 127.0.0.1:6380
+        replication: 127.0.0.1:4442
+groups:
+-   range:
+    -   a
+    -   n
+    replicas:
+        1: 127.0.0.1:4441
+        2: 127.0.0.1:4441
+        3: 127.0.0.1:4441
+-   range:
+    -   n
+    -   z
+    replicas:
+        4: 127.0.0.1:4442
+        5: 127.0.0.1:4442
+        6: 127.0.0.1:4442
+");
+
         h
     };
 }
@@ -46,6 +152,13 @@ groups:
 /// Available names are:
 /// az_1: to create a cluster with 1 group of replica 1 covers key from `[a, z)`.
 /// az_3: to create a cluster with 1 group of replica 1, 2, 3 covers key from `[a, z)`.
+/// az_5: to create a cluster with 1 group of replica 1, 2, 3, 4, 5 covers key from `[a, z)`.
+/// az_3_remote_1: the same as az_3 except replica 1 is on another node `127.0.0.1:4442`.
+/// az_3_learner_1: the same as az_3 with a learner 4 on another node `127.0.0.1:4442`.
+/// an_nz_2: to create a cluster with 2 nodes: replica 1, 2, 3 on `127.0.0.1:4441` cover `[a, n)`
+/// and replica 4, 5, 6 on `127.0.0.1:4442` cover `[n, z)`.
+/// an_nz_2_redirect: the same as an_nz_2 except a command is redirected with `REDIRECT` instead
+/// of forwarded.
 pub fn new_cluster(name: &str) -> ClusterInfo {
     let yaml = LOCAL_CLUSTERS[name];
     ClusterInfo::from_str(yaml).unwrap()
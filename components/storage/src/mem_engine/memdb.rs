@@ -1,6 +1,7 @@
 use std::collections::{BTreeMap, HashMap};
 use std::ops::Bound::Included;
 use std::ops::Bound::Unbounded;
+use std::sync::Arc;
 use std::sync::Mutex;
 
 use crate::{DBColumnFamily, MemEngine, RawKV, StorageError, WriteEntry};
@@ -67,22 +68,33 @@ impl RawKV for MemEngine {
         Ok(None)
     }
 
-    // TODO now just execute these commands in order
+    // Entries are applied with the lock held, thus a snapshot never sees a half-written batch.
     fn write_batch(&self, entrys: &Vec<WriteEntry>) -> Result<(), StorageError> {
+        let mut cfs = self._db.lock().unwrap();
         for en in entrys {
             match en {
                 WriteEntry::Nil => {}
                 WriteEntry::Set(cf, k, v) => {
-                    self.set_raw(*cf, k, v).unwrap();
+                    let bt = cfs.entry(cf.into()).or_insert(BTreeMap::new());
+                    bt.insert(k.clone(), v.clone());
                 }
                 WriteEntry::Delete(cf, k) => {
-                    self.delete_raw(*cf, k).unwrap();
+                    let bt = cfs.entry(cf.into()).or_insert(BTreeMap::new());
+                    bt.remove(k);
                 }
             }
         }
 
         Ok(())
     }
+
+    /// snapshot copies the whole db. It is meant for tests and small data sets.
+    fn snapshot(&self) -> Result<Arc<dyn RawKV>, StorageError> {
+        let cfs = self._db.lock().unwrap();
+        Ok(Arc::new(MemEngine {
+            _db: Mutex::new(cfs.clone()),
+        }))
+    }
 }
 
 #[cfg(test)]
@@ -1,8 +1,10 @@
+use std::sync::Arc;
+
 use super::open;
 use crate::DBColumnFamily;
 use crate::WriteEntry;
-use crate::{RawKV, RocksDBEngine, StorageError};
-use rocksdb::{CFHandle, SeekKey, Writable, WriteBatch};
+use crate::{RawKV, RocksDBEngine, RocksDBSnapshot, StorageError};
+use rocksdb::{CFHandle, DBIterator, ReadOptions, SeekKey, Snapshot, Writable, WriteBatch, DB};
 
 impl RocksDBEngine {
     /// Open a Engine base on rocksdb to use snapshot.
@@ -24,15 +26,12 @@ impl RocksDBEngine {
     pub fn new(path: &str) -> Result<RocksDBEngine, StorageError> {
         let db = open(path)?;
 
-        Ok(RocksDBEngine { db })
+        Ok(RocksDBEngine { db: Arc::new(db) })
     }
 
     /// make rocksdb column family handle
     fn _get_cf_handle(&self, cf: DBColumnFamily) -> Result<&CFHandle, StorageError> {
-        match self.db.cf_handle(cf.into()) {
-            Some(h) => Ok(h),
-            None => Err(format!("got column family {:?} handle failed", cf).into()),
-        }
+        get_cf_handle(&self.db, cf)
     }
 
     // TODO merge it into next()
@@ -45,42 +44,57 @@ impl RocksDBEngine {
     ) -> Result<Option<(Vec<u8>, Vec<u8>)>, StorageError> {
         let cf = self._get_cf_handle(cf)?;
         let mut iter = self.db.iter_cf(cf);
+        seek(&mut iter, key, include, reverse)
+    }
+}
 
-        let rst = iter.seek(SeekKey::from(&key[..]));
-        let valid = match rst {
-            Ok(valid) => valid,
-            Err(estr) => {
-                return Err(estr.into());
-            }
-        };
+fn get_cf_handle(db: &DB, cf: DBColumnFamily) -> Result<&CFHandle, StorageError> {
+    match db.cf_handle(cf.into()) {
+        Some(h) => Ok(h),
+        None => Err(format!("got column family {:?} handle failed", cf).into()),
+    }
+}
 
-        if !valid {
-            return Ok(None);
+fn seek(
+    iter: &mut DBIterator<&DB>,
+    key: &[u8],
+    include: bool,
+    reverse: bool,
+) -> Result<Option<(Vec<u8>, Vec<u8>)>, StorageError> {
+    let rst = iter.seek(SeekKey::from(&key[..]));
+    let valid = match rst {
+        Ok(valid) => valid,
+        Err(estr) => {
+            return Err(estr.into());
         }
+    };
 
-        let k = iter.key();
-        let v = iter.value();
-        if include {
-            return Ok(Some((k.to_vec(), v.to_vec())));
-        }
+    if !valid {
+        return Ok(None);
+    }
 
-        if k != key {
-            return Ok(Some((k.to_vec(), v.to_vec())));
-        }
+    let k = iter.key();
+    let v = iter.value();
+    if include {
+        return Ok(Some((k.to_vec(), v.to_vec())));
+    }
 
-        let valid = {
-            if reverse {
-                iter.prev()?
-            } else {
-                iter.next()?
-            }
-        };
-        if !valid {
-            return Ok(None);
-        }
+    if k != key {
+        return Ok(Some((k.to_vec(), v.to_vec())));
+    }
 
-        Ok(Some((iter.key().to_vec(), iter.value().to_vec())))
+    let valid = {
+        if reverse {
+            iter.prev()?
+        } else {
+            iter.next()?
+        }
+    };
+    if !valid {
+        return Ok(None);
     }
+
+    Ok(Some((iter.key().to_vec(), iter.value().to_vec())))
 }
 
 impl RawKV for RocksDBEngine {
@@ -128,6 +142,52 @@ impl RawKV for RocksDBEngine {
 
         Ok(self.db.write(&batch)?)
     }
+
+    fn snapshot(&self) -> Result<Arc<dyn RawKV>, StorageError> {
+        Ok(Arc::new(RocksDBSnapshot {
+            db: self.db.clone(),
+            snap: Arc::new(Snapshot::new(self.db.clone())),
+        }))
+    }
+}
+
+impl RawKV for RocksDBSnapshot {
+    fn set_raw(&self, _cf: DBColumnFamily, _key: &[u8], _value: &[u8]) -> Result<(), StorageError> {
+        Err("snapshot is read-only".to_string().into())
+    }
+
+    fn get_raw(&self, cf: DBColumnFamily, key: &[u8]) -> Result<Option<Vec<u8>>, StorageError> {
+        let cfh = get_cf_handle(&self.db, cf)?;
+        let r = self.snap.get_cf(cfh, key)?;
+        Ok(r.map(|x| x.to_vec()))
+    }
+
+    fn delete_raw(&self, _cf: DBColumnFamily, _key: &[u8]) -> Result<(), StorageError> {
+        Err("snapshot is read-only".to_string().into())
+    }
+
+    fn next_raw(
+        &self,
+        cf: DBColumnFamily,
+        key: &[u8],
+        forward: bool,
+        include: bool,
+    ) -> Result<Option<(Vec<u8>, Vec<u8>)>, StorageError> {
+        let cfh = get_cf_handle(&self.db, cf)?;
+        let mut iter = self.snap.iter_cf(cfh, ReadOptions::new());
+        seek(&mut iter, key, include, !forward)
+    }
+
+    fn write_batch(&self, _entrys: &Vec<WriteEntry>) -> Result<(), StorageError> {
+        Err("snapshot is read-only".to_string().into())
+    }
+
+    fn snapshot(&self) -> Result<Arc<dyn RawKV>, StorageError> {
+        Ok(Arc::new(RocksDBSnapshot {
+            db: self.db.clone(),
+            snap: self.snap.clone(),
+        }))
+    }
 }
 
 #[cfg(test)]
@@ -1,4 +1,6 @@
-use rocksdb::DB;
+use std::sync::Arc;
+
+use rocksdb::{Snapshot, DB};
 
 mod rocks;
 use rocks::*;
@@ -7,5 +9,11 @@ mod engine;
 pub use engine::*;
 
 pub struct RocksDBEngine {
-    db: DB,
+    db: Arc<DB>,
+}
+
+/// RocksDBSnapshot is a read-only point-in-time view of a RocksDBEngine.
+pub struct RocksDBSnapshot {
+    db: Arc<DB>,
+    snap: Arc<Snapshot<Arc<DB>>>,
 }
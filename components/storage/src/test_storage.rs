@@ -194,6 +194,26 @@ pub fn test_base_trait(eng: &dyn RawKV) {
 
     eng.write_batch(&cmds).unwrap();
     assert_eq!(None, eng.get_raw(DBColumnFamily::Record, &k1).unwrap());
+
+    // a snapshot does not see later writes.
+    let snap = eng.snapshot().unwrap();
+    eng.set_raw(DBColumnFamily::Record, &k1, &v1).unwrap();
+    eng.set_raw(DBColumnFamily::Status, &k2, &v1).unwrap();
+
+    assert_eq!(None, snap.get_raw(DBColumnFamily::Record, &k1).unwrap());
+    assert_eq!(
+        None,
+        snap.next_raw(DBColumnFamily::Record, &k1, true, true)
+            .unwrap()
+    );
+    assert_eq!(
+        Some(v2.clone()),
+        snap.get_raw(DBColumnFamily::Status, &k2).unwrap()
+    );
+    assert_eq!(
+        Some(v1.clone()),
+        eng.get_raw(DBColumnFamily::Status, &k2).unwrap()
+    );
 }
 
 pub fn test_objectkv_trait(eng: &Storage) {
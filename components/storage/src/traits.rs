@@ -141,6 +141,9 @@ pub trait RawKV: Send + Sync {
     ) -> Result<Option<(Vec<u8>, Vec<u8>)>, StorageError>;
 
     fn write_batch(&self, entrys: &Vec<WriteEntry>) -> Result<(), StorageError>;
+
+    /// snapshot returns a read-only point-in-time view that is not affected by later writes.
+    fn snapshot(&self) -> Result<Arc<dyn RawKV>, StorageError>;
 }
 
 /// ObjectKV defines access API to access object like KV and provides namespace in order to share a storage with several user.
@@ -251,6 +254,15 @@ impl Storage {
     pub fn get_inner(&self) -> &Arc<dyn RawKV> {
         &self.inner
     }
+
+    /// get_snapshot returns a read-only Storage in the same namespace, on a snapshot of the
+    /// underlying engine.
+    pub fn get_snapshot(&self) -> Result<Storage, StorageError> {
+        Ok(Storage {
+            ns: self.ns.clone(),
+            inner: self.inner.snapshot()?,
+        })
+    }
 }
 
 impl WithNameSpace for Storage {
@@ -301,6 +313,10 @@ impl RawKV for Storage {
         let inn = self.get_inner();
         inn.write_batch(&entrys)
     }
+
+    fn snapshot(&self) -> Result<Arc<dyn RawKV>, StorageError> {
+        self.get_inner().snapshot()
+    }
 }
 
 impl ObjectKV for Storage {}
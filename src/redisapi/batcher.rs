@@ -0,0 +1,391 @@
+use std::collections::HashMap;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use futures::stream::FuturesUnordered;
+use futures::StreamExt;
+use tokio::sync::mpsc;
+use tokio::sync::oneshot;
+use tokio::time::timeout;
+
+use epaxos::qpaxos::Command;
+use epaxos::qpaxos::Instance;
+use epaxos::qpaxos::InstanceId;
+use epaxos::qpaxos::Record;
+use epaxos::qpaxos::ReplicaId;
+use epaxos::replica::ReplicaError;
+use epaxos::replicate;
+use epaxos::RangeLookupError;
+use epaxos::ServerData;
+
+use crate::RedisApiError;
+
+/// The time a batcher waits for more commands after receiving the first one of a batch.
+pub const BATCH_WINDOW: Duration = Duration::from_millis(1);
+
+/// The max number of commands in one instance.
+pub const MAX_BATCH_SIZE: usize = 128;
+
+/// The max number of instances a local replica replicates concurrently.
+pub const MAX_INFLIGHT: usize = 16;
+
+/// The max number of commands waiting to be batched. Proposing blocks when it is full.
+/// It is one batch: when the pipeline is full, a client is paused as soon as the next batch is
+/// ready.
+pub const MAX_QUEUED: usize = MAX_BATCH_SIZE;
+
+/// BatchConfig defines how commands are batched and pipelined.
+#[derive(Debug, Clone, Copy)]
+pub struct BatchConfig {
+    /// the time to wait for more commands after receiving the first one of a batch.
+    pub window: Duration,
+    /// the max number of commands in one instance.
+    pub max_size: usize,
+    /// the max number of instances being replicated at the same time.
+    pub max_inflight: usize,
+    /// the max number of commands waiting to be batched.
+    pub max_queued: usize,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        Self {
+            window: BATCH_WINDOW,
+            max_size: MAX_BATCH_SIZE,
+            max_inflight: MAX_INFLIGHT,
+            max_queued: MAX_QUEUED,
+        }
+    }
+}
+
+/// PipelineStats is the depth of the proposal pipeline of a local replica.
+#[derive(Debug, Default)]
+pub struct PipelineStats {
+    /// number of instances being replicated.
+    pub inflight: AtomicUsize,
+    /// the max `inflight` ever reached.
+    pub peak: AtomicUsize,
+}
+
+// Only the batching task updates stats.
+impl PipelineStats {
+    fn incr(&self) {
+        let n = self.inflight.fetch_add(1, Ordering::SeqCst) + 1;
+        if n > self.peak.load(Ordering::SeqCst) {
+            self.peak.store(n, Ordering::SeqCst);
+        }
+    }
+
+    fn decr(&self) {
+        self.inflight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// ProposeReply is what a client receives for a proposed command: the result of executing it.
+pub type ProposeReply = Result<Option<Record>, RedisApiError>;
+
+/// Proposal is a client command waiting to be replicated.
+struct Proposal {
+    cmd: Command,
+    tx: oneshot::Sender<ProposeReply>,
+}
+
+/// Pipeline is the entry of the proposal pipeline of a local replica.
+struct Pipeline {
+    tx: mpsc::Sender<Proposal>,
+    stats: Arc<PipelineStats>,
+}
+
+/// Batcher collects commands proposed to local replicas.
+/// Commands proposed to one replica concurrently are replicated as one instance: a batch is
+/// closed after `window` since its first command, or when it has `max_size` commands.
+///
+/// There is one batching task for every local replica. It replicates at most `max_inflight`
+/// batches at the same time. When the pipeline is full, at most `max_queued` commands are queued,
+/// and then `enqueue()` blocks, which pauses reading from the client connection.
+///
+/// The task of a replica created by a range split is spawned when a command is proposed to it.
+#[derive(Clone)]
+pub struct Batcher {
+    cfg: BatchConfig,
+    sd: Arc<ServerData>,
+    commit_sender: mpsc::Sender<(ReplicaId, Instance)>,
+    pipelines: Arc<Mutex<HashMap<ReplicaId, Pipeline>>>,
+}
+
+impl Batcher {
+    /// new spawns a batching task for every local replica.
+    /// A task quits when the Batcher and all its clones are dropped.
+    pub fn new(sd: Arc<ServerData>, commit_sender: mpsc::Sender<(ReplicaId, Instance)>) -> Self {
+        Self::with_config(sd, commit_sender, BatchConfig::default())
+    }
+
+    /// with_config is the same as new except how commands are batched is specified.
+    pub fn with_config(
+        sd: Arc<ServerData>,
+        commit_sender: mpsc::Sender<(ReplicaId, Instance)>,
+        cfg: BatchConfig,
+    ) -> Self {
+        let b = Self {
+            cfg,
+            sd: sd.clone(),
+            commit_sender,
+            pipelines: Arc::new(Mutex::new(HashMap::new())),
+        };
+
+        {
+            let mut pipelines = b.pipelines.lock().unwrap();
+            for r in sd.get_local_replicas() {
+                pipelines.insert(r.replica_id, b.spawn_pipeline(r.replica_id));
+            }
+        }
+
+        b
+    }
+
+    /// spawn_pipeline spawns the batching task of a local replica.
+    fn spawn_pipeline(&self, rid: ReplicaId) -> Pipeline {
+        let (tx, rx) = mpsc::channel(self.cfg.max_queued);
+        let stats = Arc::new(PipelineStats::default());
+
+        let fut = run_batcher(
+            self.sd.clone(),
+            rid,
+            rx,
+            self.commit_sender.clone(),
+            self.cfg,
+            stats.clone(),
+        );
+        tokio::spawn(fut);
+
+        Pipeline { tx, stats }
+    }
+
+    /// stats returns `(replica_id, inflight, peak)` of the pipeline of every local replica.
+    pub fn stats(&self) -> Vec<(ReplicaId, usize, usize)> {
+        let mut rst: Vec<_> = self
+            .pipelines
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(rid, p)| {
+                (
+                    *rid,
+                    p.stats.inflight.load(Ordering::SeqCst),
+                    p.stats.peak.load(Ordering::SeqCst),
+                )
+            })
+            .collect();
+        rst.sort();
+        rst
+    }
+
+    pub fn get_config(&self) -> &BatchConfig {
+        &self.cfg
+    }
+
+    /// propose adds a command to the current batch of a local replica and waits until the batch
+    /// is executed, see `propose_batch`.
+    pub async fn propose(&self, rid: ReplicaId, cmd: Command) -> ProposeReply {
+        let rx = self.enqueue(rid, cmd).await?;
+        rx.await?
+    }
+
+    /// enqueue adds a command to the current batch of a local replica without waiting for the
+    /// batch, and returns the receiver of the reply, see `propose`.
+    /// Commands enqueued one after another are proposed in the same order.
+    /// It blocks when the queue of the replica is full.
+    pub async fn enqueue(
+        &self,
+        rid: ReplicaId,
+        cmd: Command,
+    ) -> Result<oneshot::Receiver<ProposeReply>, RedisApiError> {
+        let mut btx = {
+            let mut pipelines = self.pipelines.lock().unwrap();
+            if !pipelines.contains_key(&rid) {
+                self.sd
+                    .get_local_replica(rid)
+                    .ok_or(ReplicaError::ReplicaNotFound(rid))?;
+                pipelines.insert(rid, self.spawn_pipeline(rid));
+            }
+            pipelines[&rid].tx.clone()
+        };
+
+        let (tx, rx) = oneshot::channel();
+        if btx.send(Proposal { cmd, tx }).await.is_err() {
+            return Err(RedisApiError::ExecCommandError(format!(
+                "batcher of {} quit",
+                rid
+            )));
+        }
+
+        Ok(rx)
+    }
+}
+
+async fn run_batcher(
+    sd: Arc<ServerData>,
+    rid: ReplicaId,
+    mut rx: mpsc::Receiver<Proposal>,
+    commit_sender: mpsc::Sender<(ReplicaId, Instance)>,
+    cfg: BatchConfig,
+    stats: Arc<PipelineStats>,
+) {
+    let mut inflight = FuturesUnordered::new();
+
+    loop {
+        // Stop taking commands until a slot in the pipeline is released.
+        if inflight.len() >= cfg.max_inflight {
+            inflight.next().await;
+            stats.decr();
+            continue;
+        }
+
+        tokio::select! {
+            v = rx.recv() => {
+                let first = match v {
+                    Some(p) => p,
+                    None => {
+                        info!("exit batcher of {} with the sender had been dropped", rid);
+                        break;
+                    }
+                };
+
+                let batch = collect_batch(&mut rx, first, &cfg).await;
+
+                stats.incr();
+                inflight.push(propose_batch(sd.clone(), rid, batch, commit_sender.clone()));
+            },
+            Some(_) = inflight.next(), if inflight.len() > 0 => {
+                stats.decr();
+            }
+        }
+    }
+
+    while let Some(_) = inflight.next().await {
+        stats.decr();
+    }
+}
+
+/// collect_batch receives commands following `first` until the batch is full or the batch window
+/// is over.
+async fn collect_batch(
+    rx: &mut mpsc::Receiver<Proposal>,
+    first: Proposal,
+    cfg: &BatchConfig,
+) -> Vec<Proposal> {
+    let mut batch = vec![first];
+    let deadline = Instant::now() + cfg.window;
+
+    while batch.len() < cfg.max_size {
+        let remain = deadline.saturating_duration_since(Instant::now());
+        match timeout(remain, rx.recv()).await {
+            Ok(Some(p)) => batch.push(p),
+            Ok(None) | Err(_) => break,
+        }
+    }
+
+    batch
+}
+
+/// propose_batch replicates commands in `batch` as one instance and sends every client the result
+/// of its command, after the instance is executed.
+///
+/// A write is replied after it is executed but not after it is committed: a write ordered after a
+/// range change that moves its key away is not applied, and its client receives an error instead.
+/// A command on a key the replica no longer owns fails before replicating, without failing the
+/// others in the batch.
+async fn propose_batch(
+    sd: Arc<ServerData>,
+    rid: ReplicaId,
+    batch: Vec<Proposal>,
+    mut commit_sender: mpsc::Sender<(ReplicaId, Instance)>,
+) {
+    // a key may be moved to another group by a range change since the command was routed. Only
+    // such commands fail, the others in the batch are replicated.
+    let batch = match sd.get_local_replica(rid) {
+        Some(r) => {
+            let (owned, moved): (Vec<_>, Vec<_>) =
+                batch.into_iter().partition(|p| r.owns(&p.cmd.key));
+            for p in moved {
+                let k = String::from_utf8_lossy(&p.cmd.key).to_string();
+                let _ =
+                    p.tx.send(Err(RangeLookupError::NoLocalReplicaForKey(k).into()));
+            }
+            owned
+        }
+        None => batch,
+    };
+
+    if batch.is_empty() {
+        return;
+    }
+
+    let cmds: Vec<Command> = batch.iter().map(|p| p.cmd.clone()).collect();
+
+    let (iid, rx) = match commit_batch(&sd, rid, &cmds, &mut commit_sender).await {
+        Ok(v) => v,
+        Err(e) => {
+            for p in batch {
+                let _ = p.tx.send(Err(e.clone()));
+            }
+            return;
+        }
+    };
+
+    // Do not block the next batch while waiting for this one to be executed.
+    tokio::spawn(async move {
+        let rsts = match rx.await {
+            Ok(rsts) => rsts,
+            Err(_) => {
+                let e = RedisApiError::ExecCommandError(format!(
+                    "{} may not be applied, a key is out of the range of replica {}",
+                    iid, rid
+                ));
+                for p in batch {
+                    let _ = p.tx.send(Err(e.clone()));
+                }
+                return;
+            }
+        };
+
+        let mut rsts = rsts.into_iter();
+        for p in batch {
+            let _ = p.tx.send(Ok(rsts.next().unwrap_or(None)));
+        }
+    });
+}
+
+/// commit_batch replicates and commits `cmds` as one instance.
+/// It returns the instance id and a receiver of the execution result.
+async fn commit_batch(
+    sd: &Arc<ServerData>,
+    rid: ReplicaId,
+    cmds: &[Command],
+    commit_sender: &mut mpsc::Sender<(ReplicaId, Instance)>,
+) -> Result<(InstanceId, oneshot::Receiver<Vec<Option<Record>>>), RedisApiError> {
+    let r = sd
+        .get_local_replica(rid)
+        .ok_or(ReplicaError::ReplicaNotFound(rid))?;
+
+    let mut st = replicate(cmds, &r).await?;
+
+    let inst = &mut st.instance;
+    inst.committed = true;
+
+    let iid = inst.instance_id.unwrap();
+    let (tx, rx) = oneshot::channel();
+    r.insert_tx(iid, tx).await;
+
+    r.set_instance(inst).await?;
+
+    if let Err(err) = commit_sender.send((r.replica_id, st.instance)).await {
+        error!("send commit msg error: {:}", err);
+    }
+
+    Ok((iid, rx))
+}
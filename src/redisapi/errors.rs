@@ -5,7 +5,7 @@ use storage::StorageError;
 use tokio::sync::oneshot::error::RecvError;
 
 quick_error! {
-    #[derive(Debug, PartialEq)]
+    #[derive(Debug, PartialEq, Clone)]
     pub enum RedisApiError{
         ExecCommandError(msg: String) {
             from(err: RangeLookupError) -> (format!("{:?}", err))
@@ -13,6 +13,7 @@ quick_error! {
             from(err: ReplicationError) -> (format!("{:?}", err))
             from(err: StorageError) -> (format!("{:?}", err))
             from(err: RecvError) -> (format!("{:?}", err))
+            from(err: std::io::Error) -> (format!("{:?}", err))
         }
     }
 }
@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+use crate::RedisApiError;
+use parse::Response;
+
+/// The max time to wait for the reply of a forwarded command.
+pub const FORWARD_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The max number of idle connections kept for every node, see `ForwardPool`.
+pub const FORWARD_POOL_SIZE: usize = 4;
+
+/// ForwardPool keeps idle connections to the redis api of other nodes, to forward commands on.
+///
+/// A connection is taken out while a command is forwarded on it, and put back after the reply is
+/// received, unless `FORWARD_POOL_SIZE` connections to the node are idle. A connection that fails
+/// or times out is dropped, because a late reply on it would be taken as the reply of the next
+/// command.
+#[derive(Debug, Default)]
+pub struct ForwardPool {
+    idle: Mutex<HashMap<SocketAddr, Vec<TcpStream>>>,
+}
+
+impl ForwardPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// get returns an idle connection to `addr`, and true if it is from the pool, or connects to
+    /// it if there is none.
+    async fn get(&self, addr: &SocketAddr) -> Result<(TcpStream, bool), RedisApiError> {
+        let sock = {
+            let mut idle = self.idle.lock().unwrap();
+            idle.get_mut(addr).and_then(|socks| socks.pop())
+        };
+
+        match sock {
+            Some(s) => Ok((s, true)),
+            None => Ok((TcpStream::connect(addr).await?, false)),
+        }
+    }
+
+    /// put returns a connection to the pool after a reply is received on it.
+    fn put(&self, addr: &SocketAddr, sock: TcpStream) {
+        let mut idle = self.idle.lock().unwrap();
+        let socks = idle.entry(*addr).or_insert_with(Vec::new);
+        if socks.len() < FORWARD_POOL_SIZE {
+            socks.push(sock);
+        }
+    }
+
+    /// idle_count returns the number of idle connections to `addr`.
+    pub fn idle_count(&self, addr: &SocketAddr) -> usize {
+        let idle = self.idle.lock().unwrap();
+        idle.get(addr).map_or(0, |socks| socks.len())
+    }
+}
+
+/// forward sends a command to the redis api of another node at `addr`, and returns the reply.
+///
+/// The command is wrapped as `FORWARDED cmd args...`, with which the other node serves it only
+/// with a local replica: a command is never forwarded twice, even if the cluster configs of nodes
+/// disagree during a range change or a migration.
+/// A connection is taken from `pool`. If an idle one has been closed by the other node, the
+/// command is sent again on a new connection: nothing has been sent to the node on a closed
+/// connection.
+pub async fn forward(
+    pool: &ForwardPool,
+    addr: &SocketAddr,
+    tokens: &[redis::Value],
+) -> Result<Response, RedisApiError> {
+    let packed = pack_forwarded(tokens)?;
+
+    let fut = async {
+        loop {
+            let (mut sock, pooled) = pool.get(addr).await?;
+            match send_recv(&mut sock, &packed).await {
+                Ok(Some(r)) => {
+                    pool.put(addr, sock);
+                    return Ok(r);
+                }
+                Ok(None) if pooled => continue,
+                Ok(None) => {
+                    return Err(RedisApiError::ExecCommandError(format!(
+                        "{} closed before reply",
+                        addr
+                    )));
+                }
+                Err(e) if pooled => {
+                    warn!("{:?} on idle connection to {}, reconnect", e, addr);
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    };
+
+    match timeout(FORWARD_TIMEOUT, fut).await {
+        Ok(r) => r,
+        Err(_) => Err(RedisApiError::ExecCommandError(format!(
+            "forward to {} timeout",
+            addr
+        ))),
+    }
+}
+
+/// send_recv sends a packed command on `sock` and reads the reply. It returns None if the
+/// connection is closed before a reply.
+async fn send_recv(
+    sock: &mut TcpStream,
+    packed: &[u8],
+) -> Result<Option<Response>, std::io::Error> {
+    sock.write_all(packed).await?;
+
+    let mut buf = vec![];
+    loop {
+        let mut chunk = vec![0u8; 1024];
+        let n = sock.read(&mut chunk).await?;
+        if n == 0 {
+            return Ok(None);
+        }
+
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(r) = parse_reply(&buf) {
+            return Ok(Some(r));
+        }
+    }
+}
+
+/// pack_forwarded encodes a command in redis protocol, wrapped as `FORWARDED cmd args...`.
+/// Every token must be a bulk string.
+fn pack_forwarded(tokens: &[redis::Value]) -> Result<Vec<u8>, RedisApiError> {
+    let mut cmd = redis::cmd("FORWARDED");
+    for t in tokens.iter() {
+        match t {
+            redis::Value::Data(d) => {
+                cmd.arg(d.as_slice());
+            }
+            _ => {
+                return Err(RedisApiError::ExecCommandError(format!(
+                    "can not forward a non bulk string argument: {:?}",
+                    t
+                )));
+            }
+        }
+    }
+    Ok(cmd.get_packed_command())
+}
+
+/// parse_reply parses a complete reply, or returns None if more bytes are needed.
+/// An error reply is a single line and is kept as is.
+fn parse_reply(buf: &[u8]) -> Option<Response> {
+    if buf.starts_with(b"-") {
+        let end = buf.windows(2).position(|w| w == b"\r\n")?;
+        return Some(Response::Error(
+            String::from_utf8_lossy(&buf[1..end]).into(),
+        ));
+    }
+
+    redis::parse_redis_value(buf).ok().map(to_response)
+}
+
+fn to_response(v: redis::Value) -> Response {
+    match v {
+        redis::Value::Nil => Response::Nil,
+        redis::Value::Int(i) => Response::Integer(i),
+        redis::Value::Data(d) => Response::Data(d),
+        redis::Value::Bulk(vs) => Response::Array(vs.into_iter().map(to_response).collect()),
+        redis::Value::Status(s) => Response::Status(s),
+        redis::Value::Okay => Response::Status("OK".to_owned()),
+    }
+}
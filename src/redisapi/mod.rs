@@ -1,5 +1,11 @@
 mod redisapi;
 pub use redisapi::*;
 
+mod batcher;
+pub use batcher::*;
+
 mod errors;
 pub use errors::*;
+
+mod forward;
+pub use forward::*;
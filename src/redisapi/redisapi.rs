@@ -1,6 +1,7 @@
 use net2;
 use redis;
 
+use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::str::from_utf8;
 use std::sync::Arc;
@@ -10,29 +11,78 @@ use futures::future::FutureExt;
 
 use futures::Future;
 
+use epaxos::merge_range;
+use epaxos::migrate_replica;
+use epaxos::new_txn_id;
+use epaxos::qpaxos::Command;
+use epaxos::qpaxos::OpCode;
+use epaxos::qpaxos::Record;
+use epaxos::qpaxos::ReplicaId;
+use epaxos::qpaxos::TxnStatus;
+use epaxos::read_local;
+use epaxos::read_local_executed;
+use epaxos::replica::ReplicaError;
+use epaxos::run_txn;
+use epaxos::split_range;
+use epaxos::sync_read_index;
+use epaxos::wait_unlocked;
+use epaxos::RangeLookupError;
+use epaxos::Routing;
+use epaxos::ServerData;
+use epaxos::READ_TIMEOUT;
 use tokio;
+use tokio::io::WriteHalf;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpListener;
 use tokio::net::TcpStream;
 use tokio::sync::mpsc;
 use tokio::sync::oneshot;
 
-use epaxos::qpaxos::Command;
-use epaxos::qpaxos::Instance;
-use epaxos::qpaxos::OpCode;
-use epaxos::replica::ReplicaPeer;
-use epaxos::replicate;
-use epaxos::ServerData;
-use epaxos::StorageAPI;
-
+use crate::forward;
+use crate::Batcher;
+use crate::ForwardPool;
+use crate::ProposeReply;
 use crate::RedisApiError;
 use parse::Response;
 
+/// Consistency is the consistency of reads on a connection.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Consistency {
+    /// a read sees every write completed before it, see `read_linearizable`.
+    Linearizable,
+    /// a read is served from the executed state of the local replica and may be stale.
+    Local,
+}
+
+impl Default for Consistency {
+    fn default() -> Self {
+        Consistency::Linearizable
+    }
+}
+
+/// The max number of replies a connection waits for, see `handle_new_conn`.
+pub const MAX_PIPELINED: usize = 1024;
+
+/// Pending is the reply to a command of a connection, which may not be ready yet.
+enum Pending {
+    Ready(Response),
+    /// a SET added to a batch, it is done when the batch is executed.
+    Set(oneshot::Receiver<ProposeReply>),
+    /// notified when the replies before it are sent.
+    Flush(oneshot::Sender<()>),
+}
+
 /// ReidsApi impl redis-protocol
 #[derive(Clone)]
 pub struct RedisApi {
     pub server_data: Arc<ServerData>,
-    pub commit_sender: mpsc::Sender<(Vec<ReplicaPeer>, Instance)>,
+    /// batches commands to local replicas into instances.
+    pub batcher: Batcher,
+    /// the read consistency of a connection. Every connection has its own copy, set with
+    /// `CONSISTENCY`, `READONLY` or `READWRITE`.
+    pub consistency: Consistency,
+    /// connections to other nodes to forward commands on, shared by all connections.
+    pub forward_pool: Arc<ForwardPool>,
 }
 
 impl RedisApi {
@@ -60,7 +110,7 @@ impl RedisApi {
         loop {
             tokio::select! {
                 _v = (&mut sig) => {
-                    drop(self.commit_sender);
+                    drop(self.batcher);
                     break;
                 },
                 inc = lis.accept() => {
@@ -77,46 +127,104 @@ impl RedisApi {
         Ok(())
     }
 
-    async fn handle_new_conn(mut self, mut sock: TcpStream) {
+    /// handle_new_conn serves commands of a connection, which may be pipelined: the client sends
+    /// commands without waiting for replies. Replies are sent in the order of the commands.
+    ///
+    /// A SET is added to a batch without waiting for the previous commands, thus a connection
+    /// keeps proposing while its instances are being replicated. Any other command is executed
+    /// after the previous ones are done, thus it sees their effect.
+    /// At most `MAX_PIPELINED` replies are waited for; reading pauses when it is reached, as well
+    /// as when the batcher is full.
+    async fn handle_new_conn(mut self, sock: TcpStream) {
         info!("new connection");
 
-        loop {
-            let mut buf = vec![0u8; 1024];
-
-            let n = sock
-                .read(&mut buf)
-                .await
-                .expect("failed to read data from socket");
+        let (mut rd, wr) = tokio::io::split(sock);
+        let (mut reply_tx, reply_rx) = mpsc::channel(MAX_PIPELINED);
+        let writer = tokio::spawn(write_replies(wr, reply_rx));
+
+        let mut buf = vec![];
+
+        'conn: loop {
+            while let Some(n) = frame_len(&buf) {
+                let v = match redis::parse_redis_value(&buf[..n]) {
+                    Ok(v) => v,
+                    Err(err) => {
+                        // TODO bad protocol handling
+                        error!("redis parse error: {:}", err);
+                        break 'conn;
+                    }
+                };
+                buf.drain(..n);
+                info!("parsed redis value: {:?}", v);
+
+                let pending = match self.enqueue_set(&v).await {
+                    Some(Ok(rx)) => Pending::Set(rx),
+                    Some(Err(e)) => Pending::Ready(error_response(e)),
+                    None => {
+                        let (tx, rx) = oneshot::channel();
+                        if reply_tx.send(Pending::Flush(tx)).await.is_err() {
+                            break 'conn;
+                        }
+                        let _ = rx.await;
+
+                        match self.exec_redis_cmd(v).await {
+                            Ok(r) => Pending::Ready(r),
+                            Err(e) => Pending::Ready(error_response(e)),
+                        }
+                    }
+                };
+
+                if reply_tx.send(pending).await.is_err() {
+                    break 'conn;
+                }
+            }
 
-            info!("read buf: len={:}, {:?}", n, buf);
+            let mut chunk = vec![0u8; 1024];
+            let n = match rd.read(&mut chunk).await {
+                Ok(n) => n,
+                Err(e) => {
+                    warn!("{:?} while read from client", e);
+                    break;
+                }
+            };
 
             if n == 0 {
                 warn!("client closed");
-                return;
+                break;
             }
 
-            let v = redis::parse_redis_value(&buf);
-            let v = match v {
-                Ok(q) => {
-                    info!("parsed redis value: {:?}", q);
-                    q
-                }
-                Err(err) => {
-                    // TODO bad protocol handling
-                    error!("redis parse error: {:}", err);
-                    panic!("bad redis protocol");
-                }
-            };
-            let r = match self.exec_redis_cmd(v).await {
-                Ok(r) => r,
-                Err(e) => Response::Error(format!("exec redis cmd error: {:?}", e)),
-            };
-            info!("exec_redis_cmd r={:?}", &r);
-            let r = r.to_vec();
-            info!("response bytes:{:?}", &r);
-            sock.write_all(&r)
-                .await
-                .expect("failed to write data to socket");
+            buf.extend_from_slice(&chunk[..n]);
+        }
+
+        drop(reply_tx);
+        let _ = writer.await;
+    }
+
+    /// enqueue_set adds a `SET key value` on a key of a local voter to its batch, without
+    /// waiting for it to be committed, and returns the receiver of the reply.
+    /// It returns None for any other command, which is executed by `exec_redis_cmd`.
+    async fn enqueue_set(
+        &self,
+        v: &redis::Value,
+    ) -> Option<Result<oneshot::Receiver<ProposeReply>, RedisApiError>> {
+        let tokens = match v {
+            redis::Value::Bulk(tokens) if tokens.len() == 3 => tokens,
+            _ => return None,
+        };
+
+        match (&tokens[0], &tokens[1], &tokens[2]) {
+            (redis::Value::Data(c), redis::Value::Data(key), redis::Value::Data(value))
+                if c.eq_ignore_ascii_case(b"SET") =>
+            {
+                let r = match self.server_data.get_local_replica_for_key(key) {
+                    Ok((_, r)) if !r.is_learner() => r,
+                    _ => return None,
+                };
+
+                let cmd = Command::from((OpCode::Set, &key[..], &value[..]));
+                Some(self.batcher.enqueue(r.replica_id, cmd).await)
+            }
+            _ => None,
         }
     }
 
@@ -126,11 +234,21 @@ impl RedisApi {
         //
         // Flatten one level:
         // tokens is a vec[Value].
-        let tokens = match v {
+        let mut tokens = match v {
             redis::Value::Bulk(tokens) => tokens,
             _ => vec![],
         };
 
+        // a command forwarded by another node is served only with a local replica.
+        let forwarded = is_forwarded(&tokens);
+        if forwarded {
+            tokens.remove(0);
+        }
+
+        if tokens.len() == 0 {
+            return Ok(Response::Error("invalid command".to_owned()));
+        }
+
         // the first token is instruction, e.g. "set" or "get".
         let tok0 = &tokens[0];
 
@@ -145,18 +263,70 @@ impl RedisApi {
         info!("instruction: {:?}", t);
         let tok0str = from_utf8(&t).unwrap();
 
+        let instr = tok0str.to_uppercase();
+
+        if !forwarded && is_keyed(&instr) {
+            if let Some(redis::Value::Data(key)) = tokens.get(1) {
+                if let Some(r) = self.route(key, &tokens).await? {
+                    return Ok(r);
+                }
+            }
+        }
+
         // execute the command
 
-        let r = match &tok0str.to_uppercase()[..] {
+        let r = match &instr[..] {
             "SET" => self.cmd_set(&tokens).await,
+            "MSET" => self.cmd_mset(&tokens).await,
             "FLUSHDB" => Ok(Response::Status("OK".to_owned())),
             "GET" => self.cmd_get(&tokens).await,
+            "CONSISTENCY" => self.cmd_consistency(&tokens),
+            "READONLY" => self.set_consistency(Consistency::Local),
+            "READWRITE" => self.set_consistency(Consistency::Linearizable),
+            "PEERS" => self.cmd_peers(),
+            "PIPELINE" => self.cmd_pipeline(),
+            "SPLIT" => self.cmd_split(&tokens).await,
+            "MERGE" => self.cmd_merge(&tokens).await,
+            "MIGRATE" => self.cmd_migrate(&tokens).await,
+            "STALENESS" => self.cmd_staleness(),
             _ => Ok(Response::Error("invalid command".to_owned())),
         };
 
         r
     }
 
+    /// route serves a command on `key` if this node has no replica of the group serving it: by
+    /// forwarding it to a node that has one, or by replying a redirect, according to the routing
+    /// of the cluster, see `Routing`. It returns None if there is a local replica.
+    ///
+    /// A redirect is `-REDIRECT <start> <end> <api_addr>`: keys in the range `[start, end)` are
+    /// served by the node at `api_addr`.
+    ///
+    /// The read consistency of this connection is not forwarded: a forwarded GET is
+    /// linearizable.
+    async fn route(
+        &self,
+        key: &[u8],
+        tokens: &[redis::Value],
+    ) -> Result<Option<Response>, RedisApiError> {
+        let sd = &self.server_data;
+        match sd.get_local_replica_for_key(key) {
+            Err(RangeLookupError::NoLocalReplicaForKey(_)) => {}
+            _ => return Ok(None),
+        }
+
+        let (g, node) = sd.get_remote_node_for_key(key)?;
+        let r = match sd.get_routing() {
+            Routing::Forward => forward(&self.forward_pool, &node.api_addr, tokens).await?,
+            Routing::Redirect => Response::Error(format!(
+                "REDIRECT {} {} {}",
+                g.range.0, g.range.1, node.api_addr
+            )),
+        };
+
+        Ok(Some(r))
+    }
+
     /// cmd_set impl redis-command set. TODO impl it.
     async fn cmd_set(&mut self, tokens: &[redis::Value]) -> Result<Response, RedisApiError> {
         let cmd = OpCode::Set;
@@ -176,29 +346,55 @@ impl RedisApi {
         };
 
         let cmd = Command::from((cmd, key as &[u8], value as &[u8]));
-        let cmds = vec![cmd];
 
-        let (g, r) = self.server_data.get_local_replica_for_key(key)?;
-        let mut st = replicate(&cmds, g, r).await?;
+        let (_, r) = self.server_data.get_local_replica_for_key(key)?;
+        if r.is_learner() {
+            return Err(ReplicaError::Learner(r.replica_id).into());
+        }
+        self.batcher.propose(r.replica_id, cmd).await?;
 
-        let inst = &mut st.instance;
-        inst.committed = true;
-        let _ = r.storage.set_instance(&inst.instance_id.unwrap(), inst)?;
+        Ok(Response::Status("OK".to_owned()))
+    }
 
-        if let Err(err) = self
-            .commit_sender
-            .send((r.peers.clone(), st.instance))
-            .await
-        {
-            error!("send commit msg error: {:}", err);
+    /// cmd_mset impl redis-command mset: `MSET key value [key value ...]`.
+    ///
+    /// Keys may be served by different groups, including groups without a replica on this node.
+    /// They are written atomically in a transaction, see `run_txn`, thus it is never forwarded or
+    /// redirected. It fails if the transaction is aborted, e.g., a key is locked by another one.
+    async fn cmd_mset(&self, tokens: &[redis::Value]) -> Result<Response, RedisApiError> {
+        let args = &tokens[1..];
+        if args.len() == 0 || args.len() % 2 != 0 {
+            return Ok(Response::Error("wrong number of arguments".to_owned()));
         }
 
-        Ok(Response::Status("OK".to_owned()))
+        let mut writes = vec![];
+        for kv in args.chunks(2) {
+            match (&kv[0], &kv[1]) {
+                (redis::Value::Data(k), redis::Value::Data(v)) => {
+                    writes.push(Command::from((OpCode::Set, &k[..], &v[..])));
+                }
+                _ => return Ok(Response::Error("invalid key or value".to_owned())),
+            }
+        }
+
+        let sd = &self.server_data;
+        let txn_id = new_txn_id(&sd.node_id);
+        match run_txn(sd, &txn_id, writes).await? {
+            TxnStatus::Committed => Ok(Response::Status("OK".to_owned())),
+            _ => Ok(Response::Error("transaction aborted".to_owned())),
+        }
     }
 
-    /// cmd_set impl redis-command set. TODO impl it.
+    /// cmd_get impl redis-command get: `GET key [WITHEXECUTED]`.
+    ///
+    /// A voter serves a linearizable read without replicating it, see `read_linearizable`, unless
+    /// the connection reads with `Consistency::Local`. A linearizable read of a key written by a
+    /// pending transaction waits until the transaction is decided. A learner always serves a stale read from
+    /// its local state, see `STALENESS`.
+    ///
+    /// With `WITHEXECUTED` it returns `[value, [[replica_id, idx], ...]]`, in which the second
+    /// element is the exec status of the replica the value is read at.
     async fn cmd_get(&mut self, tokens: &[redis::Value]) -> Result<Response, RedisApiError> {
-        let cmd = OpCode::Get;
         let key = match tokens[1] {
             redis::Value::Data(ref d) => d,
             _ => {
@@ -207,31 +403,294 @@ impl RedisApi {
             }
         };
 
-        let cmd = Command::from((cmd, key as &[u8], &vec![][..]));
-        let cmds = vec![cmd];
+        let with_executed = match tokens.get(2) {
+            None => false,
+            Some(redis::Value::Data(d)) if d.eq_ignore_ascii_case(b"WITHEXECUTED") => true,
+            Some(_) => return Ok(Response::Error("invalid option".to_owned())),
+        };
+
+        let (_, r) = self.server_data.get_local_replica_for_key(key)?;
+        if self.consistency == Consistency::Linearizable && !r.is_learner() {
+            sync_read_index(&r).await?;
+            wait_unlocked(&r, key, READ_TIMEOUT).await?;
+        }
+
+        if !with_executed {
+            let v = read_local(&r, key)?;
+            return Ok(record_response(v));
+        }
+
+        let (v, executed) = read_local_executed(&r, key).await?;
+
+        let mut iids: Vec<_> = executed.iter().map(|(rid, idx)| (*rid, *idx)).collect();
+        iids.sort();
+        let iids = iids
+            .into_iter()
+            .map(|(rid, idx)| Response::Array(vec![Response::Integer(rid), Response::Integer(idx)]))
+            .collect();
+
+        Ok(Response::Array(vec![
+            record_response(v),
+            Response::Array(iids),
+        ]))
+    }
+
+    /// cmd_consistency sets the read consistency of this connection: `CONSISTENCY
+    /// linearizable|local`. Without an argument it returns the current one.
+    fn cmd_consistency(&mut self, tokens: &[redis::Value]) -> Result<Response, RedisApiError> {
+        let c = match tokens.get(1) {
+            None => {
+                let name = match self.consistency {
+                    Consistency::Linearizable => "linearizable",
+                    Consistency::Local => "local",
+                };
+                return Ok(Response::Status(name.to_owned()));
+            }
+            Some(redis::Value::Data(d)) => d.to_ascii_lowercase(),
+            Some(_) => vec![],
+        };
+
+        match &c[..] {
+            b"linearizable" => self.set_consistency(Consistency::Linearizable),
+            b"local" => self.set_consistency(Consistency::Local),
+            _ => Ok(Response::Error("invalid consistency".to_owned())),
+        }
+    }
+
+    /// set_consistency sets the read consistency of this connection.
+    fn set_consistency(&mut self, c: Consistency) -> Result<Response, RedisApiError> {
+        self.consistency = c;
+        Ok(Response::Status("OK".to_owned()))
+    }
+
+    /// cmd_peers is an admin command that returns the liveness of peers of every local replica,
+    /// as seen by the failure detector.
+    /// Every element of the returned array is: `[replica_id, peer_replica_id, peer_addr,
+    /// "alive"|"dead"]`.
+    fn cmd_peers(&self) -> Result<Response, RedisApiError> {
+        let mut rst = vec![];
+        for r in self.server_data.get_local_replicas() {
+            for p in r.get_peers().iter() {
+                let alive = if p.alive { "alive" } else { "dead" };
+                rst.push(Response::Array(vec![
+                    Response::Integer(r.replica_id),
+                    Response::Integer(p.replica_id),
+                    Response::Data(p.addr.as_bytes().to_vec()),
+                    Response::Status(alive.to_owned()),
+                ]));
+            }
+        }
+
+        Ok(Response::Array(rst))
+    }
+
+    /// cmd_pipeline is an admin command that returns the depth of the proposal pipeline of every
+    /// local replica.
+    /// Every element of the returned array is: `[replica_id, inflight, peak, max_inflight]`.
+    fn cmd_pipeline(&self) -> Result<Response, RedisApiError> {
+        let max = self.batcher.get_config().max_inflight as i64;
+
+        let mut rst = vec![];
+        for (rid, inflight, peak) in self.batcher.stats() {
+            rst.push(Response::Array(vec![
+                Response::Integer(rid),
+                Response::Integer(inflight as i64),
+                Response::Integer(peak as i64),
+                Response::Integer(max),
+            ]));
+        }
+
+        Ok(Response::Array(rst))
+    }
+
+    /// cmd_staleness is an admin command that returns the staleness bound of every local learner:
+    /// a read from it sees every write a voter had executed that many milliseconds ago.
+    /// Every element of the returned array is: `[replica_id, staleness_ms]`, in which
+    /// `staleness_ms` is nil if the learner has never caught up with a voter.
+    fn cmd_staleness(&self) -> Result<Response, RedisApiError> {
+        let mut rst = vec![];
+        for r in self.server_data.get_local_replicas() {
+            if !r.is_learner() {
+                continue;
+            }
+            let staleness = match r.staleness() {
+                Some(d) => Response::Integer(d.as_millis() as i64),
+                None => Response::Nil,
+            };
+            rst.push(Response::Array(vec![
+                Response::Integer(r.replica_id),
+                staleness,
+            ]));
+        }
+
+        Ok(Response::Array(rst))
+    }
+
+    /// cmd_split is an admin command that splits the group serving `key` at `key`:
+    /// `SPLIT key rid new_rid [rid new_rid ...]`, in which `new_rid` is the replica of the new
+    /// group on the same node as `rid`. Every replica of the group must have one.
+    async fn cmd_split(&self, tokens: &[redis::Value]) -> Result<Response, RedisApiError> {
+        let key = match tokens.get(1) {
+            Some(redis::Value::Data(d)) => d,
+            _ => return Ok(Response::Error("invalid key".to_owned())),
+        };
+
+        let pairs = match parse_pairs(&tokens[2..]) {
+            Some(v) => v,
+            None => return Ok(Response::Error("invalid pairs".to_owned())),
+        };
+
+        let (_, r) = self.server_data.get_local_replica_for_key(key)?;
+        let at = String::from_utf8_lossy(key);
+        split_range(&r, &at, pairs).await?;
+
+        Ok(Response::Status("OK".to_owned()))
+    }
+
+    /// cmd_merge is an admin command that merges the group following the one serving `key` into
+    /// the latter: `MERGE key rid merged_rid [rid merged_rid ...]`, in which `merged_rid` is the
+    /// replica of the merged group on the same node as `rid`.
+    async fn cmd_merge(&self, tokens: &[redis::Value]) -> Result<Response, RedisApiError> {
+        let key = match tokens.get(1) {
+            Some(redis::Value::Data(d)) => d,
+            _ => return Ok(Response::Error("invalid key".to_owned())),
+        };
+
+        let pairs = match parse_pairs(&tokens[2..]) {
+            Some(v) => v,
+            None => return Ok(Response::Error("invalid pairs".to_owned())),
+        };
+
+        let (_, g) = self.server_data.get_local_replica_for_key(key)?;
+        let h = pairs
+            .get(&g.replica_id)
+            .and_then(|rid| self.server_data.get_local_replica(*rid));
+        let h = match h {
+            Some(v) => v,
+            None => return Ok(Response::Error("no local replica to merge".to_owned())),
+        };
+
+        merge_range(&g, &h, pairs).await?;
+
+        Ok(Response::Status("OK".to_owned()))
+    }
+
+    /// cmd_migrate is an admin command that moves a replica from the node it is on to this node,
+    /// as a new replica: `MIGRATE rid to_rid`.
+    async fn cmd_migrate(&self, tokens: &[redis::Value]) -> Result<Response, RedisApiError> {
+        let parse = |i: usize| -> Option<ReplicaId> {
+            match tokens.get(i) {
+                Some(redis::Value::Data(d)) => from_utf8(d).ok().and_then(|x| x.parse().ok()),
+                _ => None,
+            }
+        };
+
+        let (rid, to_rid) = match (parse(1), parse(2)) {
+            (Some(a), Some(b)) => (a, b),
+            _ => return Ok(Response::Error("invalid replica id".to_owned())),
+        };
+
+        migrate_replica(&self.server_data, rid, to_rid).await?;
 
-        let (g, r) = self.server_data.get_local_replica_for_key(key)?;
-        let mut st = replicate(&cmds, g, r).await?;
+        Ok(Response::Status("OK".to_owned()))
+    }
+}
+
+/// write_replies sends replies of a connection in order, waiting for each of them to be ready.
+async fn write_replies(mut wr: WriteHalf<TcpStream>, mut rx: mpsc::Receiver<Pending>) {
+    while let Some(p) = rx.recv().await {
+        let r = match p {
+            Pending::Ready(r) => r,
+            Pending::Set(rx) => match rx.await {
+                Ok(Ok(_)) => Response::Status("OK".to_owned()),
+                Ok(Err(e)) => error_response(e),
+                Err(e) => error_response(e.into()),
+            },
+            Pending::Flush(tx) => {
+                let _ = tx.send(());
+                continue;
+            }
+        };
+
+        info!("exec_redis_cmd r={:?}", &r);
+        if let Err(e) = wr.write_all(&r.to_vec()).await {
+            warn!("{:?} while write to client", e);
+            return;
+        }
+    }
+}
 
-        let inst = &mut st.instance;
-        inst.committed = true;
-        let (tx, rx) = oneshot::channel();
-        r.insert_tx(inst.instance_id.unwrap(), tx).await;
-        r.storage.set_instance(&inst.instance_id.unwrap(), inst)?;
+fn error_response(e: RedisApiError) -> Response {
+    Response::Error(format!("exec redis cmd error: {:?}", e))
+}
 
-        if let Err(err) = self
-            .commit_sender
-            .send((r.peers.clone(), st.instance))
-            .await
-        {
-            error!("send commit msg error: {:}", err);
+/// frame_len returns the length of the first redis value in `buf`, or None if it is not complete.
+/// A malformed value is a line, which fails to parse.
+fn frame_len(buf: &[u8]) -> Option<usize> {
+    let line = buf.windows(2).position(|w| w == b"\r\n")? + 2;
+    let n = buf
+        .get(1..line - 2)
+        .and_then(|x| from_utf8(x).ok())
+        .and_then(|x| x.parse::<i64>().ok());
+
+    match (buf[0], n) {
+        (b'$', Some(n)) if n >= 0 => {
+            let end = line + n as usize + 2;
+            if buf.len() >= end {
+                Some(end)
+            } else {
+                None
+            }
+        }
+        (b'*', Some(n)) if n > 0 => {
+            let mut end = line;
+            for _ in 0..n {
+                end += frame_len(&buf[end..])?;
+            }
+            Some(end)
         }
+        _ => Some(line),
+    }
+}
+
+/// record_response returns a record as redis data, or nil if it does not exist.
+fn record_response(v: Option<Record>) -> Response {
+    match v {
+        Some(v) => Response::Data(v.to_vec()),
+        None => Response::Nil,
+    }
+}
+
+/// parse_pairs parses tokens of `rid paired_rid [rid paired_rid ...]` into a map.
+fn parse_pairs(tokens: &[redis::Value]) -> Option<HashMap<ReplicaId, ReplicaId>> {
+    if tokens.len() == 0 || tokens.len() % 2 != 0 {
+        return None;
+    }
 
-        let repl = rx.await?.pop().unwrap_or(None);
-        if let Some(v) = repl {
-            return Ok(Response::Data(v.to_vec()));
+    let mut rids = vec![];
+    for t in tokens.iter() {
+        match t {
+            redis::Value::Data(d) => rids.push(from_utf8(d).ok()?.parse().ok()?),
+            _ => return None,
         }
+    }
+
+    Some(rids.chunks(2).map(|c| (c[0], c[1])).collect())
+}
+
+/// is_forwarded returns true if a command is wrapped as `FORWARDED cmd args...` by another node,
+/// see `forward`.
+fn is_forwarded(tokens: &[redis::Value]) -> bool {
+    match tokens.get(0) {
+        Some(redis::Value::Data(d)) => d.eq_ignore_ascii_case(b"FORWARDED"),
+        _ => false,
+    }
+}
 
-        Ok(Response::Nil)
+/// is_keyed returns true if a command has only one key, by which it is routed.
+fn is_keyed(instr: &str) -> bool {
+    match instr {
+        "SET" | "GET" => true,
+        _ => false,
     }
 }
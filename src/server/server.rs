@@ -1,7 +1,11 @@
+use std::collections::HashMap;
 use std::mem::replace;
 use std::sync::Arc;
 use std::time::Duration;
 
+use futures::future;
+use futures::future::join_all;
+use futures::future::select_all;
 use futures::Future;
 
 use tokio;
@@ -14,14 +18,25 @@ use tonic;
 use epaxos::conf::ClusterInfo;
 use epaxos::conf::NodeId;
 use epaxos::qpaxos::Instance;
-use epaxos::qpaxos::MakeRequest;
 use epaxos::qpaxos::QPaxosServer;
-use epaxos::replica::ReplicaPeer;
-use epaxos::replication::bcast_msg;
+use epaxos::qpaxos::ReplicaId;
+use epaxos::replica::EXEC_IDLE_INTERVAL;
+use epaxos::replica::GC_INTERVAL;
+use epaxos::replication::catch_up;
+use epaxos::replication::deliver_commits;
+use epaxos::replication::ping_peers;
+use epaxos::replication::recover_txns;
+use epaxos::replication::COMMIT_RETRY_INTERVAL;
+use epaxos::replication::HEARTBEAT_INTERVAL;
+use epaxos::replication::RPC_TIMEOUT;
+use epaxos::replication::TXN_RECOVERY_INTERVAL;
 use epaxos::QPaxosImpl;
 use epaxos::ServerData;
 use storage::RawKV;
 
+use crate::Batcher;
+use crate::Consistency;
+use crate::ForwardPool;
 use crate::RedisApi;
 use crate::ServerError;
 
@@ -58,10 +73,13 @@ impl Server {
         let (tx_api, rx_api) = tokio::sync::oneshot::channel::<()>();
         let (tx_repl, rx_repl) = tokio::sync::oneshot::channel::<()>();
         let (tx_exec, rx_exec) = tokio::sync::oneshot::channel::<()>();
+        let (tx_detect, rx_detect) = tokio::sync::oneshot::channel::<()>();
+        let (tx_gc, rx_gc) = tokio::sync::oneshot::channel::<()>();
+        let (tx_txn, rx_txn) = tokio::sync::oneshot::channel::<()>();
 
         let (tx_commit, rx_commit) = mpsc::channel(1024);
 
-        let fut = Server::_start_replica_commit(rx_commit);
+        let fut = Server::_start_replica_commit(self.server_data.clone(), rx_commit);
         let j = tokio::spawn(fut);
         self.join_handle.push(j);
         info!("replica commit start");
@@ -76,16 +94,49 @@ impl Server {
         self.join_handle.push(j);
         info!("replica exec start");
 
+        let fut = Server::_start_failure_detect(self.server_data.clone(), rx_detect);
+        let j = tokio::spawn(fut);
+        self.join_handle.push(j);
+        info!("failure detect start");
+
+        let fut = Server::_start_gc(self.server_data.clone(), rx_gc);
+        let j = tokio::spawn(fut);
+        self.join_handle.push(j);
+        info!("gc start");
+
+        let fut = Server::_start_txn_recovery(self.server_data.clone(), rx_txn);
+        let j = tokio::spawn(fut);
+        self.join_handle.push(j);
+        info!("txn recovery start");
+
         self.stop_txs.push(("api", tx_api));
         self.stop_txs.push(("replication", tx_repl));
         self.stop_txs.push(("exec", tx_exec));
+        self.stop_txs.push(("detect", tx_detect));
+        self.stop_txs.push(("gc", tx_gc));
+        self.stop_txs.push(("txn", tx_txn));
     }
 
     async fn _start_replica_exec(sd: Arc<ServerData>, mut rx: Receiver<()>) {
         loop {
             let mut exec_count = 0;
-            for r in sd.local_replicas.values() {
-                match r.execute().await {
+
+            let rs = sd.get_local_replicas();
+
+            // replicas do not share any instance, execute them concurrently.
+            let rsts = join_all(rs.iter().map(|r| r.execute())).await;
+
+            for (r, rst) in rs.iter().zip(rsts) {
+                for cc in r.take_config_changes() {
+                    if let Err(e) = sd.apply_config_change(r.replica_id, &cc) {
+                        error!(
+                            "{:?} while apply config change {:?} for {:?}",
+                            e, cc, r.replica_id
+                        );
+                    }
+                }
+
+                match rst {
                     Ok(iids) => {
                         if iids.len() > 0 {
                             info!(
@@ -103,7 +154,31 @@ impl Server {
             }
 
             if exec_count == 0 {
-                tokio::time::delay_for(Duration::from_millis(10)).await;
+                // sleep until an instance is committed.
+                let notified: Vec<_> = rs
+                    .iter()
+                    .map(|r| Box::pin(r.commit_notify.notified()))
+                    .collect();
+
+                let woken = async {
+                    if notified.len() > 0 {
+                        select_all(notified).await;
+                    } else {
+                        future::pending::<()>().await;
+                    }
+                };
+
+                tokio::select! {
+                    _ = woken => {},
+                    _ = tokio::time::delay_for(EXEC_IDLE_INTERVAL) => {},
+                    v = &mut rx => {
+                        match v {
+                            Ok(_) => info!("exit replcia exec thread with recv stop signal"),
+                            Err(_) => error!("exit replcia exec thread with the sender had been dropped"),
+                        }
+                        break;
+                    },
+                }
             }
 
             match rx.try_recv() {
@@ -122,16 +197,172 @@ impl Server {
         }
     }
 
-    async fn _start_replica_commit(mut rx: mpsc::Receiver<(Vec<ReplicaPeer>, Instance)>) {
+    /// _start_failure_detect pings peers of every local replica periodically to update their
+    /// liveness, and the freshness of the replica. A replica that can not catch up by replaying
+    /// instances installs a snapshot from a peer.
+    async fn _start_failure_detect(sd: Arc<ServerData>, mut rx: Receiver<()>) {
+        loop {
+            for r in sd.get_local_replicas() {
+                // learners are pinged too, gc waits for them to execute.
+                let mut peers = r.get_peers();
+                peers.extend(r.get_learners());
+
+                ping_peers(r.replica_id, &r.conns, &r.detector, &peers, RPC_TIMEOUT).await;
+
+                if let Err(e) = r.update_freshness() {
+                    error!("{:?} while update freshness of {:?}", e, r.replica_id);
+                }
+
+                // instances this replica needs are deleted on a peer.
+                match r.snapshot_source() {
+                    Ok(Some(p)) => {
+                        if let Err(e) = catch_up(&r, &p).await {
+                            error!("{:?} while catch up {:?} from {:?}", e, r.replica_id, p);
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        error!("{:?} while check snapshot for {:?}", e, r.replica_id);
+                    }
+                }
+            }
+
+            tokio::time::delay_for(HEARTBEAT_INTERVAL).await;
+
+            match rx.try_recv() {
+                Ok(_) => {
+                    info!("exit failure detect thread with recv stop signal");
+                    break;
+                }
+                Err(e) => match e {
+                    TryRecvError::Empty => {}
+                    TryRecvError::Closed => {
+                        error!("exit failure detect thread with the sender had been dropped");
+                        break;
+                    }
+                },
+            }
+        }
+    }
+
+    /// _start_gc deletes instances executed on every replica, and states of transactions no
+    /// longer needed, periodically.
+    async fn _start_gc(sd: Arc<ServerData>, mut rx: Receiver<()>) {
+        loop {
+            for r in sd.get_local_replicas() {
+                match r.gc().await {
+                    Ok(n) => {
+                        if n > 0 {
+                            info!("deleted {} instances for {:?}", n, r.replica_id);
+                        }
+                    }
+                    Err(e) => {
+                        error!("{:?} while gc for {:?}", e, r.replica_id);
+                    }
+                }
+
+                match r.gc_txns().await {
+                    Ok(n) => {
+                        if n > 0 {
+                            info!("deleted {} txn states for {:?}", n, r.replica_id);
+                        }
+                    }
+                    Err(e) => {
+                        error!("{:?} while gc txns for {:?}", e, r.replica_id);
+                    }
+                }
+            }
+
+            tokio::time::delay_for(GC_INTERVAL).await;
+
+            match rx.try_recv() {
+                Ok(_) => {
+                    info!("exit gc thread with recv stop signal");
+                    break;
+                }
+                Err(e) => match e {
+                    TryRecvError::Empty => {}
+                    TryRecvError::Closed => {
+                        error!("exit gc thread with the sender had been dropped");
+                        break;
+                    }
+                },
+            }
+        }
+    }
+
+    /// _start_txn_recovery finishes transactions abandoned by their coordinators periodically.
+    async fn _start_txn_recovery(sd: Arc<ServerData>, mut rx: Receiver<()>) {
+        let mut seen = HashMap::new();
+
         loop {
-            match rx.recv().await {
-                Some((peers, inst)) => {
-                    let req = MakeRequest::commit(0, &inst);
-                    bcast_msg(&peers, req).await;
+            recover_txns(&sd, &mut seen).await;
+
+            tokio::time::delay_for(TXN_RECOVERY_INTERVAL).await;
+
+            match rx.try_recv() {
+                Ok(_) => {
+                    info!("exit txn recovery thread with recv stop signal");
+                    break;
                 }
-                None => {
-                    info!("exit replcia commit thread with the sender had been dropped");
-                    return;
+                Err(e) => match e {
+                    TryRecvError::Empty => {}
+                    TryRecvError::Closed => {
+                        error!("exit txn recovery thread with the sender had been dropped");
+                        break;
+                    }
+                },
+            }
+        }
+    }
+
+    /// _start_replica_commit delivers commits to peers.
+    /// A commit received from `rx` is sent at once, and undelivered ones are retried every
+    /// COMMIT_RETRY_INTERVAL.
+    async fn _start_replica_commit(
+        sd: Arc<ServerData>,
+        mut rx: mpsc::Receiver<(ReplicaId, Instance)>,
+    ) {
+        loop {
+            tokio::select! {
+                v = rx.recv() => match v {
+                    Some((rid, inst)) => {
+                        let r = match sd.get_local_replica(rid) {
+                            Some(r) => r,
+                            None => {
+                                error!("no local replica {} to commit {:?}", rid, inst.instance_id);
+                                continue;
+                            }
+                        };
+
+                        if let Err(e) = r.commits.add(inst.instance_id.unwrap()) {
+                            error!("{:?} while add commit {:?}", e, inst.instance_id);
+                            continue;
+                        }
+
+                        // do not block receiving other commits.
+                        let sd = sd.clone();
+                        tokio::spawn(async move {
+                            let r = match sd.get_local_replica(rid) {
+                                Some(r) => r,
+                                None => return,
+                            };
+                            if let Err(e) = deliver_commits(&r).await {
+                                error!("{:?} while deliver commits for {}", e, rid);
+                            }
+                        });
+                    }
+                    None => {
+                        info!("exit replcia commit thread with the sender had been dropped");
+                        return;
+                    }
+                },
+                _ = tokio::time::delay_for(COMMIT_RETRY_INTERVAL) => {
+                    for r in sd.get_local_replicas() {
+                        if let Err(e) = deliver_commits(&r).await {
+                            error!("{:?} while deliver commits for {}", e, r.replica_id);
+                        }
+                    }
                 }
             }
         }
@@ -141,14 +372,16 @@ impl Server {
         sd: Arc<ServerData>,
         sig_api: F,
         sig_repl: F,
-        sig_commit: mpsc::Sender<(Vec<ReplicaPeer>, Instance)>,
+        sig_commit: mpsc::Sender<(ReplicaId, Instance)>,
     ) {
         let api_addr = sd.node.api_addr;
         let repl_addr = sd.node.replication;
 
         let redisapi = RedisApi {
             server_data: sd.clone(),
-            commit_sender: sig_commit,
+            batcher: Batcher::new(sd.clone(), sig_commit),
+            consistency: Consistency::default(),
+            forward_pool: Arc::new(ForwardPool::new()),
         };
 
         let j1 = tokio::spawn(async move {
@@ -19,6 +19,7 @@ use cele::Server;
 use epaxos::qpaxos::ReplicaId;
 use epaxos::replica::Replica;
 use epaxos::testutil;
+use storage::MemEngine;
 use storage::RawKV;
 
 /// InProcContext setup a small cluster of an in-process server and a client.
@@ -30,10 +31,19 @@ pub struct InProcContext {
 
 impl InProcContext {
     /// get_replica returns the specified `Replica` in the testing cluster.
-    pub fn get_replica(&self, rid: ReplicaId) -> &Replica {
+    pub fn get_replica(&self, rid: ReplicaId) -> Arc<Replica> {
         let sd = &self.server.server_data;
-        let r = sd.local_replicas.get(&rid).unwrap();
-        r
+        sd.get_local_replica(rid).unwrap()
+    }
+
+    /// start_node starts another node of the cluster in this process, with its own in-memory
+    /// storage.
+    pub fn start_node(conf_name: &str, node_id: &str) -> Server {
+        let ci = testutil::new_cluster(conf_name);
+        let sto = Arc::new(MemEngine::new().unwrap());
+        let mut server = Server::new(sto, ci, node_id.into());
+        server.start();
+        server
     }
 
     /// new creates a cluster with a named predefined ClusterInfo.
@@ -0,0 +1,57 @@
+#[cfg(test)]
+use pretty_assertions::assert_eq;
+
+use std::time::Duration;
+
+use crate::support::*;
+use tokio::time::delay_for;
+
+mod support;
+
+#[tokio::test(threaded_scheduler)]
+async fn test_peers_liveness() {
+    // Replica 1 is on a node that is never started.
+    // Replica 2 and 3 are served by the in-process server.
+    let ctx = InProcContext::new("az_3_remote_1");
+
+    // wait for enough rounds of heartbeat
+    delay_for(Duration::from_millis(3_000)).await;
+
+    for rid in 2..=3 {
+        let peers = ctx.get_replica(rid).get_peers();
+        for p in peers.iter() {
+            assert_eq!(p.replica_id != 1, p.alive, "peer of {}: {:?}", rid, p);
+        }
+    }
+
+    let mut con = ctx.client.get_connection().unwrap();
+    let rst: Vec<(i64, i64, String, String)> = redis::cmd("PEERS").query(&mut con).unwrap();
+
+    let want = vec![
+        (
+            2,
+            1,
+            "http://127.0.0.1:4442".to_string(),
+            "dead".to_string(),
+        ),
+        (
+            2,
+            3,
+            "http://127.0.0.1:4441".to_string(),
+            "alive".to_string(),
+        ),
+        (
+            3,
+            1,
+            "http://127.0.0.1:4442".to_string(),
+            "dead".to_string(),
+        ),
+        (
+            3,
+            2,
+            "http://127.0.0.1:4441".to_string(),
+            "alive".to_string(),
+        ),
+    ];
+    assert_eq!(want, rst);
+}
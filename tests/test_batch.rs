@@ -0,0 +1,197 @@
+#[cfg(test)]
+use pretty_assertions::assert_eq;
+
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+
+use cele::BatchConfig;
+use cele::Batcher;
+use cele::MAX_INFLIGHT;
+use epaxos::conf::ClusterInfo;
+use epaxos::qpaxos::Command;
+use epaxos::ServerData;
+use epaxos::StorageAPI;
+use storage::MemEngine;
+
+use crate::support::*;
+
+mod support;
+
+#[tokio::test(threaded_scheduler)]
+async fn test_batch_concurrent_set_get() {
+    let ctx = InProcContext::new("az_3");
+
+    let (n_clients, n_per_client): (i64, i64) = (32, 10);
+
+    let mut handles = vec![];
+    for c in 0..n_clients {
+        let client = ctx.client.clone();
+        handles.push(thread::spawn(move || {
+            let mut con = client.get_connection().unwrap();
+            for i in 0..n_per_client {
+                let key = format!("k{}", c);
+                redis::cmd("SET").arg(&key).arg(i).execute(&mut con);
+
+                let v: i64 = redis::cmd("GET").arg(&key).query(&mut con).unwrap();
+                assert_eq!(i, v, "client: {}", c);
+            }
+        }));
+    }
+
+    for h in handles {
+        h.join().unwrap();
+    }
+
+    // concurrent commands are proposed in fewer instances.
+    let n_cmds = n_clients * n_per_client * 2;
+    let max = ctx
+        .get_replica(1)
+        .get_max_instance_ids(&[1])
+        .get(1)
+        .unwrap();
+    assert!(
+        max.idx + 1 < n_cmds,
+        "{} instances for {} commands",
+        max.idx + 1,
+        n_cmds
+    );
+
+    // all commands are proposed by replica 1.
+    let mut con = ctx.client.get_connection().unwrap();
+    let rst: Vec<(i64, i64, i64, i64)> = redis::cmd("PIPELINE").query(&mut con).unwrap();
+    assert_eq!(3, rst.len());
+
+    let (rid, inflight, peak, max) = rst[0];
+    assert_eq!((1, 0, MAX_INFLIGHT as i64), (rid, inflight, max));
+    assert!(peak <= max, "peak: {}", peak);
+
+    for (rid, inflight, peak, _) in rst[1..].iter() {
+        assert_eq!((0, 0), (*inflight, *peak), "replica: {}", rid);
+    }
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_batch_pipelined_one_connection() {
+    let ctx = InProcContext::new("az_3");
+
+    let n = 1000;
+
+    let mut pipe = redis::pipe();
+    for i in 0..n {
+        pipe.cmd("SET").arg("x").arg(i);
+    }
+    pipe.cmd("GET").arg("x");
+
+    let mut con = ctx.client.get_connection().unwrap();
+    let rst: Vec<redis::Value> = pipe.query(&mut con).unwrap();
+
+    assert_eq!(n + 1, rst.len());
+    for (i, v) in rst[..n].iter().enumerate() {
+        assert_eq!(redis::Value::Okay, *v, "SET {}", i);
+    }
+
+    // a GET sees the SETs sent before it.
+    let want = redis::Value::Data((n - 1).to_string().into_bytes());
+    assert_eq!(want, rst[n]);
+
+    // SETs of one connection are replicated in more than one instance at the same time.
+    let rst: Vec<(i64, i64, i64, i64)> = redis::cmd("PIPELINE").query(&mut con).unwrap();
+    let (rid, inflight, peak, max) = rst[0];
+    assert_eq!((1, 0), (rid, inflight));
+    assert!(peak > 1 && peak <= max, "peak: {}", peak);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_batch_backpressure() {
+    // peers of replica 1 accept connections but never reply, thus an instance is being
+    // replicated until RPC_TIMEOUT.
+    let lis = TcpListener::bind("127.0.0.1:0").unwrap();
+    let peer = lis.local_addr().unwrap();
+
+    let yaml = format!(
+        "
+nodes:
+    127.0.0.1:4441:
+        api_addr: 127.0.0.1:6379
+        replication: 127.0.0.1:4441
+    {peer}:
+        api_addr: 127.0.0.1:6380
+        replication: {peer}
+groups:
+-   range:
+    -   a
+    -   z
+    replicas:
+        1: 127.0.0.1:4441
+        2: {peer}
+        3: {peer}
+",
+        peer = peer
+    );
+
+    let ci = ClusterInfo::from_str(&yaml).unwrap();
+    let sto = Arc::new(MemEngine::new().unwrap());
+    let sd = Arc::new(ServerData::new(sto, ci, "127.0.0.1:4441".into()));
+
+    let cfg = BatchConfig {
+        window: Duration::from_millis(1),
+        max_size: 1,
+        max_inflight: 2,
+        max_queued: 1,
+    };
+    let (commit_tx, _commit_rx) = mpsc::channel(16);
+    let b = Batcher::with_config(sd, commit_tx, cfg);
+
+    let set = |i: i64| Command::from(("Set", "x", i.to_string().as_str()));
+    let wait = Duration::from_millis(300);
+
+    // 2 in the pipeline and 1 queued.
+    let mut rxs = vec![];
+    for i in 0..3 {
+        let rx = timeout(wait, b.enqueue(1, set(i))).await;
+        rxs.push(rx.expect("not blocked").unwrap());
+    }
+
+    // the pipeline and the queue are full.
+    let rst = timeout(wait, b.enqueue(1, set(3))).await;
+    assert!(rst.is_err(), "enqueue is blocked");
+
+    assert_eq!(vec![(1, 2, 2)], b.stats());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_batch_key_moved_away() {
+    let ctx = InProcContext::new("az_3");
+
+    // keys from `n` are moved away by a range change not yet applied to the cluster config.
+    let r1 = ctx.get_replica(1);
+    *r1.range.lock().unwrap() = Some(("a", "n").into());
+
+    let (commit_tx, _commit_rx) = mpsc::channel(16);
+    let b = Batcher::new(ctx.server.server_data.clone(), commit_tx);
+
+    // enqueued in one batch.
+    let mut rxs = vec![];
+    for k in vec!["b", "x", "c"] {
+        rxs.push(b.enqueue(1, Command::from(("Set", k, "1"))).await.unwrap());
+    }
+
+    let mut rsts = vec![];
+    for rx in rxs {
+        rsts.push(rx.await.unwrap());
+    }
+
+    // only the command on the moved key fails.
+    assert!(rsts[0].is_ok());
+    assert!(rsts[1].is_err());
+    assert!(rsts[2].is_ok());
+
+    assert_eq!(Some("1".into()), r1.storage.get_kv(b"b").unwrap());
+    assert_eq!(None, r1.storage.get_kv(b"x").unwrap());
+    assert_eq!(Some("1".into()), r1.storage.get_kv(b"c").unwrap());
+}
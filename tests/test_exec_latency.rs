@@ -0,0 +1,72 @@
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use epaxos::replica::EXEC_IDLE_INTERVAL;
+
+use crate::support::*;
+
+mod support;
+
+/// The interval the executor used to poll at, before it is woken up by commits.
+const OLD_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+#[tokio::test(threaded_scheduler)]
+async fn test_exec_get_latency() {
+    let ctx = InProcContext::new("az_3");
+
+    let client = ctx.client.clone();
+    let handle = thread::spawn(move || {
+        let mut con = client.get_connection().unwrap();
+
+        let n = 200;
+        let mut lats = vec![];
+        for i in 0..n {
+            let start = Instant::now();
+            redis::cmd("SET").arg("k").arg(i).execute(&mut con);
+            let v: usize = redis::cmd("GET").arg("k").query(&mut con).unwrap();
+            lats.push(start.elapsed());
+
+            assert_eq!(i, v);
+        }
+
+        lats.sort();
+        (lats[n / 2], lats[n * 99 / 100])
+    });
+
+    let (p50, p99) = handle.join().unwrap();
+
+    // The read index of the GET covers the instance of the SET just committed, thus the pair
+    // returns only after the instance is executed. The executor is woken up by the commit
+    // instead of waiting for the next round of polling, and the GET by the exec status.
+    // With polling, a command waits for half of the interval on average.
+    let max = OLD_POLL_INTERVAL / 2;
+    assert!(p50 < max, "p50: {:?}, expected less than {:?}", p50, max);
+    let max = OLD_POLL_INTERVAL;
+    assert!(p99 < max, "p99: {:?}, expected less than {:?}", p99, max);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_exec_idle_wakeups() {
+    let ctx = InProcContext::new("az_3");
+
+    let mut con = ctx.client.get_connection().unwrap();
+    redis::cmd("SET").arg("k").arg(1).execute(&mut con);
+
+    let r1 = ctx.get_replica(1);
+    let rounds = || r1.exec_rounds.load(Ordering::Relaxed);
+
+    // let the executor go idle.
+    tokio::time::delay_for(EXEC_IDLE_INTERVAL * 2).await;
+
+    let idle = EXEC_IDLE_INTERVAL * 5;
+    let start = rounds();
+    tokio::time::delay_for(idle).await;
+    let woken = rounds() - start;
+
+    // An idle executor only wakes up every EXEC_IDLE_INTERVAL, instead of every poll interval.
+    let max = (idle.as_millis() / EXEC_IDLE_INTERVAL.as_millis()) as u64 + 2;
+    assert!(woken <= max, "woken: {}, expected at most {}", woken, max);
+    assert!(woken < (idle.as_millis() / OLD_POLL_INTERVAL.as_millis()) as u64);
+}
@@ -0,0 +1,131 @@
+#[cfg(test)]
+use pretty_assertions::assert_eq;
+
+use std::net::SocketAddr;
+
+use cele::forward;
+use cele::ForwardPool;
+use cele::Server;
+use epaxos::qpaxos::Record;
+use epaxos::testutil;
+use epaxos::StorageAPI;
+
+use crate::support::*;
+
+mod support;
+
+/// start_remote starts node `127.0.0.1:4442` that serves `[n, z)`, and waits until its redis api
+/// is ready.
+fn start_remote(conf_name: &str) -> Server {
+    let server = InProcContext::start_node(conf_name, "127.0.0.1:4442");
+
+    let client = redis::Client::open("redis://127.0.0.1:6380/").unwrap();
+    testutil::wait_for(
+        || client.get_connection(),
+        |err| err.is_connection_refusal(),
+    );
+
+    server
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_forward() {
+    let ctx = InProcContext::new("an_nz_2");
+    let remote = start_remote("an_nz_2");
+
+    let mut con = ctx.client.get_connection().unwrap();
+
+    // x is served by node 127.0.0.1:4442.
+    let rst: String = redis::cmd("SET").arg("x").arg(1).query(&mut con).unwrap();
+    assert_eq!("OK", rst);
+
+    let r4 = remote.server_data.get_local_replica(4).unwrap();
+    assert_eq!(Some(Record::from("1")), r4.storage.get_kv(b"x").unwrap());
+
+    let v: i64 = redis::cmd("GET").arg("x").query(&mut con).unwrap();
+    assert_eq!(1, v);
+
+    let v: Option<i64> = redis::cmd("GET").arg("y").query(&mut con).unwrap();
+    assert_eq!(None, v);
+
+    // MSET is not forwarded by its first key, but runs a transaction with every group.
+    let rst: String = redis::cmd("MSET")
+        .arg("x")
+        .arg(2)
+        .arg("y")
+        .arg(3)
+        .query(&mut con)
+        .unwrap();
+    assert_eq!("OK", rst);
+
+    let v: i64 = redis::cmd("GET").arg("y").query(&mut con).unwrap();
+    assert_eq!(3, v);
+
+    let rst: String = redis::cmd("MSET")
+        .arg("c")
+        .arg(5)
+        .arg("z0")
+        .arg(6)
+        .query(&mut con)
+        .unwrap();
+    assert_eq!("OK", rst);
+
+    let r1 = ctx.get_replica(1);
+    assert_eq!(Some(Record::from("5")), r1.storage.get_kv(b"c").unwrap());
+    assert_eq!(Some(Record::from("6")), r4.storage.get_kv(b"z0").unwrap());
+
+    // a key with a local replica is not forwarded.
+    redis::cmd("SET").arg("b").arg(4).execute(&mut con);
+    assert_eq!(Some(Record::from("4")), r1.storage.get_kv(b"b").unwrap());
+
+    // a forwarded command is not forwarded again.
+    let rst: Result<String, _> = redis::cmd("FORWARDED").arg("GET").arg("x").query(&mut con);
+    assert!(rst.is_err());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_forward_pool() {
+    let _remote = start_remote("an_nz_2");
+
+    let pool = ForwardPool::new();
+    let addr: SocketAddr = "127.0.0.1:6380".parse().unwrap();
+
+    // a connection is reused.
+    for _ in 0..3 {
+        let get = vec![
+            redis::Value::Data(b"GET".to_vec()),
+            redis::Value::Data(b"x".to_vec()),
+        ];
+        let r = forward(&pool, &addr, &get).await.unwrap();
+        assert_eq!(parse::Response::Nil, r);
+        assert_eq!(1, pool.idle_count(&addr));
+    }
+
+    // an argument that is not a bulk string is not dropped silently.
+    let bad = vec![redis::Value::Data(b"GET".to_vec()), redis::Value::Int(1)];
+    assert!(forward(&pool, &addr, &bad).await.is_err());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_redirect() {
+    let ctx = InProcContext::new("an_nz_2_redirect");
+
+    let mut con = ctx.client.get_connection().unwrap();
+
+    // the range of the group serving x and a node serving it.
+    let rst: Result<String, _> = redis::cmd("GET").arg("x").query(&mut con);
+    let err = rst.unwrap_err();
+    assert_eq!(Some("REDIRECT"), err.code());
+    assert_eq!(Some("n z 127.0.0.1:6380"), err.detail());
+
+    // a key with a local replica is served.
+    let rst: String = redis::cmd("SET").arg("b").arg(1).query(&mut con).unwrap();
+    assert_eq!("OK", rst);
+
+    let v: i64 = redis::cmd("GET").arg("b").query(&mut con).unwrap();
+    assert_eq!(1, v);
+
+    // keys are routed by range, hash slots are not served.
+    let rst: Result<redis::Value, _> = redis::cmd("CLUSTER").arg("SLOTS").query(&mut con);
+    assert!(rst.is_err());
+}
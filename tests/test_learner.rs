@@ -0,0 +1,74 @@
+#[cfg(test)]
+use pretty_assertions::assert_eq;
+
+use std::thread::sleep;
+use std::time::Duration;
+
+use epaxos::qpaxos::Record;
+use epaxos::testutil;
+use epaxos::StorageAPI;
+
+use crate::support::*;
+
+mod support;
+
+#[tokio::test(threaded_scheduler)]
+async fn test_learner_stale_read() {
+    // node b serves replica 1, 2 and 3, and learner 4 is on node a.
+    let ctx = InProcContext::new("az_3_learner_1");
+    let a = InProcContext::start_node("az_3_learner_1", "127.0.0.1:4442");
+    let l = a.server_data.get_local_replica(4).unwrap();
+    assert!(l.is_learner());
+
+    let mut con = ctx.client.get_connection().unwrap();
+    for i in 0..10 {
+        redis::cmd("SET")
+            .arg(format!("k{}", i))
+            .arg(i)
+            .execute(&mut con);
+    }
+
+    // commits are delivered to the learner.
+    for _ in 0..100 {
+        if l.storage.get_kv(b"k9").unwrap().is_some() {
+            break;
+        }
+        sleep(Duration::from_millis(50));
+    }
+    assert_eq!(Some(Record::from("9")), l.storage.get_kv(b"k9").unwrap());
+
+    let client = redis::Client::open("redis://127.0.0.1:6380/").unwrap();
+    let mut lcon = testutil::wait_for(
+        || client.get_connection(),
+        |err| err.is_connection_refusal(),
+    );
+
+    // the learner serves reads but no writes.
+    for i in 0..10 {
+        let v: i64 = redis::cmd("GET")
+            .arg(format!("k{}", i))
+            .query(&mut lcon)
+            .unwrap();
+        assert_eq!(i, v);
+    }
+
+    let rst: redis::RedisResult<String> = redis::cmd("SET").arg("k0").arg(1).query(&mut lcon);
+    assert!(rst.is_err());
+
+    // the learner catches up with a voter in a few heartbeats.
+    let mut staleness = None;
+    for _ in 0..20 {
+        let rst: Vec<(i64, Option<i64>)> = redis::cmd("STALENESS").query(&mut lcon).unwrap();
+        assert_eq!(4, rst[0].0);
+        staleness = rst[0].1;
+        if staleness.is_some() {
+            break;
+        }
+        sleep(Duration::from_millis(200));
+    }
+    assert!(staleness.unwrap() < 5_000, "{:?}", staleness);
+
+    // voters have no staleness to report.
+    let rst: Vec<(i64, Option<i64>)> = redis::cmd("STALENESS").query(&mut con).unwrap();
+    assert_eq!(0, rst.len());
+}
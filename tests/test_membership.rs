@@ -0,0 +1,116 @@
+#[cfg(test)]
+use pretty_assertions::assert_eq;
+
+use std::thread::sleep;
+use std::time::Duration;
+
+use epaxos::qpaxos::Membership;
+use epaxos::replica::Replica;
+use epaxos::replication::change_membership;
+
+use crate::support::*;
+
+mod support;
+
+/// wait_membership waits for a replica to apply a membership.
+fn wait_membership(r: &Replica, want: &Membership) {
+    for _ in 0..100 {
+        if r.get_membership() == *want {
+            return;
+        }
+        sleep(Duration::from_millis(50));
+    }
+    assert_eq!(*want, r.get_membership(), "replica {}", r.replica_id);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_membership_change() {
+    let ctx = InProcContext::new("az_3");
+    let mut con = ctx.client.get_connection().unwrap();
+
+    redis::cmd("SET").arg("a").arg(1).execute(&mut con);
+
+    let r1 = ctx.get_replica(1);
+    let m = r1.get_membership();
+    assert_eq!(vec![1, 2, 3], m.replica_ids());
+
+    // remove replica 3
+    let mut voters = m.voters.clone();
+    let addr3 = voters.remove(&3).unwrap();
+
+    let got = change_membership(&r1, voters.clone()).await.unwrap();
+    let want = Membership::new(voters.clone());
+    assert_eq!(want, got);
+    assert_eq!(want, r1.get_membership());
+    assert_eq!(vec![1, 2], r1.get_group_replica_ids());
+
+    for rid in 1..=3 {
+        wait_membership(&ctx.get_replica(rid), &want);
+    }
+
+    redis::cmd("SET").arg("b").arg(2).execute(&mut con);
+    let v: i64 = redis::cmd("GET").arg("a").query(&mut con).unwrap();
+    assert_eq!(1, v);
+
+    // add it back
+    voters.insert(3, addr3);
+
+    let got = change_membership(&ctx.get_replica(2), voters.clone())
+        .await
+        .unwrap();
+    let want = Membership::new(voters.clone());
+    assert_eq!(want, got);
+
+    for rid in 1..=3 {
+        wait_membership(&ctx.get_replica(rid), &want);
+    }
+
+    let v: i64 = redis::cmd("GET").arg("b").query(&mut con).unwrap();
+    assert_eq!(2, v);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_membership_swap_voters() {
+    let ctx = InProcContext::new("az_5");
+    let mut con = ctx.client.get_connection().unwrap();
+
+    redis::cmd("SET").arg("a").arg(1).execute(&mut con);
+
+    let r1 = ctx.get_replica(1);
+    let all = r1.get_membership().voters.clone();
+
+    // start with voters 1, 2, 3.
+    let mut voters = all.clone();
+    voters.remove(&4);
+    voters.remove(&5);
+    change_membership(&r1, voters.clone()).await.unwrap();
+
+    // swap out 2 and 3 for 4 and 5, no replica of the old quorum {2, 3} is in the new config.
+    let mut voters = all.clone();
+    voters.remove(&2);
+    voters.remove(&3);
+
+    let got = change_membership(&r1, voters.clone()).await.unwrap();
+    let want = Membership::new(voters.clone());
+    assert_eq!(want, got);
+
+    // the joint config had been executed by a quorum of the new config before leaving it.
+    let n = vec![4, 5]
+        .into_iter()
+        .filter(|rid| {
+            let m = ctx.get_replica(*rid).get_membership();
+            m == want || m.joint == voters
+        })
+        .count();
+    assert!(n >= 1, "{} of replica 4, 5 executed the joint config", n);
+
+    for rid in vec![1, 4, 5] {
+        wait_membership(&ctx.get_replica(rid), &want);
+    }
+
+    redis::cmd("SET").arg("b").arg(2).execute(&mut con);
+    let v: i64 = redis::cmd("GET").arg("a").query(&mut con).unwrap();
+    assert_eq!(1, v);
+    let v: i64 = redis::cmd("GET").arg("b").query(&mut con).unwrap();
+    assert_eq!(2, v);
+}
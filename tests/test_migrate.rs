@@ -0,0 +1,106 @@
+#[cfg(test)]
+use pretty_assertions::assert_eq;
+
+use std::thread::sleep;
+use std::time::Duration;
+
+use epaxos::qpaxos::Record;
+use epaxos::replica::Replica;
+use epaxos::StorageAPI;
+
+use crate::support::*;
+
+mod support;
+
+/// wait_records waits until replica `r` has executed `k0` to `k{n-1}`, and checks their values.
+fn wait_records(r: &Replica, n: i64) {
+    let key = format!("k{}", n - 1).into_bytes();
+    for _ in 0..100 {
+        if r.storage.get_kv(&key).unwrap().is_some() {
+            break;
+        }
+        sleep(Duration::from_millis(50));
+    }
+
+    for i in 0..n {
+        let got = r.storage.get_kv(&format!("k{}", i).into_bytes()).unwrap();
+        assert_eq!(
+            Some(Record::from(i.to_string().as_str())),
+            got,
+            "replica:{} k{}",
+            r.replica_id,
+            i
+        );
+    }
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_migrate_replica() {
+    let (na, nb) = ("127.0.0.1:4442", "127.0.0.1:4441");
+
+    // node b serves replica 2 and 3, and replica 1 is on node a.
+    let ctx = InProcContext::new("az_3_remote_1");
+    let a = InProcContext::start_node("az_3_remote_1", na);
+    let sda = &a.server_data;
+    let sdb = &ctx.server.server_data;
+
+    let mut con = ctx.client.get_connection().unwrap();
+    for i in 0..10 {
+        redis::cmd("SET")
+            .arg(format!("k{}", i))
+            .arg(i)
+            .execute(&mut con);
+    }
+
+    wait_records(&sda.get_local_replica(1).unwrap(), 10);
+
+    // replica 1 is replaced by replica 4 on node b.
+    let rst: String = redis::cmd("MIGRATE").arg(1).arg(4).query(&mut con).unwrap();
+    assert_eq!("OK", rst);
+
+    assert!(sda.get_local_replica(1).is_none());
+    assert!(sda.get_cluster().get_replica(1).is_none());
+    assert_eq!(nb, sda.get_cluster().get_replica(4).unwrap().node_id);
+
+    // node b applies it after executing it.
+    let voters = || {
+        let g = sdb.get_cluster().groups[0].clone();
+        (
+            g.replicas.keys().cloned().collect::<Vec<_>>(),
+            g.learners.len(),
+        )
+    };
+    for _ in 0..100 {
+        if voters() == (vec![2, 3, 4], 0) {
+            break;
+        }
+        sleep(Duration::from_millis(50));
+    }
+    assert_eq!((vec![2, 3, 4], 0), voters());
+
+    let r4 = sdb.get_local_replica(4).unwrap();
+    assert!(!r4.is_learner());
+    assert_eq!(vec![2, 3, 4], r4.get_membership().replica_ids());
+    assert_eq!(
+        Some(&format!("http://{}", nb)),
+        r4.get_membership().get_addr(4)
+    );
+
+    // nothing is lost, and the migrated replica goes on replicating.
+    for i in 10..20 {
+        redis::cmd("SET")
+            .arg(format!("k{}", i))
+            .arg(i)
+            .execute(&mut con);
+    }
+
+    for i in 0..20 {
+        let v: i64 = redis::cmd("GET")
+            .arg(format!("k{}", i))
+            .query(&mut con)
+            .unwrap();
+        assert_eq!(i, v);
+    }
+
+    wait_records(&r4, 20);
+}
@@ -0,0 +1,134 @@
+#[cfg(test)]
+use pretty_assertions::assert_eq;
+
+use std::thread::sleep;
+use std::time::Duration;
+
+use epaxos::qpaxos::ReplicaId;
+use epaxos::ServerData;
+use epaxos::StorageAPI;
+
+use crate::support::*;
+
+mod support;
+
+/// wait_replicas waits until the local replicas of a server are `want`.
+fn wait_replicas(sd: &ServerData, want: &[ReplicaId]) {
+    let rids = || -> Vec<ReplicaId> {
+        sd.get_local_replicas()
+            .iter()
+            .map(|r| r.replica_id)
+            .collect()
+    };
+
+    for _ in 0..100 {
+        if rids() == want {
+            return;
+        }
+        sleep(Duration::from_millis(50));
+    }
+    assert_eq!(want.to_vec(), rids());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_range_split_merge() {
+    let ctx = InProcContext::new("az_3");
+    let sd = &ctx.server.server_data;
+    let mut con = ctx.client.get_connection().unwrap();
+
+    redis::cmd("SET").arg("b").arg(1).execute(&mut con);
+    redis::cmd("SET").arg("n").arg(2).execute(&mut con);
+
+    let rst: String = redis::cmd("SPLIT")
+        .arg("n")
+        .arg(vec![1, 11, 2, 12, 3, 13])
+        .query(&mut con)
+        .unwrap();
+    assert_eq!("OK", rst);
+
+    wait_replicas(sd, &[1, 2, 3, 11, 12, 13]);
+
+    let cluster = sd.get_cluster();
+    assert_eq!(2, cluster.groups.len());
+    let g = cluster.get_group_for_key("n").unwrap();
+    assert_eq!(("n".to_string(), "z".to_string()), g.range);
+    assert_eq!(
+        vec![11, 12, 13],
+        g.replicas.keys().cloned().collect::<Vec<_>>()
+    );
+
+    // records are moved to the new group.
+    for (rid, key, want) in vec![(1, "b", true), (1, "n", false), (11, "n", true)] {
+        let r = ctx.get_replica(rid);
+        let got = r.storage.get_kv(key.as_bytes()).unwrap();
+        assert_eq!(want, got.is_some(), "replica:{} key:{}", rid, key);
+    }
+
+    let v: i64 = redis::cmd("GET").arg("n").query(&mut con).unwrap();
+    assert_eq!(2, v);
+
+    redis::cmd("SET").arg("n").arg(3).execute(&mut con);
+    redis::cmd("SET").arg("o").arg(4).execute(&mut con);
+
+    let v: i64 = redis::cmd("GET").arg("b").query(&mut con).unwrap();
+    assert_eq!(1, v);
+
+    // merge it back
+    let rst: String = redis::cmd("MERGE")
+        .arg("b")
+        .arg(vec![1, 11, 2, 12, 3, 13])
+        .query(&mut con)
+        .unwrap();
+    assert_eq!("OK", rst);
+
+    wait_replicas(sd, &[1, 2, 3]);
+    assert_eq!(1, sd.get_cluster().groups.len());
+
+    for (key, want) in vec![("b", 1), ("n", 3), ("o", 4)] {
+        let v: i64 = redis::cmd("GET").arg(key).query(&mut con).unwrap();
+        assert_eq!(want, v, "key:{}", key);
+    }
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_range_split_concurrent_writes() {
+    let ctx = InProcContext::new("az_3");
+    let sd = &ctx.server.server_data;
+
+    let client = ctx.client.clone();
+    let writer = std::thread::spawn(move || {
+        let mut con = client.get_connection().unwrap();
+
+        // keys in the split range, written while splitting.
+        let mut oks = vec![];
+        for i in 0..300 {
+            let k = format!("n{}", i);
+            let rst: redis::RedisResult<String> = redis::cmd("SET").arg(&k).arg(i).query(&mut con);
+            if rst.is_ok() {
+                oks.push((k, i));
+            }
+        }
+        oks
+    });
+
+    sleep(Duration::from_millis(50));
+
+    let mut con = ctx.client.get_connection().unwrap();
+    let rst: String = redis::cmd("SPLIT")
+        .arg("n")
+        .arg(vec![1, 11, 2, 12, 3, 13])
+        .query(&mut con)
+        .unwrap();
+    assert_eq!("OK", rst);
+
+    let oks = writer.join().unwrap();
+    assert!(oks.len() > 0);
+
+    wait_replicas(sd, &[1, 2, 3, 11, 12, 13]);
+
+    // every write replied with OK is kept.
+    for (k, want) in oks {
+        let v: Option<i64> = redis::cmd("GET").arg(&k).query(&mut con).unwrap();
+        assert_eq!(Some(want), v, "key:{}", k);
+    }
+}
@@ -0,0 +1,87 @@
+#[cfg(test)]
+use pretty_assertions::assert_eq;
+
+use crate::support::*;
+
+mod support;
+
+#[tokio::test(threaded_scheduler)]
+async fn test_read_without_instance() {
+    let ctx = InProcContext::new("az_3");
+    let r = ctx.get_replica(1);
+
+    let mut con = ctx.client.get_connection().unwrap();
+    for i in 0..10 {
+        redis::cmd("SET").arg("k").arg(i).execute(&mut con);
+
+        let v: i64 = redis::cmd("GET").arg("k").query(&mut con).unwrap();
+        assert_eq!(i, v);
+    }
+
+    // a GET is served with a read index from a quorum, not replicated.
+    let maxs = r.get_max_instance_ids(&[1, 2, 3]);
+    for _ in 0..10 {
+        let v: i64 = redis::cmd("GET").arg("k").query(&mut con).unwrap();
+        assert_eq!(9, v);
+
+        let v: Option<i64> = redis::cmd("GET").arg("x").query(&mut con).unwrap();
+        assert_eq!(None, v);
+    }
+    assert_eq!(maxs, r.get_max_instance_ids(&[1, 2, 3]));
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_read_local() {
+    let ctx = InProcContext::new("az_3");
+    let r = ctx.get_replica(1);
+
+    let mut con = ctx.client.get_connection().unwrap();
+    redis::cmd("SET").arg("k").arg(1).execute(&mut con);
+
+    let rst: String = redis::cmd("CONSISTENCY").query(&mut con).unwrap();
+    assert_eq!("linearizable", rst);
+
+    // the consistency is per connection.
+    let rst: String = redis::cmd("READONLY").query(&mut con).unwrap();
+    assert_eq!("OK", rst);
+    let rst: String = redis::cmd("CONSISTENCY").query(&mut con).unwrap();
+    assert_eq!("local", rst);
+
+    let mut con2 = ctx.client.get_connection().unwrap();
+    let rst: String = redis::cmd("CONSISTENCY").query(&mut con2).unwrap();
+    assert_eq!("linearizable", rst);
+
+    // a local read sees what the replica has executed.
+    let v: i64 = redis::cmd("GET").arg("k").query(&mut con).unwrap();
+    assert_eq!(1, v);
+
+    let (v, executed): (i64, Vec<(i64, i64)>) = redis::cmd("GET")
+        .arg("k")
+        .arg("WITHEXECUTED")
+        .query(&mut con)
+        .unwrap();
+    assert_eq!(1, v);
+    assert_eq!(
+        vec![1, 2, 3],
+        executed.iter().map(|x| x.0).collect::<Vec<_>>()
+    );
+    assert_eq!(
+        r.get_max_instance_ids(&[1]).get(1).unwrap().idx,
+        executed[0].1
+    );
+
+    let rst: redis::RedisResult<String> = redis::cmd("CONSISTENCY").arg("foo").query(&mut con);
+    assert!(rst.is_err());
+
+    let rst: String = redis::cmd("CONSISTENCY")
+        .arg("LINEARIZABLE")
+        .query(&mut con)
+        .unwrap();
+    assert_eq!("OK", rst);
+    let (v, _): (i64, Vec<(i64, i64)>) = redis::cmd("GET")
+        .arg("k")
+        .arg("WITHEXECUTED")
+        .query(&mut con)
+        .unwrap();
+    assert_eq!(1, v);
+}
@@ -0,0 +1,84 @@
+#[cfg(test)]
+use pretty_assertions::assert_eq;
+
+use epaxos::cmdvec;
+
+use epaxos::qpaxos::*;
+use epaxos::recover;
+use epaxos::replica::Replica;
+use epaxos::testutil;
+use epaxos::StorageAPI;
+
+use std::sync::Arc;
+use storage::MemEngine;
+
+use crate::support::*;
+
+mod support;
+
+#[tokio::test(threaded_scheduler)]
+async fn test_recover_with_leader_crashed() {
+    // Replica 1 is on a node that is never started, i.e., the leader is dead.
+    // Replica 2 and 3 are served by the in-process server.
+    let ctx = InProcContext::new("az_3_remote_1");
+    let cluster = testutil::new_cluster("az_3_remote_1");
+    let leader = Replica::new(1, &cluster, Arc::new(MemEngine::new().unwrap())).unwrap();
+
+    {
+        // The leader crashed after its Prepare reached replica 2.
+        // Replica 3 recovers the value from replica 2.
+        let inst = leader
+            .new_instance(&cmdvec![("Set", "x", "1")])
+            .await
+            .unwrap();
+        let iid = inst.instance_id.unwrap();
+
+        let req = MakeRequest::prepare(2, &inst, &[false, false, false]);
+        ctx.get_replica(2).handle_replicate(req).await.unwrap();
+
+        let got = recover(&ctx.get_replica(3), iid).await.unwrap();
+        assert!(got.committed);
+        assert_eq!(inst.cmds, got.cmds);
+
+        for rid in 2..=3 {
+            let r = ctx.get_replica(rid);
+            let sto = &r.storage;
+            let inst = sto.get_instance(&iid).unwrap().unwrap();
+
+            assert!(inst.committed, "replica:{}", rid);
+            assert_eq!(got.cmds, inst.cmds, "replica:{}", rid);
+            assert_eq!(got.deps, inst.deps, "replica:{}", rid);
+        }
+    }
+
+    {
+        // The leader crashed before sending anything.
+        // No one has seen the instance thus a NoOp is committed.
+        let inst = leader
+            .new_instance(&cmdvec![("Set", "y", "2")])
+            .await
+            .unwrap();
+        let iid = inst.instance_id.unwrap();
+
+        let got = recover(&ctx.get_replica(2), iid).await.unwrap();
+        assert!(got.committed);
+        assert_eq!(cmdvec![()], got.cmds);
+
+        for rid in 2..=3 {
+            let r = ctx.get_replica(rid);
+            let sto = &r.storage;
+            let inst = sto.get_instance(&iid).unwrap().unwrap();
+
+            assert!(inst.committed, "replica:{}", rid);
+            assert_eq!(cmdvec![()], inst.cmds, "replica:{}", rid);
+        }
+    }
+
+    {
+        // Recovering a committed instance changes nothing.
+        let iid = InstanceId::from((1, 0));
+        let before = ctx.get_replica(2).get_instance(iid).unwrap();
+        let got = recover(&ctx.get_replica(2), iid).await.unwrap();
+        assert_eq!(before, got);
+    }
+}
@@ -25,7 +25,8 @@ async fn test_replica_exec_thread() {
     // there is only replica
 
     for inst in cases.iter() {
-        let sto = &ctx.get_replica(1).storage;
+        let r = ctx.get_replica(1);
+        let sto = &r.storage;
         sto.set_instance(&inst.instance_id.unwrap(), &inst).unwrap();
 
         loop {
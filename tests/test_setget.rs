@@ -25,7 +25,8 @@ async fn test_set() {
     // TODO no replica receives accept because 1, 2 consitutes a fast-quorum
 
     for rid in 1..=3 {
-        let sto = &ctx.get_replica(rid).storage;
+        let r = ctx.get_replica(rid);
+        let sto = &r.storage;
         let inst = sto.get_instance(&(1, 0).into());
 
         assert!(inst.is_ok());
@@ -49,7 +50,8 @@ async fn test_set() {
         delay_for(Duration::from_millis(1_000)).await;
 
         for rid in 1..=3 {
-            let sto = &ctx.get_replica(rid).storage;
+            let r = ctx.get_replica(rid);
+            let sto = &r.storage;
             let inst = sto.get_instance(&(1, 0).into());
             let inst = inst.unwrap().unwrap();
 
@@ -0,0 +1,79 @@
+#[cfg(test)]
+use pretty_assertions::assert_eq;
+
+use std::sync::Arc;
+use std::thread::sleep;
+use std::time::Duration;
+
+use epaxos::qpaxos::Record;
+use epaxos::replica::SNAPSHOT_CHUNK_SIZE;
+use epaxos::replication::catch_up;
+use epaxos::testutil;
+use epaxos::StorageAPI;
+use storage::MemEngine;
+
+use crate::support::*;
+
+mod support;
+
+#[tokio::test(threaded_scheduler)]
+async fn test_snapshot_catch_up() {
+    let ctx = InProcContext::new("az_3");
+
+    let mut con = ctx.client.get_connection().unwrap();
+    for i in 0..10 {
+        redis::cmd("SET")
+            .arg(format!("k{}", i))
+            .arg(i)
+            .execute(&mut con);
+    }
+
+    // wait for replica 2 to execute all of them.
+    let r2 = ctx.get_replica(2);
+    for _ in 0..100 {
+        if r2.storage.get_kv(&b"k9".to_vec()).unwrap().is_some() {
+            break;
+        }
+        sleep(Duration::from_millis(50));
+    }
+
+    // more records than one chunk.
+    let n = SNAPSHOT_CHUNK_SIZE * 2 + 1;
+    for i in 0..n {
+        r2.storage
+            .set_kv(&format!("big{}", i).into_bytes(), &Record::from("v"))
+            .unwrap();
+    }
+
+    let peer = ctx
+        .get_replica(1)
+        .get_peers()
+        .iter()
+        .find(|p| p.replica_id == 2)
+        .unwrap()
+        .clone();
+
+    // a brand new replica 3
+    let r3 = testutil::new_replica(
+        3,
+        vec![1, 2, 3],
+        vec![peer.clone()],
+        Arc::new(MemEngine::new().unwrap()),
+    );
+
+    catch_up(&r3, &peer).await.unwrap();
+
+    assert_eq!(r2.get_executed().unwrap(), r3.get_executed().unwrap());
+    for i in 0..10 {
+        let got = r3.storage.get_kv(&format!("k{}", i).into_bytes()).unwrap();
+        assert_eq!(Some(Record::from(i.to_string().as_str())), got);
+    }
+    for i in 0..n {
+        let got = r3
+            .storage
+            .get_kv(&format!("big{}", i).into_bytes())
+            .unwrap();
+        assert_eq!(Some(Record::from("v")), got);
+    }
+    assert!(!r3.is_installing().unwrap());
+}
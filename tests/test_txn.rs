@@ -0,0 +1,208 @@
+#[cfg(test)]
+use pretty_assertions::assert_eq;
+
+use std::thread::sleep;
+use std::time::Duration;
+
+use cele::Server;
+use epaxos::prepare_txn;
+use epaxos::qpaxos::Command;
+use epaxos::qpaxos::OpCode;
+use epaxos::qpaxos::Record;
+use epaxos::qpaxos::ReplicaId;
+use epaxos::qpaxos::TxnPrepare;
+use epaxos::qpaxos::TxnStatus;
+use epaxos::recover_txn;
+use epaxos::run_txn;
+use epaxos::testutil;
+use epaxos::ServerData;
+use epaxos::StorageAPI;
+
+use crate::support::*;
+
+mod support;
+
+/// split splits the only group of "az_3" at "n": replica 1, 2, 3 serve `[a, n)` and 11, 12, 13
+/// serve `[n, z)`.
+fn split(ctx: &InProcContext) {
+    let mut con = ctx.client.get_connection().unwrap();
+    let rst: String = redis::cmd("SPLIT")
+        .arg("n")
+        .arg(vec![1, 11, 2, 12, 3, 13])
+        .query(&mut con)
+        .unwrap();
+    assert_eq!("OK", rst);
+
+    wait_replicas(&ctx.server.server_data, &[1, 2, 3, 11, 12, 13]);
+}
+
+/// wait_replicas waits until the local replicas of a server are `want`.
+fn wait_replicas(sd: &ServerData, want: &[ReplicaId]) {
+    let rids = || -> Vec<ReplicaId> {
+        sd.get_local_replicas()
+            .iter()
+            .map(|r| r.replica_id)
+            .collect()
+    };
+
+    for _ in 0..100 {
+        if rids() == want {
+            return;
+        }
+        sleep(Duration::from_millis(50));
+    }
+    assert_eq!(want.to_vec(), rids());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_txn_mset_across_groups() {
+    let ctx = InProcContext::new("az_3");
+    split(&ctx);
+
+    let mut con = ctx.client.get_connection().unwrap();
+    let rst: String = redis::cmd("MSET")
+        .arg("b")
+        .arg(1)
+        .arg("x")
+        .arg(2)
+        .arg("c")
+        .arg(3)
+        .query(&mut con)
+        .unwrap();
+    assert_eq!("OK", rst);
+
+    for (key, want) in vec![("b", 1), ("x", 2), ("c", 3)] {
+        let v: i64 = redis::cmd("GET").arg(key).query(&mut con).unwrap();
+        assert_eq!(want, v, "key:{}", key);
+    }
+
+    for rid in vec![1, 11] {
+        let r = ctx.get_replica(rid);
+        assert_eq!(0, r.pending_txns().unwrap().len(), "replica:{}", rid);
+    }
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_txn_recover_abandoned() {
+    let ctx = InProcContext::new("az_3");
+    split(&ctx);
+
+    let mut con = ctx.client.get_connection().unwrap();
+    redis::cmd("SET").arg("x").arg(1).execute(&mut con);
+
+    // a coordinator prepares x in the second group and quits.
+    let r = ctx.get_replica(11);
+    let p = TxnPrepare {
+        txn_id: b"abandoned".to_vec(),
+        primary: b"b".to_vec(),
+        writes: vec![Command::from((OpCode::Set, "x", "2"))],
+    };
+    assert_eq!(TxnStatus::Pending, prepare_txn(&r, &p).await.unwrap());
+
+    // x is locked.
+    let rst: Result<String, _> = redis::cmd("MSET")
+        .arg("b")
+        .arg(3)
+        .arg("x")
+        .arg(3)
+        .query(&mut con);
+    assert!(rst.is_err());
+
+    // a read waits until recovery aborts it in the primary group, and then in the second group.
+    let v: i64 = redis::cmd("GET").arg("x").query(&mut con).unwrap();
+    assert_eq!(1, v);
+    assert_eq!(0, r.pending_txns().unwrap().len());
+
+    // an aborted state may have been deleted by gc.
+    let st = ctx
+        .get_replica(1)
+        .storage
+        .get_txn_state(b"abandoned")
+        .unwrap();
+    if let Some(st) = st {
+        assert_eq!(TxnStatus::Aborted, st.status());
+    }
+
+    let rst: String = redis::cmd("MSET")
+        .arg("b")
+        .arg(4)
+        .arg("x")
+        .arg(4)
+        .query(&mut con)
+        .unwrap();
+    assert_eq!("OK", rst);
+
+    for key in vec!["b", "x"] {
+        let v: i64 = redis::cmd("GET").arg(key).query(&mut con).unwrap();
+        assert_eq!(4, v, "key:{}", key);
+    }
+}
+
+/// start_remote starts node `127.0.0.1:4442` of "an_nz_2", which serves `[n, z)`, and waits
+/// until its redis api is ready.
+fn start_remote() -> Server {
+    let server = InProcContext::start_node("an_nz_2", "127.0.0.1:4442");
+
+    let client = redis::Client::open("redis://127.0.0.1:6380/").unwrap();
+    testutil::wait_for(
+        || client.get_connection(),
+        |err| err.is_connection_refusal(),
+    );
+
+    server
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_txn_remote_group() {
+    // the coordinator has no replica of `[n, z)`.
+    let ctx = InProcContext::new("an_nz_2");
+    let remote = start_remote();
+
+    let sd = &ctx.server.server_data;
+    let writes = vec![
+        Command::from((OpCode::Set, "b", "1")),
+        Command::from((OpCode::Set, "x", "2")),
+    ];
+    let st = run_txn(sd, b"remote_group", writes).await.unwrap();
+    assert_eq!(TxnStatus::Committed, st);
+
+    let r1 = ctx.get_replica(1);
+    let r4 = remote.server_data.get_local_replica(4).unwrap();
+    assert_eq!(Some(Record::from("1")), r1.storage.get_kv(b"b").unwrap());
+    assert_eq!(Some(Record::from("2")), r4.storage.get_kv(b"x").unwrap());
+    assert_eq!(0, r1.pending_txns().unwrap().len());
+    assert_eq!(0, r4.pending_txns().unwrap().len());
+
+    // the primary group is the remote one.
+    let writes = vec![
+        Command::from((OpCode::Set, "y", "3")),
+        Command::from((OpCode::Set, "c", "4")),
+    ];
+    let st = run_txn(sd, b"remote_primary", writes).await.unwrap();
+    assert_eq!(TxnStatus::Committed, st);
+
+    assert_eq!(Some(Record::from("4")), r1.storage.get_kv(b"c").unwrap());
+    assert_eq!(Some(Record::from("3")), r4.storage.get_kv(b"y").unwrap());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn test_txn_recover_remote_primary() {
+    // the primary group `[n, z)` is served only by another node.
+    let ctx = InProcContext::new("an_nz_2");
+    let _remote = start_remote();
+
+    let r = ctx.get_replica(1);
+    let p = TxnPrepare {
+        txn_id: b"remote_primary".to_vec(),
+        primary: b"x".to_vec(),
+        writes: vec![Command::from((OpCode::Set, "b", "2"))],
+    };
+    assert_eq!(TxnStatus::Pending, prepare_txn(&r, &p).await.unwrap());
+
+    // this node asks the primary group for the decision through a voter on the other node.
+    let st = recover_txn(&ctx.server.server_data, &r, &p).await.unwrap();
+    assert_eq!(TxnStatus::Aborted, st);
+
+    assert_eq!(0, r.pending_txns().unwrap().len());
+    assert_eq!(None, r.storage.get_kv(b"b").unwrap());
+}